@@ -0,0 +1,443 @@
+//! Shared helpers for rewriting expressions: product normal forms and shorthand constructors.
+
+use std::collections::HashMap;
+
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+use crate::rational::Rational;
+
+pub(crate) fn node_count(expr: &Expr) -> usize {
+    match expr {
+        Expr::Call { arg, .. } => 1 + node_count(arg),
+        Expr::UnaryOp { expr, .. } => 1 + node_count(expr),
+        Expr::BinaryOp { left, right, .. } => 1 + node_count(left) + node_count(right),
+        _ => 1,
+    }
+}
+
+/// Stand-in values for every letter in `expr` other than `var`, used for numeric checks.
+pub(crate) fn parameters(expr: &Expr, var: char) -> HashMap<char, f64> {
+    expr.free_symbols()
+        .iter()
+        .filter_map(|name| {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c != var => Some(c),
+                _ => None,
+            }
+        })
+        .enumerate()
+        .map(|(idx, c)| (c, 0.7548776662 + 0.5698402910 * idx as f64))
+        .collect()
+}
+
+/// Splits a product into its rational factor and the rest, `None` standing for `1`:
+/// `-3*a*x/4` becomes `(-3/4, a*x)`.
+pub(crate) fn split_constant(expr: &Expr) -> (Rational, Option<Expr>) {
+    if let Some(exact) = Rational::from_expr(expr) {
+        return (exact, None);
+    }
+    let split = match expr {
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => {
+            let (factor, rest) = split_constant(expr);
+            Some((-factor, rest))
+        }
+        Expr::BinaryOp {
+            op: op @ (BinaryOp::Mul | BinaryOp::Div),
+            left,
+            right,
+        } => {
+            let (left_factor, left_rest) = split_constant(left);
+            let (right_factor, right_rest) = split_constant(right);
+            let factor = match op {
+                BinaryOp::Mul => left_factor.checked_mul(right_factor),
+                _ if right_rest.is_none() => left_factor.checked_div(right_factor),
+                _ => None,
+            };
+            factor.map(|factor| {
+                let rest = match (left_rest, right_rest) {
+                    (None, None) => None,
+                    (Some(rest), None) => Some(rest),
+                    (None, Some(rest)) => Some(rest),
+                    (Some(left), Some(right)) => Some(Expr::BinaryOp {
+                        op: BinaryOp::Mul,
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    }),
+                };
+                (factor, rest)
+            })
+        }
+        _ => None,
+    };
+    split.unwrap_or((Rational::ONE, Some(expr.clone())))
+}
+
+/// `|factor| * rest * power`, divided by the denominator of `factor`, with the sign of
+/// `factor`. `None` if the numbers don't fit an `i32`.
+pub(crate) fn scaled(
+    factor: Rational,
+    rest: Option<Expr>,
+    power: Option<Expr>,
+) -> Option<(bool, Expr)> {
+    let numer = i32::try_from(factor.numer().unsigned_abs()).ok()?;
+    let denom = i32::try_from(factor.denom()).ok()?;
+    let mut factors: Vec<Expr> = rest.into_iter().chain(power).collect();
+    if numer != 1 || factors.is_empty() {
+        factors.insert(0, Expr::Number(numer));
+    }
+    let mut magnitude = factors
+        .into_iter()
+        .reduce(|product, factor| Expr::BinaryOp {
+            op: BinaryOp::Mul,
+            left: Box::new(product),
+            right: Box::new(factor),
+        })?;
+    if denom != 1 {
+        magnitude = Expr::BinaryOp {
+            op: BinaryOp::Div,
+            left: Box::new(magnitude),
+            right: Box::new(Expr::Number(denom)),
+        };
+    }
+    Some((factor.is_negative(), magnitude))
+}
+
+/// Cancels factors shared by `n` and `d` and combines powers of the same base, returning the
+/// new numerator and denominator: `x^-1/(-1*x^-2)` becomes `(-x, 1)`.
+pub(crate) fn cancel_factors(n: &Expr, d: &Expr) -> (Expr, Expr) {
+    factors(&div(n, d))
+        .and_then(|(coefficient, factors)| fraction(coefficient, factors))
+        .unwrap_or_else(|| (n.clone(), d.clone()))
+}
+
+/// `expr` as a rational coefficient times powers of distinct bases with integer exponents,
+/// `None` if the numbers overflow. Exponents can be zero where factors cancelled.
+pub(crate) fn factors(expr: &Expr) -> Option<(Rational, Vec<(Expr, i32)>)> {
+    let mut coefficient = Rational::ONE;
+    let mut factors = Vec::new();
+    collect_factors(expr, 1, &mut coefficient, &mut factors).then_some((coefficient, factors))
+}
+
+/// Numerator and denominator of a product in the form returned by [`factors`], `None` if the
+/// coefficient doesn't fit an `i32`.
+pub(crate) fn fraction(coefficient: Rational, factors: Vec<(Expr, i32)>) -> Option<(Expr, Expr)> {
+    let mut numerator = Vec::new();
+    let mut denominator = Vec::new();
+    if coefficient.numer() != 1 {
+        numerator.push(Expr::Number(i32::try_from(coefficient.numer()).ok()?));
+    }
+    if coefficient.denom() != 1 {
+        denominator.push(Expr::Number(i32::try_from(coefficient.denom()).ok()?));
+    }
+    for (base, exponent) in factors {
+        let (side, exponent) = match exponent {
+            0 => continue,
+            e if e > 0 => (&mut numerator, e),
+            e => (&mut denominator, -e),
+        };
+        side.push(match exponent {
+            1 => base,
+            _ => pow(&base, &Expr::Number(exponent)),
+        });
+    }
+    let product = |factors: Vec<Expr>| {
+        factors
+            .into_iter()
+            .reduce(|a, b| mul(&a, &b))
+            .unwrap_or(Expr::Number(1))
+    };
+    Some((product(numerator), product(denominator)))
+}
+
+/// Adds the factors of `expr`, raised to `exponent`, to `coefficient` and `factors`. Returns
+/// false if the numbers overflow.
+fn collect_factors(
+    expr: &Expr,
+    exponent: i32,
+    coefficient: &mut Rational,
+    factors: &mut Vec<(Expr, i32)>,
+) -> bool {
+    match expr {
+        Expr::Number(n) if *n != 0 => {
+            match Rational::integer(*n as i64)
+                .checked_pow(exponent)
+                .and_then(|factor| coefficient.checked_mul(factor))
+            {
+                Some(product) => *coefficient = product,
+                None => return false,
+            }
+            true
+        }
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => {
+            if exponent % 2 != 0 {
+                *coefficient = -*coefficient;
+            }
+            collect_factors(expr, exponent, coefficient, factors)
+        }
+        Expr::BinaryOp {
+            op: BinaryOp::Mul,
+            left,
+            right,
+        } => {
+            collect_factors(left, exponent, coefficient, factors)
+                && collect_factors(right, exponent, coefficient, factors)
+        }
+        Expr::BinaryOp {
+            op: BinaryOp::Div,
+            left,
+            right,
+        } => {
+            collect_factors(left, exponent, coefficient, factors)
+                && collect_factors(right, -exponent, coefficient, factors)
+        }
+        Expr::BinaryOp {
+            op: BinaryOp::Pow,
+            left,
+            right,
+        } if matches!(**right, Expr::Number(_)) => {
+            let Expr::Number(n) = **right else {
+                return false;
+            };
+            match exponent.checked_mul(n) {
+                Some(exponent) => collect_factors(left, exponent, coefficient, factors),
+                None => false,
+            }
+        }
+        _ => {
+            match factors.iter_mut().find(|(base, _)| base == expr) {
+                Some((_, existing)) => match existing.checked_add(exponent) {
+                    Some(sum) => *existing = sum,
+                    None => return false,
+                },
+                None => factors.push((expr.clone(), exponent)),
+            }
+            true
+        }
+    }
+}
+
+pub(crate) fn neg(expr: &Expr) -> Expr {
+    Expr::UnaryOp {
+        op: UnaryOp::Neg,
+        expr: Box::new(expr.clone()),
+    }
+}
+
+fn binary(op: BinaryOp, left: &Expr, right: &Expr) -> Expr {
+    Expr::BinaryOp {
+        op,
+        left: Box::new(left.clone()),
+        right: Box::new(right.clone()),
+    }
+}
+
+pub(crate) fn add(left: &Expr, right: &Expr) -> Expr {
+    binary(BinaryOp::Add, left, right)
+}
+
+pub(crate) fn sub(left: &Expr, right: &Expr) -> Expr {
+    binary(BinaryOp::Sub, left, right)
+}
+
+pub(crate) fn mul(left: &Expr, right: &Expr) -> Expr {
+    binary(BinaryOp::Mul, left, right)
+}
+
+pub(crate) fn div(left: &Expr, right: &Expr) -> Expr {
+    binary(BinaryOp::Div, left, right)
+}
+
+pub(crate) fn pow(left: &Expr, right: &Expr) -> Expr {
+    binary(BinaryOp::Pow, left, right)
+}
+
+pub(crate) fn square(expr: &Expr) -> Expr {
+    pow(expr, &Expr::Number(2))
+}
+
+/// `name(arg)`.
+pub(crate) fn call(name: &str, arg: &Expr) -> Expr {
+    Expr::Call {
+        name: name.to_string(),
+        arg: Box::new(arg.clone()),
+    }
+}
+
+/// Largest magnitude of an integer exponent anywhere in `expr`.
+pub(crate) fn highest_power(expr: &Expr) -> u32 {
+    match expr {
+        Expr::Number(_) | Expr::Variable(_) | Expr::Constant(_) | Expr::Symbol(_) => 0,
+        Expr::Call { arg, .. } => highest_power(arg),
+        Expr::UnaryOp { expr, .. } => highest_power(expr),
+        Expr::BinaryOp { op, left, right } => {
+            let own = match (op, &**right) {
+                (BinaryOp::Pow, Expr::Number(n)) => n.unsigned_abs(),
+                _ => 0,
+            };
+            own.max(highest_power(left)).max(highest_power(right))
+        }
+    }
+}
+
+/// The letter `var` as an expression: the variable for `x`, a constant otherwise.
+pub(crate) fn variable(var: char) -> Expr {
+    if var == 'x' {
+        Expr::Variable('x')
+    } else {
+        Expr::Constant(var)
+    }
+}
+
+/// Adds up terms given as sign and magnitude, subtracting the negative ones: `[(false, a),
+/// (true, b)]` becomes `a-b`. An empty sum is `0`.
+pub(crate) fn signed_sum(terms: impl IntoIterator<Item = (bool, Expr)>) -> Expr {
+    let mut sum: Option<Expr> = None;
+    for (negative, term) in terms {
+        sum = Some(match (sum, negative) {
+            (None, false) => term,
+            (None, true) => neg(&term),
+            (Some(sum), false) => add(&sum, &term),
+            (Some(sum), true) => sub(&sum, &term),
+        });
+    }
+    sum.unwrap_or(Expr::Number(0))
+}
+
+/// Tidies up a sum of products: the rational factors of each term are combined into one
+/// coefficient in front, or a denominator behind, and sums inside are tidied as well.
+/// `2*(1/2)*sin(x)-(-1)*x/3` becomes `sin(x)+x/3`. A leading negative term swaps places with
+/// the first positive one.
+pub(crate) fn tidy(expr: &Expr) -> Expr {
+    let mut terms = Vec::new();
+    signed_terms(expr, false, &mut terms);
+    let mut terms: Vec<(bool, Expr)> = terms
+        .into_iter()
+        .map(|(negative, term)| {
+            let (flip, magnitude) = tidy_term(&term);
+            (negative != flip, magnitude)
+        })
+        .collect();
+    if terms.first().is_some_and(|(negative, _)| *negative) {
+        if let Some(positive) = terms.iter().position(|(negative, _)| !negative) {
+            let term = terms.remove(positive);
+            terms.insert(0, term);
+        }
+    }
+    // A sign on the first factor reads better than one on the whole product: `-x*cos(x)`
+    // instead of `-(x*cos(x))`
+    if let Some((negative @ true, first)) = terms.first_mut() {
+        if let Some(negated) = negate_first_factor(first) {
+            *first = negated;
+            *negative = false;
+        }
+    }
+    signed_sum(terms)
+}
+
+/// `term` with its first factor negated, if it is a product or quotient.
+pub(crate) fn negate_first_factor(term: &Expr) -> Option<Expr> {
+    match term {
+        Expr::BinaryOp {
+            op: op @ (BinaryOp::Mul | BinaryOp::Div),
+            left,
+            right,
+        } => Some(Expr::BinaryOp {
+            op: op.clone(),
+            left: Box::new(match left.as_ref() {
+                Expr::Number(n) => Expr::Number(n.checked_neg()?),
+                left => negate_first_factor(left).unwrap_or_else(|| neg(left)),
+            }),
+            right: right.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// The terms of a sum with their signs, `negative` applying to all of `expr`.
+fn signed_terms(expr: &Expr, negative: bool, terms: &mut Vec<(bool, Expr)>) {
+    match expr {
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => signed_terms(expr, !negative, terms),
+        Expr::BinaryOp {
+            op: op @ (BinaryOp::Add | BinaryOp::Sub),
+            left,
+            right,
+        } => {
+            signed_terms(left, negative, terms);
+            signed_terms(right, negative != (*op == BinaryOp::Sub), terms);
+        }
+        _ => terms.push((negative, expr.clone())),
+    }
+}
+
+/// One product as its sign and its magnitude with the coefficient combined.
+fn tidy_term(term: &Expr) -> (bool, Expr) {
+    let Some((coefficient, mut factors)) = factors(term) else {
+        return (false, term.clone());
+    };
+    // Powers of variables before functions of them: `x^2*ln(x)`
+    factors.sort_by_key(|(base, _)| matches!(base, Expr::Call { .. }));
+    let factors = factors
+        .into_iter()
+        .map(|(base, exponent)| {
+            let base = match base {
+                Expr::Call { name, arg } => Expr::Call {
+                    name,
+                    arg: Box::new(tidy(&arg)),
+                },
+                Expr::BinaryOp {
+                    op: BinaryOp::Add | BinaryOp::Sub,
+                    ..
+                } => tidy(&base),
+                base => base,
+            };
+            (base, exponent)
+        })
+        .collect();
+    signed_product(coefficient, factors).unwrap_or_else(|| (false, term.clone()))
+}
+
+/// A product as its sign and magnitude, with the numbers in front of the numerator and
+/// denominator and factors with negative exponents in the denominator: `(-3/2, [x^1, y^-2])`
+/// becomes `(true, 3x/(2y^2))`. `None` if the coefficient doesn't fit an `i32`.
+pub(crate) fn signed_product(
+    coefficient: Rational,
+    factors: Vec<(Expr, i32)>,
+) -> Option<(bool, Expr)> {
+    let numer = i32::try_from(coefficient.numer().unsigned_abs()).ok()?;
+    let denom = i32::try_from(coefficient.denom()).ok()?;
+    let mut numerator = Vec::new();
+    let mut denominator = Vec::new();
+    for (base, exponent) in factors {
+        let (side, exponent) = match exponent {
+            0 => continue,
+            e if e > 0 => (&mut numerator, e),
+            e => (&mut denominator, -e),
+        };
+        side.push(match exponent {
+            1 => base,
+            _ => pow(&base, &Expr::Number(exponent)),
+        });
+    }
+    if numer != 1 || numerator.is_empty() {
+        numerator.insert(0, Expr::Number(numer));
+    }
+    if denom != 1 {
+        denominator.insert(0, Expr::Number(denom));
+    }
+    let product = |factors: Vec<Expr>| factors.into_iter().reduce(|a, b| mul(&a, &b));
+    let numerator = product(numerator).unwrap_or(Expr::Number(1));
+    let magnitude = match product(denominator) {
+        Some(denominator) => div(&numerator, &denominator),
+        None => numerator,
+    };
+    Some((coefficient.is_negative(), magnitude))
+}
@@ -0,0 +1,618 @@
+//! Where a function peaks: critical points from the first derivative, classified by the
+//! second, with the intervals where the function increases and decreases and its inflection
+//! points.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::algebra::highest_power;
+use crate::differentiator::DiffError;
+use crate::domain::Interval;
+use crate::evaluator::compiled::CompiledExpr;
+use crate::evaluator::EvalError;
+use crate::parser::ast::{BinaryOp, Expr};
+use crate::polynomial::{Polynomial, MAX_DEGREE};
+use crate::rational::Rational;
+
+/// Where [`Expr::analyze`] looks for roots it can't find exactly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisOptions {
+    /// The range searched numerically.
+    pub range: (f64, f64),
+    /// Number of pieces the range is split into when looking for sign changes.
+    pub samples: usize,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions {
+            range: (-10.0, 10.0),
+            samples: 2000,
+        }
+    }
+}
+
+/// A point where a derivative vanishes, exactly as an expression when it could be solved
+/// for, and always as a number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub exact: Option<Expr>,
+    /// The function's value there.
+    pub value: f64,
+}
+
+impl Display for Point {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.exact {
+            Some(exact) if Rational::from_expr(exact).is_some_and(|r| r.is_integer()) => {
+                write!(f, "{}", exact.pretty())
+            }
+            Some(exact) => write!(f, "{} ≈ {:.6}", exact.pretty(), self.x),
+            None => write!(f, "{:.6}", self.x),
+        }
+    }
+}
+
+/// What happens at a critical point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Extremum {
+    Minimum,
+    Maximum,
+    /// The function keeps increasing or decreasing through the point, like `x^3` at `0`.
+    Neither,
+}
+
+impl Display for Extremum {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Extremum::Minimum => "local minimum",
+            Extremum::Maximum => "local maximum",
+            Extremum::Neither => "no extremum",
+        })
+    }
+}
+
+/// Which way the function goes over an interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Trend {
+    Increasing,
+    Decreasing,
+    Constant,
+}
+
+impl Display for Trend {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Trend::Increasing => "increasing",
+            Trend::Decreasing => "decreasing",
+            Trend::Constant => "constant",
+        })
+    }
+}
+
+/// The outcome of [`Expr::analyze`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub derivative: Expr,
+    pub second_derivative: Expr,
+    /// Where the derivative vanishes, in increasing order, with how each one was classified.
+    pub critical_points: Vec<(Point, Extremum)>,
+    /// Open intervals where the function is defined, covering the range analyzed.
+    pub monotonicity: Vec<(Interval, Trend)>,
+    /// Where the second derivative changes sign.
+    pub inflection_points: Vec<Point>,
+    /// The range searched numerically, `None` when both derivatives were solved exactly and
+    /// the results hold on the whole line.
+    pub range: Option<(f64, f64)>,
+}
+
+/// Reasons a function can't be analyzed.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum AnalysisError {
+    Differentiate(DiffError),
+    /// The function or a derivative can't be evaluated, e.g. because of another symbol.
+    Eval(EvalError),
+    /// The function holds a power of higher degree than [`MAX_DEGREE`], whose derivatives
+    /// underflow to zero near the origin in floating point.
+    DegreeTooHigh(u32),
+}
+
+impl Display for AnalysisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AnalysisError::Differentiate(e) => write!(f, "{e}"),
+            AnalysisError::Eval(e) => write!(f, "{e}"),
+            AnalysisError::DegreeTooHigh(degree) => {
+                write!(
+                    f,
+                    "degree {degree} is above the limit of {MAX_DEGREE} for analysis"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnalysisError {}
+
+impl From<DiffError> for AnalysisError {
+    fn from(e: DiffError) -> Self {
+        AnalysisError::Differentiate(e)
+    }
+}
+
+impl From<EvalError> for AnalysisError {
+    fn from(e: EvalError) -> Self {
+        AnalysisError::Eval(e)
+    }
+}
+
+/// Zeros of an expression, and the points where it's undefined.
+struct Solutions {
+    roots: Vec<(f64, Option<Expr>)>,
+    poles: Vec<f64>,
+    /// Whether the roots are all the real ones rather than those found in the range.
+    exact: bool,
+}
+
+impl Expr {
+    /// Critical points, intervals of increase and decrease and inflection points of the
+    /// expression as a function of `var`.
+    ///
+    /// `f'(x) = 0` and `f''(x) = 0` are solved exactly when, after cancelling, they are
+    /// polynomial equations: by formula up to degree two, by rational roots and numerically
+    /// beyond. Otherwise the roots are the sign changes found in `options.range`. Critical
+    /// points are classified by the sign of the second derivative, or by the trend on either
+    /// side when it vanishes too. Powers above [`MAX_DEGREE`] are refused.
+    ///
+    /// ```
+    /// use derivative_calculator::analysis::{AnalysisOptions, Extremum, Trend};
+    /// use derivative_calculator::parse;
+    ///
+    /// let analysis = parse("x^3 - 3x")?.analyze('x', &AnalysisOptions::default()).unwrap();
+    /// let critical: Vec<_> = analysis.critical_points.iter().map(|(p, e)| (p.x, *e)).collect();
+    /// assert_eq!(critical, [(-1.0, Extremum::Maximum), (1.0, Extremum::Minimum)]);
+    /// let trends: Vec<_> = analysis.monotonicity.iter().map(|(i, t)| format!("{i} {t}")).collect();
+    /// assert_eq!(trends, ["(-inf, -1) increasing", "(-1, 1) decreasing", "(1, inf) increasing"]);
+    /// assert_eq!(analysis.inflection_points[0].x, 0.0);
+    ///
+    /// // Exact roots by the quadratic formula
+    /// let analysis = parse("x^3 - 3x^2 - 3x")?.analyze('x', &AnalysisOptions::default()).unwrap();
+    /// assert_eq!(analysis.critical_points[0].0.to_string(), "1-sqrt(2) ≈ -0.414214");
+    ///
+    /// // Numerically, within the range
+    /// let analysis = parse("x*exp(-x)")?.analyze('x', &AnalysisOptions::default()).unwrap();
+    /// assert_eq!(analysis.critical_points.len(), 1);
+    /// assert!((analysis.critical_points[0].0.x - 1.0).abs() < 1e-9);
+    /// assert_eq!(analysis.monotonicity[0].1, Trend::Increasing);
+    /// assert!((analysis.inflection_points[0].x - 2.0).abs() < 1e-9);
+    ///
+    /// // Not defined for x <= 0
+    /// let analysis = parse("x*ln(x)")?.analyze('x', &AnalysisOptions::default()).unwrap();
+    /// assert_eq!(analysis.monotonicity.len(), 2);
+    /// assert_eq!(analysis.monotonicity[0].0.lower, 0.0);
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn analyze(&self, var: char, options: &AnalysisOptions) -> Result<Analysis, AnalysisError> {
+        let degree = highest_power(self);
+        if degree as usize > MAX_DEGREE {
+            return Err(AnalysisError::DegreeTooHigh(degree));
+        }
+        let derivative = self.diff(var)?.simplify();
+        let second_derivative = derivative.diff(var)?.simplify();
+        let none = HashMap::new();
+        let f = self.compile(var, &none)?;
+        let df = derivative.compile(var, &none)?;
+        let ddf = second_derivative.compile(var, &none)?;
+
+        let first = solve(&derivative, &df, var, options);
+        let second = solve(&second_derivative, &ddf, var, options);
+        let range = (!(first.exact && second.exact)).then_some(options.range);
+        let (lower, upper) = range.unwrap_or((f64::NEG_INFINITY, f64::INFINITY));
+        let inside = |x: f64| lower <= x && x <= upper && f.eval(x).is_finite();
+        let point = |(x, exact): (f64, Option<Expr>)| Point {
+            x,
+            exact,
+            value: f.eval(x),
+        };
+
+        let critical: Vec<Point> = first
+            .roots
+            .into_iter()
+            .filter(|(x, _)| inside(*x))
+            .map(point)
+            .collect();
+        let mut breaks: Vec<f64> = critical.iter().map(|p| p.x).collect();
+        breaks.extend(first.poles.iter().chain(&second.poles));
+        let monotonicity = signs(&df, &f, &breaks, (lower, upper))
+            .into_iter()
+            .map(|(interval, sign)| {
+                let trend = match sign {
+                    1 => Trend::Increasing,
+                    -1 => Trend::Decreasing,
+                    _ => Trend::Constant,
+                };
+                (interval, trend)
+            })
+            .collect::<Vec<_>>();
+        let trend_at = |x: f64, before: bool| {
+            monotonicity.iter().find_map(|(interval, trend)| {
+                let end = if before {
+                    interval.upper
+                } else {
+                    interval.lower
+                };
+                (end == x).then_some(*trend)
+            })
+        };
+        let critical_points = critical
+            .into_iter()
+            .map(|point| {
+                let curvature = ddf.eval(point.x);
+                let extremum = if curvature > 1e-9 {
+                    Extremum::Minimum
+                } else if curvature < -1e-9 {
+                    Extremum::Maximum
+                } else {
+                    match (trend_at(point.x, true), trend_at(point.x, false)) {
+                        (Some(Trend::Decreasing), Some(Trend::Increasing)) => Extremum::Minimum,
+                        (Some(Trend::Increasing), Some(Trend::Decreasing)) => Extremum::Maximum,
+                        _ => Extremum::Neither,
+                    }
+                };
+                (point, extremum)
+            })
+            .collect::<Vec<_>>();
+        let monotonicity = merge(monotonicity, &critical_points);
+
+        let candidates: Vec<f64> = second.roots.iter().map(|(x, _)| *x).collect();
+        let mut breaks = candidates.clone();
+        breaks.extend(first.poles.iter().chain(&second.poles));
+        let concavity = signs(&ddf, &f, &breaks, (lower, upper));
+        let changes_sign = |x: f64| {
+            let before = concavity.iter().find(|(i, _)| i.upper == x);
+            let after = concavity.iter().find(|(i, _)| i.lower == x);
+            matches!((before, after), (Some((_, a)), Some((_, b))) if a * b < 0)
+        };
+        let inflection_points = second
+            .roots
+            .into_iter()
+            .filter(|(x, _)| inside(*x) && changes_sign(*x))
+            .map(point)
+            .collect();
+
+        Ok(Analysis {
+            derivative,
+            second_derivative,
+            critical_points,
+            monotonicity,
+            inflection_points,
+            range,
+        })
+    }
+}
+
+/// The zeros of `g`, compiled as `compiled`, and where it's undefined.
+fn solve(g: &Expr, compiled: &CompiledExpr, var: char, options: &AnalysisOptions) -> Solutions {
+    let cancelled = g.cancel();
+    let (numer, denom) = match &cancelled {
+        Expr::BinaryOp {
+            op: BinaryOp::Div,
+            left,
+            right,
+        } => (
+            Polynomial::from_expr(left, var),
+            Polynomial::from_expr(right, var),
+        ),
+        expr => (
+            Polynomial::from_expr(expr, var),
+            Some(Polynomial::constant(var, Rational::ONE)),
+        ),
+    };
+    if let (Some(numer), Some(denom)) = (numer, denom) {
+        if numer.is_zero() {
+            return Solutions {
+                roots: Vec::new(),
+                poles: Vec::new(),
+                exact: true,
+            };
+        }
+        if let (Some(roots), Some(poles)) = (exact_roots(&numer), denom.real_roots()) {
+            return Solutions {
+                roots,
+                poles,
+                exact: true,
+            };
+        }
+    }
+    numeric_roots(compiled, options)
+}
+
+/// Real roots of `p`, as expressions where they are rational or of degree two. `None` for the
+/// zero polynomial or on overflow.
+fn exact_roots(p: &Polynomial) -> Option<Vec<(f64, Option<Expr>)>> {
+    if p.degree() == Some(2) {
+        let (c, b, a) = (p.coefficient(0), p.coefficient(1), p.coefficient(2));
+        let discriminant = b
+            .checked_mul(b)?
+            .checked_sub(Rational::integer(4).checked_mul(a)?.checked_mul(c)?)?;
+        if discriminant.is_negative() {
+            return Some(Vec::new());
+        }
+        if discriminant.sqrt().is_none() {
+            // x = p ± sqrt(discriminant)/(2a) = p ± c*sqrt(k) with k a square-free integer
+            let two_a = Rational::integer(2).checked_mul(a)?;
+            let centre = (-b).checked_div(two_a)?;
+            let radicand = discriminant.checked_mul(Rational::integer(discriminant.denom()))?;
+            let (outside, k) = square_free(radicand.numer())?;
+            let c = Rational::integer(outside)
+                .checked_div(Rational::integer(discriminant.denom()))?
+                .checked_div(two_a.abs())?;
+            let mut roots = Vec::new();
+            for sign in ['-', '+'] {
+                let offset = if c == Rational::ONE {
+                    format!("sqrt({k})")
+                } else {
+                    format!("({c})*sqrt({k})")
+                };
+                let text = if centre.is_zero() {
+                    format!("{sign}{offset}")
+                } else {
+                    format!("({centre}) {sign} {offset}")
+                };
+                let spread = c.to_f64() * (k as f64).sqrt();
+                let x = centre.to_f64() + if sign == '-' { -spread } else { spread };
+                roots.push((x, Some(crate::parse(&text).ok()?.simplify())));
+            }
+            return Some(roots);
+        }
+    }
+    let rational = p.rational_roots();
+    let roots = p.real_roots()?;
+    Some(
+        roots
+            .into_iter()
+            .map(|x| {
+                let exact = rational.iter().find(|r| r.to_f64() == x);
+                (x, exact.and_then(Rational::to_expr))
+            })
+            .collect(),
+    )
+}
+
+/// `n = s^2 * k` with `k` square-free, as `(s, k)`, for positive `n`. `None` if `n` is too
+/// large to factor by trial division.
+fn square_free(n: i64) -> Option<(i64, i64)> {
+    if !(1..=1 << 40).contains(&n) {
+        return None;
+    }
+    let (mut outside, mut inside) = (1, n);
+    let mut d = 2;
+    while d * d <= inside {
+        while inside % (d * d) == 0 {
+            inside /= d * d;
+            outside *= d;
+        }
+        d += 1;
+    }
+    Some((outside, inside))
+}
+
+/// Sign changes of `g` in the range, refined by bisection. Those where `g` grows without
+/// bound rather than crossing zero, as `1/x` does at `0`, are poles.
+fn numeric_roots(g: &CompiledExpr, options: &AnalysisOptions) -> Solutions {
+    let (lower, upper) = options.range;
+    let step = (upper - lower) / options.samples.max(1) as f64;
+    let mut roots = Vec::new();
+    let mut poles = Vec::new();
+    let mut previous = (lower, g.eval(lower));
+    for idx in 1..=options.samples.max(1) {
+        let x = lower + idx as f64 * step;
+        let value = g.eval(x);
+        let (last, last_value) = previous;
+        previous = (x, value);
+        if last_value == 0.0 {
+            roots.push((last, None));
+            continue;
+        }
+        if !last_value.is_finite() || !value.is_finite() || last_value.signum() == value.signum() {
+            continue;
+        }
+        let (mut lo, mut hi) = (last, x);
+        loop {
+            let mid = 0.5 * (lo + hi);
+            if mid <= lo || mid >= hi {
+                break;
+            }
+            if g.eval(mid).signum() == last_value.signum() {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        let (root, residual) = [lo, hi]
+            .into_iter()
+            .map(|x| (x, g.eval(x).abs()))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((lo, f64::NAN));
+        let scale = last_value.abs().min(value.abs()).max(1.0);
+        if residual <= 1e-6 * scale {
+            roots.push((root, None));
+        } else {
+            poles.push(root);
+        }
+    }
+    if previous.1 == 0.0 {
+        roots.push((previous.0, None));
+    }
+    for (root, _) in &mut roots {
+        // Adding zero turns -0 into 0
+        let whole = root.round() + 0.0;
+        if (*root - whole).abs() < 1e-9 && g.eval(whole).abs() <= g.eval(*root).abs() {
+            *root = whole;
+        }
+    }
+    // A root on a sample point also shows up as a sign change next to it
+    roots.dedup_by(|(b, _), (a, _)| (*b - *a).abs() <= step);
+    Solutions {
+        roots,
+        poles,
+        exact: false,
+    }
+}
+
+/// Number of points probed in each piece by [`signs`].
+const PROBES: usize = 64;
+
+/// The sign of `g` on the open intervals between the `breaks` within the range, leaving out
+/// the parts where `f` or `g` is undefined.
+fn signs(
+    g: &CompiledExpr,
+    f: &CompiledExpr,
+    breaks: &[f64],
+    (lower, upper): (f64, f64),
+) -> Vec<(Interval, i8)> {
+    let mut ends: Vec<f64> = breaks
+        .iter()
+        .copied()
+        .filter(|x| lower < *x && *x < upper)
+        .collect();
+    ends.push(lower);
+    ends.push(upper);
+    ends.sort_by(f64::total_cmp);
+    ends.dedup();
+    let mut result = Vec::new();
+    for pair in ends.windows(2) {
+        let (lo, hi) = (pair[0], pair[1]);
+        let probes: Vec<f64> = (1..PROBES)
+            .map(|k| {
+                let k = k as f64;
+                match (lo.is_finite(), hi.is_finite()) {
+                    (true, true) => lo + (hi - lo) * k / PROBES as f64,
+                    (true, false) => lo + k,
+                    (false, true) => hi - (PROBES as f64 - k),
+                    (false, false) => k - PROBES as f64 / 2.0,
+                }
+            })
+            .collect();
+        let defined: Vec<f64> = probes
+            .iter()
+            .copied()
+            .filter(|&x| f.eval(x).is_finite() && g.eval(x).is_finite())
+            .collect();
+        let (Some(&first), Some(&last)) = (defined.first(), defined.last()) else {
+            continue;
+        };
+        // Where the domain ends inside the piece, only the part where f is defined counts
+        let lo = if first == probes[0] {
+            lo
+        } else {
+            boundary(f, first, lo)
+        };
+        let hi = if last == probes[probes.len() - 1] {
+            hi
+        } else {
+            boundary(f, last, hi)
+        };
+        let value = g.eval(defined[defined.len() / 2]);
+        let interval = Interval {
+            lower: lo,
+            upper: hi,
+            lower_closed: false,
+            upper_closed: false,
+        };
+        result.push((
+            interval,
+            if value == 0.0 {
+                0
+            } else {
+                value.signum() as i8
+            },
+        ));
+    }
+    result
+}
+
+/// Where `f` stops being defined between `inside`, where it is, and `outside`, by bisection.
+fn boundary(f: &CompiledExpr, mut inside: f64, outside: f64) -> f64 {
+    if !outside.is_finite() {
+        return outside;
+    }
+    let mut outside = outside;
+    for _ in 0..200 {
+        let mid = 0.5 * (inside + outside);
+        if mid == inside || mid == outside {
+            break;
+        }
+        if f.eval(mid).is_finite() {
+            inside = mid;
+        } else {
+            outside = mid;
+        }
+    }
+    // Snap to a nearby integer, which domain boundaries mostly are
+    let rounded = outside.round();
+    if (rounded - outside).abs() < 1e-9 {
+        rounded
+    } else {
+        outside
+    }
+}
+
+/// Joins neighbouring intervals with the same trend on either side of a critical point, as
+/// on both sides of `x^3`'s.
+fn merge(
+    intervals: Vec<(Interval, Trend)>,
+    critical_points: &[(Point, Extremum)],
+) -> Vec<(Interval, Trend)> {
+    let mut merged: Vec<(Interval, Trend)> = Vec::new();
+    for (interval, trend) in intervals {
+        let joins = |last: &Interval| {
+            last.upper == interval.lower
+                && critical_points.iter().any(|(p, _)| p.x == interval.lower)
+        };
+        match merged.last_mut() {
+            Some((last, last_trend)) if *last_trend == trend && joins(last) => {
+                last.upper = interval.upper;
+            }
+            _ => merged.push((interval, trend)),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnalysisError, AnalysisOptions, Extremum};
+    use crate::parse;
+
+    fn critical_points(input: &str) -> Vec<(f64, Extremum)> {
+        parse(input)
+            .unwrap()
+            .analyze('x', &AnalysisOptions::default())
+            .unwrap()
+            .critical_points
+            .iter()
+            .map(|(p, e)| (p.x, *e))
+            .collect()
+    }
+
+    #[test]
+    fn polynomials_are_solved_exactly() {
+        assert_eq!(critical_points("x^2-2x"), [(1.0, Extremum::Minimum)]);
+        assert_eq!(critical_points("x^1000"), [(0.0, Extremum::Minimum)]);
+    }
+
+    #[test]
+    fn huge_degrees_are_refused() {
+        assert_eq!(
+            parse("x^1000000")
+                .unwrap()
+                .analyze('x', &AnalysisOptions::default()),
+            Err(AnalysisError::DegreeTooHigh(1000000))
+        );
+    }
+}
@@ -0,0 +1,586 @@
+//! Facts about symbols, such as `x > 0`, and what follows from them for whole expressions.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+use crate::rational::Rational;
+use crate::rewrite::RuleSet;
+use crate::Error;
+
+/// Something that can be known about a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Property {
+    /// `> 0`
+    Positive,
+    /// `>= 0`
+    Nonnegative,
+    /// `< 0`
+    Negative,
+    /// `!= 0`
+    Nonzero,
+    Real,
+    Integer,
+}
+
+impl Property {
+    /// Every property with the ways of writing it after an expression, the first one being
+    /// how it's displayed.
+    const NOTATIONS: [(Property, &'static [&'static str]); 6] = [
+        (Property::Positive, &["> 0", "positive"]),
+        (Property::Nonnegative, &[">= 0", "nonnegative"]),
+        (Property::Negative, &["< 0", "negative"]),
+        (Property::Nonzero, &["!= 0", "nonzero"]),
+        (Property::Real, &["real"]),
+        (Property::Integer, &["integer"]),
+    ];
+
+    fn notation(&self) -> &'static str {
+        Self::NOTATIONS
+            .iter()
+            .find(|(property, _)| property == self)
+            .map_or("", |(_, notations)| notations[0])
+    }
+}
+
+/// A property of an expression, either assumed or needed by a simplification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assumption {
+    pub expr: Expr,
+    pub property: Property,
+}
+
+impl Display for Assumption {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.expr.pretty(), self.property.notation())
+    }
+}
+
+/// Reasons an assumption can't be read.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum AssumptionError {
+    /// No property such as `> 0` or `integer` follows the expression.
+    MissingProperty,
+    Parse(Error),
+    /// The assumption can't hold together with those already made.
+    Contradiction(String),
+}
+
+impl Display for AssumptionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AssumptionError::MissingProperty => f.write_str(
+                "expected an expression followed by '> 0', '>= 0', '< 0', '!= 0', 'real' or 'integer'",
+            ),
+            AssumptionError::Parse(e) => write!(f, "{e}"),
+            AssumptionError::Contradiction(existing) => {
+                write!(f, "contradicts the assumption {existing}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AssumptionError {}
+
+impl FromStr for Assumption {
+    type Err = AssumptionError;
+
+    /// Reads `expr > 0`, `expr >= 0`, `expr < 0`, `expr != 0` or `expr` followed by
+    /// `positive`, `nonnegative`, `negative`, `nonzero`, `real` or `integer`, optionally after
+    /// `is`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (expr, property) = Property::NOTATIONS
+            .iter()
+            .flat_map(|(property, notations)| notations.iter().map(move |n| (n, *property)))
+            .filter_map(|(notation, property)| {
+                let expr = s.strip_suffix(notation)?;
+                // Words must stand apart from the expression: `xreal` isn't `x real`
+                if notation.starts_with(char::is_alphabetic) && !expr.ends_with(' ') {
+                    return None;
+                }
+                let expr = expr.trim_end();
+                Some((expr.strip_suffix(" is").unwrap_or(expr), property))
+            })
+            .next()
+            .ok_or(AssumptionError::MissingProperty)?;
+        let expr = crate::parse(expr).map_err(AssumptionError::Parse)?;
+        Ok(Assumption { expr, property })
+    }
+}
+
+/// What a simplification does when it needs a property that the assumptions neither prove
+/// nor rule out, e.g. `x != 0` to turn `x/x` into `1`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Unproven {
+    /// Leave the expression as it is.
+    #[default]
+    Skip,
+    /// Simplify anyway and report the property as a side condition.
+    Record,
+}
+
+/// An expression simplified under assumptions, valid where the side conditions hold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simplified {
+    pub expr: Expr,
+    /// Properties the simplification needed without them being known, see
+    /// [`Unproven::Record`].
+    pub side_conditions: Vec<Assumption>,
+}
+
+/// The facts assumed about symbols (or whole expressions), consulted by
+/// [`Expr::simplify_assuming`] and by rule conditions such as `positive(a)`.
+///
+/// ```
+/// use derivative_calculator::assumptions::{Assumptions, Property, Unproven};
+/// use derivative_calculator::parse;
+///
+/// let mut assumptions = Assumptions::default();
+/// let expr = parse("sqrt(x^2) + y/y")?;
+/// let simplified = expr.simplify_assuming(&assumptions, Unproven::Skip);
+/// assert_eq!(simplified.expr.pretty(), "sqrt(x^2)+y/y");
+///
+/// assumptions.assume("x > 0").unwrap();
+/// assumptions.assume("y nonzero").unwrap();
+/// assert_eq!(expr.simplify_assuming(&assumptions, Unproven::Skip).expr.pretty(), "x+1");
+/// assert_eq!(assumptions.check(&parse("x^3 + exp(x)")?, Property::Positive), Some(true));
+/// assert!(assumptions.assume("x < 0").is_err());
+///
+/// // Without assumptions, the needed properties come back as side conditions
+/// let simplified = expr.simplify_assuming(&Assumptions::default(), Unproven::Record);
+/// assert_eq!(simplified.expr.pretty(), "x+1");
+/// let conditions: Vec<String> = simplified.side_conditions.iter().map(|c| c.to_string()).collect();
+/// assert_eq!(conditions, ["x >= 0", "y != 0"]);
+/// # Ok::<(), derivative_calculator::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Assumptions {
+    facts: Vec<Assumption>,
+}
+
+impl Assumptions {
+    /// Reads and adds an assumption such as `x > 0` or `n integer`, see
+    /// [`Assumption::from_str`].
+    pub fn assume(&mut self, statement: &str) -> Result<(), AssumptionError> {
+        self.add(statement.parse()?)
+    }
+
+    /// Adds an assumption unless it contradicts the others.
+    pub fn add(&mut self, assumption: Assumption) -> Result<(), AssumptionError> {
+        if self.check(&assumption.expr, assumption.property) == Some(false) {
+            let existing = self
+                .facts
+                .iter()
+                .find(|fact| fact.expr == assumption.expr)
+                .map_or_else(|| assumption.expr.pretty(), |fact| fact.to_string());
+            return Err(AssumptionError::Contradiction(existing));
+        }
+        if !self.facts.contains(&assumption) {
+            self.facts.push(assumption);
+        }
+        Ok(())
+    }
+
+    /// Drops every assumption about `expr`, returning whether there were any.
+    pub fn forget(&mut self, expr: &Expr) -> bool {
+        let count = self.facts.len();
+        self.facts.retain(|fact| fact.expr != *expr);
+        self.facts.len() != count
+    }
+
+    pub fn clear(&mut self) {
+        self.facts.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.facts.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Assumption> {
+        self.facts.iter()
+    }
+
+    /// Whether `expr` has `property`: `Some(true)` if the assumptions prove it,
+    /// `Some(false)` if they rule it out and `None` if they don't tell.
+    pub fn check(&self, expr: &Expr, property: Property) -> Option<bool> {
+        if property == Property::Integer {
+            return self.integer(expr);
+        }
+        let sign = self.sign(expr);
+        match property {
+            Property::Positive if sign.real => sign.exactly(false, false, true),
+            Property::Nonnegative if sign.real => sign.exactly(false, true, true),
+            Property::Negative if sign.real => sign.exactly(true, false, false),
+            Property::Nonzero if !sign.zero => Some(true),
+            Property::Nonzero if sign.real => sign.exactly(false, true, false).map(|z| !z),
+            Property::Real if sign.real => Some(true),
+            _ => None,
+        }
+    }
+
+    /// The signs `expr` may have under the assumptions.
+    fn sign(&self, expr: &Expr) -> Sign {
+        if let Some(value) = Rational::from_expr(expr) {
+            return Sign::of(value);
+        }
+        let sign = self
+            .facts
+            .iter()
+            .filter(|fact| fact.expr == *expr)
+            .fold(Sign::ANY, |sign, fact| sign.assuming(fact.property));
+        let derived = match expr {
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+            } => self.sign(expr).negated(),
+            Expr::BinaryOp { op, left, right } => {
+                let (left, right) = (self.sign(left), self.sign(right));
+                match op {
+                    BinaryOp::Add => left.plus(right),
+                    BinaryOp::Sub => left.plus(right.negated()),
+                    BinaryOp::Mul => left.times(right),
+                    BinaryOp::Div => left.times(Sign {
+                        zero: false,
+                        ..right
+                    }),
+                    BinaryOp::Pow => self.power(left, right, expr),
+                }
+            }
+            Expr::Call { name, arg } => {
+                let arg = self.sign(arg);
+                match name.as_str() {
+                    "exp" if arg.real => Sign::POSITIVE,
+                    "sqrt" if arg.real && !arg.negative => Sign {
+                        zero: arg.zero,
+                        ..Sign::NONNEGATIVE
+                    },
+                    "atan" if arg.real => arg,
+                    "cos" | "sin" | "tan" if arg.real => Sign::REAL,
+                    "ln" if arg.real && !arg.negative && !arg.zero => Sign::REAL,
+                    _ => Sign::ANY,
+                }
+            }
+            _ => Sign::ANY,
+        };
+        sign.and(derived)
+    }
+
+    /// Signs of `base^exponent` where the exponent is `expr`'s right operand.
+    fn power(&self, base: Sign, exponent: Sign, expr: &Expr) -> Sign {
+        let Expr::BinaryOp { right, .. } = expr else {
+            return Sign::ANY;
+        };
+        match Rational::from_expr(right) {
+            _ if base.real && !base.negative && !base.zero && exponent.real => Sign::POSITIVE,
+            Some(n) if n.is_integer() && n.numer() % 2 == 0 && base.real => Sign {
+                zero: base.zero && !n.is_negative(),
+                positive: true,
+                ..Sign::NONNEGATIVE
+            },
+            Some(n) if n.is_integer() && base.real => Sign {
+                zero: base.zero && !n.is_negative(),
+                ..base
+            },
+            _ if base.real && self.integer(right) == Some(true) => Sign {
+                negative: base.negative,
+                zero: base.zero,
+                ..Sign::REAL
+            },
+            _ => Sign::ANY,
+        }
+    }
+
+    /// Whether `expr` is an integer, see [`Assumptions::check`].
+    fn integer(&self, expr: &Expr) -> Option<bool> {
+        if let Some(value) = Rational::from_expr(expr) {
+            return Some(value.is_integer());
+        }
+        let assumed = self
+            .facts
+            .iter()
+            .any(|fact| fact.expr == *expr && fact.property == Property::Integer);
+        let derived = match expr {
+            Expr::UnaryOp { expr, .. } => self.integer(expr) == Some(true),
+            Expr::BinaryOp {
+                op: BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul,
+                left,
+                right,
+            } => self.integer(left) == Some(true) && self.integer(right) == Some(true),
+            Expr::BinaryOp {
+                op: BinaryOp::Pow,
+                left,
+                right,
+            } => {
+                self.integer(left) == Some(true)
+                    && self.integer(right) == Some(true)
+                    && self.check(right, Property::Nonnegative) == Some(true)
+            }
+            _ => false,
+        };
+        (assumed || derived).then_some(true)
+    }
+}
+
+/// The signs a value may have. `negative` and `positive` only mean something for real
+/// values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Sign {
+    negative: bool,
+    zero: bool,
+    positive: bool,
+    /// Known to be real.
+    real: bool,
+}
+
+impl Sign {
+    const ANY: Sign = Sign {
+        negative: true,
+        zero: true,
+        positive: true,
+        real: false,
+    };
+    const REAL: Sign = Sign {
+        real: true,
+        ..Sign::ANY
+    };
+    const POSITIVE: Sign = Sign {
+        negative: false,
+        zero: false,
+        positive: true,
+        real: true,
+    };
+    const NONNEGATIVE: Sign = Sign {
+        zero: true,
+        ..Sign::POSITIVE
+    };
+
+    fn of(value: Rational) -> Sign {
+        Sign {
+            negative: value.is_negative(),
+            zero: value.is_zero(),
+            positive: value > Rational::ZERO,
+            real: true,
+        }
+    }
+
+    /// `Some(true)` if the value is certainly one of the signs given, `Some(false)` if it
+    /// certainly isn't.
+    fn exactly(&self, negative: bool, zero: bool, positive: bool) -> Option<bool> {
+        let inside =
+            (self.negative && negative) || (self.zero && zero) || (self.positive && positive);
+        let outside =
+            (self.negative && !negative) || (self.zero && !zero) || (self.positive && !positive);
+        match (inside, outside) {
+            (true, false) => Some(true),
+            (false, true) => Some(false),
+            _ => None,
+        }
+    }
+
+    fn assuming(self, property: Property) -> Sign {
+        self.and(match property {
+            Property::Positive => Sign::POSITIVE,
+            Property::Nonnegative => Sign::NONNEGATIVE,
+            Property::Negative => Sign {
+                negative: true,
+                positive: false,
+                ..Sign::POSITIVE
+            },
+            Property::Nonzero => Sign {
+                zero: false,
+                ..Sign::ANY
+            },
+            Property::Real | Property::Integer => Sign::REAL,
+        })
+    }
+
+    /// What both signs allow.
+    fn and(self, other: Sign) -> Sign {
+        Sign {
+            negative: self.negative && other.negative,
+            zero: self.zero && other.zero,
+            positive: self.positive && other.positive,
+            real: self.real || other.real,
+        }
+    }
+
+    fn negated(self) -> Sign {
+        Sign {
+            negative: self.positive,
+            positive: self.negative,
+            ..self
+        }
+    }
+
+    fn plus(self, other: Sign) -> Sign {
+        if !(self.real && other.real) {
+            return Sign::ANY;
+        }
+        let mixed = (self.negative && other.positive) || (self.positive && other.negative);
+        Sign {
+            negative: mixed || self.negative || other.negative,
+            zero: mixed || (self.zero && other.zero),
+            positive: mixed || self.positive || other.positive,
+            real: true,
+        }
+    }
+
+    fn times(self, other: Sign) -> Sign {
+        let zero = self.zero || other.zero;
+        if !(self.real && other.real) {
+            return Sign { zero, ..Sign::ANY };
+        }
+        Sign {
+            negative: (self.negative && other.positive) || (self.positive && other.negative),
+            zero,
+            positive: (self.positive && other.positive) || (self.negative && other.negative),
+            real: true,
+        }
+    }
+}
+
+impl Expr {
+    /// A single [`Expr::simplify`] pass that also uses the rules needing properties of
+    /// symbols, such as `sqrt(x^2) = x` for `x >= 0`, where `assumptions` prove them. What
+    /// happens where they don't is up to `unproven`.
+    pub fn simplify_assuming(&self, assumptions: &Assumptions, unproven: Unproven) -> Simplified {
+        RuleSet::builtin().rewrite_assuming(self, assumptions, unproven)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Assumption, AssumptionError, Assumptions, Property, Unproven};
+    use crate::parse;
+
+    fn assuming(statements: &[&str]) -> Assumptions {
+        let mut assumptions = Assumptions::default();
+        for statement in statements {
+            assumptions.assume(statement).unwrap();
+        }
+        assumptions
+    }
+
+    fn check(assumptions: &Assumptions, expr: &str, property: Property) -> Option<bool> {
+        assumptions.check(&parse(expr).unwrap(), property)
+    }
+
+    fn simplified(
+        assumptions: &Assumptions,
+        input: &str,
+        unproven: Unproven,
+    ) -> (String, Vec<String>) {
+        let simplified = parse(input)
+            .unwrap()
+            .simplify_assuming(assumptions, unproven);
+        let conditions = simplified
+            .side_conditions
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        (simplified.expr.pretty(), conditions)
+    }
+
+    #[test]
+    fn statements_name_a_property() {
+        let read = |s: &str| s.parse::<Assumption>().map(|a| a.to_string());
+        assert_eq!(read("x > 0").unwrap(), "x > 0");
+        assert_eq!(read("x is positive").unwrap(), "x > 0");
+        assert_eq!(read("x+1 nonnegative").unwrap(), "x+1 >= 0");
+        assert_eq!(read("n integer").unwrap(), "n integer");
+        assert_eq!(read("xreal"), Err(AssumptionError::MissingProperty));
+        assert!(matches!(read("(x > 0"), Err(AssumptionError::Parse(_))));
+    }
+
+    #[test]
+    fn each_property_follows_from_the_assumptions() {
+        let positive = assuming(&["x > 0"]);
+        assert_eq!(check(&positive, "x", Property::Positive), Some(true));
+        assert_eq!(check(&positive, "x", Property::Nonnegative), Some(true));
+        assert_eq!(check(&positive, "x", Property::Negative), Some(false));
+        assert_eq!(check(&positive, "x", Property::Nonzero), Some(true));
+        assert_eq!(check(&positive, "-x", Property::Negative), Some(true));
+        assert_eq!(
+            check(&positive, "x^3+exp(x)", Property::Positive),
+            Some(true)
+        );
+        assert_eq!(check(&positive, "y", Property::Positive), None);
+
+        let nonnegative = assuming(&["x >= 0"]);
+        assert_eq!(check(&nonnegative, "x", Property::Positive), None);
+        assert_eq!(check(&nonnegative, "x", Property::Negative), Some(false));
+
+        let nonzero = assuming(&["x != 0"]);
+        assert_eq!(check(&nonzero, "x", Property::Nonzero), Some(true));
+        assert_eq!(check(&nonzero, "2x", Property::Nonzero), Some(true));
+        assert_eq!(check(&nonzero, "x", Property::Positive), None);
+
+        let real = assuming(&["y real"]);
+        assert_eq!(check(&real, "y^2", Property::Nonnegative), Some(true));
+        assert_eq!(check(&real, "sin(y)", Property::Real), Some(true));
+        assert_eq!(check(&real, "y", Property::Positive), None);
+
+        let integer = assuming(&["n integer"]);
+        assert_eq!(check(&integer, "n^2+3n", Property::Integer), Some(true));
+        assert_eq!(check(&integer, "n/2", Property::Integer), None);
+        assert_eq!(check(&integer, "n", Property::Real), Some(true));
+        assert_eq!(check(&integer, "1/2", Property::Integer), Some(false));
+    }
+
+    #[test]
+    fn contradictions_are_refused() {
+        let mut assumptions = assuming(&["x > 0"]);
+        assert_eq!(
+            assumptions.assume("x < 0"),
+            Err(AssumptionError::Contradiction("x > 0".to_string()))
+        );
+        assert!(assumptions.forget(&parse("x").unwrap()));
+        assert!(assumptions.assume("x < 0").is_ok());
+    }
+
+    #[test]
+    fn simplifications_that_need_a_property() {
+        let skip = Unproven::Skip;
+        let none = Assumptions::default();
+        assert_eq!(simplified(&none, "sqrt(x^2)", skip).0, "sqrt(x^2)");
+        assert_eq!(simplified(&assuming(&["x > 0"]), "sqrt(x^2)", skip).0, "x");
+        assert_eq!(simplified(&assuming(&["x < 0"]), "sqrt(x^2)", skip).0, "-x");
+        assert_eq!(simplified(&assuming(&["x != 0"]), "x/x", skip).0, "1");
+        assert_eq!(simplified(&assuming(&["x > 0"]), "exp(ln(x))", skip).0, "x");
+        assert_eq!(
+            simplified(&assuming(&["y real"]), "ln(exp(y))", skip).0,
+            "y"
+        );
+        assert_eq!(simplified(&none, "ln(exp(y))", skip).0, "ln(exp(y))");
+    }
+
+    #[test]
+    fn missing_properties_become_side_conditions() {
+        let record = Unproven::Record;
+        let none = Assumptions::default();
+        assert_eq!(
+            simplified(&none, "sqrt(x^2)", record),
+            ("x".to_string(), vec!["x >= 0".to_string()])
+        );
+        assert_eq!(
+            simplified(&none, "x/x + exp(ln(y))", record),
+            (
+                "1+y".to_string(),
+                vec!["x != 0".to_string(), "y > 0".to_string()]
+            )
+        );
+        // Nothing to record where the assumptions already settle it
+        assert_eq!(
+            simplified(&assuming(&["x > 0"]), "sqrt(x^2)", record),
+            ("x".to_string(), Vec::new())
+        );
+        assert_eq!(
+            simplified(&none, "sqrt(x^2)", Unproven::Skip).1,
+            Vec::<String>::new()
+        );
+    }
+}
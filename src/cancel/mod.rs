@@ -0,0 +1,179 @@
+use crate::algebra::{call, div, pow};
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+use crate::polynomial::multivariate::MultiPolynomial;
+use crate::rational::Rational;
+
+/// Largest integer power multiplied out; higher powers are left as variables.
+const MAX_POWER: u64 = 64;
+
+impl Expr {
+    /// Puts a rational expression over a common denominator and divides out the polynomial
+    /// gcd of numerator and denominator, so `(x^2-1)/(x-1)` becomes `x+1`. Both come out
+    /// expanded, with the denominator's coefficients coprime integers.
+    ///
+    /// Letters, symbols, function calls and powers that aren't integer powers are the
+    /// variables of the polynomials; function arguments are cancelled in turn. Leaves the
+    /// expression as is when a coefficient overflows or a denominator is zero.
+    ///
+    /// ```
+    /// use derivative_calculator::{derivative, parse, SimplifyLevel};
+    ///
+    /// assert_eq!(parse("(x^2-1)/(x-1)")?.cancel().pretty(), "x+1");
+    /// assert_eq!(parse("1/x + 1/(x+1)")?.cancel().pretty(), "(2x+1)/(x^2+x)");
+    /// assert_eq!(parse("(a^2-b^2)/(2a-2b)")?.cancel().pretty(), "(a+b)/2");
+    ///
+    /// let quotient = parse("(x^2-1)/(x-1)")?;
+    /// assert_eq!(derivative(&quotient, 'x', 1, SimplifyLevel::Cancel)?.pretty(), "1");
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn cancel(&self) -> Expr {
+        let mut fraction = Fraction::default();
+        fraction
+            .cancel(self)
+            .and_then(|(numer, denom)| fraction.to_expr(&numer, &denom))
+            .unwrap_or_else(|| self.clone())
+    }
+}
+
+/// Reads an expression as a quotient of polynomials, numbering the parts it can't look into
+/// as variables.
+#[derive(Default)]
+struct Fraction {
+    /// The expression each variable stands for.
+    variables: Vec<Expr>,
+}
+
+impl Fraction {
+    /// Numerator and denominator in lowest terms, both with coprime integer coefficients
+    /// and the denominator's leading one positive.
+    fn cancel(&mut self, expr: &Expr) -> Option<(MultiPolynomial, MultiPolynomial)> {
+        let (numer, denom) = self.read(expr)?;
+        let common = numer.gcd(&denom)?;
+        let (numer, denom) = (numer.exact_div(&common)?, denom.exact_div(&common)?);
+        let (numer_content, numer) = numer.primitive()?;
+        let (denom_content, denom) = denom.primitive()?;
+        let ratio = numer_content.checked_div(denom_content)?;
+        Some((
+            numer.checked_scale(Rational::integer(ratio.numer()))?,
+            denom.checked_scale(Rational::integer(ratio.denom()))?,
+        ))
+    }
+
+    fn read(&mut self, expr: &Expr) -> Option<(MultiPolynomial, MultiPolynomial)> {
+        let one = MultiPolynomial::constant(Rational::ONE);
+        Some(match expr {
+            Expr::Number(n) => (MultiPolynomial::constant(Rational::integer(*n as i64)), one),
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+            } => {
+                let (numer, denom) = self.read(expr)?;
+                (numer.checked_scale(-Rational::ONE)?, denom)
+            }
+            Expr::BinaryOp { op, left, right } => {
+                let exponent = Rational::from_expr(right)
+                    .filter(|e| e.is_integer() && e.numer().unsigned_abs() <= MAX_POWER);
+                match (op, exponent) {
+                    (BinaryOp::Pow, Some(exponent)) => {
+                        let (numer, denom) = self.read(left)?;
+                        let n = u32::try_from(exponent.numer().unsigned_abs()).ok()?;
+                        if exponent.is_negative() {
+                            if numer.is_zero() {
+                                return None;
+                            }
+                            (denom.checked_pow(n)?, numer.checked_pow(n)?)
+                        } else {
+                            (numer.checked_pow(n)?, denom.checked_pow(n)?)
+                        }
+                    }
+                    (BinaryOp::Pow, None) => (self.variable(&pow(left, &right.cancel())), one),
+                    _ => {
+                        let (a, b) = self.read(left)?;
+                        let (c, d) = self.read(right)?;
+                        combine(op, a, b, c, d)?
+                    }
+                }
+            }
+            Expr::Call { name, arg } => (self.variable(&call(name, &arg.cancel())), one),
+            _ => (self.variable(expr), one),
+        })
+    }
+
+    /// The variable standing for `expr`, numbering it if it's new.
+    fn variable(&mut self, expr: &Expr) -> MultiPolynomial {
+        let index = match self.variables.iter().position(|v| v == expr) {
+            Some(index) => index,
+            None => {
+                self.variables.push(expr.clone());
+                self.variables.len() - 1
+            }
+        };
+        MultiPolynomial::var(index)
+    }
+
+    fn to_expr(&self, numer: &MultiPolynomial, denom: &MultiPolynomial) -> Option<Expr> {
+        let numerator = numer.to_expr(&self.variables)?;
+        if denom.as_constant() == Some(Rational::ONE) {
+            return Some(numerator);
+        }
+        Some(div(&numerator, &denom.to_expr(&self.variables)?))
+    }
+}
+
+/// `a/b op c/d` for the four arithmetic operations, over the smallest common denominator.
+/// `None` when dividing by zero.
+fn combine(
+    op: &BinaryOp,
+    a: MultiPolynomial,
+    b: MultiPolynomial,
+    c: MultiPolynomial,
+    d: MultiPolynomial,
+) -> Option<(MultiPolynomial, MultiPolynomial)> {
+    match op {
+        BinaryOp::Add | BinaryOp::Sub => {
+            let common = b.gcd(&d)?;
+            let (b_rest, d_rest) = (b.exact_div(&common)?, d.exact_div(&common)?);
+            let left = a.checked_mul(&d_rest)?;
+            let right = c.checked_mul(&b_rest)?;
+            let numer = match op {
+                BinaryOp::Add => left.checked_add(&right)?,
+                _ => left.checked_sub(&right)?,
+            };
+            Some((numer, b.checked_mul(&d_rest)?))
+        }
+        BinaryOp::Mul => Some((a.checked_mul(&c)?, b.checked_mul(&d)?)),
+        BinaryOp::Div if c.is_zero() => None,
+        BinaryOp::Div => Some((a.checked_mul(&d)?, b.checked_mul(&c)?)),
+        BinaryOp::Pow => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{derivative, parse, SimplifyLevel};
+
+    fn cancelled(input: &str) -> String {
+        parse(input).unwrap().cancel().pretty()
+    }
+
+    #[test]
+    fn derivatives_of_removable_singularities() {
+        let quotient = parse("(x^2-1)/(x-1)").unwrap();
+        let derivative = derivative(&quotient, 'x', 1, SimplifyLevel::Cancel).unwrap();
+        assert_eq!(derivative.pretty(), "1");
+    }
+
+    #[test]
+    fn common_factors_in_several_variables() {
+        assert_eq!(cancelled("(x^2-y^2)/(x-y)"), "x+y");
+        assert_eq!(cancelled("(x^2*y+x*y^2)/(x*y)"), "x+y");
+        assert_eq!(cancelled("(sin(x)^2-1)/(sin(x)+1)"), "sin(x)-1");
+    }
+
+    #[test]
+    fn coprime_quotients_are_only_expanded() {
+        assert_eq!(cancelled("(x+1)/(x-1)"), "(x+1)/(x-1)");
+        assert_eq!(cancelled("(x^2+y^2)/(x+y)"), "(x^2+y^2)/(x+y)");
+        assert_eq!(cancelled("1/0"), "1/0");
+    }
+}
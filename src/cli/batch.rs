@@ -0,0 +1,242 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::ExitCode;
+
+use super::{Options, EXIT_BATCH_FAILURES, EXIT_IO};
+use derivative_calculator::printer;
+
+/// How batch results are written to stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(super) enum Emit {
+    /// `line: input => derivative`, or `line: input => error: ...`
+    #[default]
+    Text,
+    /// A header row followed by `line,input,derivative,error` records.
+    Csv,
+    /// One JSON object per line with `line`, `input`, `derivative` and `error` keys.
+    JsonLines,
+}
+
+impl Emit {
+    pub(super) fn from_name(name: &str) -> Option<Emit> {
+        match name {
+            "text" => Some(Emit::Text),
+            "csv" => Some(Emit::Csv),
+            "jsonl" => Some(Emit::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of differentiating one line of the batch input.
+struct Record<'a> {
+    line: usize,
+    input: &'a str,
+    result: Result<String, String>,
+}
+
+/// Differentiates every expression read from `path` (stdin for `-`), writing one record per
+/// expression. Lines that fail are reported in their record and don't stop the run.
+pub(super) fn run(path: &str, options: &Options) -> ExitCode {
+    let reader: Box<dyn BufRead> = if path == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        match File::open(path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                eprintln!("error: can't open '{path}': {e}");
+                return ExitCode::from(EXIT_IO);
+            }
+        }
+    };
+
+    differentiate_lines(reader, &mut io::stdout().lock(), path, options)
+}
+
+/// The loop of [`run`], reading from `reader` and writing the records to `out`.
+fn differentiate_lines(
+    reader: impl BufRead,
+    out: &mut impl Write,
+    path: &str,
+    options: &Options,
+) -> ExitCode {
+    if options.emit == Emit::Csv {
+        let _ = writeln!(out, "line,input,derivative,error");
+    }
+    let mut failures = 0;
+    for (idx, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("error: can't read '{path}': {e}");
+                return ExitCode::from(EXIT_IO);
+            }
+        };
+        let input = line.trim();
+        if input.is_empty() || input.starts_with('#') {
+            continue;
+        }
+        let result = super::differentiate(input, options)
+            .map(|derivative| printer::render(&derivative, options.format))
+            .map_err(|e| e.to_string());
+        if result.is_err() {
+            failures += 1;
+        }
+        let record = Record {
+            line: idx + 1,
+            input,
+            result,
+        };
+        if writeln!(out, "{}", format_record(&record, options.emit)).is_err() {
+            // stdout was closed (e.g. piped into `head`), nobody is reading anymore.
+            break;
+        }
+    }
+
+    if failures > 0 {
+        ExitCode::from(EXIT_BATCH_FAILURES)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn format_record(record: &Record, emit: Emit) -> String {
+    let (derivative, error) = match &record.result {
+        Ok(derivative) => (Some(derivative.as_str()), None),
+        Err(error) => (None, Some(error.as_str())),
+    };
+    match emit {
+        Emit::Text => match &record.result {
+            Ok(derivative) => format!("{}: {} => {derivative}", record.line, record.input),
+            Err(error) => format!("{}: {} => error: {error}", record.line, record.input),
+        },
+        Emit::Csv => format!(
+            "{},{},{},{}",
+            record.line,
+            csv_field(record.input),
+            csv_field(derivative.unwrap_or("")),
+            csv_field(error.unwrap_or(""))
+        ),
+        Emit::JsonLines => format!(
+            "{{\"line\":{},\"input\":{},\"derivative\":{},\"error\":{}}}",
+            record.line,
+            json_string(record.input),
+            derivative.map_or("null".to_string(), json_string),
+            error.map_or("null".to_string(), json_string)
+        ),
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break (RFC 4180).
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Encodes `text` as a JSON string literal.
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for chr in text.chars() {
+        match chr {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::ExitCode;
+
+    use super::*;
+    use crate::cli::{parse_args, Command, EXIT_BATCH_FAILURES};
+
+    fn batch(input: &str, emit: &str) -> (String, ExitCode) {
+        let args: Vec<String> = ["--batch", "-", "--emit", emit]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let Ok(Command::Differentiate(options)) = parse_args(&args) else {
+            panic!("expected batch options");
+        };
+        let mut out = Vec::new();
+        let code = differentiate_lines(input.as_bytes(), &mut out, "-", &options);
+        (String::from_utf8(out).unwrap(), code)
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("2x"), "2x");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("x^2"), "\"x^2\"");
+        assert_eq!(json_string("a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(json_string("\n\t\r"), "\"\\n\\t\\r\"");
+        assert_eq!(json_string("\u{1}"), "\"\\u0001\"");
+        assert_eq!(json_string("π"), "\"π\"");
+    }
+
+    #[test]
+    fn records_in_every_format() {
+        let ok = Record {
+            line: 3,
+            input: "x^2",
+            result: Ok("2x".to_string()),
+        };
+        let failed = Record {
+            line: 4,
+            input: "x+",
+            result: Err("unexpected end, \"x+\"".to_string()),
+        };
+        assert_eq!(format_record(&ok, Emit::Text), "3: x^2 => 2x");
+        assert_eq!(
+            format_record(&failed, Emit::Text),
+            "4: x+ => error: unexpected end, \"x+\""
+        );
+        assert_eq!(format_record(&ok, Emit::Csv), "3,x^2,2x,");
+        assert_eq!(
+            format_record(&failed, Emit::Csv),
+            "4,x+,,\"unexpected end, \"\"x+\"\"\""
+        );
+        assert_eq!(
+            format_record(&ok, Emit::JsonLines),
+            r#"{"line":3,"input":"x^2","derivative":"2x","error":null}"#
+        );
+        assert_eq!(
+            format_record(&failed, Emit::JsonLines),
+            r#"{"line":4,"input":"x+","derivative":null,"error":"unexpected end, \"x+\""}"#
+        );
+    }
+
+    #[test]
+    fn any_failing_line_fails_the_run() {
+        let (out, code) = batch("# squares\nx^2\n\nx^3\n", "text");
+        assert_eq!(out, "2: x^2 => 2x\n4: x^3 => 3x^2\n");
+        assert_eq!(code, ExitCode::SUCCESS);
+
+        let (out, code) = batch("x^2\nx+\nsin(x)\n", "csv");
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "line,input,derivative,error");
+        assert_eq!(lines[1], "1,x^2,2x,");
+        assert!(lines[2].starts_with("2,x+,,"));
+        assert_eq!(lines[3], "3,sin(x),cos(x),");
+        assert_eq!(code, ExitCode::from(EXIT_BATCH_FAILURES));
+    }
+}
@@ -0,0 +1,295 @@
+mod batch;
+
+use std::process::ExitCode;
+
+use batch::Emit;
+
+use derivative_calculator::assumptions::{Assumptions, Unproven};
+use derivative_calculator::rewrite::RuleSet;
+use derivative_calculator::{printer, Error, Expr, OutputFormat, SimplifyLevel};
+
+/// Exit code for malformed command line arguments.
+pub const EXIT_USAGE: u8 = 2;
+/// Exit code when the expression contains characters the tokenizer doesn't accept.
+pub const EXIT_TOKENIZE: u8 = 3;
+/// Exit code when the tokens don't form a valid expression.
+pub const EXIT_PARSE: u8 = 4;
+/// Exit code when the expression can't be differentiated.
+pub const EXIT_DIFFERENTIATE: u8 = 5;
+/// Exit code when a batch run finished but at least one line failed.
+pub const EXIT_BATCH_FAILURES: u8 = 1;
+/// Exit code when the batch input can't be read.
+pub const EXIT_IO: u8 = 6;
+
+const USAGE: &str = "\
+Usage: derivative_calculator [OPTIONS] [--] <EXPRESSION>
+       derivative_calculator [OPTIONS] --batch <FILE>
+       derivative_calculator            (starts the interactive REPL)
+
+Prints the derivative of EXPRESSION to stdout. With --batch, differentiates every line of
+FILE ('-' for stdin) instead; blank lines and lines starting with '#' are skipped and a
+failing line is reported without stopping the run.
+
+Options:
+  -w, --wrt <VAR>         variable to differentiate with respect to [default: x]
+  -n, --order <N>         number of times to differentiate [default: 1]
+  -f, --format <FORMAT>   pretty, unicode, latex, mathml or content-mathml [default: pretty]
+  -s, --simplify <LEVEL>  none, basic, full, cancel, expand, factor, saturate or identities
+                          [default: basic]
+  -r, --rules <FILE>      also rewrite the result with the rules in FILE
+  -a, --assume <FACT>     simplify assuming FACT, e.g. 'x > 0' or 'n integer'; repeatable
+  -b, --batch <FILE>      read one expression per line from FILE, or stdin for '-'
+  -e, --emit <KIND>       batch output: text, csv or jsonl [default: text]
+  -h, --help              print this help
+
+Exit codes:
+  0  success
+  1  some lines of a batch failed
+  2  invalid arguments
+  3  tokenize error
+  4  parse error
+  5  differentiation error
+  6  batch input could not be read";
+
+/// Where the expressions of a non-interactive run come from.
+#[derive(Debug)]
+enum Input {
+    Expression(String),
+    /// Path to a file with one expression per line, `-` being stdin.
+    Batch(String),
+}
+
+/// Settings for a single non-interactive run.
+#[derive(Debug)]
+struct Options {
+    input: Input,
+    emit: Emit,
+    wrt: char,
+    order: u32,
+    format: OutputFormat,
+    simplify: SimplifyLevel,
+    /// Rules from `--rules`, applied after simplifying.
+    rules: Option<RuleSet>,
+    /// Facts from `--assume`, which the rules may rely on.
+    assumptions: Assumptions,
+}
+
+/// What the command line asked for.
+enum Command {
+    Help,
+    Differentiate(Options),
+}
+
+/// Runs the one-shot mode with the given arguments (program name excluded). Only the result
+/// is written to stdout; errors go to stderr and are reflected in the exit code.
+pub fn run(args: &[String]) -> ExitCode {
+    let options = match parse_args(args) {
+        Ok(Command::Help) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Differentiate(options)) => options,
+        Err(message) => {
+            eprintln!("error: {message}");
+            eprintln!("Try '--help' for more information.");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let expression = match &options.input {
+        Input::Expression(expression) => expression,
+        Input::Batch(path) => return batch::run(path, &options),
+    };
+    match differentiate(expression, &options) {
+        Ok(derivative) => {
+            println!("{}", printer::render(&derivative, options.format));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+/// Parses `input` and differentiates it as configured in `options`.
+fn differentiate(input: &str, options: &Options) -> Result<Expr, Error> {
+    let derivative = derivative_calculator::parse(input).and_then(|expr| {
+        derivative_calculator::derivative(&expr, options.wrt, options.order, options.simplify)
+    })?;
+    if options.rules.is_none() && options.assumptions.is_empty() {
+        return Ok(derivative);
+    }
+    let mut rules = RuleSet::builtin().clone();
+    rules.extend(options.rules.clone().unwrap_or_default());
+    Ok(rules
+        .rewrite_assuming(&derivative, &options.assumptions, Unproven::Skip)
+        .expr)
+}
+
+/// Exit code reported for a pipeline error.
+pub fn exit_code(error: &Error) -> u8 {
+    match error {
+        Error::Tokenize(_) => EXIT_TOKENIZE,
+        Error::Differentiate(_) => EXIT_DIFFERENTIATE,
+        _ => EXIT_PARSE,
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut expression = None;
+    let mut batch = None;
+    let mut emit = Emit::default();
+    let mut wrt = 'x';
+    let mut order = 1;
+    let mut format = OutputFormat::default();
+    let mut simplify = SimplifyLevel::default();
+    let mut rules = None;
+    let mut assumptions = Assumptions::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        // Everything after `--` is positional, so expressions like `-x` can be passed.
+        if arg == "--" {
+            for arg in args.by_ref() {
+                if expression.is_some() {
+                    return Err(format!("unexpected argument '{arg}'"));
+                }
+                expression = Some(arg.clone());
+            }
+            break;
+        }
+        // Accept both `--flag value` and `--flag=value`.
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = |name: &str| {
+            inline_value
+                .clone()
+                .or_else(|| args.next().cloned())
+                .ok_or_else(|| format!("'{name}' requires a value"))
+        };
+        match flag {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-w" | "--wrt" => {
+                let var = value(flag)?;
+                let mut chars = var.chars();
+                wrt = match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii_lowercase() => c,
+                    _ => return Err(format!("'{var}' is not a single lowercase variable")),
+                };
+            }
+            "-n" | "--order" => {
+                let n = value(flag)?;
+                order = n
+                    .parse()
+                    .map_err(|_| format!("'{n}' is not a valid order"))?;
+            }
+            "-f" | "--format" => {
+                let name = value(flag)?;
+                format = OutputFormat::from_name(&name)
+                    .ok_or_else(|| format!("unknown format '{name}'"))?;
+            }
+            "-s" | "--simplify" => {
+                let name = value(flag)?;
+                simplify = SimplifyLevel::from_name(&name)
+                    .ok_or_else(|| format!("unknown simplification level '{name}'"))?;
+            }
+            "-r" | "--rules" => {
+                let path = value(flag)?;
+                rules = Some(RuleSet::load(&path).map_err(|e| format!("'{path}': {e}"))?);
+            }
+            "-a" | "--assume" => {
+                let fact = value(flag)?;
+                assumptions
+                    .assume(&fact)
+                    .map_err(|e| format!("can't assume '{fact}': {e}"))?;
+            }
+            "-b" | "--batch" => batch = Some(value(flag)?),
+            "-e" | "--emit" => {
+                let name = value(flag)?;
+                emit =
+                    Emit::from_name(&name).ok_or_else(|| format!("unknown emit kind '{name}'"))?;
+            }
+            // Something like `-x` or `-x+1` is an expression, not a flag.
+            _ if flag.starts_with("--")
+                || (flag.len() == 2
+                    && flag.starts_with('-')
+                    && derivative_calculator::parse(flag).is_err()) =>
+            {
+                return Err(format!("unknown option '{flag}'"));
+            }
+            _ if expression.is_none() => expression = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+
+    let input = match (expression, batch) {
+        (Some(expression), None) => Input::Expression(expression),
+        (None, Some(path)) => Input::Batch(path),
+        (Some(_), Some(_)) => return Err("an expression can't be combined with --batch".into()),
+        (None, None) => return Err("no expression given".into()),
+    };
+    Ok(Command::Differentiate(Options {
+        input,
+        emit,
+        wrt,
+        order,
+        format,
+        simplify,
+        rules,
+        assumptions,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn expression(command: Result<Command, String>) -> Option<String> {
+        match command {
+            Ok(Command::Differentiate(Options {
+                input: Input::Expression(expression),
+                ..
+            })) => Some(expression),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn negated_expressions_are_not_flags() {
+        assert_eq!(expression(parse_args(&args(&["-x"]))), Some("-x".into()));
+        assert_eq!(
+            expression(parse_args(&args(&["-w", "y", "-y^2"]))),
+            Some("-y^2".into())
+        );
+        assert_eq!(
+            expression(parse_args(&args(&["--", "-x"]))),
+            Some("-x".into())
+        );
+    }
+
+    #[test]
+    fn unknown_flags_are_errors() {
+        assert!(parse_args(&args(&["-Q"])).is_err());
+        assert!(parse_args(&args(&["--frobnicate", "x"])).is_err());
+    }
+
+    #[test]
+    fn negation_applies_after_powers() {
+        let options = match parse_args(&args(&["-x^3"])) {
+            Ok(Command::Differentiate(options)) => options,
+            _ => panic!("expected an expression"),
+        };
+        assert_eq!(differentiate("-x^3", &options).unwrap().pretty(), "-3x^2");
+        assert_eq!(
+            exit_code(&differentiate("cosh(x)", &options).unwrap_err()),
+            EXIT_PARSE
+        );
+    }
+}
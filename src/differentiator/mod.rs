@@ -1,97 +1,156 @@
+use std::fmt::Display;
+
+use crate::algebra::{add, call, div, mul, neg, pow, square, sub};
 use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
 
-pub fn differentiate(expr: &Expr) -> Expr {
-    match expr {
+/// Reasons an expression can't be differentiated.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum DiffError {
+    /// Powers are only supported with a number as the exponent.
+    UnsupportedPower(Expr),
+    /// The function is neither built in nor defined.
+    UnknownFunction(String),
+    /// A multi-letter name such as `ans` or `$2` that was never given a value.
+    UnboundName(String),
+}
+
+impl Display for DiffError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DiffError::UnsupportedPower(expr) => {
+                write!(
+                    f,
+                    "general power rule not implemented for '{}'",
+                    expr.pretty()
+                )
+            }
+            DiffError::UnknownFunction(name) => write!(f, "unknown function '{name}'"),
+            DiffError::UnboundName(name) => write!(f, "'{name}' has no value"),
+        }
+    }
+}
+
+impl std::error::Error for DiffError {}
+
+impl Expr {
+    /// Differentiates the expression with respect to `var`, see [`differentiate`]. The result
+    /// isn't simplified.
+    pub fn diff(&self, var: char) -> Result<Expr, DiffError> {
+        differentiate(self, var)
+    }
+}
+
+/// Differentiates `expr` with respect to `var`. Every other letter is treated as a constant,
+/// while a multi-letter [`Expr::Symbol`] is an error since its value, and so whether it
+/// depends on `var`, is unknown.
+pub fn differentiate(expr: &Expr, var: char) -> Result<Expr, DiffError> {
+    let derivative = match expr {
         Expr::Number(_) => Expr::Number(0),
-        Expr::Variable(c) if *c == 'x' => Expr::Number(1),
+        Expr::Variable(c) | Expr::Constant(c) if *c == var => Expr::Number(1),
         Expr::Variable(_) => Expr::Number(0),
         Expr::Constant(_) => Expr::Number(0),
+        Expr::Symbol(name) => return Err(DiffError::UnboundName(name.clone())),
+        // Chain rule: f(u)' = f'(u) * u', with a sign on f'(u) moved in front of the product
+        Expr::Call { name, arg } => match outer_derivative(name, arg)? {
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr: outer,
+            } => neg(&mul(&outer, &differentiate(arg, var)?)),
+            outer => mul(&outer, &differentiate(arg, var)?),
+        },
         Expr::UnaryOp {
             op: UnaryOp::Neg,
             expr,
-        } => Expr::UnaryOp {
-            op: UnaryOp::Neg,
-            expr: Box::new(differentiate(expr)),
-        },
-        Expr::BinaryOp {
-            op: BinaryOp::Add,
-            left,
-            right,
-        } => Expr::BinaryOp {
-            op: BinaryOp::Add,
-            left: Box::new(differentiate(left)),
-            right: Box::new(differentiate(right)),
-        },
-        Expr::BinaryOp {
-            op: BinaryOp::Sub,
-            left,
-            right,
-        } => Expr::BinaryOp {
-            op: BinaryOp::Sub,
-            left: Box::new(differentiate(left)),
-            right: Box::new(differentiate(right)),
-        },
-        Expr::BinaryOp {
-            op: BinaryOp::Mul,
-            left,
-            right,
-        } => Expr::BinaryOp {
-            op: BinaryOp::Add,
-            left: Box::new(Expr::BinaryOp {
-                op: BinaryOp::Mul,
-                left: Box::new(differentiate(left)),
-                right: right.clone(),
-            }),
-            right: Box::new(Expr::BinaryOp {
-                op: BinaryOp::Mul,
-                left: left.clone(),
-                right: Box::new(differentiate(right)),
-            }),
-        },
-        Expr::BinaryOp {
-            op: BinaryOp::Div,
-            left,
-            right,
-        } => Expr::BinaryOp {
-            op: BinaryOp::Div,
-            left: Box::new(Expr::BinaryOp {
-                op: BinaryOp::Sub,
-                left: Box::new(Expr::BinaryOp {
-                    op: BinaryOp::Mul,
-                    left: Box::new(differentiate(left)),
-                    right: right.clone(),
-                }),
-                right: Box::new(Expr::BinaryOp {
-                    op: BinaryOp::Mul,
-                    left: left.clone(),
-                    right: Box::new(differentiate(right)),
-                }),
-            }),
-            right: Box::new(Expr::BinaryOp {
-                op: BinaryOp::Pow,
-                left: right.clone(),
-                right: Box::new(Expr::Number(2)),
-            }),
-        },
-        Expr::BinaryOp {
-            op: BinaryOp::Pow,
-            left,
-            right,
-        } => {
-            // Only handle x^n where n is a constant for now
-            if let Expr::Number(n) = **right {
-                Expr::BinaryOp {
-                    op: BinaryOp::Mul,
-                    left: Box::new(Expr::Number(n)),
-                    right: Box::new(Expr::BinaryOp {
-                        op: BinaryOp::Pow,
-                        left: left.clone(),
-                        right: Box::new(Expr::Number(n - 1)),
-                    }),
-                }
-            } else {
-                panic!("General power rule not implemented");
+        } => neg(&differentiate(expr, var)?),
+        Expr::BinaryOp { op, left, right } => {
+            let (du, dv) = (differentiate(left, var), differentiate(right, var));
+            match op {
+                BinaryOp::Add => add(&du?, &dv?),
+                BinaryOp::Sub => sub(&du?, &dv?),
+                // (uv)' = u'v + uv'
+                BinaryOp::Mul => add(&mul(&du?, right), &mul(left, &dv?)),
+                // (u/v)' = (u'v - uv') / v^2
+                BinaryOp::Div => div(&sub(&mul(&du?, right), &mul(left, &dv?)), &square(right)),
+                // Only handle f^n where n is a constant for now: n*f^(n-1)*f'
+                BinaryOp::Pow => match **right {
+                    Expr::Number(n) => mul(
+                        &mul(&Expr::Number(n), &pow(left, &Expr::Number(n - 1))),
+                        &du?,
+                    ),
+                    _ => return Err(DiffError::UnsupportedPower(expr.clone())),
+                },
             }
         }
+    };
+    Ok(derivative)
+}
+
+/// Derivative of the built-in function `name`, evaluated at `arg`.
+fn outer_derivative(name: &str, arg: &Expr) -> Result<Expr, DiffError> {
+    let one = Expr::Number(1);
+    let derivative = match name {
+        "sin" => call("cos", arg),
+        "cos" => neg(&call("sin", arg)),
+        "tan" => div(&one, &square(&call("cos", arg))),
+        "asin" => div(&one, &call("sqrt", &sub(&one, &square(arg)))),
+        "atan" => div(&one, &add(&one, &square(arg))),
+        "exp" => call("exp", arg),
+        "ln" => div(&one, arg),
+        "sqrt" => div(&one, &mul(&Expr::Number(2), &call("sqrt", arg))),
+        _ => return Err(DiffError::UnknownFunction(name.to_string())),
+    };
+    Ok(derivative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiffError;
+    use crate::{parse, Expr};
+
+    fn derivative(input: &str) -> String {
+        parse(input)
+            .unwrap()
+            .diff('x')
+            .unwrap()
+            .simplify_full()
+            .pretty()
+    }
+
+    #[test]
+    fn other_letters_are_constants() {
+        assert_eq!(derivative("ax^2+bx+c"), "2*a*x+b");
+        assert_eq!(derivative("xy"), "y");
+        assert_eq!(derivative("x2"), "2");
+    }
+
+    #[test]
+    fn built_in_functions_use_the_chain_rule() {
+        assert_eq!(derivative("tan(x)"), "1/cos(x)^2");
+        assert_eq!(derivative("asin(x)"), "1/sqrt(1-x^2)");
+        assert_eq!(derivative("atan(x)"), "1/(1+x^2)");
+        assert_eq!(derivative("sqrt(x)"), "1/(2*sqrt(x))");
+        assert_eq!(derivative("cos(x^2)"), "-2*sin(x^2)*x");
+    }
+
+    #[test]
+    fn products_quotients_and_powers() {
+        assert_eq!(derivative("x*sin(x)"), "sin(x)+x*cos(x)");
+        assert_eq!(derivative("1/x"), "-1/x^2");
+        assert_eq!(derivative("(x+1)^3"), "3(x+1)^2");
+        assert_eq!(
+            parse("x^x").unwrap().diff('x'),
+            Err(DiffError::UnsupportedPower(parse("x^x").unwrap()))
+        );
+    }
+
+    #[test]
+    fn unbound_names_are_errors() {
+        let symbol = Expr::Symbol("ans".to_string());
+        assert_eq!(
+            symbol.diff('x'),
+            Err(DiffError::UnboundName("ans".to_string()))
+        );
+        assert!(parse("$2").unwrap().diff('x').is_err());
     }
 }
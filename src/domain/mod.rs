@@ -0,0 +1,427 @@
+//! Where an expression is defined: the conditions its denominators, logarithms, roots and
+//! powers put on the symbols, solved to intervals when they are polynomials in one variable.
+
+use std::fmt::Display;
+
+use crate::algebra::{call, square, sub};
+use crate::assumptions::{Assumption, Assumptions, Property};
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+use crate::polynomial::Polynomial;
+use crate::rational::Rational;
+
+/// Conditions under which an expression is defined, with real values for every symbol.
+///
+/// ```
+/// use derivative_calculator::parse;
+///
+/// let expr = parse("ln(x)/(x-2)")?;
+/// let domain = expr.domain();
+/// assert_eq!(domain.to_string(), "x > 0 and x-2 != 0");
+/// let intervals: Vec<String> = domain.intervals('x').unwrap().iter().map(|i| i.to_string()).collect();
+/// assert_eq!(intervals, ["(0, 2)", "(2, inf)"]);
+///
+/// // The quotient rule divides by the square of the denominator
+/// let derivative = parse("sqrt(x)/(x^2-1)")?.diff('x')?.simplify();
+/// let domain = parse("sqrt(x)/(x^2-1)")?.domain().and(derivative.domain());
+/// assert_eq!(domain.to_string(), "x > 0 and x^2-1 != 0");
+/// let intervals: Vec<String> = domain.intervals('x').unwrap().iter().map(|i| i.to_string()).collect();
+/// assert_eq!(intervals, ["(0, 1)", "(1, inf)"]);
+///
+/// assert!(parse("x^2 + 1")?.domain().is_everywhere());
+/// assert_eq!(parse("sqrt(x^2 + 1)")?.domain().to_string(), "everywhere");
+/// # Ok::<(), derivative_calculator::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Domain {
+    /// Properties the subexpressions need, each already simplified, none implied by another.
+    pub conditions: Vec<Assumption>,
+}
+
+/// A range of real numbers, possibly unbounded (with infinite ends, which are open).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub lower: f64,
+    pub upper: f64,
+    /// Whether `lower` itself is included.
+    pub lower_closed: bool,
+    /// Whether `upper` itself is included.
+    pub upper_closed: bool,
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let end = |x: f64| match x {
+            f64::INFINITY => "inf".to_string(),
+            f64::NEG_INFINITY => "-inf".to_string(),
+            x => {
+                // Six decimals at most, which hides the rounding of numerical roots
+                let text = format!("{x:.6}");
+                let text = text.trim_end_matches('0').trim_end_matches('.');
+                if text == "-0" { "0" } else { text }.to_string()
+            }
+        };
+        if self.lower == self.upper {
+            return write!(f, "{{{}}}", end(self.lower));
+        }
+        write!(
+            f,
+            "{}{}, {}{}",
+            if self.lower_closed { '[' } else { '(' },
+            end(self.lower),
+            end(self.upper),
+            if self.upper_closed { ']' } else { ')' }
+        )
+    }
+}
+
+impl Display for Domain {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.conditions.is_empty() {
+            return f.write_str("everywhere");
+        }
+        for (idx, condition) in self.conditions.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(" and ")?;
+            }
+            write!(f, "{condition}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Domain {
+    /// Whether the expression is defined for every real value of its symbols.
+    pub fn is_everywhere(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    /// Where both expressions are defined.
+    pub fn and(mut self, other: Domain) -> Domain {
+        for condition in other.conditions {
+            self.require(condition.expr, condition.property);
+        }
+        self
+    }
+
+    /// Adds `expr` having `property` as a condition, simplified and unless another condition
+    /// or the expression's own form already implies it.
+    fn require(&mut self, expr: Expr, property: Property) {
+        let expr = expr.simplify();
+        if property == Property::Nonzero {
+            if let Some(parts) = nonzero_parts(&expr) {
+                for part in parts {
+                    self.require(part, Property::Nonzero);
+                }
+                return;
+            }
+        }
+        if real_symbols(&expr).check(&expr, property) == Some(true) {
+            return;
+        }
+        let implies = |stronger: Property, weaker: Property| {
+            stronger == weaker
+                || matches!(
+                    (stronger, weaker),
+                    (
+                        Property::Positive,
+                        Property::Nonnegative | Property::Nonzero
+                    ) | (Property::Negative, Property::Nonzero)
+                )
+        };
+        if self
+            .conditions
+            .iter()
+            .any(|c| c.expr == expr && implies(c.property, property))
+        {
+            return;
+        }
+        // A stronger condition takes the place of the weaker ones
+        let weaker = |c: &Assumption| c.expr == expr && implies(property, c.property);
+        if let Some(idx) = self.conditions.iter().position(weaker) {
+            self.conditions.retain(|c| !weaker(c));
+            self.conditions.insert(idx, Assumption { expr, property });
+            return;
+        }
+        // `>= 0` and `!= 0` together make `> 0`
+        let completes = |other: Property| {
+            matches!(
+                (property, other),
+                (Property::Nonzero, Property::Nonnegative)
+                    | (Property::Nonnegative, Property::Nonzero)
+            )
+        };
+        match self
+            .conditions
+            .iter_mut()
+            .find(|c| c.expr == expr && completes(c.property))
+        {
+            Some(condition) => condition.property = Property::Positive,
+            None => self.conditions.push(Assumption { expr, property }),
+        }
+    }
+
+    /// The values of `var` meeting every condition, as disjoint intervals in increasing order.
+    /// `None` unless every condition is a polynomial in `var` alone.
+    pub fn intervals(&self, var: char) -> Option<Vec<Interval>> {
+        let mut constraints = Vec::new();
+        for condition in &self.conditions {
+            let polynomial = Polynomial::from_expr(&condition.expr, var)?;
+            let roots = if polynomial.is_zero() {
+                Vec::new()
+            } else {
+                polynomial.real_roots()?
+            };
+            constraints.push((polynomial, roots, condition.property));
+        }
+        let mut roots: Vec<f64> = constraints
+            .iter()
+            .flat_map(|(_, roots, _)| roots.iter().copied())
+            .collect();
+        roots.sort_by(f64::total_cmp);
+        roots.dedup();
+
+        // The roots and the open gaps around them, in order
+        let mut pieces = Vec::new();
+        let mut lower = f64::NEG_INFINITY;
+        for &root in &roots {
+            pieces.push((lower, root));
+            pieces.push((root, root));
+            lower = root;
+        }
+        pieces.push((lower, f64::INFINITY));
+
+        let meets = |&(lo, hi): &(f64, f64)| {
+            let x = match (lo.is_finite(), hi.is_finite()) {
+                _ if lo == hi => lo,
+                (true, true) => 0.5 * (lo + hi),
+                (true, false) => lo + 1.0,
+                (false, true) => hi - 1.0,
+                (false, false) => 0.0,
+            };
+            constraints.iter().all(|(polynomial, roots, property)| {
+                let value = if roots.contains(&x) {
+                    0.0
+                } else {
+                    eval(polynomial, x)
+                };
+                match property {
+                    Property::Positive => value > 0.0,
+                    Property::Nonnegative => value >= 0.0,
+                    Property::Negative => value < 0.0,
+                    Property::Nonzero => value != 0.0,
+                    _ => true,
+                }
+            })
+        };
+        let mut intervals: Vec<Interval> = Vec::new();
+        let mut extending = false;
+        for piece in &pieces {
+            let closed = piece.0 == piece.1;
+            let met = meets(piece);
+            match intervals.last_mut() {
+                Some(last) if met && extending => {
+                    last.upper = piece.1;
+                    last.upper_closed = closed;
+                }
+                _ if met => intervals.push(Interval {
+                    lower: piece.0,
+                    upper: piece.1,
+                    lower_closed: closed,
+                    upper_closed: closed,
+                }),
+                _ => {}
+            }
+            extending = met;
+        }
+        Some(intervals)
+    }
+}
+
+/// Value of the polynomial at `x` in floating point.
+fn eval(polynomial: &Polynomial, x: f64) -> f64 {
+    polynomial
+        .coefficients()
+        .iter()
+        .rev()
+        .fold(0.0, |acc, c| acc * x + c.to_f64())
+}
+
+/// Simpler expressions that are all nonzero exactly when `expr` is: the factors of a
+/// product, the numerator of a quotient, the base of a positive power, the argument of a
+/// square root. `None` if there are none.
+fn nonzero_parts(expr: &Expr) -> Option<Vec<Expr>> {
+    match expr {
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => Some(vec![(**expr).clone()]),
+        Expr::BinaryOp {
+            op: BinaryOp::Mul,
+            left,
+            right,
+        } => Some(vec![(**left).clone(), (**right).clone()]),
+        Expr::BinaryOp {
+            op: BinaryOp::Div,
+            left,
+            ..
+        } => Some(vec![(**left).clone()]),
+        Expr::BinaryOp {
+            op: BinaryOp::Pow,
+            left,
+            right,
+        } if Rational::from_expr(right).is_some_and(|n| n > Rational::ZERO) => {
+            Some(vec![(**left).clone()])
+        }
+        Expr::Call { name, arg } if name == "sqrt" => Some(vec![(**arg).clone()]),
+        _ => None,
+    }
+}
+
+/// Assumptions that every letter in `expr` is real.
+fn real_symbols(expr: &Expr) -> Assumptions {
+    let mut assumptions = Assumptions::default();
+    let mut add = |expr: &Expr| {
+        let _ = assumptions.add(Assumption {
+            expr: expr.clone(),
+            property: Property::Real,
+        });
+    };
+    visit_letters(expr, &mut add);
+    assumptions
+}
+
+fn visit_letters(expr: &Expr, visit: &mut impl FnMut(&Expr)) {
+    match expr {
+        Expr::Variable(_) | Expr::Constant(_) => visit(expr),
+        Expr::Call { arg, .. } => visit_letters(arg, visit),
+        Expr::UnaryOp { expr, .. } => visit_letters(expr, visit),
+        Expr::BinaryOp { left, right, .. } => {
+            visit_letters(left, visit);
+            visit_letters(right, visit);
+        }
+        _ => {}
+    }
+}
+
+impl Expr {
+    /// Where the expression is defined: denominators and the bases of negative powers must be
+    /// nonzero, logarithms need positive arguments, square roots and other even roots
+    /// non-negative ones, `x^y` with a symbolic exponent a positive base, `tan` a nonzero
+    /// cosine and `asin` an argument between `-1` and `1`.
+    pub fn domain(&self) -> Domain {
+        let mut domain = Domain::default();
+        collect(self, &mut domain);
+        domain
+    }
+}
+
+fn collect(expr: &Expr, domain: &mut Domain) {
+    match expr {
+        Expr::Call { name, arg } => {
+            collect(arg, domain);
+            let arg = (**arg).clone();
+            match name.as_str() {
+                "ln" => domain.require(arg, Property::Positive),
+                "sqrt" => domain.require(arg, Property::Nonnegative),
+                "tan" => domain.require(call("cos", &arg), Property::Nonzero),
+                "asin" => {
+                    domain.require(sub(&Expr::Number(1), &square(&arg)), Property::Nonnegative)
+                }
+                _ => {}
+            }
+        }
+        Expr::UnaryOp { expr, .. } => collect(expr, domain),
+        Expr::BinaryOp { op, left, right } => {
+            collect(left, domain);
+            collect(right, domain);
+            match op {
+                BinaryOp::Div => domain.require((**right).clone(), Property::Nonzero),
+                BinaryOp::Pow => match Rational::from_expr(right) {
+                    Some(n) if n.is_integer() && n.is_negative() => {
+                        domain.require((**left).clone(), Property::Nonzero)
+                    }
+                    Some(n) if n.is_integer() => {}
+                    // Even roots, such as x^(1/2) or x^(-3/4)
+                    Some(n) if n.denom() % 2 == 0 => domain.require(
+                        (**left).clone(),
+                        if n.is_negative() {
+                            Property::Positive
+                        } else {
+                            Property::Nonnegative
+                        },
+                    ),
+                    Some(n) if n.is_negative() => {
+                        domain.require((**left).clone(), Property::Nonzero)
+                    }
+                    Some(_) => {}
+                    None => domain.require((**left).clone(), Property::Positive),
+                },
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+
+    fn domain(input: &str) -> String {
+        parse(input).unwrap().domain().to_string()
+    }
+
+    fn intervals(input: &str) -> Option<Vec<String>> {
+        let domain = parse(input).unwrap().domain();
+        let intervals = domain.intervals('x')?;
+        Some(intervals.iter().map(|i| i.to_string()).collect())
+    }
+
+    #[test]
+    fn conditions_come_from_each_function() {
+        assert_eq!(domain("ln(x)"), "x > 0");
+        assert_eq!(domain("sqrt(x-1)"), "x-1 >= 0");
+        assert_eq!(domain("1/(x^2-4)"), "x^2-4 != 0");
+        assert_eq!(domain("tan(x)"), "cos(x) != 0");
+        assert_eq!(domain("asin(x)"), "1-x^2 >= 0");
+        assert_eq!(domain("x^(1/2) + x^(-1)"), "x > 0");
+        assert_eq!(domain("ln(y)*z^x"), "y > 0 and z > 0");
+    }
+
+    #[test]
+    fn conditions_are_merged() {
+        // `>= 0` and `!= 0` make `> 0`, which implies both
+        assert_eq!(domain("sqrt(x)/x"), "x > 0");
+        assert_eq!(domain("1/x + ln(x)"), "x > 0");
+        // Only the factors of a denominator need to be nonzero
+        assert_eq!(domain("1/((x-1)*(x+2))"), "x-1 != 0 and x+2 != 0");
+    }
+
+    #[test]
+    fn polynomial_conditions_are_solved() {
+        let expected = |intervals: &[&str]| Some(intervals.iter().map(|i| i.to_string()).collect());
+        assert_eq!(
+            intervals("ln(x^2-1)"),
+            expected(&["(-inf, -1)", "(1, inf)"])
+        );
+        assert_eq!(intervals("sqrt(4-x^2)"), expected(&["[-2, 2]"]));
+        assert_eq!(intervals("sqrt(x^3-x)"), expected(&["[-1, 0]", "[1, inf)"]));
+        assert_eq!(
+            intervals("1/(x^2-4)"),
+            expected(&["(-inf, -2)", "(-2, 2)", "(2, inf)"])
+        );
+        assert_eq!(intervals("sqrt(-x^2)"), expected(&["{0}"]));
+        assert_eq!(intervals("tan(x)"), None);
+        assert_eq!(intervals("ln(y)"), None);
+    }
+
+    #[test]
+    fn empty_and_unrestricted_domains() {
+        assert_eq!(domain("1/(x^2+1) + 2^x"), "everywhere");
+        assert!(parse("sqrt(x^2+1)").unwrap().domain().is_everywhere());
+        assert_eq!(intervals("x^3"), Some(vec!["(-inf, inf)".to_string()]));
+        assert_eq!(domain("ln(-x^2-1)"), "-x^2-1 > 0");
+        assert_eq!(intervals("ln(-x^2-1)"), Some(Vec::new()));
+        assert_eq!(intervals("ln(x)+sqrt(-x)"), Some(Vec::new()));
+    }
+}
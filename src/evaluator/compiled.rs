@@ -0,0 +1,142 @@
+//! Expressions compiled for fast repeated evaluation in one variable.
+//!
+//! ```
+//! use std::collections::HashMap;
+//! use derivative_calculator::parse;
+//!
+//! let expr = parse("a*x^2 + sin(a)")?;
+//! let compiled = expr.compile('x', &HashMap::from([('a', 2.0)])).unwrap();
+//! assert_eq!(compiled.eval(3.0), 18.0 + 2f64.sin());
+//! # Ok::<(), derivative_calculator::Error>(())
+//! ```
+
+use std::collections::HashMap;
+
+use super::EvalError;
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+
+/// One step of the stack machine a [`CompiledExpr`] runs on.
+#[derive(Debug, Clone, Copy)]
+enum Instruction {
+    Const(f64),
+    Var,
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Powi(i32),
+    Call(fn(f64) -> f64),
+}
+
+/// An expression in one variable turned into a flat program. Every part that doesn't depend on
+/// the variable is evaluated once, when compiling.
+#[derive(Debug, Clone)]
+pub struct CompiledExpr {
+    code: Vec<Instruction>,
+    stack_size: usize,
+}
+
+impl Expr {
+    /// Compiles the expression as a function of `var`, taking every other symbol from `values`.
+    /// Fails like [`Expr::eval`] on unbound symbols and unknown functions.
+    pub fn compile(
+        &self,
+        var: char,
+        values: &HashMap<char, f64>,
+    ) -> Result<CompiledExpr, EvalError> {
+        let mut compiled = CompiledExpr {
+            code: Vec::new(),
+            stack_size: 0,
+        };
+        compiled.emit(self, var, values, 0)?;
+        Ok(compiled)
+    }
+}
+
+impl CompiledExpr {
+    /// Value of the expression at `var = x`.
+    pub fn eval(&self, x: f64) -> f64 {
+        let mut stack = Vec::with_capacity(self.stack_size);
+        for instruction in &self.code {
+            let value = match *instruction {
+                Instruction::Const(c) => c,
+                Instruction::Var => x,
+                Instruction::Call(function) => function(stack.pop().unwrap_or(f64::NAN)),
+                Instruction::Neg => -stack.pop().unwrap_or(f64::NAN),
+                Instruction::Powi(n) => stack.pop().unwrap_or(f64::NAN).powi(n),
+                binary => {
+                    let r = stack.pop().unwrap_or(f64::NAN);
+                    let l = stack.pop().unwrap_or(f64::NAN);
+                    match binary {
+                        Instruction::Add => l + r,
+                        Instruction::Sub => l - r,
+                        Instruction::Mul => l * r,
+                        Instruction::Div => l / r,
+                        _ => l.powf(r),
+                    }
+                }
+            };
+            stack.push(value);
+        }
+        stack.pop().unwrap_or(f64::NAN)
+    }
+
+    /// Appends the code for `expr`, which runs with `depth` values already on the stack.
+    fn emit(
+        &mut self,
+        expr: &Expr,
+        var: char,
+        values: &HashMap<char, f64>,
+        depth: usize,
+    ) -> Result<(), EvalError> {
+        self.stack_size = self.stack_size.max(depth + 1);
+        if !expr.contains_variable(var) {
+            self.code.push(Instruction::Const(expr.eval(values)?));
+            return Ok(());
+        }
+        match expr {
+            Expr::Call { name, arg } => {
+                let function: fn(f64) -> f64 = match name.as_str() {
+                    "sin" => f64::sin,
+                    "cos" => f64::cos,
+                    "tan" => f64::tan,
+                    "asin" => f64::asin,
+                    "atan" => f64::atan,
+                    "exp" => f64::exp,
+                    "ln" => f64::ln,
+                    "sqrt" => f64::sqrt,
+                    _ => return Err(EvalError::UnknownFunction(name.clone())),
+                };
+                self.emit(arg, var, values, depth)?;
+                self.code.push(Instruction::Call(function));
+            }
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+            } => {
+                self.emit(expr, var, values, depth)?;
+                self.code.push(Instruction::Neg);
+            }
+            Expr::BinaryOp { op, left, right } => {
+                self.emit(left, var, values, depth)?;
+                if let (BinaryOp::Pow, Expr::Number(n)) = (op, right.as_ref()) {
+                    self.code.push(Instruction::Powi(*n));
+                    return Ok(());
+                }
+                self.emit(right, var, values, depth + 1)?;
+                self.code.push(match op {
+                    BinaryOp::Add => Instruction::Add,
+                    BinaryOp::Sub => Instruction::Sub,
+                    BinaryOp::Mul => Instruction::Mul,
+                    BinaryOp::Div => Instruction::Div,
+                    BinaryOp::Pow => Instruction::Pow,
+                });
+            }
+            // The only leaf depending on the variable is the variable itself
+            _ => self.code.push(Instruction::Var),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+pub mod compiled;
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+
+/// Reasons an expression can't be evaluated to a number.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum EvalError {
+    /// The symbol has no value assigned.
+    UnboundSymbol(String),
+    /// The function is neither built in nor defined.
+    UnknownFunction(String),
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EvalError::UnboundSymbol(name) => write!(f, "no value given for '{name}'"),
+            EvalError::UnknownFunction(name) => write!(f, "unknown function '{name}'"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+impl Expr {
+    /// Evaluates the expression with floating point arithmetic, looking up every variable and
+    /// constant in `values`. Division by zero follows IEEE 754 and yields an infinity or NaN.
+    pub fn eval(&self, values: &HashMap<char, f64>) -> Result<f64, EvalError> {
+        let value = match self {
+            Expr::Number(n) => *n as f64,
+            Expr::Variable(c) | Expr::Constant(c) => *values
+                .get(c)
+                .ok_or_else(|| EvalError::UnboundSymbol(c.to_string()))?,
+            Expr::Symbol(name) => return Err(EvalError::UnboundSymbol(name.clone())),
+            Expr::Call { name, arg } => {
+                let arg = arg.eval(values)?;
+                match name.as_str() {
+                    "sin" => arg.sin(),
+                    "cos" => arg.cos(),
+                    "tan" => arg.tan(),
+                    "asin" => arg.asin(),
+                    "atan" => arg.atan(),
+                    "exp" => arg.exp(),
+                    "ln" => arg.ln(),
+                    "sqrt" => arg.sqrt(),
+                    _ => return Err(EvalError::UnknownFunction(name.clone())),
+                }
+            }
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+            } => -expr.eval(values)?,
+            Expr::BinaryOp { op, left, right } => {
+                let (l, r) = (left.eval(values)?, right.eval(values)?);
+                match op {
+                    BinaryOp::Add => l + r,
+                    BinaryOp::Sub => l - r,
+                    BinaryOp::Mul => l * r,
+                    BinaryOp::Div => l / r,
+                    BinaryOp::Pow => l.powf(r),
+                }
+            }
+        };
+        Ok(value)
+    }
+}
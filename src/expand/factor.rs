@@ -0,0 +1,255 @@
+use std::fmt::Display;
+
+use crate::algebra::{highest_power, neg, negate_first_factor, signed_product};
+use crate::parser::ast::{BinaryOp, Expr};
+use crate::polynomial::{Polynomial, MAX_DEGREE};
+use crate::rational::{gcd, Rational};
+
+use super::{expand_terms, factor_key, terms_to_expr, Term};
+
+/// Reasons an expression can't be factored.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum FactorError {
+    /// The expression holds a power of higher degree than [`MAX_DEGREE`].
+    DegreeTooHigh(u32),
+}
+
+impl Display for FactorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FactorError::DegreeTooHigh(degree) => write!(
+                f,
+                "degree {degree} is above the limit of {MAX_DEGREE} for factoring"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FactorError {}
+
+impl Expr {
+    /// Writes the expanded expression as a product: the rational content and the powers
+    /// common to every term come out first, then a polynomial in a single letter is split by
+    /// square-free factorization and its rational roots. Sums in other variables are kept
+    /// whole, and sums inside a power or a denominator are factored in turn.
+    ///
+    /// Leaves the expression as is when a coefficient overflows.
+    ///
+    /// ```
+    /// use derivative_calculator::parse;
+    ///
+    /// assert_eq!(parse("2x^2-2")?.factor().pretty(), "2*(x-1)*(x+1)");
+    /// assert_eq!(parse("x^4-2x^3+x^2")?.factor().pretty(), "x^2*(x-1)^2");
+    /// assert_eq!(parse("x^3/2+x/2")?.factor().pretty(), "x*(x^2+1)/2");
+    /// assert_eq!(parse("a*x+a*y")?.factor().pretty(), "a*(x+y)");
+    /// assert_eq!(parse("1/(x^2-1)")?.factor().pretty(), "1/((x-1)*(x+1))");
+    /// assert_eq!(parse("x/(x^2+2x+1)")?.factor().pretty(), "x/(x+1)^2");
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn factor(&self) -> Expr {
+        expand_terms(self)
+            .and_then(|terms| factor_terms(&terms))
+            .and_then(|(coefficient, factors)| signed_product(coefficient, factors))
+            .map(|(negative, product)| match negative {
+                true => negate_first_factor(&product).unwrap_or_else(|| neg(&product)),
+                false => product,
+            })
+            .unwrap_or_else(|| self.clone())
+    }
+
+    /// Like [`Expr::factor`], but refuses powers of a degree above [`MAX_DEGREE`] instead of
+    /// leaving them unfactored.
+    ///
+    /// ```
+    /// use derivative_calculator::{expand::FactorError, parse};
+    ///
+    /// assert_eq!(parse("x^2-1")?.try_factor().unwrap().pretty(), "(x-1)*(x+1)");
+    /// assert_eq!(
+    ///     parse("x^1000000-1")?.try_factor(),
+    ///     Err(FactorError::DegreeTooHigh(1000000))
+    /// );
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn try_factor(&self) -> Result<Expr, FactorError> {
+        match highest_power(self) {
+            degree if degree as usize > MAX_DEGREE => Err(FactorError::DegreeTooHigh(degree)),
+            _ => Ok(self.factor()),
+        }
+    }
+}
+
+/// The sum of `terms` as a coefficient times powers of factors.
+fn factor_terms(terms: &[Term]) -> Option<(Rational, Vec<(Expr, i32)>)> {
+    match terms {
+        [] => Some((Rational::ZERO, Vec::new())),
+        [term] => factor_term(term),
+        [leading, ..] => {
+            let content = content(terms, leading.coefficient.is_negative())?;
+            let common = common_powers(terms);
+            let remainder: Vec<Term> = terms
+                .iter()
+                .map(|term| {
+                    let mut factors = term.factors.clone();
+                    for (base, exponent) in &common {
+                        if let Some((_, e)) = factors.iter_mut().find(|(b, _)| b == base) {
+                            *e -= exponent;
+                        } else {
+                            factors.push((base.clone(), -exponent));
+                        }
+                    }
+                    factors.retain(|(_, exponent)| *exponent != 0);
+                    Some(Term {
+                        coefficient: term.coefficient.checked_div(content)?,
+                        factors,
+                    })
+                })
+                .collect::<Option<_>>()?;
+            let (coefficient, mut factors) = factor_term(&Term {
+                coefficient: content,
+                factors: common,
+            })?;
+            let coefficient = match univariate(&remainder) {
+                Some(polynomial) => {
+                    let (c, parts) = polynomial.factor()?;
+                    for (part, multiplicity) in parts {
+                        factors.push((part.to_expr()?, i32::try_from(multiplicity).ok()?));
+                    }
+                    coefficient.checked_mul(c)?
+                }
+                None => {
+                    factors.push((terms_to_expr(&remainder)?, 1));
+                    coefficient
+                }
+            };
+            Some((coefficient, factors))
+        }
+    }
+}
+
+/// A single term with the sums among its bases factored.
+fn factor_term(term: &Term) -> Option<(Rational, Vec<(Expr, i32)>)> {
+    let mut coefficient = term.coefficient;
+    let mut factors = Vec::new();
+    for (base, exponent) in &term.factors {
+        let is_sum = matches!(
+            base,
+            Expr::BinaryOp {
+                op: BinaryOp::Add | BinaryOp::Sub,
+                ..
+            }
+        );
+        if !is_sum {
+            factors.push((base.clone(), *exponent));
+            continue;
+        }
+        let (c, parts) = factor_terms(&expand_terms(base)?)?;
+        coefficient = coefficient.checked_mul(c.checked_pow(*exponent)?)?;
+        for (part, e) in parts {
+            factors.push((part, e.checked_mul(*exponent)?));
+        }
+    }
+    Some((coefficient, factors))
+}
+
+/// Largest rational dividing every coefficient to an integer, negative when the leading term
+/// is.
+fn content(terms: &[Term], negative: bool) -> Option<Rational> {
+    let numer = terms
+        .iter()
+        .fold(0, |acc, term| gcd(acc, term.coefficient.numer() as i128));
+    let mut denom = 1i128;
+    for term in terms {
+        let d = term.coefficient.denom() as i128;
+        denom = denom.checked_mul(d / gcd(denom, d))?;
+    }
+    let content = Rational::new(i64::try_from(numer).ok()?, i64::try_from(denom).ok()?)?;
+    Some(if negative { -content } else { content })
+}
+
+/// Bases with their smallest exponent over all terms, a missing base counting as exponent
+/// zero, where that's not zero.
+fn common_powers(terms: &[Term]) -> Vec<(Expr, i32)> {
+    let mut bases: Vec<&Expr> = Vec::new();
+    for term in terms {
+        for (base, _) in &term.factors {
+            if !bases.contains(&base) {
+                bases.push(base);
+            }
+        }
+    }
+    let mut common: Vec<(Expr, i32)> = bases
+        .into_iter()
+        .map(|base| {
+            let exponent = terms
+                .iter()
+                .map(|term| {
+                    term.factors
+                        .iter()
+                        .find(|(b, _)| b == base)
+                        .map_or(0, |(_, e)| *e)
+                })
+                .min()
+                .unwrap_or(0);
+            (base.clone(), exponent)
+        })
+        .filter(|(_, exponent)| *exponent != 0)
+        .collect();
+    common.sort_by_cached_key(|(base, _)| factor_key(base));
+    common
+}
+
+/// The terms as a polynomial when they only hold non-negative powers of one letter, up to
+/// [`MAX_DEGREE`].
+fn univariate(terms: &[Term]) -> Option<Polynomial> {
+    let mut var = None;
+    let mut coefficients = Vec::new();
+    for term in terms {
+        let degree = match term.factors.as_slice() {
+            [] => 0,
+            [(Expr::Variable(c) | Expr::Constant(c), e)]
+                if *e > 0 && *var.get_or_insert(*c) == *c =>
+            {
+                *e as usize
+            }
+            _ => return None,
+        };
+        if degree > MAX_DEGREE {
+            return None;
+        }
+        if coefficients.len() <= degree {
+            coefficients.resize(degree + 1, Rational::ZERO);
+        }
+        coefficients[degree] = term.coefficient;
+    }
+    Some(Polynomial::new(var?, coefficients))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FactorError;
+    use crate::parse;
+
+    fn factored(input: &str) -> String {
+        parse(input).unwrap().factor().pretty()
+    }
+
+    #[test]
+    fn rational_roots_and_square_free_parts() {
+        assert_eq!(factored("x^3-x"), "x*(x-1)*(x+1)");
+        assert_eq!(factored("x^6-1"), "(x-1)*(x+1)*(x^4+x^2+1)");
+    }
+
+    #[test]
+    fn huge_degrees_are_left_alone() {
+        assert_eq!(factored("x^1000000-1"), "x^1000000-1");
+        assert_eq!(
+            parse("x^1000000-1").unwrap().try_factor(),
+            Err(FactorError::DegreeTooHigh(1000000))
+        );
+        assert_eq!(
+            parse("1/(x^2000+1)").unwrap().try_factor(),
+            Err(FactorError::DegreeTooHigh(2000))
+        );
+    }
+}
@@ -0,0 +1,269 @@
+mod factor;
+
+pub use factor::FactorError;
+
+use std::cmp::Reverse;
+
+use crate::algebra::{pow, signed_product, signed_sum};
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+use crate::rational::Rational;
+
+/// Largest number of terms a multinomial expansion may produce before the power is left as is.
+const MAX_TERMS: usize = 2000;
+
+/// A rational multiple of a product of powers of distinct bases, none of them a number. The
+/// factors are sorted by [`factor_key`] and no exponent is zero.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Term {
+    pub(crate) coefficient: Rational,
+    pub(crate) factors: Vec<(Expr, i32)>,
+}
+
+impl Expr {
+    /// Multiplies out products and integer powers of sums and collects like terms:
+    /// `(x+1)*(x-1)` becomes `x^2-1` and `(a+b)^3` uses the multinomial expansion. Function
+    /// arguments are expanded too, sums in a denominator stay together.
+    ///
+    /// Leaves the expression as is when a coefficient overflows.
+    ///
+    /// ```
+    /// use derivative_calculator::parse;
+    ///
+    /// assert_eq!(parse("(x+1)*(x-1)")?.expand().pretty(), "x^2-1");
+    /// assert_eq!(parse("(x+a)^3")?.expand().pretty(), "x^3+3*a*x^2+3a^2*x+a^3");
+    /// assert_eq!(parse("(2x+4)/(2x)")?.expand().pretty(), "1+2/x");
+    /// assert_eq!(parse("sin((x+1)^2)/(x+2)")?.expand().pretty(), "sin(x^2+2x+1)/(x+2)");
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn expand(&self) -> Expr {
+        match expand_terms(self) {
+            Some(terms) => terms_to_expr(&terms).unwrap_or_else(|| self.clone()),
+            None => self.clone(),
+        }
+    }
+}
+
+/// The terms of the fully expanded expression, `None` on overflow.
+pub(crate) fn expand_terms(expr: &Expr) -> Option<Vec<Term>> {
+    let terms = match expr {
+        Expr::Number(n) => vec![constant(Rational::integer(*n as i64))],
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => scale(&expand_terms(expr)?, -Rational::ONE)?,
+        Expr::BinaryOp { op, left, right } => {
+            let left = expand_terms(left)?;
+            match op {
+                BinaryOp::Add => [left, expand_terms(right)?].concat(),
+                BinaryOp::Sub => [left, scale(&expand_terms(right)?, -Rational::ONE)?].concat(),
+                BinaryOp::Mul => multiply(&left, &expand_terms(right)?)?,
+                BinaryOp::Div => multiply(&left, &power(&expand_terms(right)?, -1)?)?,
+                BinaryOp::Pow => match **right {
+                    Expr::Number(n) => power(&left, n)?,
+                    _ => vec![atom(pow(&terms_to_expr(&left)?, &right.expand()), 1)],
+                },
+            }
+        }
+        Expr::Call { name, arg } => vec![atom(
+            Expr::Call {
+                name: name.clone(),
+                arg: Box::new(arg.expand()),
+            },
+            1,
+        )],
+        _ => vec![atom(expr.clone(), 1)],
+    };
+    collect(terms)
+}
+
+/// Adds up terms with the same factors, drops the ones that cancel and puts the rest in
+/// order: highest total degree in single letters first, then highest degree in `x`, with the
+/// constant last among its degree.
+pub(crate) fn collect(terms: Vec<Term>) -> Option<Vec<Term>> {
+    let mut collected: Vec<Term> = Vec::new();
+    for term in terms {
+        match collected.iter_mut().find(|t| t.factors == term.factors) {
+            Some(existing) => {
+                existing.coefficient = existing.coefficient.checked_add(term.coefficient)?
+            }
+            None => collected.push(term),
+        }
+    }
+    collected.retain(|term| !term.coefficient.is_zero());
+    collected.sort_by_cached_key(|term| {
+        let degree = |letter: Option<char>| -> i32 {
+            term.factors
+                .iter()
+                .filter(|(base, _)| match (base, letter) {
+                    (Expr::Variable(c) | Expr::Constant(c), Some(letter)) => *c == letter,
+                    (Expr::Variable(_) | Expr::Constant(_), None) => true,
+                    _ => false,
+                })
+                .map(|(_, exponent)| *exponent)
+                .sum()
+        };
+        let factors: Vec<String> = term.factors.iter().map(|(b, _)| b.to_canonical()).collect();
+        (
+            Reverse(degree(None)),
+            Reverse(degree(Some('x'))),
+            factors.is_empty(),
+            factors,
+        )
+    });
+    Some(collected)
+}
+
+/// The sum of `terms`, `None` if a coefficient doesn't fit an `i32`.
+pub(crate) fn terms_to_expr(terms: &[Term]) -> Option<Expr> {
+    let terms = terms
+        .iter()
+        .map(|term| signed_product(term.coefficient, term.factors.clone()))
+        .collect::<Option<Vec<_>>>()?;
+    Some(signed_sum(terms))
+}
+
+/// `coefficient` times `factors`, with the factors put in order.
+pub(crate) fn term(coefficient: Rational, mut factors: Vec<(Expr, i32)>) -> Term {
+    factors.sort_by_cached_key(|(base, _)| factor_key(base));
+    Term {
+        coefficient,
+        factors,
+    }
+}
+
+fn constant(c: Rational) -> Term {
+    Term {
+        coefficient: c,
+        factors: Vec::new(),
+    }
+}
+
+/// `base^exponent` as a term. A negative number comes out as its magnitude and a sign.
+fn atom(base: Expr, exponent: i32) -> Term {
+    match base {
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } if exponent % 2 != 0 => Term {
+            coefficient: -Rational::ONE,
+            ..atom(*expr, exponent)
+        },
+        base => Term {
+            coefficient: Rational::ONE,
+            factors: vec![(base, exponent)],
+        },
+    }
+}
+
+fn scale(terms: &[Term], factor: Rational) -> Option<Vec<Term>> {
+    terms
+        .iter()
+        .map(|term| {
+            Some(Term {
+                coefficient: term.coefficient.checked_mul(factor)?,
+                factors: term.factors.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Every term of `left` times every term of `right`.
+fn multiply(left: &[Term], right: &[Term]) -> Option<Vec<Term>> {
+    let mut product = Vec::with_capacity(left.len() * right.len());
+    for a in left {
+        for b in right {
+            product.push(multiply_terms(a, b)?);
+        }
+    }
+    collect(product)
+}
+
+fn multiply_terms(a: &Term, b: &Term) -> Option<Term> {
+    let mut factors = a.factors.clone();
+    for (base, exponent) in &b.factors {
+        match factors.iter_mut().find(|(existing, _)| existing == base) {
+            Some((_, existing)) => *existing = existing.checked_add(*exponent)?,
+            None => factors.push((base.clone(), *exponent)),
+        }
+    }
+    factors.retain(|(_, exponent)| *exponent != 0);
+    factors.sort_by_cached_key(|(base, _)| factor_key(base));
+    Some(Term {
+        coefficient: a.coefficient.checked_mul(b.coefficient)?,
+        factors,
+    })
+}
+
+/// Order of factors within a term: letters alphabetically, then symbols, function calls and
+/// anything else.
+fn factor_key(base: &Expr) -> (u8, String) {
+    let rank = match base {
+        Expr::Variable(_) | Expr::Constant(_) => 0,
+        Expr::Symbol(_) => 1,
+        Expr::Call { .. } => 2,
+        _ => 3,
+    };
+    (rank, base.to_canonical())
+}
+
+/// A sum raised to an integer power. A single term is raised factor by factor, a sum by the
+/// multinomial theorem when `n` is positive, otherwise it stays a power of the sum.
+fn power(terms: &[Term], n: i32) -> Option<Vec<Term>> {
+    match terms {
+        [] if n > 0 => Some(Vec::new()),
+        [] => None,
+        [term] => Some(vec![Term {
+            coefficient: term.coefficient.checked_pow(n)?,
+            factors: term
+                .factors
+                .iter()
+                .map(|(base, exponent)| Some((base.clone(), exponent.checked_mul(n)?)))
+                .collect::<Option<_>>()?,
+        }]),
+        _ if n == 0 => Some(vec![constant(Rational::ONE)]),
+        _ if n > 0 && multinomial_count(terms.len(), n as usize) <= MAX_TERMS => {
+            multinomial(terms, n as u32)
+        }
+        _ => Some(vec![atom(terms_to_expr(terms)?, n)]),
+    }
+}
+
+/// Number of terms in the multinomial expansion of a sum of `k` terms to the power `n`,
+/// `C(n+k-1, k-1)`, saturating.
+fn multinomial_count(k: usize, n: usize) -> usize {
+    (1..k).fold(1usize, |count, i| count.saturating_mul(n + i) / i)
+}
+
+/// `(t_1 + ... + t_k)^n` as the sum over `j_1 + ... + j_k = n` of
+/// `n!/(j_1!...j_k!) * t_1^j_1 ... t_k^j_k`, built up one binomial choice at a time.
+fn multinomial(terms: &[Term], n: u32) -> Option<Vec<Term>> {
+    fn choose(terms: &[Term], remaining: u32, partial: Term, out: &mut Vec<Term>) -> Option<()> {
+        let [first, rest @ ..] = terms else {
+            if remaining == 0 {
+                out.push(partial);
+            }
+            return Some(());
+        };
+        // The last term takes whatever is left of the exponent
+        let smallest = if rest.is_empty() { remaining } else { 0 };
+        let mut binomial = Rational::ONE;
+        for j in 0..=remaining {
+            if j > 0 {
+                binomial = binomial
+                    .checked_mul(Rational::integer((remaining - j + 1) as i64))?
+                    .checked_div(Rational::integer(j as i64))?;
+            }
+            if j < smallest {
+                continue;
+            }
+            let power = power(std::slice::from_ref(first), j as i32)?.pop()?;
+            let mut term = multiply_terms(&partial, &power)?;
+            term.coefficient = term.coefficient.checked_mul(binomial)?;
+            choose(rest, remaining - j, term, out)?;
+        }
+        Some(())
+    }
+    let mut out = Vec::new();
+    choose(terms, n, constant(Rational::ONE), &mut out)?;
+    collect(out)
+}
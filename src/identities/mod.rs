@@ -0,0 +1,278 @@
+//! Identities of the elementary functions, applied as rewrite rules.
+//!
+//! Each [`Family`] is a rules file next to this one (see [`crate::rewrite`] for the syntax).
+//! Identities that read both ways come in two files, and the [`Strategy`] picks one.
+
+use std::sync::OnceLock;
+
+use crate::parser::ast::Expr;
+use crate::rewrite::RuleSet;
+
+/// Rules every pass starts with, putting products and differences in the forms the
+/// identities are written for.
+const COMMON_RULES: &str = include_str!("common.rules");
+/// Rules turning the forms of [`COMMON_RULES`] back into differences.
+const TIDY_RULES: &str = include_str!("tidy.rules");
+
+/// A group of related identities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Family {
+    /// `sin(x)^2 + cos(x)^2 = 1` and its rearrangements.
+    Pythagorean,
+    /// `exp(ln(x)) = x` for positive `x`, `ln(exp(x)) = x` for real `x` and
+    /// `exp(a)*exp(b) = exp(a+b)`.
+    ExpLog,
+    /// `ln(a*b) = ln(a) + ln(b)`, `ln(a/b) = ln(a) - ln(b)` and `ln(a^n) = n*ln(a)`.
+    Logarithm,
+    /// `sin(2x) = 2sin(x)cos(x)` and `cos(2x) = cos(x)^2 - sin(x)^2`.
+    DoubleAngle,
+}
+
+impl Family {
+    pub const ALL: [Family; 4] = [
+        Family::Pythagorean,
+        Family::ExpLog,
+        Family::Logarithm,
+        Family::DoubleAngle,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Family::Pythagorean => "pythagorean",
+            Family::ExpLog => "exp-log",
+            Family::Logarithm => "log",
+            Family::DoubleAngle => "double-angle",
+        }
+    }
+
+    /// Looks up a family by its [`Family::name`].
+    pub fn from_name(name: &str) -> Option<Family> {
+        Self::ALL.into_iter().find(|family| family.name() == name)
+    }
+
+    /// Text of the rules of the family under `strategy`.
+    pub fn rules(&self, strategy: Strategy) -> Vec<&'static str> {
+        let (both, contract, expand) = match self {
+            Family::Pythagorean => (Some(include_str!("pythagorean.rules")), None, None),
+            Family::ExpLog => (
+                Some(include_str!("exp_log.rules")),
+                Some(include_str!("exp_log.contract.rules")),
+                Some(include_str!("exp_log.expand.rules")),
+            ),
+            Family::Logarithm => (
+                None,
+                Some(include_str!("logarithm.contract.rules")),
+                Some(include_str!("logarithm.expand.rules")),
+            ),
+            Family::DoubleAngle => (
+                None,
+                Some(include_str!("double_angle.contract.rules")),
+                Some(include_str!("double_angle.expand.rules")),
+            ),
+        };
+        let directed = match strategy {
+            Strategy::Contract => contract,
+            Strategy::Expand => expand,
+        };
+        both.into_iter().chain(directed).collect()
+    }
+
+    /// The parsed [`Family::rules`], parsed once for every family and strategy.
+    fn rule_set(&self, strategy: Strategy) -> &'static RuleSet {
+        static PARSED: OnceLock<Vec<RuleSet>> = OnceLock::new();
+        let parsed = PARSED.get_or_init(|| {
+            Family::ALL
+                .iter()
+                .flat_map(|family| Strategy::ALL.map(|strategy| family.rules(strategy)))
+                .map(|texts| {
+                    let mut rules = RuleSet::default();
+                    for text in texts {
+                        rules.extend(parse_rules(text));
+                    }
+                    rules
+                })
+                .collect()
+        });
+        // `ALL` lists both in declaration order
+        &parsed[*self as usize * Strategy::ALL.len() + strategy as usize]
+    }
+}
+
+/// Which way the identities that read both ways are applied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Strategy {
+    /// Toward fewer function calls: `2*sin(x)*cos(x)` becomes `sin(2*x)` and `ln(a)+ln(b)`
+    /// becomes `ln(a*b)`.
+    #[default]
+    Contract,
+    /// Toward functions of simpler arguments: `sin(2*x)` becomes `2*sin(x)*cos(x)` and
+    /// `ln(a*b)` becomes `ln(a)+ln(b)`.
+    Expand,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 2] = [Strategy::Contract, Strategy::Expand];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Contract => "contract",
+            Strategy::Expand => "expand",
+        }
+    }
+
+    /// Looks up a strategy by its [`Strategy::name`].
+    pub fn from_name(name: &str) -> Option<Strategy> {
+        Self::ALL
+            .into_iter()
+            .find(|strategy| strategy.name() == name)
+    }
+}
+
+/// Which identities [`Expr::simplify_identities`] applies, and how.
+///
+/// ```
+/// use derivative_calculator::identities::{Family, Identities, Strategy};
+/// use derivative_calculator::parse;
+///
+/// let contract = Identities::default();
+/// let derivative = parse("sin(x)*cos(x)")?.diff('x')?.simplify();
+/// assert_eq!(derivative.simplify_identities(&contract).pretty(), "cos(2x)");
+/// let expr = parse("3*sin(x)^2 + 3*cos(x)^2 + exp(ln(2))")?;
+/// assert_eq!(expr.simplify_identities(&contract).pretty(), "5");
+/// // exp(ln(x)) = x only where ln(x) is defined
+/// assert_eq!(parse("exp(ln(x))")?.simplify_identities(&contract).pretty(), "exp(ln(x))");
+/// assert_eq!(parse("ln(x) + ln(y)")?.simplify_identities(&contract).pretty(), "ln(x*y)");
+///
+/// // Expanding logarithms is only valid for positive arguments
+/// let mut expand = Identities {
+///     families: vec![Family::Logarithm, Family::DoubleAngle],
+///     strategy: Strategy::Expand,
+///     ..Identities::default()
+/// };
+/// assert_eq!(parse("ln(x^2)")?.simplify_identities(&expand).pretty(), "ln(x^2)");
+/// assert_eq!(parse("ln(4^x)")?.simplify_identities(&expand).pretty(), "x*ln(4)");
+/// expand.assume_positive = true;
+/// assert_eq!(parse("ln(x^2)")?.simplify_identities(&expand).pretty(), "2*ln(x)");
+/// assert_eq!(
+///     parse("sin(2*x)")?.simplify_identities(&expand).pretty(),
+///     "2*sin(x)*cos(x)"
+/// );
+/// # Ok::<(), derivative_calculator::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identities {
+    pub families: Vec<Family>,
+    pub strategy: Strategy,
+    /// Take every expression to be positive, so that rules such as `ln(x^2) = 2*ln(x)`,
+    /// which fail for negative `x`, apply to more than positive numbers.
+    pub assume_positive: bool,
+}
+
+impl Default for Identities {
+    fn default() -> Self {
+        Identities {
+            families: Family::ALL.to_vec(),
+            strategy: Strategy::default(),
+            assume_positive: false,
+        }
+    }
+}
+
+impl Identities {
+    /// The rules of the chosen families after those of [`Expr::simplify`].
+    pub fn rules(&self) -> RuleSet {
+        static COMMON: OnceLock<RuleSet> = OnceLock::new();
+        let mut rules = RuleSet::builtin().clone();
+        rules.extend(COMMON.get_or_init(|| parse_rules(COMMON_RULES)).clone());
+        for family in &self.families {
+            rules.extend(family.rule_set(self.strategy).clone());
+        }
+        if self.assume_positive {
+            rules.assuming_positive()
+        } else {
+            rules
+        }
+    }
+}
+
+impl Expr {
+    /// Simplifies with the identities of the elementary functions, see [`Identities`].
+    pub fn simplify_identities(&self, identities: &Identities) -> Expr {
+        static TIDY: OnceLock<RuleSet> = OnceLock::new();
+        let tidy = TIDY.get_or_init(|| {
+            let mut tidy = RuleSet::builtin().clone();
+            tidy.extend(parse_rules(TIDY_RULES));
+            tidy
+        });
+        self.rewrite(&identities.rules()).rewrite(tidy)
+    }
+}
+
+fn parse_rules(text: &str) -> RuleSet {
+    RuleSet::parse(text).expect("the identity rules are valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Family, Identities, Strategy};
+    use crate::parse;
+
+    fn simplified(input: &str, identities: &Identities) -> String {
+        parse(input)
+            .unwrap()
+            .simplify_identities(identities)
+            .pretty()
+    }
+
+    fn exp_log(assume_positive: bool) -> Identities {
+        Identities {
+            families: vec![Family::ExpLog],
+            assume_positive,
+            ..Identities::default()
+        }
+    }
+
+    #[test]
+    fn exp_of_ln_needs_a_positive_argument() {
+        assert_eq!(simplified("exp(ln(x))", &exp_log(false)), "exp(ln(x))");
+        assert_eq!(simplified("exp(ln(-2))", &exp_log(false)), "exp(ln(-2))");
+        assert_eq!(simplified("exp(ln(3))", &exp_log(false)), "3");
+        assert_eq!(simplified("exp(ln(x))", &exp_log(true)), "x");
+        assert_eq!(simplified("exp(2*ln(x))", &exp_log(true)), "x^2");
+    }
+
+    #[test]
+    fn ln_of_exp_needs_a_real_argument() {
+        assert_eq!(simplified("ln(exp(x))", &exp_log(false)), "ln(exp(x))");
+        assert_eq!(simplified("ln(exp(3))", &exp_log(false)), "3");
+        assert_eq!(simplified("ln(exp(x))", &exp_log(true)), "x");
+    }
+
+    #[test]
+    fn the_identities_level_keeps_the_domain() {
+        let level = crate::SimplifyLevel::Identities;
+        assert_eq!(
+            level.apply(&parse("exp(ln(x))").unwrap()).pretty(),
+            "exp(ln(x))"
+        );
+    }
+
+    #[test]
+    fn every_family_parses_under_both_strategies() {
+        for family in Family::ALL {
+            for strategy in Strategy::ALL {
+                let texts = family.rules(strategy).len();
+                assert_eq!(family.rule_set(strategy).is_empty(), texts == 0);
+            }
+        }
+    }
+
+    #[test]
+    fn pythagorean_identities_contract() {
+        let identities = Identities::default();
+        assert_eq!(simplified("sin(x)^2+cos(x)^2", &identities), "1");
+        assert_eq!(simplified("2*sin(x)*cos(x)", &identities), "sin(2x)");
+    }
+}
@@ -0,0 +1,700 @@
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::algebra::{
+    add, call, cancel_factors, div, factors, fraction, mul, neg, node_count, parameters, pow,
+    square, sub, tidy, variable,
+};
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+use crate::polynomial::Polynomial;
+use crate::rational::Rational;
+
+/// How deeply substitutions and integration by parts may nest.
+const MAX_DEPTH: u32 = 6;
+/// Upper bound on the number of integrands tried, so hopeless cases give up quickly.
+const MAX_STEPS: u32 = 2000;
+/// Subexpressions tried as the new variable of a substitution, largest first.
+const MAX_SUBSTITUTIONS: usize = 12;
+/// Letters tried, in order, as the new variable of a substitution.
+const SUBSTITUTION_LETTERS: [char; 8] = ['u', 'v', 'w', 't', 's', 'z', 'y', 'q'];
+
+/// Reasons an antiderivative can't be given.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum IntegrateError {
+    /// None of the methods finds an antiderivative of this integrand. Many integrals, such as
+    /// that of `exp(x^2)`, have no closed form in elementary functions at all.
+    NoClosedForm(Expr),
+    /// A candidate was found, but its derivative doesn't agree with the integrand.
+    Unverified(Expr),
+}
+
+impl Display for IntegrateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IntegrateError::NoClosedForm(expr) => {
+                write!(
+                    f,
+                    "no closed form found for the integral of '{}'",
+                    expr.pretty()
+                )
+            }
+            IntegrateError::Unverified(expr) => write!(
+                f,
+                "candidate antiderivative '{}' doesn't differentiate back to the integrand",
+                expr.pretty()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IntegrateError {}
+
+impl Expr {
+    /// An antiderivative with respect to `var`, see [`integrate`].
+    ///
+    /// ```
+    /// use derivative_calculator::parse;
+    ///
+    /// let integral = parse("3x^2 + cos(x)")?.integrate('x').unwrap();
+    /// assert_eq!(integral.pretty(), "x^3+sin(x)");
+    /// assert_eq!(parse("2x*exp(x^2)")?.integrate('x').unwrap().pretty(), "exp(x^2)");
+    /// assert!(parse("exp(x^2)")?.integrate('x').is_err());
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn integrate(&self, var: char) -> Result<Expr, IntegrateError> {
+        integrate(self, var)
+    }
+}
+
+/// Finds an antiderivative of `expr` with respect to `var`, without the constant of
+/// integration. Every other symbol is treated as a constant.
+///
+/// Sums and constant factors are split off, then the first method that applies wins:
+/// the power rule for polynomials, partial fractions for quotients of polynomials, a table
+/// of elementary integrals (with linear arguments such as `sin(2x+1)`), substitution of a
+/// subexpression whose derivative is a factor of the integrand, and integration by parts.
+/// Logarithms are written without absolute values: the antiderivative of `1/x` is `ln(x)`.
+///
+/// The result is checked by differentiating it back and comparing with the integrand at a
+/// handful of points.
+pub fn integrate(expr: &Expr, var: char) -> Result<Expr, IntegrateError> {
+    let integrand = expr.simplify_full();
+    let steps = Cell::new(0);
+    let antiderivative = Integrator {
+        var,
+        depth: 0,
+        steps: &steps,
+    }
+    .integrate(&integrand)
+    .ok_or_else(|| IntegrateError::NoClosedForm(expr.clone()))?;
+    let antiderivative = tidy(&antiderivative.simplify_full());
+    if verify(&antiderivative, &integrand, var) {
+        Ok(antiderivative)
+    } else {
+        Err(IntegrateError::Unverified(antiderivative))
+    }
+}
+
+/// Whether the derivative of `antiderivative` matches `integrand` wherever both are defined,
+/// at no fewer than three sample points.
+fn verify(antiderivative: &Expr, integrand: &Expr, var: char) -> bool {
+    const POINTS: [f64; 16] = [
+        0.31, 0.73, 1.37, 1.91, 2.63, 3.71, 5.29, 8.17, 13.4, 0.05, -0.47, -1.13, -2.3, -5.7, -9.1,
+        -14.6,
+    ];
+    let Ok(derivative) = antiderivative.diff(var) else {
+        return false;
+    };
+    let mut values = parameters(&add(antiderivative, integrand), var);
+    let mut agreed = 0;
+    for x in POINTS {
+        values.insert(var, x);
+        match (derivative.eval(&values), integrand.eval(&values)) {
+            (Ok(a), Ok(b)) if a.is_finite() && b.is_finite() => {
+                if (a - b).abs() > 1e-6 * (1.0 + a.abs() + b.abs()) {
+                    return false;
+                }
+                agreed += 1;
+            }
+            _ => {}
+        }
+    }
+    agreed >= 3
+}
+
+struct Integrator<'a> {
+    var: char,
+    depth: u32,
+    /// Integrands tried so far, shared by every nested integrator.
+    steps: &'a Cell<u32>,
+}
+
+impl Integrator<'_> {
+    /// An integrator for a nested integral in `var`.
+    fn nested(&self, var: char) -> Option<Integrator<'_>> {
+        (self.depth < MAX_DEPTH).then_some(Integrator {
+            var,
+            depth: self.depth + 1,
+            steps: self.steps,
+        })
+    }
+
+    fn integrate(&self, f: &Expr) -> Option<Expr> {
+        self.steps.set(self.steps.get() + 1);
+        if self.steps.get() > MAX_STEPS {
+            return None;
+        }
+        let var = self.var;
+        if !f.contains_variable(var) {
+            return Some(mul(f, &variable(var)));
+        }
+        match f {
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+            } => return Some(neg(&self.integrate(expr)?)),
+            Expr::BinaryOp {
+                op: op @ (BinaryOp::Add | BinaryOp::Sub),
+                left,
+                right,
+            } => {
+                let left = self.integrate(left)?;
+                let right = self.integrate(right)?;
+                return Some(match op {
+                    BinaryOp::Add => add(&left, &right),
+                    _ => sub(&left, &right),
+                });
+            }
+            _ => {}
+        }
+        if let Some(polynomial) = Polynomial::from_expr(f, var) {
+            return integrate_polynomial(&polynomial)?.to_expr();
+        }
+        let (coefficient, factors) = factors(f)?;
+        let (constant, dependent): (Vec<_>, Vec<_>) = factors
+            .into_iter()
+            .filter(|(_, exponent)| *exponent != 0)
+            .partition(|(base, _)| !base.contains_variable(var));
+        if coefficient != Rational::ONE || !constant.is_empty() {
+            let constant = quotient(fraction(coefficient, constant)?);
+            let rest = quotient(fraction(Rational::ONE, dependent)?);
+            return Some(mul(&constant, &self.integrate(&rest)?));
+        }
+        let f = quotient(fraction(Rational::ONE, dependent)?);
+        self.rational(&f)
+            .or_else(|| self.table(&f))
+            .or_else(|| self.substitution(&f))
+            .or_else(|| self.by_parts(&f))
+    }
+
+    /// The slope `a` if `u` is `a*var + b` with `a` nonzero.
+    fn linear(&self, u: &Expr) -> Option<Rational> {
+        let polynomial = Polynomial::from_expr(u, self.var)?;
+        (polynomial.degree() == Some(1)).then(|| polynomial.leading())
+    }
+
+    /// Quotients of polynomials, by polynomial division and partial fractions.
+    fn rational(&self, f: &Expr) -> Option<Expr> {
+        let (numer, denom) = match f {
+            Expr::BinaryOp {
+                op: BinaryOp::Div,
+                left,
+                right,
+            } => (left, right),
+            _ => return None,
+        };
+        let numer = Polynomial::from_expr(numer, self.var)?;
+        let denom = Polynomial::from_expr(denom, self.var)?;
+        partial_fractions(&numer, &denom)
+    }
+
+    /// Integrals of the elementary functions, of powers and of a few of their products, all
+    /// with a linear argument `u = a*var + b`.
+    fn table(&self, f: &Expr) -> Option<Expr> {
+        let (slope, antiderivative) = match f {
+            Expr::Call { name, arg } => {
+                let u = arg.as_ref();
+                let antiderivative = match name.as_str() {
+                    "sin" => neg(&call("cos", u)),
+                    "cos" => call("sin", u),
+                    "tan" => neg(&call("ln", &call("cos", u))),
+                    "exp" => call("exp", u),
+                    "ln" => sub(&mul(u, &call("ln", u)), u),
+                    "sqrt" => mul(
+                        &div(&Expr::Number(2), &Expr::Number(3)),
+                        &mul(u, &call("sqrt", u)),
+                    ),
+                    "asin" => add(
+                        &mul(u, &call("asin", u)),
+                        &call("sqrt", &sub(&Expr::Number(1), &square(u))),
+                    ),
+                    "atan" => sub(
+                        &mul(u, &call("atan", u)),
+                        &div(
+                            &call("ln", &add(&Expr::Number(1), &square(u))),
+                            &Expr::Number(2),
+                        ),
+                    ),
+                    _ => return None,
+                };
+                (self.linear(u)?, antiderivative)
+            }
+            Expr::BinaryOp {
+                op: BinaryOp::Pow,
+                left,
+                right,
+            } => match (left.as_ref(), right.as_ref()) {
+                (Expr::Call { name, arg }, Expr::Number(2)) if name == "sin" || name == "cos" => {
+                    let u = arg.as_ref();
+                    // sin(u)^2 = (1 - cos(2u))/2, integrated as u/2 - sin(u)cos(u)/2
+                    let product = div(&mul(&call("sin", u), &call("cos", u)), &Expr::Number(2));
+                    let half = div(u, &Expr::Number(2));
+                    let antiderivative = match name.as_str() {
+                        "sin" => sub(&half, &product),
+                        _ => add(&half, &product),
+                    };
+                    (self.linear(u)?, antiderivative)
+                }
+                (u, Expr::Number(n)) => {
+                    let exponent = Expr::Number(n.checked_add(1)?);
+                    let antiderivative = match n {
+                        -1 => call("ln", u),
+                        _ => div(&pow(u, &exponent), &exponent),
+                    };
+                    (self.linear(u)?, antiderivative)
+                }
+                _ => return None,
+            },
+            Expr::BinaryOp {
+                op: BinaryOp::Div,
+                left,
+                right,
+            } if **left == Expr::Number(1) => return self.reciprocal(right),
+            _ => return None,
+        };
+        Some(mul(
+            &Rational::ONE.checked_div(slope)?.to_expr()?,
+            &antiderivative,
+        ))
+    }
+
+    /// The table entries for `1/g`.
+    fn reciprocal(&self, g: &Expr) -> Option<Expr> {
+        let (slope, antiderivative) = match g {
+            Expr::Call { name, arg } if name == "sqrt" => {
+                let u = arg.as_ref();
+                if let Some(slope) = self.linear(u) {
+                    (slope, mul(&Expr::Number(2), g))
+                } else {
+                    return self.reciprocal_sqrt_quadratic(u);
+                }
+            }
+            Expr::BinaryOp {
+                op: BinaryOp::Pow,
+                left,
+                right,
+            } if **right == Expr::Number(2) => match left.as_ref() {
+                Expr::Call { name, arg } if name == "cos" => (self.linear(arg)?, call("tan", arg)),
+                Expr::Call { name, arg } if name == "sin" => (
+                    self.linear(arg)?,
+                    neg(&div(&call("cos", arg), &call("sin", arg))),
+                ),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(mul(
+            &Rational::ONE.checked_div(slope)?.to_expr()?,
+            &antiderivative,
+        ))
+    }
+
+    /// `1/sqrt(c + k*var^2)`: an arcsine for `c > 0 > k`, a logarithm for `k > 0`.
+    fn reciprocal_sqrt_quadratic(&self, p: &Expr) -> Option<Expr> {
+        let polynomial = Polynomial::from_expr(p, self.var)?;
+        if polynomial.degree() != Some(2) || !polynomial.coefficient(1).is_zero() {
+            return None;
+        }
+        let (c, k) = (polynomial.coefficient(0), polynomial.coefficient(2));
+        let x = variable(self.var);
+        if k.is_negative() {
+            if c.is_negative() || c.is_zero() {
+                return None;
+            }
+            // asin(x*sqrt(-k/c))/sqrt(-k)
+            let scale = square_root((-k).checked_div(c)?)?;
+            let arcsine = call("asin", &mul(&x, &scale));
+            return Some(div(&arcsine, &square_root(-k)?));
+        }
+        // ln(x*sqrt(k) + sqrt(p))/sqrt(k)
+        let root = square_root(k)?;
+        let log = call("ln", &add(&mul(&x, &root), &call("sqrt", p)));
+        Some(div(&log, &root))
+    }
+
+    /// Substitutes `u = g(var)` for a subexpression `g` when the integrand divided by `g'` can be
+    /// written in `u` alone.
+    fn substitution(&self, f: &Expr) -> Option<Expr> {
+        let letters = f.free_symbols();
+        let letter = *SUBSTITUTION_LETTERS
+            .iter()
+            .find(|c| **c != self.var && !letters.contains(&c.to_string()))?;
+        let nested = self.nested(letter)?;
+        let mut candidates = Vec::new();
+        self.subexpressions(f, &mut candidates);
+        candidates.retain(|g| g != f);
+        candidates.sort_by_key(|g| Reverse(node_count(g)));
+        for g in candidates.iter().take(MAX_SUBSTITUTIONS) {
+            let Ok(derivative) = g.diff(self.var) else {
+                continue;
+            };
+            let derivative = derivative.simplify_full();
+            if derivative == Expr::Number(0) {
+                continue;
+            }
+            let rest = quotient(cancel_factors(f, &derivative));
+            let substituted = replace(&rest, g, &variable(letter)).simplify_full();
+            if substituted.contains_variable(self.var) {
+                continue;
+            }
+            if let Some(antiderivative) = nested.integrate(&substituted) {
+                let back = HashMap::from([(letter.to_string(), g.clone())]);
+                return Some(antiderivative.substitute(&back));
+            }
+        }
+        None
+    }
+
+    /// Subexpressions depending on `var` that aren't linear in it, without duplicates.
+    fn subexpressions(&self, expr: &Expr, found: &mut Vec<Expr>) {
+        if !expr.contains_variable(self.var) || self.linear(expr).is_some() {
+            return;
+        }
+        if !found.contains(expr) {
+            found.push(expr.clone());
+        }
+        match expr {
+            Expr::Call { arg, .. } => self.subexpressions(arg, found),
+            Expr::UnaryOp { expr, .. } => self.subexpressions(expr, found),
+            Expr::BinaryOp { left, right, .. } => {
+                self.subexpressions(left, found);
+                self.subexpressions(right, found);
+            }
+            _ => {}
+        }
+    }
+
+    /// Integration by parts, `∫u dv = u*v - ∫v du`, taking as `u` a logarithm or inverse
+    /// trigonometric factor if there is one, a polynomial factor otherwise.
+    fn by_parts(&self, f: &Expr) -> Option<Expr> {
+        let nested = self.nested(self.var)?;
+        let (coefficient, factors) = factors(f)?;
+        let mut choices: Vec<(u8, usize)> = factors
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, (base, exponent))| {
+                let priority = match base {
+                    _ if *exponent <= 0 => return None,
+                    Expr::Call { name, .. } if ["ln", "asin", "atan"].contains(&name.as_str()) => 0,
+                    _ if Polynomial::from_expr(base, self.var).is_some() => 1,
+                    _ => return None,
+                };
+                Some((priority, idx))
+            })
+            .collect();
+        choices.sort();
+        for (_, idx) in choices {
+            let mut rest = factors.clone();
+            let (base, exponent) = rest.remove(idx);
+            let u = match exponent {
+                1 => base,
+                _ => pow(&base, &Expr::Number(exponent)),
+            };
+            let dv = quotient(fraction(coefficient, rest)?);
+            let Some(v) = nested.integrate(&dv) else {
+                continue;
+            };
+            let v = v.simplify_full();
+            let Ok(du) = u.diff(self.var) else {
+                continue;
+            };
+            let remaining = mul(&v, &du).simplify_full();
+            if let Some(w) = nested.integrate(&remaining) {
+                return Some(sub(&mul(&u, &v), &w));
+            }
+        }
+        None
+    }
+}
+
+/// The power rule applied to every term.
+fn integrate_polynomial(polynomial: &Polynomial) -> Option<Polynomial> {
+    let mut coefficients = vec![Rational::ZERO];
+    for (k, c) in polynomial.coefficients().iter().enumerate() {
+        coefficients.push(c.checked_div(Rational::integer(k as i64 + 1))?);
+    }
+    Some(Polynomial::new(polynomial.var(), coefficients))
+}
+
+/// `∫numer/denom` by polynomial division and partial fractions. The denominator has to split
+/// into rational linear factors and at most one quadratic without rational roots.
+fn partial_fractions(numer: &Polynomial, denom: &Polynomial) -> Option<Expr> {
+    let var = numer.var();
+    let (whole, remainder) = numer.checked_div_rem(denom)?;
+    let mut terms = vec![integrate_polynomial(&whole)?.to_expr()?];
+    if remainder.is_zero() {
+        return Some(terms.remove(0));
+    }
+    let remainder = remainder.checked_scale(Rational::ONE.checked_div(denom.leading())?)?;
+    let denom = denom.monic()?;
+
+    // Each unknown's term times the denominator: (x - r)^-k for every root and multiplicity,
+    // then x and 1 over the quadratic
+    let mut quadratic = denom.clone();
+    let mut columns = Vec::new();
+    let mut roots = Vec::new();
+    for root in denom.rational_roots() {
+        let factor = Polynomial::new(var, vec![-root, Rational::ONE]);
+        let multiplicity = denom.multiplicity(root);
+        for k in 1..=multiplicity {
+            let (column, _) = denom.checked_div_rem(&factor.checked_pow(k as u32)?)?;
+            columns.push(column);
+        }
+        quadratic = quadratic
+            .checked_div_rem(&factor.checked_pow(multiplicity as u32)?)?
+            .0;
+        roots.push((root, multiplicity));
+    }
+    let quadratic = match quadratic.degree()? {
+        0 => None,
+        2 => {
+            let (cofactor, _) = denom.checked_div_rem(&quadratic)?;
+            columns.push(cofactor.checked_mul(&Polynomial::monomial(var, Rational::ONE, 1))?);
+            columns.push(cofactor);
+            Some(quadratic)
+        }
+        _ => return None,
+    };
+    let unknowns = solve(&columns, &remainder)?;
+
+    let mut unknowns = unknowns.into_iter();
+    for (root, multiplicity) in roots {
+        let linear = Polynomial::new(var, vec![-root, Rational::ONE]).to_expr()?;
+        for k in 1..=multiplicity {
+            let c = unknowns.next()?;
+            if c.is_zero() {
+                continue;
+            }
+            terms.push(match k {
+                1 => mul(&c.to_expr()?, &call("ln", &linear)),
+                _ => {
+                    let c = (-c).checked_div(Rational::integer(k as i64 - 1))?;
+                    div(&c.to_expr()?, &pow(&linear, &Expr::Number(k as i32 - 1)))
+                }
+            });
+        }
+    }
+    if let Some(quadratic) = quadratic {
+        // (a*x + b)/(x^2 + p*x + q) = (a/2)(2x + p)/(x^2 + p*x + q) + (b - a*p/2)/(x^2 + p*x + q)
+        let (a, b) = (unknowns.next()?, unknowns.next()?);
+        let p = quadratic.coefficient(1);
+        let q = quadratic.coefficient(0);
+        let half = Rational::new(1, 2)?;
+        let log = a.checked_mul(half)?;
+        let rest = b.checked_sub(a.checked_mul(p)?.checked_mul(half)?)?;
+        if !log.is_zero() {
+            terms.push(mul(&log.to_expr()?, &call("ln", &quadratic.to_expr()?)));
+        }
+        if !rest.is_zero() {
+            // Completing the square, x^2 + p*x + q = (x + p/2)^2 + k with k = q - p^2/4, and
+            // writing sqrt(|k|) = c*sqrt(m)
+            let k = q.checked_sub(p.checked_mul(p)?.checked_mul(Rational::new(1, 4)?)?)?;
+            let (c, m) = surd(k.abs())?;
+            let shifted = Polynomial::new(var, vec![p.checked_mul(half)?, Rational::ONE]);
+            // Dividing by c inside the square root keeps (2x+1)/sqrt(3) free of fractions
+            let scaled_shift = shifted
+                .checked_scale(Rational::ONE.checked_div(c)?)?
+                .to_expr()?;
+            let root = match m {
+                1 => None,
+                m => Some(call("sqrt", &Expr::Number(i32::try_from(m).ok()?))),
+            };
+            let integral = match (k.is_negative(), root) {
+                // ln((x + p/2 - h)/(x + p/2 + h))/(2h) with h = sqrt(-k), which is irrational
+                // since the quadratic has no rational roots
+                (true, Some(root)) => {
+                    let ratio = div(&sub(&scaled_shift, &root), &add(&scaled_shift, &root));
+                    div(&call("ln", &ratio), &mul(&Expr::Number(2), &root))
+                }
+                (true, None) => return None,
+                // atan((x + p/2)/h)/h with h = sqrt(k)
+                (false, Some(root)) => div(&call("atan", &div(&scaled_shift, &root)), &root),
+                (false, None) => call("atan", &div(&shifted.to_expr()?, &c.to_expr()?)),
+            };
+            let rest = rest.checked_div(c)?;
+            terms.push(mul(&rest.to_expr()?, &integral));
+        }
+    }
+    terms.into_iter().reduce(|sum, term| add(&sum, &term))
+}
+
+/// `r` as `c^2 * m` with `m` a square-free integer, `None` if that's too large to factor.
+fn surd(r: Rational) -> Option<(Rational, i64)> {
+    let mut m = r.numer().checked_mul(r.denom())?;
+    if m > 1 << 40 {
+        return None;
+    }
+    let mut square = 1;
+    let mut f = 2;
+    while f * f <= m {
+        while m % (f * f) == 0 {
+            m /= f * f;
+            square *= f;
+        }
+        f += 1;
+    }
+    Some((Rational::new(square, r.denom())?, m))
+}
+
+/// The square root of `r`, written `c*sqrt(m)` as in [`surd`].
+fn square_root(r: Rational) -> Option<Expr> {
+    let (c, m) = surd(r)?;
+    Some(match m {
+        1 => c.to_expr()?,
+        m => mul(
+            &c.to_expr()?,
+            &call("sqrt", &Expr::Number(i32::try_from(m).ok()?)),
+        ),
+    })
+}
+
+/// Solves `sum(unknowns[j] * columns[j]) = target` coefficient by coefficient, by Gaussian
+/// elimination. `None` if the system is singular or overflows.
+fn solve(columns: &[Polynomial], target: &Polynomial) -> Option<Vec<Rational>> {
+    let n = columns.len();
+    let mut rows: Vec<Vec<Rational>> = (0..n)
+        .map(|k| {
+            let mut row: Vec<Rational> = columns.iter().map(|c| c.coefficient(k)).collect();
+            row.push(target.coefficient(k));
+            row
+        })
+        .collect();
+    for col in 0..n {
+        let pivot = (col..n).find(|&r| !rows[r][col].is_zero())?;
+        rows.swap(col, pivot);
+        for r in 0..n {
+            if r == col || rows[r][col].is_zero() {
+                continue;
+            }
+            let factor = rows[r][col].checked_div(rows[col][col])?;
+            let pivot_row = rows[col].clone();
+            for (entry, pivot_entry) in rows[r].iter_mut().zip(pivot_row).skip(col) {
+                *entry = entry.checked_sub(factor.checked_mul(pivot_entry)?)?;
+            }
+        }
+    }
+    (0..n).map(|r| rows[r][n].checked_div(rows[r][r])).collect()
+}
+
+/// A quotient from [`fraction`] as one expression, leaving out a denominator of one.
+fn quotient((numer, denom): (Expr, Expr)) -> Expr {
+    match denom {
+        Expr::Number(1) => numer,
+        denom => div(&numer, &denom),
+    }
+}
+
+/// `expr` with every occurrence of `target` replaced by `replacement`.
+fn replace(expr: &Expr, target: &Expr, replacement: &Expr) -> Expr {
+    if expr == target {
+        return replacement.clone();
+    }
+    match expr {
+        Expr::Call { name, arg } => call(name, &replace(arg, target, replacement)),
+        Expr::UnaryOp { op, expr } => Expr::UnaryOp {
+            op: op.clone(),
+            expr: Box::new(replace(expr, target, replacement)),
+        },
+        Expr::BinaryOp { op, left, right } => Expr::BinaryOp {
+            op: op.clone(),
+            left: Box::new(replace(left, target, replacement)),
+            right: Box::new(replace(right, target, replacement)),
+        },
+        _ => expr.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify, IntegrateError};
+    use crate::parse;
+
+    fn antiderivative(input: &str) -> String {
+        super::integrate(&parse(input).unwrap(), 'x')
+            .unwrap()
+            .pretty()
+    }
+
+    #[test]
+    fn polynomials() {
+        assert_eq!(antiderivative("3x^2+1"), "x^3+x");
+    }
+
+    #[test]
+    fn huge_powers_use_the_power_rule_without_expanding() {
+        assert_eq!(antiderivative("x^100000"), "x^100001/100001");
+        assert_eq!(antiderivative("2x^5000"), "2x^5001/5001");
+    }
+
+    #[test]
+    fn inverse_trigonometric_integrands() {
+        assert_eq!(antiderivative("1/(1+x^2)"), "atan(x)");
+    }
+
+    #[test]
+    fn partial_fractions() {
+        assert_eq!(antiderivative("1/(x^2-1)"), "ln(x-1)/2-ln(x+1)/2");
+        assert_eq!(antiderivative("(2x+3)/((x+1)*(x+2))"), "ln(x+2)+ln(x+1)");
+    }
+
+    #[test]
+    fn substitution() {
+        assert_eq!(antiderivative("cos(x^2)*x"), "sin(x^2)/2");
+        assert_eq!(antiderivative("sin(x)^2*cos(x)"), "sin(x)^3/3");
+    }
+
+    #[test]
+    fn integration_by_parts() {
+        assert_eq!(antiderivative("x*exp(x)"), "x*exp(x)-exp(x)");
+        assert_eq!(antiderivative("x*ln(x)"), "x^2*ln(x)/2-x^2/4");
+    }
+
+    #[test]
+    fn integrals_without_a_closed_form() {
+        let integrand = parse("exp(x^2)").unwrap();
+        assert_eq!(
+            super::integrate(&integrand, 'x'),
+            Err(IntegrateError::NoClosedForm(integrand))
+        );
+    }
+
+    #[test]
+    fn candidates_are_verified_by_differentiating() {
+        let check = |antiderivative: &str, integrand: &str| {
+            verify(
+                &parse(antiderivative).unwrap(),
+                &parse(integrand).unwrap(),
+                'x',
+            )
+        };
+        assert!(check("x^2", "2x"));
+        assert!(!check("x^2", "3x"));
+        // Agrees wherever ln(x) is defined
+        assert!(check("ln(x)", "1/x"));
+        // Too few points where both are defined
+        assert!(!check("sqrt(x-10)", "1/(2sqrt(x-10))"));
+    }
+}
@@ -0,0 +1,220 @@
+//! Symbolic differentiator for simple mathematical expressions.
+//!
+//! Expressions are parsed from text with [`parse`], differentiated with [`Expr::diff`],
+//! cleaned up with [`Expr::simplify`] and printed with [`Expr::pretty`] or any other
+//! [`OutputFormat`] through [`render`].
+//!
+//! ```
+//! use derivative_calculator::{parse, OutputFormat};
+//!
+//! let expr = parse("x^3 + 2x")?;
+//! let derivative = expr.diff('x')?.simplify();
+//! assert_eq!(derivative.pretty(), "3x^2+2");
+//! assert_eq!(derivative.to_latex(), "3 x^{2} + 2");
+//! assert_eq!(derivative_calculator::render(&derivative, OutputFormat::Pretty), "3x^2+2");
+//! # Ok::<(), derivative_calculator::Error>(())
+//! ```
+//!
+//! Every public enum is `#[non_exhaustive]` so that new expression kinds, formats and errors
+//! can be added without a breaking release.
+
+mod algebra;
+pub mod analysis;
+pub mod assumptions;
+pub mod cancel;
+pub mod differentiator;
+pub mod domain;
+pub mod evaluator;
+pub mod expand;
+pub mod identities;
+pub mod integrator;
+pub mod limit;
+mod parser;
+pub mod polynomial;
+pub mod printer;
+pub mod quadrature;
+pub mod rational;
+pub mod rewrite;
+pub mod roots;
+#[cfg(feature = "serde")]
+pub mod serialize;
+pub mod series;
+pub mod substitution;
+pub(crate) mod tokenizer;
+
+use std::collections::HashSet;
+use std::fmt::Display;
+
+use differentiator::DiffError;
+use parser::Parser;
+use tokenizer::tokens::Token;
+use tokenizer::Tokenizer;
+
+pub use tokenizer::TokenizeError;
+
+pub use parser::ast::{BinaryOp, Expr, SimplifyLevel, UnaryOp, BUILTIN_FUNCTIONS};
+pub use printer::{render, OutputFormat};
+
+/// Failure at one of the stages between the input text and its derivative.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The input contains a character that isn't part of any token.
+    Tokenize(TokenizeError),
+    /// The tokens don't form a valid expression.
+    Parse,
+    /// A word that is neither a known name nor made of single letters and numbers, e.g. `my_f`,
+    /// or that is called without being a function, e.g. `cosh(x)`.
+    UnknownName(String),
+    /// The expression can't be differentiated.
+    Differentiate(DiffError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Tokenize(e) => write!(f, "Tokenize error: {e}"),
+            Error::Parse => f.write_str("Parse error: not a valid expression"),
+            Error::UnknownName(name) => write!(f, "Parse error: unknown name '{name}'"),
+            Error::Differentiate(e) => write!(f, "Differentiation error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Tokenize(e) => Some(e),
+            Error::Parse | Error::UnknownName(_) => None,
+            Error::Differentiate(e) => Some(e),
+        }
+    }
+}
+
+impl From<TokenizeError> for Error {
+    fn from(e: TokenizeError) -> Self {
+        Error::Tokenize(e)
+    }
+}
+
+impl From<DiffError> for Error {
+    fn from(e: DiffError) -> Self {
+        Error::Differentiate(e)
+    }
+}
+
+/// Result type of the fallible functions in this crate.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Tokenizes and parses `input` into an expression.
+///
+/// Words other than the [`BUILTIN_FUNCTIONS`] are products of single letters and numbers,
+/// and a word that can't be read that way is an error:
+///
+/// ```
+/// use derivative_calculator::{parse, Error};
+///
+/// assert_eq!(parse("ax^2+bx")?, parse("a*x^2+b*x")?);
+/// assert_eq!(parse("xsin(x)")?, parse("x*sin(x)")?);
+/// assert_eq!(parse("cosh(x)"), Err(Error::UnknownName("cosh".to_string())));
+/// # Ok::<(), derivative_calculator::Error>(())
+/// ```
+pub fn parse(input: &str) -> Result<Expr> {
+    parse_with_functions(input, HashSet::new())
+}
+
+/// Like [`parse`], additionally reading `c(...)` as a call for every single letter function `c`
+/// instead of as `c` times `(...)`.
+pub fn parse_with_functions(input: &str, functions: HashSet<char>) -> Result<Expr> {
+    parse_with_names(input, functions, HashSet::new())
+}
+
+/// Like [`parse_with_functions`], additionally keeping every word in `names` whole, as the name
+/// of a binding or a function defined elsewhere.
+pub fn parse_with_names(
+    input: &str,
+    functions: HashSet<char>,
+    names: HashSet<String>,
+) -> Result<Expr> {
+    let tokens = Tokenizer::new(input).tokenize()?;
+    let mut parser = Parser::new(tokens)
+        .with_functions(functions)
+        .with_names(names);
+    parser.split_words().map_err(Error::UnknownName)?;
+    parser.parse().ok_or(Error::Parse)
+}
+
+/// A piece of the input as [`lex`] reads it.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Lexeme {
+    /// A whole number; a sign is a separate [`Lexeme::Operator`].
+    Number(i32),
+    /// The letter `x`.
+    Variable(char),
+    /// Any other single letter, including `e`.
+    Constant(char),
+    /// A word kept whole: a function, a binding or a result reference like `$2`. Other words
+    /// are only split into products by [`parse`].
+    Name(String),
+    /// One of `+ - * / ^`.
+    Operator(char),
+    /// `(`
+    LeftParen,
+    /// `)`
+    RightParen,
+    /// A space, tab or newline.
+    WhiteSpace,
+}
+
+/// Splits `input` into lexemes, each with the index of the character it starts at, for
+/// highlighting and completion. Ends with the first error, if any.
+///
+/// ```
+/// use derivative_calculator::{lex, Lexeme};
+///
+/// let lexemes: Vec<_> = lex("2sin(x)").into_iter().map(Result::unwrap).collect();
+/// assert_eq!(lexemes[0], (0, Lexeme::Number(2)));
+/// assert_eq!(lexemes[1], (1, Lexeme::Name("sin".to_string())));
+/// assert_eq!(lexemes.len(), 5);
+/// assert_eq!(lex("x+#").pop().unwrap().unwrap_err().position(), 2);
+/// ```
+pub fn lex(input: &str) -> Vec<std::result::Result<(usize, Lexeme), TokenizeError>> {
+    let mut lexemes = Vec::new();
+    for token in Tokenizer::new(input).iter() {
+        let token = match token {
+            Ok(token) => token,
+            Err(e) => {
+                lexemes.push(Err(e));
+                break;
+            }
+        };
+        let lexeme = match token.variant {
+            Token::NUMBER(n) => Lexeme::Number(n),
+            Token::VARIABLE(c) => Lexeme::Variable(c),
+            Token::CONSTANT(c) => Lexeme::Constant(c),
+            Token::IDENT(name) => Lexeme::Name(name),
+            Token::PLUS => Lexeme::Operator('+'),
+            Token::MINUS => Lexeme::Operator('-'),
+            Token::MUL => Lexeme::Operator('*'),
+            Token::DIV => Lexeme::Operator('/'),
+            Token::POW => Lexeme::Operator('^'),
+            Token::LEFTPAREN => Lexeme::LeftParen,
+            Token::RIGHTPAREN => Lexeme::RightParen,
+            Token::WhiteSpace(_) => Lexeme::WhiteSpace,
+            _ => continue,
+        };
+        lexemes.push(Ok((token.location.expr_idx, lexeme)));
+    }
+    lexemes
+}
+
+/// The `order`-th derivative of `expr` with respect to `var`, simplified to `level` after every
+/// step.
+pub fn derivative(expr: &Expr, var: char, order: u32, level: SimplifyLevel) -> Result<Expr> {
+    let mut result = expr.clone();
+    for _ in 0..order {
+        result = level.apply(&result.diff(var)?);
+    }
+    Ok(result)
+}
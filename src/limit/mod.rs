@@ -0,0 +1,775 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::algebra::{add, call, cancel_factors, div, mul, neg, node_count, parameters, pow, sub};
+use crate::differentiator::DiffError;
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+use crate::rational::Rational;
+
+/// Times L'Hôpital's rule is applied to one quotient before giving up.
+const MAX_LHOPITAL: u32 = 8;
+/// How deeply indeterminate forms may nest inside each other.
+const MAX_DEPTH: u32 = 6;
+/// Subexpression limits computed for one side before giving up.
+const MAX_STEPS: u32 = 5000;
+/// Largest derivative, in nodes, L'Hôpital's rule continues with.
+const MAX_NODES: usize = 400;
+
+/// Where the variable goes.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Target {
+    Point(Expr),
+    PosInfinity,
+    NegInfinity,
+}
+
+/// Side a finite point is approached from. Ignored for infinite targets.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[non_exhaustive]
+pub enum Direction {
+    /// Both one-sided limits, which have to agree.
+    #[default]
+    Both,
+    /// From below, `x -> a-`.
+    Below,
+    /// From above, `x -> a+`.
+    Above,
+}
+
+/// Value of a limit.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Limit {
+    Finite(Expr),
+    PosInfinity,
+    NegInfinity,
+    /// The one-sided limits differ, or the expression oscillates or has no real values.
+    DoesNotExist,
+}
+
+/// What it took to find a limit, from least to most work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum Strategy {
+    /// Plugging in the point gave a defined value.
+    Substitution,
+    /// Combining the limits of the parts, such as `c/0 = ±∞` or `e^-∞ = 0`.
+    LimitLaws,
+    /// Cancelling common factors removed the indeterminate form.
+    Simplification,
+    /// L'Hôpital's rule, applied this many times.
+    LHopital(u32),
+}
+
+/// A limit together with the strategy that resolved it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitResult {
+    pub limit: Limit,
+    pub strategy: Strategy,
+}
+
+/// Reasons a limit can't be found.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum LimitError {
+    /// A derivative needed by L'Hôpital's rule can't be taken.
+    Differentiate(DiffError),
+    /// None of the strategies resolved the limit.
+    Unresolved,
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Limit::Finite(expr) => f.write_str(&expr.pretty()),
+            Limit::PosInfinity => f.write_str("∞"),
+            Limit::NegInfinity => f.write_str("-∞"),
+            Limit::DoesNotExist => f.write_str("does not exist"),
+        }
+    }
+}
+
+impl Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Strategy::Substitution => f.write_str("direct substitution"),
+            Strategy::Simplification => f.write_str("algebraic simplification"),
+            Strategy::LimitLaws => f.write_str("limit laws"),
+            Strategy::LHopital(1) => f.write_str("L'Hôpital's rule, applied once"),
+            Strategy::LHopital(n) => write!(f, "L'Hôpital's rule, applied {n} times"),
+        }
+    }
+}
+
+impl Display for LimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LimitError::Differentiate(e) => write!(f, "{e}"),
+            LimitError::Unresolved => f.write_str("could not determine the limit"),
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+impl From<DiffError> for LimitError {
+    fn from(e: DiffError) -> Self {
+        LimitError::Differentiate(e)
+    }
+}
+
+impl Expr {
+    /// Limit of the expression as `var` goes to `target`.
+    ///
+    /// Tries substituting the point first. Otherwise the limits of the parts are combined,
+    /// `0/0` and `∞/∞` are resolved by cancelling common factors and then by L'Hôpital's rule,
+    /// and `0·∞`, `∞-∞` and variable exponents are rewritten into quotients first. Other
+    /// letters are treated as nonzero parameters.
+    ///
+    /// ```
+    /// use derivative_calculator::limit::{Direction, Limit, Strategy, Target};
+    /// use derivative_calculator::{parse, Expr};
+    ///
+    /// let result = parse("sin(x)/x")?
+    ///     .limit('x', &Target::Point(Expr::Number(0)), Direction::Both)
+    ///     .unwrap();
+    /// assert_eq!(result.limit, Limit::Finite(Expr::Number(1)));
+    /// assert_eq!(result.strategy, Strategy::LHopital(1));
+    ///
+    /// let result = parse("1/x")?
+    ///     .limit('x', &Target::Point(Expr::Number(0)), Direction::Above)
+    ///     .unwrap();
+    /// assert_eq!(result.limit, Limit::PosInfinity);
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn limit(
+        &self,
+        var: char,
+        target: &Target,
+        direction: Direction,
+    ) -> Result<LimitResult, LimitError> {
+        let params = parameters(self, var);
+        let sides: &[Side] = match (target, direction) {
+            (Target::Point(_), Direction::Both) => &[Side::Below, Side::Above],
+            (Target::Point(_), Direction::Below) => &[Side::Below],
+            _ => &[Side::Above],
+        };
+        let evaluator = |side| Evaluator {
+            var,
+            target,
+            side,
+            params: &params,
+            depth: 0,
+            steps: 0,
+            simplified: false,
+            lhopital: 0,
+        };
+        if let Target::Point(point) = target {
+            let substituted = self.substitute(&HashMap::from([(var.to_string(), point.clone())]));
+            // Only where the expression is real on every side asked for: `sqrt(0)` is defined,
+            // but `sqrt(x)` has no values as `x -> 0-`
+            let real_near = sides
+                .iter()
+                .all(|&side| evaluator(side).value_near(self).is_ok_and(|v| !v.is_nan()));
+            if real_near && is_defined(&substituted, &params) {
+                return Ok(LimitResult {
+                    limit: Limit::Finite(substituted.simplify_full()),
+                    strategy: Strategy::Substitution,
+                });
+            }
+        }
+        let mut strategy = Strategy::Substitution;
+        let mut values = Vec::new();
+        for &side in sides {
+            let mut evaluator = evaluator(side);
+            let value = evaluator.lim(self)?;
+            strategy = strategy.max(evaluator.strategy());
+            values.push(value);
+        }
+        // A side without real values doesn't count, as for `ln(x)` at `0`
+        let real: Vec<&Value> = values
+            .iter()
+            .filter(|value| !matches!(value, Value::NotReal))
+            .collect();
+        let limit = match real.as_slice() {
+            [] => Limit::DoesNotExist,
+            [value] => value.to_limit(),
+            [first, second] => match (first, second) {
+                (Value::Finite(a), Value::Finite(b)) if is_zero(&sub(a, b), &params) => {
+                    Limit::Finite(a.clone())
+                }
+                (Value::PosInf, Value::PosInf) => Limit::PosInfinity,
+                (Value::NegInf, Value::NegInf) => Limit::NegInfinity,
+                _ => Limit::DoesNotExist,
+            },
+            _ => unreachable!(),
+        };
+        Ok(LimitResult { limit, strategy })
+    }
+}
+
+/// Side of a finite point the variable approaches from; infinite targets always use `Above`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Side {
+    Below,
+    Above,
+}
+
+/// Limit of a subexpression in the extended reals.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Finite(Expr),
+    PosInf,
+    NegInf,
+    /// Bounded but without a limit, like `sin(x)` as `x -> ∞`.
+    Bounded,
+    /// No real values near the point, like `ln(x)` as `x -> 0-`.
+    NotReal,
+}
+
+impl Value {
+    fn infinite(sign: f64) -> Value {
+        if sign > 0.0 {
+            Value::PosInf
+        } else {
+            Value::NegInf
+        }
+    }
+
+    fn negate(self) -> Value {
+        match self {
+            Value::Finite(expr) => Value::Finite(neg(&expr).simplify_full()),
+            Value::PosInf => Value::NegInf,
+            Value::NegInf => Value::PosInf,
+            other => other,
+        }
+    }
+
+    fn is_infinite(&self) -> bool {
+        matches!(self, Value::PosInf | Value::NegInf)
+    }
+
+    fn sign(&self) -> f64 {
+        match self {
+            Value::NegInf => -1.0,
+            _ => 1.0,
+        }
+    }
+
+    fn to_limit(&self) -> Limit {
+        match self {
+            Value::Finite(expr) => Limit::Finite(expr.clone()),
+            Value::PosInf => Limit::PosInfinity,
+            Value::NegInf => Limit::NegInfinity,
+            Value::Bounded | Value::NotReal => Limit::DoesNotExist,
+        }
+    }
+}
+
+/// Computes one-sided limits by combining the limits of subexpressions.
+struct Evaluator<'a> {
+    var: char,
+    target: &'a Target,
+    side: Side,
+    /// Stand-in values for the other letters, used to find signs numerically.
+    params: &'a HashMap<char, f64>,
+    depth: u32,
+    steps: u32,
+    simplified: bool,
+    lhopital: u32,
+}
+
+type LimitValue = Result<Value, LimitError>;
+
+impl Evaluator<'_> {
+    fn strategy(&self) -> Strategy {
+        if self.lhopital > 0 {
+            Strategy::LHopital(self.lhopital)
+        } else if self.simplified {
+            Strategy::Simplification
+        } else {
+            Strategy::LimitLaws
+        }
+    }
+
+    fn lim(&mut self, expr: &Expr) -> LimitValue {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return Err(LimitError::Unresolved);
+        }
+        match expr {
+            Expr::Variable(c) | Expr::Constant(c) if *c == self.var => Ok(match self.target {
+                Target::Point(point) => Value::Finite(point.clone()),
+                Target::PosInfinity => Value::PosInf,
+                Target::NegInfinity => Value::NegInf,
+            }),
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+            } => Ok(self.lim(expr)?.negate()),
+            Expr::BinaryOp {
+                op: BinaryOp::Add,
+                left,
+                right,
+            } => self.sum(left, right),
+            Expr::BinaryOp {
+                op: BinaryOp::Sub,
+                left,
+                right,
+            } => self.sum(left, &neg(right)),
+            Expr::BinaryOp {
+                op: BinaryOp::Mul,
+                left,
+                right,
+            } => self.product(left, right),
+            Expr::BinaryOp {
+                op: BinaryOp::Div,
+                left,
+                right,
+            } => self.quotient(left, right),
+            Expr::BinaryOp {
+                op: BinaryOp::Pow,
+                left,
+                right,
+            } => self.power(left, right),
+            Expr::Call { name, arg } => self.call(name, arg),
+            _ => Ok(Value::Finite(expr.clone())),
+        }
+    }
+
+    fn sum(&mut self, a: &Expr, b: &Expr) -> LimitValue {
+        let value = match (self.lim(a)?, self.lim(b)?) {
+            (Value::NotReal, _) | (_, Value::NotReal) => Value::NotReal,
+            (Value::Finite(x), Value::Finite(y)) => Value::Finite(add(&x, &y).simplify_full()),
+            (inf @ (Value::PosInf | Value::NegInf), Value::Finite(_) | Value::Bounded)
+            | (Value::Finite(_) | Value::Bounded, inf @ (Value::PosInf | Value::NegInf)) => inf,
+            (Value::PosInf, Value::PosInf) => Value::PosInf,
+            (Value::NegInf, Value::NegInf) => Value::NegInf,
+            (la @ (Value::PosInf | Value::NegInf), _) => self.difference(a, b, &la)?,
+            (Value::Bounded, Value::Finite(_)) | (Value::Finite(_), Value::Bounded) => {
+                Value::Bounded
+            }
+            _ => return Err(LimitError::Unresolved),
+        };
+        Ok(value)
+    }
+
+    /// `a + b` where `a` and `b` go to infinities of opposite sign, written as `a(1 + b/a)`.
+    fn difference(&mut self, a: &Expr, b: &Expr, la: &Value) -> LimitValue {
+        let ratio = div(b, a);
+        match self.nested(|this| this.lim(&ratio))? {
+            Value::Finite(l) => {
+                let factor = add(&Expr::Number(1), &l).simplify_full();
+                if self.is_zero(&factor) {
+                    // (1 + b/a) / (1/a) is 0/0
+                    self.quotient(&add(&Expr::Number(1), &ratio), &div(&Expr::Number(1), a))
+                } else {
+                    let sign = self.constant_sign(&factor)?;
+                    Ok(Value::infinite(sign * la.sign()))
+                }
+            }
+            inf if inf.is_infinite() => Ok(Value::infinite(-la.sign())),
+            _ => Err(LimitError::Unresolved),
+        }
+    }
+
+    fn product(&mut self, a: &Expr, b: &Expr) -> LimitValue {
+        let value = match (self.lim(a)?, self.lim(b)?) {
+            (Value::NotReal, _) | (_, Value::NotReal) => Value::NotReal,
+            (Value::Finite(x), Value::Finite(y)) => Value::Finite(mul(&x, &y).simplify_full()),
+            (inf, Value::Finite(f)) if inf.is_infinite() => {
+                if self.is_zero(&f) {
+                    self.zero_times_infinity(b, a)?
+                } else {
+                    Value::infinite(inf.sign() * self.constant_sign(&f)?)
+                }
+            }
+            (Value::Finite(f), inf) if inf.is_infinite() => {
+                if self.is_zero(&f) {
+                    self.zero_times_infinity(a, b)?
+                } else {
+                    Value::infinite(inf.sign() * self.constant_sign(&f)?)
+                }
+            }
+            (x, y) if x.is_infinite() && y.is_infinite() => Value::infinite(x.sign() * y.sign()),
+            (Value::Bounded, Value::Finite(f)) | (Value::Finite(f), Value::Bounded) => {
+                if self.is_zero(&f) {
+                    // Squeezed between ±bound * f
+                    Value::Finite(Expr::Number(0))
+                } else {
+                    Value::Bounded
+                }
+            }
+            _ => return Err(LimitError::Unresolved),
+        };
+        Ok(value)
+    }
+
+    /// `zero * inf` as `inf / (1/zero)`, or failing that `zero / (1/inf)`. An infinite
+    /// factor without the variable, like `ln(0)`, is undefined rather than a limit.
+    fn zero_times_infinity(&mut self, zero: &Expr, inf: &Expr) -> LimitValue {
+        if !inf.contains_variable(self.var) {
+            return Err(LimitError::Unresolved);
+        }
+        let one = Expr::Number(1);
+        let saved = (self.simplified, self.lhopital);
+        let first = self.nested(|this| this.quotient(inf, &div(&one, zero)));
+        if first.is_ok() {
+            return first;
+        }
+        (self.simplified, self.lhopital) = saved;
+        self.nested(|this| this.quotient(zero, &div(&one, inf)))
+    }
+
+    fn quotient(&mut self, n: &Expr, d: &Expr) -> LimitValue {
+        let (ln, ld) = (self.lim(n)?, self.lim(d)?);
+        match self.determinate_quotient(d, ln, ld)? {
+            Some(value) => Ok(value),
+            None => self.nested(|this| this.lhopital(n, d)),
+        }
+    }
+
+    /// Limit of `n/d` from the limits of its parts, `None` for `0/0` and `∞/∞`.
+    fn determinate_quotient(
+        &mut self,
+        d: &Expr,
+        ln: Value,
+        ld: Value,
+    ) -> Result<Option<Value>, LimitError> {
+        let value = match (ln, ld) {
+            (Value::NotReal, _) | (_, Value::NotReal) => Value::NotReal,
+            (Value::Finite(x), Value::Finite(y)) => match (self.is_zero(&x), self.is_zero(&y)) {
+                (_, false) => Value::Finite(div(&x, &y).simplify_full()),
+                (true, true) => return Ok(None),
+                // c/0: the sign comes from the side the denominator approaches 0 from
+                (false, true) => Value::infinite(self.constant_sign(&x)? * self.sign_near(d)?),
+            },
+            (Value::Finite(_) | Value::Bounded, inf) if inf.is_infinite() => {
+                Value::Finite(Expr::Number(0))
+            }
+            (inf, Value::Finite(y)) if inf.is_infinite() => {
+                let sign = if self.is_zero(&y) {
+                    self.sign_near(d)?
+                } else {
+                    self.constant_sign(&y)?
+                };
+                Value::infinite(inf.sign() * sign)
+            }
+            (x, y) if x.is_infinite() && y.is_infinite() => return Ok(None),
+            (Value::Bounded, Value::Finite(y)) if !self.is_zero(&y) => Value::Bounded,
+            _ => return Err(LimitError::Unresolved),
+        };
+        Ok(Some(value))
+    }
+
+    /// Resolves `n/d` in the form `0/0` or `∞/∞`: first by cancelling common factors, then
+    /// common polynomial factors as in `(x^2-4)/(x-2)`, then by replacing it with `n'/d'` until
+    /// the form is determinate.
+    fn lhopital(&mut self, n: &Expr, d: &Expr) -> LimitValue {
+        let cancelled = match div(n, d).cancel() {
+            Expr::BinaryOp {
+                op: BinaryOp::Div,
+                left,
+                right,
+            } => (*left, *right),
+            expr => (expr, Expr::Number(1)),
+        };
+        for (cancelled_n, cancelled_d) in [cancel_factors(n, d), cancelled] {
+            if !self.indeterminate_parts(&cancelled_n, &cancelled_d) {
+                self.simplified = true;
+                return self.quotient(&cancelled_n, &cancelled_d);
+            }
+        }
+        let (mut n, mut d) = (n.clone(), d.clone());
+        for _ in 0..MAX_LHOPITAL {
+            self.lhopital += 1;
+            n = n.diff(self.var)?.simplify_full();
+            d = d.diff(self.var)?.simplify_full();
+            if node_count(&n) + node_count(&d) > MAX_NODES {
+                return Err(LimitError::Unresolved);
+            }
+            // Cancelling often resolves the new quotient, but when it doesn't and leaves no
+            // denominator behind the rule can't continue from it
+            let (cancelled_n, cancelled_d) = cancel_factors(&n, &d);
+            if !self.indeterminate_parts(&cancelled_n, &cancelled_d) {
+                return self.quotient(&cancelled_n, &cancelled_d);
+            }
+            if cancelled_d != Expr::Number(1) {
+                (n, d) = (cancelled_n, cancelled_d);
+            }
+        }
+        Err(LimitError::Unresolved)
+    }
+
+    /// Whether `n/d` is still `0/0` or `∞/∞`, treating failures as indeterminate.
+    fn indeterminate_parts(&mut self, n: &Expr, d: &Expr) -> bool {
+        let saved = (self.simplified, self.lhopital);
+        let result = match (
+            self.nested(|this| this.lim(n)),
+            self.nested(|this| this.lim(d)),
+        ) {
+            (Ok(Value::Finite(x)), Ok(Value::Finite(y))) => self.is_zero(&x) && self.is_zero(&y),
+            (Ok(x), Ok(y)) => x.is_infinite() && y.is_infinite(),
+            _ => true,
+        };
+        (self.simplified, self.lhopital) = saved;
+        result
+    }
+
+    fn power(&mut self, base: &Expr, exponent: &Expr) -> LimitValue {
+        if exponent.contains_variable(self.var) {
+            // 0^e for a positive e, where ln(b) below would be a constant -inf when b is 0.
+            // A base that is exactly 0 only needs e positive near the target, unlike x^x
+            if let Value::Finite(b) = self.lim(base)? {
+                let constant = !base.contains_variable(self.var);
+                let positive = match self.lim(exponent)? {
+                    Value::Finite(e) if self.is_zero(&e) => {
+                        constant && self.sign_near(exponent).is_ok_and(|sign| sign > 0.0)
+                    }
+                    Value::Finite(e) => self.is_positive(&e),
+                    Value::PosInf => true,
+                    _ => false,
+                };
+                let real = !self.value_near(&pow(base, exponent))?.is_nan();
+                if self.is_zero(&b) && positive && real {
+                    return Ok(Value::Finite(Expr::Number(0)));
+                }
+            }
+            // b^e = exp(e ln b)
+            return self.lim(&call("exp", &mul(exponent, &call("ln", base))));
+        }
+        let exact = Rational::from_expr(exponent);
+        let value = match self.lim(base)? {
+            Value::NotReal => Value::NotReal,
+            Value::Finite(b) if !self.is_zero(&b) => {
+                Value::Finite(pow(&b, exponent).simplify_full())
+            }
+            Value::Finite(_) => match exact {
+                Some(e) if e.is_zero() => Value::Finite(Expr::Number(1)),
+                // x^(1/2) as x -> 0-
+                Some(e) if !e.is_integer() && self.sign_near(base)? < 0.0 => Value::NotReal,
+                Some(e) if !e.is_negative() => Value::Finite(Expr::Number(0)),
+                // b^-n = 1/b^n
+                Some(e) => {
+                    let positive = pow(base, &(-e).to_expr().ok_or(LimitError::Unresolved)?);
+                    return self.quotient(&Expr::Number(1), &positive);
+                }
+                None => return Err(LimitError::Unresolved),
+            },
+            Value::PosInf => match exact {
+                Some(e) if e.is_zero() => Value::Finite(Expr::Number(1)),
+                Some(e) if e.is_negative() => Value::Finite(Expr::Number(0)),
+                Some(_) => Value::PosInf,
+                None => return Err(LimitError::Unresolved),
+            },
+            Value::NegInf => match exact {
+                Some(e) if e.is_zero() => Value::Finite(Expr::Number(1)),
+                Some(e) if e.is_negative() => Value::Finite(Expr::Number(0)),
+                Some(e) if e.is_integer() && e.numer() % 2 == 0 => Value::PosInf,
+                Some(e) if e.is_integer() => Value::NegInf,
+                _ => Value::NotReal,
+            },
+            Value::Bounded => match exact {
+                Some(e) if !e.is_negative() => Value::Bounded,
+                _ => return Err(LimitError::Unresolved),
+            },
+        };
+        Ok(value)
+    }
+
+    fn call(&mut self, name: &str, arg: &Expr) -> LimitValue {
+        let value = match (name, self.lim(arg)?) {
+            (_, Value::NotReal) => Value::NotReal,
+            (_, Value::Finite(a)) => {
+                let at_limit = call(name, &a);
+                match at_limit.eval(self.params) {
+                    // At the edge of the domain, like sqrt(x) as x -> 0-
+                    Ok(v) if v.is_finite() && self.value_near(&call(name, arg))?.is_nan() => {
+                        Value::NotReal
+                    }
+                    Ok(v) if v.is_finite() => Value::Finite(at_limit.simplify_full()),
+                    // At a singularity, like ln(0) or tan(pi/2)
+                    Ok(_) => {
+                        let near = self.value_near(&call(name, arg))?;
+                        if near.is_nan() {
+                            Value::NotReal
+                        } else {
+                            Value::infinite(near.signum())
+                        }
+                    }
+                    Err(_) => return Err(LimitError::Unresolved),
+                }
+            }
+            ("exp" | "ln" | "sqrt", Value::PosInf) => Value::PosInf,
+            ("exp", Value::NegInf) => Value::Finite(Expr::Number(0)),
+            ("ln" | "sqrt", Value::NegInf) => Value::NotReal,
+            ("sin" | "cos", Value::PosInf | Value::NegInf) => Value::Bounded,
+            ("sin" | "cos" | "exp", Value::Bounded) => Value::Bounded,
+            _ => return Err(LimitError::Unresolved),
+        };
+        Ok(value)
+    }
+
+    /// Runs `f` one level deeper, failing once indeterminate forms nest too deeply.
+    fn nested(&mut self, f: impl FnOnce(&mut Self) -> LimitValue) -> LimitValue {
+        if self.depth >= MAX_DEPTH {
+            return Err(LimitError::Unresolved);
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn is_zero(&self, expr: &Expr) -> bool {
+        is_zero(expr, self.params)
+    }
+
+    fn is_positive(&self, expr: &Expr) -> bool {
+        self.constant_sign(expr).is_ok_and(|sign| sign > 0.0)
+    }
+
+    /// Sign of a nonzero limit value. It has to be a number: the sign of `a` is unknown.
+    fn constant_sign(&self, expr: &Expr) -> Result<f64, LimitError> {
+        match expr.eval(&HashMap::new()) {
+            Ok(v) if v != 0.0 && !v.is_nan() => Ok(v.signum()),
+            _ => Err(LimitError::Unresolved),
+        }
+    }
+
+    /// Sign of `expr` just next to the target, on this evaluator's side.
+    fn sign_near(&self, expr: &Expr) -> Result<f64, LimitError> {
+        let v = self.value_near(expr)?;
+        if v == 0.0 || v.is_nan() {
+            Err(LimitError::Unresolved)
+        } else {
+            Ok(v.signum())
+        }
+    }
+
+    /// Numeric value of `expr` close to the target. Backs off while the value is zero or NaN,
+    /// which is often just underflow, as for `exp(-x)` at `x = 10^6`.
+    fn value_near(&self, expr: &Expr) -> Result<f64, LimitError> {
+        let mut values = self.params.clone();
+        let mut last = f64::NAN;
+        for h in [1e-6, 1e-4, 1e-2] {
+            let x = match self.target {
+                Target::Point(point) => {
+                    let a = point
+                        .eval(self.params)
+                        .map_err(|_| LimitError::Unresolved)?;
+                    let h = h * (1.0 + a.abs());
+                    match self.side {
+                        Side::Below => a - h,
+                        Side::Above => a + h,
+                    }
+                }
+                Target::PosInfinity => 1.0 / h,
+                Target::NegInfinity => -1.0 / h,
+            };
+            values.insert(self.var, x);
+            last = expr.eval(&values).map_err(|_| LimitError::Unresolved)?;
+            if last != 0.0 && !last.is_nan() {
+                break;
+            }
+        }
+        Ok(last)
+    }
+}
+
+/// Whether every part of `expr` evaluates to a finite number, ruling out `0^0` as well. Plain
+/// evaluation isn't enough: `exp(-1/0)` comes out as `0`.
+fn is_defined(expr: &Expr, params: &HashMap<char, f64>) -> bool {
+    let parts_defined = match expr {
+        Expr::Call { arg, .. } => is_defined(arg, params),
+        Expr::UnaryOp { expr, .. } => is_defined(expr, params),
+        Expr::BinaryOp {
+            op: BinaryOp::Pow,
+            left,
+            right,
+        } => {
+            is_defined(left, params)
+                && is_defined(right, params)
+                && !(left.eval(params) == Ok(0.0) && right.eval(params).is_ok_and(|e| e <= 0.0))
+        }
+        Expr::BinaryOp { left, right, .. } => is_defined(left, params) && is_defined(right, params),
+        _ => true,
+    };
+    parts_defined && expr.eval(params).is_ok_and(f64::is_finite)
+}
+
+/// Whether a limit value is zero, exactly if possible and numerically otherwise.
+fn is_zero(expr: &Expr, params: &HashMap<char, f64>) -> bool {
+    let expr = expr.simplify_full();
+    match Rational::from_expr(&expr) {
+        Some(exact) => exact.is_zero(),
+        None => expr.eval(params).is_ok_and(|v| v.abs() < 1e-12),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, Limit, Strategy, Target};
+    use crate::{parse, Expr};
+
+    fn limit_at(input: &str, point: i32, direction: Direction) -> (Limit, Strategy) {
+        let result = parse(input)
+            .unwrap()
+            .limit('x', &Target::Point(Expr::Number(point)), direction)
+            .unwrap();
+        (result.limit, result.strategy)
+    }
+
+    #[test]
+    fn substitution_respects_the_direction() {
+        assert_eq!(
+            limit_at("sqrt(x)", 0, Direction::Below).0,
+            Limit::DoesNotExist
+        );
+        assert_eq!(
+            limit_at("sqrt(x)", 0, Direction::Above).0,
+            Limit::Finite(Expr::Number(0))
+        );
+        assert_eq!(
+            limit_at("x^(1/2)", 0, Direction::Below).0,
+            Limit::DoesNotExist
+        );
+        assert_eq!(
+            limit_at("sqrt(x)", 4, Direction::Below),
+            (Limit::Finite(Expr::Number(2)), Strategy::Substitution)
+        );
+    }
+
+    #[test]
+    fn removable_singularities_cancel_before_lhopital() {
+        assert_eq!(
+            limit_at("(x^2-4)/(x-2)", 2, Direction::Both),
+            (Limit::Finite(Expr::Number(4)), Strategy::Simplification)
+        );
+        assert_eq!(
+            limit_at("(x^3-1)/(x^2-1)", 1, Direction::Both).0,
+            Limit::Finite(parse("3/2").unwrap())
+        );
+    }
+
+    #[test]
+    fn lhopital_for_transcendental_quotients() {
+        assert_eq!(
+            limit_at("(1-cos(x))/x^2", 0, Direction::Both),
+            (Limit::Finite(parse("1/2").unwrap()), Strategy::LHopital(2))
+        );
+    }
+
+    #[test]
+    fn zero_to_a_positive_power_is_zero() {
+        assert_eq!(
+            limit_at("0^x", 0, Direction::Above).0,
+            Limit::Finite(Expr::Number(0))
+        );
+        assert_eq!(
+            limit_at("x^x", 0, Direction::Above).0,
+            Limit::Finite(Expr::Number(1))
+        );
+    }
+}
@@ -1,9 +1,21 @@
+use crate::identities::Identities;
+use crate::rewrite::egraph::SaturationOptions;
+use crate::rewrite::RuleSet;
+
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 /// Represents an expression in the AST. An expression is just a variation of these enum members.
 pub enum Expr {
     Number(i32),
     Variable(char),
     Constant(char),
+    /// Multi-letter name such as a session binding (`ans`, `$2`, `area`), a constant otherwise.
+    Symbol(String),
+    /// Application of a built-in (see [`BUILTIN_FUNCTIONS`]) or user-defined function.
+    Call {
+        name: String,
+        arg: Box<Expr>,
+    },
     UnaryOp {
         op: UnaryOp,
         expr: Box<Expr>,
@@ -15,13 +27,28 @@ pub enum Expr {
     },
 }
 
+/// Functions every expression can call without defining them first.
+pub const BUILTIN_FUNCTIONS: [&str; 8] = ["sin", "cos", "tan", "asin", "atan", "exp", "ln", "sqrt"];
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
 /// Represents a unary operation in the AST.
 pub enum UnaryOp {
     Neg,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
 /// Represents a binary operation in the AST.
 pub enum BinaryOp {
     Add,
@@ -31,189 +58,187 @@ pub enum BinaryOp {
     Pow,
 }
 
-impl Expr {
-    /// Recursively simplify the AST.
-    /// - Combines all constant multiplications (e.g., 3*4*x^2 -> 12*x^2)
-    /// - Handles basic arithmetic simplifications (0, 1, etc.)
-    pub fn simplify(&self) -> Expr {
+/// How much simplification is applied to a result.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[non_exhaustive]
+pub enum SimplifyLevel {
+    /// Leave the expression exactly as produced.
+    None,
+    /// A single [`Expr::simplify`] pass.
+    #[default]
+    Basic,
+    /// [`Expr::simplify_full`].
+    Full,
+    /// [`Expr::simplify_full`] followed by [`Expr::cancel`].
+    Cancel,
+    /// [`Expr::simplify_full`] followed by [`Expr::expand`].
+    Expand,
+    /// [`Expr::simplify_full`] followed by [`Expr::cancel`] and [`Expr::factor`].
+    Factor,
+    /// [`Expr::simplify_full`] followed by [`Expr::saturate`] with the default options.
+    Saturate,
+    /// [`Expr::simplify_full`] followed by [`Expr::simplify_identities`] with every family of
+    /// identities, contracting.
+    Identities,
+}
+
+impl SimplifyLevel {
+    /// Every level, from least to most simplification, then the post-processing ones.
+    pub const ALL: [SimplifyLevel; 8] = [
+        SimplifyLevel::None,
+        SimplifyLevel::Basic,
+        SimplifyLevel::Full,
+        SimplifyLevel::Cancel,
+        SimplifyLevel::Expand,
+        SimplifyLevel::Factor,
+        SimplifyLevel::Saturate,
+        SimplifyLevel::Identities,
+    ];
+
+    /// Name used to select the level on the command line.
+    pub fn name(&self) -> &'static str {
         match self {
-            // Multiplication: recursively combine all constant factors
-            Expr::BinaryOp {
-                op: BinaryOp::Mul,
-                left,
-                right,
-            } => {
-                // Helper to flatten multiplication tree and collect constants
-                fn flatten_mul(expr: &Expr, constants: &mut i32, others: &mut Vec<Expr>) {
-                    match expr {
-                        Expr::BinaryOp {
-                            op: BinaryOp::Mul,
-                            left,
-                            right,
-                        } => {
-                            flatten_mul(left, constants, others);
-                            flatten_mul(right, constants, others);
-                        }
-                        Expr::Number(n) => *constants *= *n,
-                        other => others.push(other.clone()),
-                    }
-                }
-                let mut const_product = 1;
-                let mut others = Vec::new();
-                flatten_mul(&left.simplify(), &mut const_product, &mut others);
-                flatten_mul(&right.simplify(), &mut const_product, &mut others);
-
-                // If any factor is zero, the whole product is zero
-                if const_product == 0 || others.iter().any(|e| matches!(e, Expr::Number(0))) {
-                    return Expr::Number(0);
-                }
+            SimplifyLevel::None => "none",
+            SimplifyLevel::Basic => "basic",
+            SimplifyLevel::Full => "full",
+            SimplifyLevel::Cancel => "cancel",
+            SimplifyLevel::Expand => "expand",
+            SimplifyLevel::Factor => "factor",
+            SimplifyLevel::Saturate => "saturate",
+            SimplifyLevel::Identities => "identities",
+        }
+    }
 
-                // Remove all 1s from others (except if all are removed)
-                others.retain(|e| !matches!(e, Expr::Number(1)));
+    /// Looks up a level by its [`SimplifyLevel::name`].
+    pub fn from_name(name: &str) -> Option<SimplifyLevel> {
+        Self::ALL.into_iter().find(|level| level.name() == name)
+    }
 
-                // If only constant remains
-                if others.is_empty() {
-                    return Expr::Number(const_product);
-                }
-                // If constant is 1, just multiply the rest
-                if const_product == 1 && others.len() == 1 {
-                    return others.into_iter().next().unwrap();
-                }
-                // If constant is not 1, prepend it
-                let mut expr = if const_product != 1 {
-                    Expr::BinaryOp {
-                        op: BinaryOp::Mul,
-                        left: Box::new(Expr::Number(const_product)),
-                        right: Box::new(others[0].clone()),
-                    }
-                } else {
-                    others[0].clone()
-                };
-                for other in &others[1..] {
-                    expr = Expr::BinaryOp {
-                        op: BinaryOp::Mul,
-                        left: Box::new(expr),
-                        right: Box::new(other.clone()),
-                    };
-                }
-                expr
-            }
-            Expr::BinaryOp {
-                op: BinaryOp::Add,
-                left,
-                right,
-            } => {
-                let left = left.simplify();
-                let right = right.simplify();
-                match (&left, &right) {
-                    (Expr::Number(0), r) => r.clone(),
-                    (l, Expr::Number(0)) => l.clone(),
-                    (Expr::Number(a), Expr::Number(b)) => Expr::Number(a + b),
-                    _ => Expr::BinaryOp {
-                        op: BinaryOp::Add,
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    },
-                }
+    /// Simplifies `expr` as much as this level allows.
+    pub fn apply(&self, expr: &Expr) -> Expr {
+        match self {
+            SimplifyLevel::None => expr.clone(),
+            SimplifyLevel::Basic => expr.simplify(),
+            SimplifyLevel::Full => expr.simplify_full(),
+            SimplifyLevel::Cancel => expr.simplify_full().cancel(),
+            SimplifyLevel::Expand => expr.simplify_full().expand(),
+            SimplifyLevel::Factor => expr.simplify_full().cancel().factor(),
+            SimplifyLevel::Saturate => {
+                expr.simplify_full()
+                    .saturate(&SaturationOptions::default())
+                    .expr
             }
+            SimplifyLevel::Identities => expr
+                .simplify_full()
+                .simplify_identities(&Identities::default()),
+        }
+    }
+}
+
+impl Expr {
+    /// Binding strength of the outermost operation, used by the printers to decide where
+    /// parentheses are needed. Atoms bind tightest.
+    pub(crate) fn precedence(&self) -> u8 {
+        match self {
             Expr::BinaryOp {
-                op: BinaryOp::Sub,
-                left,
-                right,
-            } => {
-                let left = left.simplify();
-                let right = right.simplify();
-                match (&left, &right) {
-                    (l, Expr::Number(0)) => l.clone(),
-                    (Expr::Number(a), Expr::Number(b)) => Expr::Number(a - b),
-                    _ => Expr::BinaryOp {
-                        op: BinaryOp::Sub,
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    },
-                }
-            }
+                op: BinaryOp::Add | BinaryOp::Sub,
+                ..
+            } => 1,
             Expr::BinaryOp {
-                op: BinaryOp::Div,
-                left,
-                right,
-            } => {
-                let left = left.simplify();
-                let right = right.simplify();
-                match (&left, &right) {
-                    (Expr::Number(0), _) => Expr::Number(0),
-                    (l, Expr::Number(1)) => l.clone(),
-                    (Expr::Number(a), Expr::Number(b)) if *b != 0 => Expr::Number(a / b),
-                    _ => Expr::BinaryOp {
-                        op: BinaryOp::Div,
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    },
-                }
-            }
+                op: BinaryOp::Mul | BinaryOp::Div,
+                ..
+            } => 2,
+            Expr::UnaryOp { .. } => 3,
+            Expr::Number(n) if *n < 0 => 3,
             Expr::BinaryOp {
-                op: BinaryOp::Pow,
-                left,
-                right,
-            } => {
-                let left = left.simplify();
-                let right = right.simplify();
-                match (&left, &right) {
-                    (_, Expr::Number(0)) => Expr::Number(1),
-                    (l, Expr::Number(1)) => l.clone(),
-                    (Expr::Number(a), Expr::Number(b)) => Expr::Number(a.pow(*b as u32)),
-                    _ => Expr::BinaryOp {
-                        op: BinaryOp::Pow,
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    },
-                }
+                op: BinaryOp::Pow, ..
+            } => 4,
+            _ => 5,
+        }
+    }
+
+    /// Whether the printers may write `left * right` without an operator: a number followed by
+    /// a variable or by a power of something other than a number, as in `2x` or `3x^2`. Any
+    /// other factor would run into the number, as in `23^x` for `2*3^x` or `x3` for `x*3`.
+    pub(crate) fn is_implicit_product(left: &Expr, right: &Expr) -> bool {
+        match (left, right) {
+            (Expr::Number(_), Expr::Variable(_)) => true,
+            (
+                Expr::Number(_),
+                Expr::BinaryOp {
+                    op: BinaryOp::Pow,
+                    left: base,
+                    ..
+                },
+            ) => !matches!(**base, Expr::Number(_)),
+            _ => false,
+        }
+    }
+
+    /// Whether the symbol `var` occurs anywhere in the expression.
+    pub fn contains_variable(&self, var: char) -> bool {
+        match self {
+            Expr::Variable(c) | Expr::Constant(c) => *c == var,
+            Expr::Number(_) | Expr::Symbol(_) => false,
+            Expr::Call { arg, .. } => arg.contains_variable(var),
+            Expr::UnaryOp { expr, .. } => expr.contains_variable(var),
+            Expr::BinaryOp { left, right, .. } => {
+                left.contains_variable(var) || right.contains_variable(var)
             }
-            Expr::UnaryOp {
-                op: UnaryOp::Neg,
-                expr,
-            } => {
-                let expr = expr.simplify();
-                if let Expr::Number(n) = expr {
-                    Expr::Number(-n)
-                } else {
-                    Expr::UnaryOp {
-                        op: UnaryOp::Neg,
-                        expr: Box::new(expr),
-                    }
-                }
+        }
+    }
+
+    /// Simplifies with the built-in rules of [`RuleSet::builtin`], written out in
+    /// [`DEFAULT_RULES`](crate::rewrite::DEFAULT_RULES):
+    /// - Combines all constant multiplications (e.g., 3*4*x^2 -> 12*x^2)
+    /// - Handles basic arithmetic simplifications (0, 1, etc.)
+    pub fn simplify(&self) -> Expr {
+        RuleSet::builtin().rewrite(self)
+    }
+
+    /// Repeats [`Expr::simplify`] until the expression stops changing, since a single pass can
+    /// expose new opportunities (e.g. `(x*1)^1` only becomes `x` on the second pass).
+    pub fn simplify_full(&self) -> Expr {
+        const MAX_PASSES: usize = 32;
+        let mut current = self.simplify();
+        for _ in 0..MAX_PASSES {
+            let next = current.simplify();
+            if next == current {
+                break;
             }
-            _ => self.clone(),
+            current = next;
         }
+        current
     }
 
+    /// Renders the expression on a single line, adding parentheses only where they are needed
+    /// for it to parse back to the same tree.
     pub fn pretty(&self) -> String {
         match self {
             Expr::Number(n) => n.to_string(),
             Expr::Variable(c) => c.to_string(),
             Expr::Constant(c) => c.to_string(),
+            Expr::Symbol(name) => name.clone(),
+            Expr::Call { name, arg } => format!("{name}({})", arg.pretty()),
+            // `-2` would read back as the number -2
             Expr::UnaryOp {
                 op: UnaryOp::Neg,
                 expr,
-            } => format!("-{}", expr.pretty()),
+            } if matches!(**expr, Expr::Number(n) if n > 0) => format!("-({})", expr.pretty()),
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+            } => format!("-{}", expr.pretty_with_parens(3)),
             Expr::BinaryOp {
                 op: BinaryOp::Mul,
                 left,
                 right,
             } => {
-                // Omit * between number and variable/power
-                match (&**left, &**right) {
-                    (Expr::Number(_), Expr::Variable(_))
-                    | (
-                        Expr::Number(_),
-                        Expr::BinaryOp {
-                            op: BinaryOp::Pow, ..
-                        },
-                    )
-                    | (Expr::Variable(_), Expr::Number(_)) => {
-                        format!("{}{}", left.pretty(), right.pretty())
-                    }
-                    _ => format!("{}*{}", left.pretty(), right.pretty()),
+                let (l, r) = (left.pretty_with_parens(2), right.pretty_with_parens(3));
+                if Expr::is_implicit_product(left, right) {
+                    format!("{l}{r}")
+                } else {
+                    format!("{l}*{r}")
                 }
             }
             Expr::BinaryOp {
@@ -221,28 +246,159 @@ impl Expr {
                 left,
                 right,
             } => {
-                format!("{}^{}", left.pretty(), right.pretty())
+                format!(
+                    "{}^{}",
+                    left.pretty_with_parens(5),
+                    right.pretty_with_parens(4)
+                )
             }
             Expr::BinaryOp {
                 op: BinaryOp::Add,
                 left,
                 right,
             } => {
-                format!("{}+{}", left.pretty(), right.pretty())
+                format!(
+                    "{}+{}",
+                    left.pretty_with_parens(1),
+                    right.pretty_with_parens(2)
+                )
             }
             Expr::BinaryOp {
                 op: BinaryOp::Sub,
                 left,
                 right,
             } => {
-                format!("{}-{}", left.pretty(), right.pretty())
+                format!(
+                    "{}-{}",
+                    left.pretty_with_parens(1),
+                    right.pretty_with_parens(2)
+                )
             }
             Expr::BinaryOp {
                 op: BinaryOp::Div,
                 left,
                 right,
             } => {
-                format!("{}/{}", left.pretty(), right.pretty())
+                format!(
+                    "{}/{}",
+                    left.pretty_with_parens(2),
+                    right.pretty_with_parens(3)
+                )
+            }
+        }
+    }
+
+    /// [`Expr::pretty`], wrapped in parentheses when the expression binds looser than `min_prec`.
+    fn pretty_with_parens(&self, min_prec: u8) -> String {
+        if self.precedence() < min_prec {
+            format!("({})", self.pretty())
+        } else {
+            self.pretty()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BinaryOp, Expr, UnaryOp};
+    use crate::parse;
+
+    fn neg(expr: Expr) -> Expr {
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr: Box::new(expr),
+        }
+    }
+
+    fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+        Expr::BinaryOp {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    fn assert_round_trip(expr: &Expr) {
+        let printed = expr.pretty();
+        assert_eq!(parse(&printed).as_ref(), Ok(expr), "printed as {printed}");
+    }
+
+    #[test]
+    fn unary_minus_binds_looser_than_powers() {
+        let square = binary(BinaryOp::Pow, Expr::Variable('x'), Expr::Number(2));
+        assert_eq!(parse("-x^2"), Ok(neg(square.clone())));
+        assert_eq!(neg(square).pretty(), "-x^2");
+        let negated_base = binary(BinaryOp::Pow, neg(Expr::Variable('x')), Expr::Number(2));
+        assert_eq!(negated_base.pretty(), "(-x)^2");
+        assert_eq!(parse("-2^2"), parse("-(2^2)"));
+        assert_eq!(parse("2^-x^2"), parse("2^(-(x^2))"));
+    }
+
+    #[test]
+    fn negative_numbers_are_literals() {
+        assert_eq!(parse("-2"), Ok(Expr::Number(-2)));
+        assert_eq!(parse("-(2)"), Ok(neg(Expr::Number(2))));
+        assert_eq!(neg(Expr::Number(2)).pretty(), "-(2)");
+        let product = binary(BinaryOp::Mul, Expr::Number(-2), Expr::Variable('x'));
+        assert_eq!(product.pretty(), "-2x");
+        assert_round_trip(&product);
+    }
+
+    #[test]
+    fn only_numbers_are_juxtaposed_before_variables() {
+        let times = |left, right| binary(BinaryOp::Mul, left, right);
+        assert_eq!(times(Expr::Variable('x'), Expr::Number(3)).pretty(), "x*3");
+        assert_eq!(
+            times(Expr::Variable('x'), Expr::Number(-1)).pretty(),
+            "x*-1"
+        );
+        assert_eq!(times(Expr::Number(3), Expr::Variable('x')).pretty(), "3x");
+        let power = binary(BinaryOp::Pow, Expr::Number(3), Expr::Variable('x'));
+        assert_eq!(times(Expr::Number(2), power).pretty(), "2*3^x");
+    }
+
+    #[test]
+    fn pretty_parses_back_to_the_same_tree() {
+        let atoms = [
+            Expr::Number(2),
+            Expr::Number(-3),
+            Expr::Variable('x'),
+            Expr::Constant('a'),
+            Expr::Call {
+                name: "sin".to_string(),
+                arg: Box::new(Expr::Variable('x')),
+            },
+        ];
+        let ops = [
+            BinaryOp::Add,
+            BinaryOp::Sub,
+            BinaryOp::Mul,
+            BinaryOp::Div,
+            BinaryOp::Pow,
+        ];
+        let combine = |parts: &[Expr]| {
+            let mut exprs: Vec<Expr> = parts.to_vec();
+            exprs.extend(parts.iter().cloned().map(neg));
+            for op in &ops {
+                for left in parts {
+                    for right in parts {
+                        exprs.push(binary(op.clone(), left.clone(), right.clone()));
+                    }
+                }
+            }
+            exprs
+        };
+        let shallow = combine(&atoms);
+        for expr in &shallow {
+            assert_round_trip(expr);
+        }
+        for op in &ops {
+            for (i, left) in shallow.iter().enumerate().step_by(3) {
+                for right in shallow.iter().skip(i % 5).step_by(5) {
+                    let expr = binary(op.clone(), left.clone(), right.clone());
+                    assert_round_trip(&expr);
+                    assert_round_trip(&neg(expr));
+                }
             }
         }
     }
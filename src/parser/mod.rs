@@ -1,15 +1,107 @@
 pub mod ast;
-use crate::tokenizer::tokens::{self, Token};
-use ast::{BinaryOp, Expr, UnaryOp};
+use std::collections::HashSet;
+
+use crate::tokenizer::tokens::Token;
+use ast::{BinaryOp, Expr, UnaryOp, BUILTIN_FUNCTIONS};
 
 pub struct Parser {
     tokens: Vec<Token>,
     pos: usize,
+    /// Single letter names that are parsed as calls rather than implicit multiplication when
+    /// followed by `(`. Multi-letter names followed by `(` are always calls.
+    functions: HashSet<char>,
+    /// Multi-letter names besides the built-in functions that are kept whole, see
+    /// [`Parser::split_words`].
+    names: HashSet<String>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, pos: 0 }
+        Parser {
+            tokens,
+            pos: 0,
+            functions: HashSet::new(),
+            names: HashSet::new(),
+        }
+    }
+
+    /// Treats `c(...)` as a call for every `c` in `functions`, so `f(2x)` calls `f` instead of
+    /// multiplying `f` by `2x`.
+    pub fn with_functions(mut self, functions: HashSet<char>) -> Self {
+        self.functions = functions;
+        self
+    }
+
+    /// Keeps every word in `names` whole, as the name of a binding or a function.
+    pub fn with_names(mut self, names: HashSet<String>) -> Self {
+        self.names = names;
+        self
+    }
+
+    /// Splits every word that isn't a built-in function, one of the known names or a result
+    /// reference like `$2` into letters and numbers multiplied together, so `ax^2` is `a*x^2`
+    /// and `x2` is `x*2`. Known names inside a word are kept, as in `xsin(x)`.
+    ///
+    /// Returns the word as the error when it can't be split: when it contains `_`, or when it
+    /// is followed by `(` without ending in a function, which makes it look like a call to an
+    /// unknown function such as `cosh(x)`.
+    pub fn split_words(&mut self) -> Result<(), String> {
+        let mut tokens = Vec::with_capacity(self.tokens.len());
+        for (i, token) in self.tokens.iter().enumerate() {
+            let Token::IDENT(word) = token else {
+                tokens.push(token.clone());
+                continue;
+            };
+            if word.starts_with('$') || self.is_name(word) {
+                tokens.push(token.clone());
+                continue;
+            }
+            let pieces = self.split_word(word).ok_or_else(|| word.clone())?;
+            let called = self.tokens[i + 1..]
+                .iter()
+                .find(|t| !matches!(t, Token::WhiteSpace(_)))
+                == Some(&Token::LEFTPAREN);
+            let ends_in_function = match pieces.last() {
+                Some(Token::IDENT(_)) => true,
+                Some(Token::CONSTANT(c)) => self.functions.contains(c),
+                _ => false,
+            };
+            if called && !ends_in_function {
+                return Err(word.clone());
+            }
+            tokens.extend(pieces);
+        }
+        self.tokens = tokens;
+        Ok(())
+    }
+
+    /// `word` as a sequence of known names, numbers and single letters, preferring the longest
+    /// name at each position. `None` if it contains anything else.
+    fn split_word(&self, word: &str) -> Option<Vec<Token>> {
+        let mut pieces = Vec::new();
+        let mut rest = word;
+        while let Some(first) = rest.chars().next() {
+            let name = (2..=rest.len())
+                .rev()
+                .map(|len| &rest[..len])
+                .find(|prefix| self.is_name(prefix));
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let (piece, len) = match name {
+                Some(name) => (Token::IDENT(name.to_string()), name.len()),
+                None if digits > 0 => (Token::NUMBER(rest[..digits].parse().ok()?), digits),
+                None if first == 'x' => (Token::VARIABLE('x'), 1),
+                None if first.is_ascii_lowercase() => (Token::CONSTANT(first), 1),
+                None => return None,
+            };
+            pieces.push(piece);
+            rest = &rest[len..];
+        }
+        Some(pieces)
+    }
+
+    /// Whether `word` is kept whole: a built-in function or one of [`Parser::names`].
+    fn is_name(&self, word: &str) -> bool {
+        BUILTIN_FUNCTIONS.contains(&word) || self.names.contains(word)
     }
 
     /// Peek at the next non-whitespace token.
@@ -30,20 +122,14 @@ impl Parser {
         None
     }
 
+    /// Parses the whole token stream. Returns `None` if the tokens don't form an expression or
+    /// if anything other than whitespace is left over after it.
     pub fn parse(&mut self) -> Option<Expr> {
         let expr = self.parse_expr()?;
-        // Ensure all tokens are consumed (except whitespace or EOF)
-        // Not needed as parser already ensures no whitespaces are consumed
-        // while let Some(token) = self.peek() {
-        //     match token {
-        //         Token::WhiteSpace(_) => {
-        //             self.next();
-        //         }
-        //         Token::EOF => break,
-        //         _ => return None, // Unexpected token after valid expr
-        //     }
-        // }
-        Some(expr)
+        match self.peek() {
+            None | Some(Token::EOF) => Some(expr),
+            Some(_) => None,
+        }
     }
 
     // expr = term (('+' | '-') term)*
@@ -73,11 +159,23 @@ impl Parser {
         Some(node)
     }
 
-    // term = factor (('*' | '/') factor)*
+    // term = factor (('*' | '/')? factor)*
     fn parse_term(&mut self) -> Option<Expr> {
         let mut node = self.parse_factor()?;
         while let Some(tok) = self.peek() {
             match tok {
+                // Implicit multiplication, e.g. `2x` or `3(x+1)`
+                Token::NUMBER(_)
+                | Token::VARIABLE(_)
+                | Token::CONSTANT(_)
+                | Token::IDENT(_)
+                | Token::LEFTPAREN => {
+                    node = Expr::BinaryOp {
+                        op: BinaryOp::Mul,
+                        left: Box::new(node),
+                        right: Box::new(self.parse_factor()?),
+                    };
+                }
                 Token::MUL => {
                     self.next();
                     node = Expr::BinaryOp {
@@ -100,8 +198,31 @@ impl Parser {
         Some(node)
     }
 
-    // factor = base ('^' factor)?
+    // factor = '-' NUMBER | '-' factor | power
+    // A minus binds looser than '^', so `-x^2` is -(x^2), while `-2` on its own is a negative
+    // number rather than the negation of 2
     fn parse_factor(&mut self) -> Option<Expr> {
+        if self.peek() != Some(&Token::MINUS) {
+            return self.parse_power();
+        }
+        self.next();
+        let mut ahead = self.tokens[self.pos..]
+            .iter()
+            .filter(|t| !matches!(t, Token::WhiteSpace(_)));
+        if let (Some(&Token::NUMBER(n)), next) = (ahead.next(), ahead.next()) {
+            if n > 0 && next != Some(&Token::POW) {
+                self.next();
+                return Some(Expr::Number(-n));
+            }
+        }
+        Some(Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr: Box::new(self.parse_factor()?),
+        })
+    }
+
+    // power = base ('^' factor)?
+    fn parse_power(&mut self) -> Option<Expr> {
         let mut node = self.parse_base()?;
         if let Some(Token::POW) = self.peek() {
             self.next();
@@ -114,7 +235,8 @@ impl Parser {
         Some(node)
     }
 
-    // base = NUMBER | VARIABLE | '(' expr ')' | '-' base
+    // base = NUMBER | VARIABLE | CONSTANT | IDENT | call | '(' expr ')'
+    // call = (IDENT | CONSTANT) '(' expr ')' | BUILTIN base
     fn parse_base(&mut self) -> Option<Expr> {
         match self.peek()? {
             Token::NUMBER(n) => {
@@ -130,8 +252,20 @@ impl Parser {
             Token::CONSTANT(c) => {
                 let c = *c;
                 self.next();
+                if self.functions.contains(&c) && self.peek() == Some(&Token::LEFTPAREN) {
+                    return self.parse_call(c.to_string());
+                }
                 Some(Expr::Constant(c))
             }
+            Token::IDENT(name) => {
+                let name = name.clone();
+                self.next();
+                // Built-in functions take their argument without parentheses too, as in `sinx`
+                if self.peek() == Some(&Token::LEFTPAREN) || BUILTIN_FUNCTIONS.contains(&&*name) {
+                    return self.parse_call(name);
+                }
+                Some(Expr::Symbol(name))
+            }
             Token::LEFTPAREN => {
                 self.next();
                 let expr = self.parse_expr();
@@ -142,14 +276,61 @@ impl Parser {
                     None
                 }
             }
-            Token::MINUS => {
-                self.next();
-                Some(Expr::UnaryOp {
-                    op: UnaryOp::Neg,
-                    expr: Box::new(self.parse_base()?),
-                })
-            }
             _ => None,
         }
     }
+
+    /// Parses the parenthesized argument of a call to `name`.
+    fn parse_call(&mut self, name: String) -> Option<Expr> {
+        let arg = self.parse_base()?;
+        Some(Expr::Call {
+            name,
+            arg: Box::new(arg),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{parse, parse_with_names, Error};
+
+    #[test]
+    fn unknown_words_are_products_of_letters_and_numbers() {
+        assert_eq!(parse("ax^2+bx+c"), parse("a*x^2+b*x+c"));
+        assert_eq!(parse("xy"), parse("x*y"));
+        assert_eq!(parse("x2"), parse("x*2"));
+        assert_eq!(parse("ab12c"), parse("a*b*12*c"));
+    }
+
+    #[test]
+    fn function_names_are_kept_inside_words() {
+        assert_eq!(parse("xsin(x)"), parse("x*sin(x)"));
+        assert_eq!(parse("sinx"), parse("sin(x)"));
+        assert_eq!(parse("2xexp(x)"), parse("2*x*exp(x)"));
+    }
+
+    #[test]
+    fn words_that_cant_be_split_are_errors() {
+        assert_eq!(parse("my_var"), Err(Error::UnknownName("my_var".into())));
+        assert_eq!(parse("cosh(x)"), Err(Error::UnknownName("cosh".into())));
+        assert_eq!(parse("ax (x+1)"), Err(Error::UnknownName("ax".into())));
+    }
+
+    #[test]
+    fn known_names_and_result_references_are_symbols() {
+        let names = HashSet::from(["area".to_string(), "ans".to_string()]);
+        let parsed = parse_with_names("area*ans+$2", HashSet::new(), names.clone()).unwrap();
+        assert_eq!(parsed.pretty(), "area*ans+$2");
+        let parsed = parse_with_names("areax", HashSet::new(), names).unwrap();
+        assert_eq!(parsed.pretty(), "area*x");
+        assert_eq!(parse("ans"), parse("a*n*s"));
+    }
+
+    #[test]
+    fn single_letter_functions_end_a_called_word() {
+        let parsed = crate::parse_with_functions("af(x)", HashSet::from(['f'])).unwrap();
+        assert_eq!(parsed.pretty(), "a*f(x)");
+    }
 }
@@ -0,0 +1,547 @@
+pub mod multivariate;
+
+use std::fmt::Display;
+
+use crate::algebra::{pow, scaled, signed_sum, variable};
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+use crate::rational::Rational;
+
+/// Highest degree a [`Polynomial`] may reach. Anything above it is treated like an overflow,
+/// so `x^1000000` isn't expanded into a million coefficients.
+pub const MAX_DEGREE: usize = 1000;
+
+/// Polynomial in one variable with exact rational coefficients.
+///
+/// Arithmetic is checked like that of [`Rational`]: operations return `None` when a coefficient
+/// overflows or the degree would exceed [`MAX_DEGREE`].
+///
+/// ```
+/// use derivative_calculator::{parse, polynomial::Polynomial};
+///
+/// let p = Polynomial::from_expr(&parse("(x-1)(x+2)^2")?, 'x').unwrap();
+/// assert_eq!(p.degree(), Some(3));
+/// assert_eq!(p.to_string(), "x^3+3x^2-4");
+/// assert_eq!(p.rational_roots().len(), 2);
+/// # Ok::<(), derivative_calculator::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Polynomial {
+    var: char,
+    /// Coefficient of `var^k` at index `k`, without trailing zeros.
+    coefficients: Vec<Rational>,
+}
+
+impl Polynomial {
+    /// The polynomial with these coefficients, lowest degree first.
+    pub fn new(var: char, coefficients: Vec<Rational>) -> Polynomial {
+        let mut polynomial = Polynomial { var, coefficients };
+        polynomial.trim();
+        polynomial
+    }
+
+    /// The constant polynomial `c`.
+    pub fn constant(var: char, c: Rational) -> Polynomial {
+        Polynomial::new(var, vec![c])
+    }
+
+    /// `c * var^k`.
+    pub fn monomial(var: char, c: Rational, k: usize) -> Polynomial {
+        let mut coefficients = vec![Rational::ZERO; k + 1];
+        coefficients[k] = c;
+        Polynomial::new(var, coefficients)
+    }
+
+    pub fn var(&self) -> char {
+        self.var
+    }
+
+    /// Coefficients lowest degree first, empty for the zero polynomial.
+    pub fn coefficients(&self) -> &[Rational] {
+        &self.coefficients
+    }
+
+    /// Coefficient of `var^k`.
+    pub fn coefficient(&self, k: usize) -> Rational {
+        self.coefficients.get(k).copied().unwrap_or(Rational::ZERO)
+    }
+
+    /// Degree of the polynomial, `None` for zero.
+    pub fn degree(&self) -> Option<usize> {
+        self.coefficients.len().checked_sub(1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coefficients.is_empty()
+    }
+
+    /// Coefficient of the highest power, zero for the zero polynomial.
+    pub fn leading(&self) -> Rational {
+        self.coefficients.last().copied().unwrap_or(Rational::ZERO)
+    }
+
+    /// Reads a polynomial in `var` from an expression built from numbers, `var` and
+    /// `+ - * /`, with non-negative integer powers and only numbers as divisors. `None` for
+    /// anything else, including other letters.
+    pub fn from_expr(expr: &Expr, var: char) -> Option<Polynomial> {
+        match expr {
+            Expr::Number(n) => Some(Polynomial::constant(var, Rational::integer(*n as i64))),
+            Expr::Variable(c) | Expr::Constant(c) if *c == var => {
+                Some(Polynomial::monomial(var, Rational::ONE, 1))
+            }
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+            } => Polynomial::from_expr(expr, var)?.checked_scale(-Rational::ONE),
+            Expr::BinaryOp { op, left, right } => {
+                let left = Polynomial::from_expr(left, var)?;
+                match op {
+                    BinaryOp::Add => left.checked_add(&Polynomial::from_expr(right, var)?),
+                    BinaryOp::Sub => left.checked_sub(&Polynomial::from_expr(right, var)?),
+                    BinaryOp::Mul => left.checked_mul(&Polynomial::from_expr(right, var)?),
+                    BinaryOp::Div => {
+                        left.checked_scale(Rational::ONE.checked_div(Rational::from_expr(right)?)?)
+                    }
+                    BinaryOp::Pow => match **right {
+                        Expr::Number(n) if n >= 0 => left.checked_pow(n as u32),
+                        _ => None,
+                    },
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The polynomial as an expression, highest power first: `x^2/2-3x+1`. `None` if a
+    /// coefficient doesn't fit an `i32`.
+    pub fn to_expr(&self) -> Option<Expr> {
+        let mut terms = Vec::new();
+        for (k, &c) in self.coefficients.iter().enumerate().rev() {
+            if c.is_zero() {
+                continue;
+            }
+            let power = match k {
+                0 => None,
+                1 => Some(variable(self.var)),
+                _ => Some(pow(&variable(self.var), &Expr::Number(k as i32))),
+            };
+            terms.push(scaled(c, None, power)?);
+        }
+        Some(signed_sum(terms))
+    }
+
+    /// Value at `x`, `None` on overflow.
+    pub fn eval(&self, x: Rational) -> Option<Rational> {
+        self.coefficients
+            .iter()
+            .rev()
+            .try_fold(Rational::ZERO, |acc, &c| acc.checked_mul(x)?.checked_add(c))
+    }
+
+    pub fn checked_add(&self, other: &Polynomial) -> Option<Polynomial> {
+        let len = self.coefficients.len().max(other.coefficients.len());
+        let coefficients = (0..len)
+            .map(|k| self.coefficient(k).checked_add(other.coefficient(k)))
+            .collect::<Option<_>>()?;
+        Some(Polynomial::new(self.var, coefficients))
+    }
+
+    pub fn checked_sub(&self, other: &Polynomial) -> Option<Polynomial> {
+        self.checked_add(&other.checked_scale(-Rational::ONE)?)
+    }
+
+    pub fn checked_mul(&self, other: &Polynomial) -> Option<Polynomial> {
+        if self.is_zero() || other.is_zero() {
+            return Some(Polynomial::new(self.var, Vec::new()));
+        }
+        let len = self.coefficients.len() + other.coefficients.len() - 1;
+        if len > MAX_DEGREE + 1 {
+            return None;
+        }
+        let mut coefficients = vec![Rational::ZERO; len];
+        for (i, &a) in self.coefficients.iter().enumerate() {
+            for (j, &b) in other.coefficients.iter().enumerate() {
+                coefficients[i + j] = coefficients[i + j].checked_add(a.checked_mul(b)?)?;
+            }
+        }
+        Some(Polynomial::new(self.var, coefficients))
+    }
+
+    /// Every coefficient multiplied by `factor`.
+    pub fn checked_scale(&self, factor: Rational) -> Option<Polynomial> {
+        let coefficients = self
+            .coefficients
+            .iter()
+            .map(|c| c.checked_mul(factor))
+            .collect::<Option<_>>()?;
+        Some(Polynomial::new(self.var, coefficients))
+    }
+
+    pub fn checked_pow(&self, exp: u32) -> Option<Polynomial> {
+        if self.degree().unwrap_or(0).checked_mul(exp as usize)? > MAX_DEGREE {
+            return None;
+        }
+        (0..exp).try_fold(Polynomial::constant(self.var, Rational::ONE), |acc, _| {
+            acc.checked_mul(self)
+        })
+    }
+
+    /// Quotient and remainder of polynomial long division. `None` when dividing by zero or on
+    /// overflow.
+    pub fn checked_div_rem(&self, divisor: &Polynomial) -> Option<(Polynomial, Polynomial)> {
+        let divisor_degree = divisor.degree()?;
+        let mut remainder = self.clone();
+        let mut quotient = Polynomial::new(self.var, Vec::new());
+        while let Some(degree) = remainder.degree().filter(|&d| d >= divisor_degree) {
+            let c = remainder.leading().checked_div(divisor.leading())?;
+            let term = Polynomial::monomial(self.var, c, degree - divisor_degree);
+            quotient = quotient.checked_add(&term)?;
+            remainder = remainder.checked_sub(&divisor.checked_mul(&term)?)?;
+            // Cancelling the leading term exactly keeps the degree dropping
+            remainder.coefficients.truncate(degree);
+            remainder.trim();
+        }
+        Some((quotient, remainder))
+    }
+
+    pub fn derivative(&self) -> Option<Polynomial> {
+        let coefficients = self
+            .coefficients
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(k, c)| c.checked_mul(Rational::integer(k as i64)))
+            .collect::<Option<_>>()?;
+        Some(Polynomial::new(self.var, coefficients))
+    }
+
+    /// The same polynomial divided by its leading coefficient. Zero stays zero.
+    pub fn monic(&self) -> Option<Polynomial> {
+        if self.is_zero() {
+            return Some(self.clone());
+        }
+        self.checked_scale(Rational::ONE.checked_div(self.leading())?)
+    }
+
+    /// Monic greatest common divisor, by the Euclidean algorithm.
+    pub fn gcd(&self, other: &Polynomial) -> Option<Polynomial> {
+        let (mut a, mut b) = (self.clone(), other.clone());
+        while !b.is_zero() {
+            let (_, remainder) = a.checked_div_rem(&b)?;
+            (a, b) = (b, remainder.monic()?);
+        }
+        a.monic()
+    }
+
+    /// Distinct rational roots in increasing order, found by testing every candidate `p/q`
+    /// with `p` dividing the constant term and `q` the leading coefficient. Gives up (returning
+    /// the roots found so far) when those are too large to enumerate.
+    pub fn rational_roots(&self) -> Vec<Rational> {
+        let mut roots = Vec::new();
+        let Some(integral) = self.integral_coefficients() else {
+            return roots;
+        };
+        let Some(lowest) = integral.iter().position(|&c| c != 0) else {
+            return roots;
+        };
+        if lowest > 0 {
+            roots.push(Rational::ZERO);
+        }
+        let (Some(constants), Some(leadings)) = (
+            divisors(integral[lowest]),
+            divisors(*integral.last().unwrap_or(&1)),
+        ) else {
+            return roots;
+        };
+        for &p in &constants {
+            for &q in &leadings {
+                for candidate in [Rational::new(p, q), Rational::new(-p, q)]
+                    .into_iter()
+                    .flatten()
+                {
+                    if !roots.contains(&candidate)
+                        && self.eval(candidate).is_some_and(|v| v.is_zero())
+                    {
+                        roots.push(candidate);
+                    }
+                }
+            }
+        }
+        roots.sort();
+        roots
+    }
+
+    /// How many times `(var - root)` divides the polynomial.
+    pub fn multiplicity(&self, root: Rational) -> usize {
+        let factor = Polynomial::new(self.var, vec![-root, Rational::ONE]);
+        let mut remaining = self.clone();
+        let mut count = 0;
+        while !remaining.is_zero() {
+            match remaining.checked_div_rem(&factor) {
+                Some((quotient, remainder)) if remainder.is_zero() => {
+                    remaining = quotient;
+                    count += 1;
+                }
+                _ => break,
+            }
+        }
+        count
+    }
+
+    /// Splits off the content: returns `(c, p)` with `self = c*p`, where `p` has coprime integer
+    /// coefficients and a positive leading coefficient.
+    pub fn primitive(&self) -> Option<(Rational, Polynomial)> {
+        if self.is_zero() {
+            return Some((Rational::ONE, self.clone()));
+        }
+        let integral = self.integral_coefficients()?;
+        let divisor = integral
+            .iter()
+            .fold(0, |acc, &c| crate::rational::gcd(acc, c as i128)) as i64;
+        let divisor = if self.leading().is_negative() {
+            -divisor
+        } else {
+            divisor
+        };
+        let primitive = Polynomial::new(
+            self.var,
+            integral
+                .iter()
+                .map(|&c| Rational::integer(c / divisor))
+                .collect(),
+        );
+        let content = self.leading().checked_div(primitive.leading())?;
+        Some((content, primitive))
+    }
+
+    /// Square-free factorization by Yun's algorithm: monic factors `a_i`, each without repeated
+    /// roots and coprime to the others, with `self = c * a_1 * a_2^2 * a_3^3 ...`. Only the
+    /// factors of positive degree are listed, with their multiplicity.
+    pub fn square_free(&self) -> Option<Vec<(Polynomial, usize)>> {
+        let mut factors = Vec::new();
+        if self.degree().unwrap_or(0) == 0 {
+            return Some(factors);
+        }
+        let f = self.monic()?;
+        let derivative = f.derivative()?;
+        let repeated = f.gcd(&derivative)?;
+        let mut b = f.checked_div_rem(&repeated)?.0;
+        let mut d = derivative
+            .checked_div_rem(&repeated)?
+            .0
+            .checked_sub(&b.derivative()?)?;
+        let mut multiplicity = 1;
+        while b.degree().is_some_and(|degree| degree > 0) {
+            let a = b.gcd(&d)?;
+            b = b.checked_div_rem(&a)?.0;
+            d = d.checked_div_rem(&a)?.0.checked_sub(&b.derivative()?)?;
+            if a.degree().is_some_and(|degree| degree > 0) {
+                factors.push((a, multiplicity));
+            }
+            multiplicity += 1;
+        }
+        Some(factors)
+    }
+
+    /// Factorization over the rationals as far as rational roots go: `self = c * product of
+    /// p_i^m_i` where every `p_i` is primitive (see [`Polynomial::primitive`]) and either
+    /// linear or without rational roots. Linear factors come first.
+    ///
+    /// ```
+    /// use derivative_calculator::{parse, polynomial::Polynomial};
+    ///
+    /// let p = Polynomial::from_expr(&parse("2x^4 - 2x^3 - 2x + 2")?, 'x').unwrap();
+    /// let (c, factors) = p.factor().unwrap();
+    /// let factors: Vec<_> = factors.iter().map(|(f, m)| (f.to_string(), *m)).collect();
+    /// assert_eq!(c.to_string(), "2");
+    /// assert_eq!(factors, [("x-1".to_string(), 2), ("x^2+x+1".to_string(), 1)]);
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn factor(&self) -> Option<(Rational, Vec<(Polynomial, usize)>)> {
+        let mut factors = Vec::new();
+        for (part, multiplicity) in self.square_free()? {
+            let mut rest = part.clone();
+            for root in part.rational_roots().into_iter().rev() {
+                let linear = Polynomial::new(self.var, vec![-root, Rational::ONE]);
+                rest = rest.checked_div_rem(&linear)?.0;
+                factors.push((linear.primitive()?.1, multiplicity));
+            }
+            if rest.degree().is_some_and(|degree| degree > 0) {
+                factors.push((rest.primitive()?.1, multiplicity));
+            }
+        }
+        factors.sort_by_key(|(factor, _)| factor.degree());
+        let mut product = Polynomial::constant(self.var, Rational::ONE);
+        for (factor, multiplicity) in &factors {
+            product = product.checked_mul(&factor.checked_pow(*multiplicity as u32)?)?;
+        }
+        Some((self.leading().checked_div(product.leading())?, factors))
+    }
+
+    /// Distinct real roots in increasing order. Rational roots are exact, the others are
+    /// found in floating point by bisection. `None` on overflow or for the zero polynomial.
+    ///
+    /// ```
+    /// use derivative_calculator::{parse, polynomial::Polynomial};
+    ///
+    /// let p = Polynomial::from_expr(&parse("(x-1)^2*(x^2-2)")?, 'x').unwrap();
+    /// let roots = p.real_roots().unwrap();
+    /// assert_eq!(roots.len(), 3);
+    /// assert!((roots[0] + 2f64.sqrt()).abs() < 1e-12 && roots[1] == 1.0);
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn real_roots(&self) -> Option<Vec<f64>> {
+        if self.is_zero() {
+            return None;
+        }
+        let mut roots = Vec::new();
+        for (part, _) in self.square_free()? {
+            let mut rest = part.clone();
+            for root in part.rational_roots() {
+                rest = rest
+                    .checked_div_rem(&Polynomial::new(self.var, vec![-root, Rational::ONE]))?
+                    .0;
+                roots.push(root.to_f64());
+            }
+            let coefficients: Vec<f64> = rest.coefficients.iter().map(Rational::to_f64).collect();
+            roots.extend(simple_roots(&coefficients));
+        }
+        roots.sort_by(f64::total_cmp);
+        Some(roots)
+    }
+
+    /// The coefficients scaled by the common denominator, as integers.
+    fn integral_coefficients(&self) -> Option<Vec<i64>> {
+        let mut lcm = 1i64;
+        for c in &self.coefficients {
+            let d = c.denom();
+            lcm = lcm.checked_mul(d / crate::rational::gcd(lcm as i128, d as i128) as i64)?;
+        }
+        self.coefficients
+            .iter()
+            .map(|c| {
+                c.checked_mul(Rational::integer(lcm))
+                    .map(|scaled| scaled.numer())
+            })
+            .collect()
+    }
+
+    fn trim(&mut self) {
+        while self.coefficients.last().is_some_and(|c| c.is_zero()) {
+            self.coefficients.pop();
+        }
+    }
+}
+
+/// Real roots of the polynomial with these coefficients, lowest degree first, which has no
+/// repeated roots: between consecutive roots of the derivative it's monotonic, so each of
+/// those pieces holds at most one root, found by bisection.
+fn simple_roots(coefficients: &[f64]) -> Vec<f64> {
+    let degree = coefficients.len().saturating_sub(1);
+    if degree == 0 {
+        return Vec::new();
+    }
+    let eval = |x: f64| coefficients.iter().rev().fold(0.0, |acc, c| acc * x + c);
+    let derivative: Vec<f64> = coefficients
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(k, c)| c * k as f64)
+        .collect();
+    // Cauchy's bound on the size of the roots
+    let leading = coefficients[degree];
+    let bound = 1.0
+        + coefficients[..degree]
+            .iter()
+            .map(|c| (c / leading).abs())
+            .fold(0.0, f64::max);
+    let mut ends = vec![-bound];
+    ends.extend(simple_roots(&derivative));
+    ends.push(bound);
+    let mut roots = Vec::new();
+    for pair in ends.windows(2) {
+        let (mut lo, mut hi) = (pair[0], pair[1]);
+        let (f_lo, f_hi) = (eval(lo), eval(hi));
+        if f_lo == 0.0 {
+            roots.push(lo);
+            continue;
+        }
+        if f_lo.signum() == f_hi.signum() {
+            continue;
+        }
+        loop {
+            let mid = 0.5 * (lo + hi);
+            if mid <= lo || mid >= hi {
+                break;
+            }
+            if eval(mid).signum() == f_lo.signum() {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        roots.push(if eval(hi) == 0.0 { hi } else { lo });
+    }
+    roots.dedup();
+    roots
+}
+
+/// Positive divisors of `n`, `None` if there are too many candidates to try.
+fn divisors(n: i64) -> Option<Vec<i64>> {
+    let n = n.unsigned_abs();
+    if n > 1 << 40 {
+        return None;
+    }
+    let mut small = Vec::new();
+    let mut large = Vec::new();
+    let mut d = 1;
+    while d * d <= n {
+        if n.is_multiple_of(d) {
+            small.push(d as i64);
+            if d * d != n {
+                large.push((n / d) as i64);
+            }
+        }
+        d += 1;
+    }
+    small.extend(large.into_iter().rev());
+    Some(small)
+}
+
+impl Display for Polynomial {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.to_expr() {
+            Some(expr) => f.write_str(&expr.pretty()),
+            None => {
+                let terms: Vec<String> = self
+                    .coefficients
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .filter(|(_, c)| !c.is_zero())
+                    .map(|(k, c)| format!("({c}){}^{k}", self.var))
+                    .collect();
+                f.write_str(&terms.join("+"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Polynomial, MAX_DEGREE};
+    use crate::parse;
+
+    fn polynomial(input: &str) -> Option<Polynomial> {
+        Polynomial::from_expr(&parse(input).unwrap(), 'x')
+    }
+
+    #[test]
+    fn degrees_above_the_cap_are_refused() {
+        assert_eq!(polynomial("x^1000").unwrap().degree(), Some(MAX_DEGREE));
+        assert_eq!(polynomial("x^1001"), None);
+        assert_eq!(polynomial("x^100000"), None);
+        assert_eq!(polynomial("(x^2+1)^501"), None);
+        assert_eq!(polynomial("x^600*x^600"), None);
+        assert_eq!(polynomial("1^1000000").unwrap().to_string(), "1");
+    }
+}
@@ -0,0 +1,386 @@
+//! Polynomials in several variables, for reducing rational functions.
+
+use std::collections::BTreeMap;
+
+use crate::expand::{collect, term, terms_to_expr, Term};
+use crate::parser::ast::Expr;
+use crate::rational::{gcd, Rational};
+
+/// Most steps [`MultiPolynomial::exact_div`] takes before giving up.
+const MAX_DIVISION_STEPS: usize = 10_000;
+
+/// Sparse polynomial with exact rational coefficients in variables numbered from 0.
+///
+/// What the variables stand for is up to the caller; [`MultiPolynomial::to_expr`] takes the
+/// expressions to put in their place. Terms are ordered lexicographically with variable 0 the
+/// most significant, and arithmetic is checked like that of [`Rational`].
+///
+/// ```
+/// use derivative_calculator::polynomial::multivariate::MultiPolynomial;
+/// use derivative_calculator::{parse, rational::Rational};
+///
+/// let (x, y) = (MultiPolynomial::var(0), MultiPolynomial::var(1));
+/// let sum = x.checked_add(&y).unwrap();
+/// let a = x.checked_pow(2).unwrap().checked_sub(&y.checked_pow(2).unwrap()).unwrap();
+/// let b = sum.checked_pow(2).unwrap().checked_scale(Rational::integer(3)).unwrap();
+/// let vars = [parse("x")?, parse("y")?];
+/// assert_eq!(a.gcd(&b).unwrap().to_expr(&vars).unwrap().pretty(), "x+y");
+/// # Ok::<(), derivative_calculator::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MultiPolynomial {
+    /// Coefficient of each monomial, keyed by its exponents without trailing zeros so that
+    /// the key order is the lexicographic order. No coefficient is zero.
+    terms: BTreeMap<Vec<u32>, Rational>,
+}
+
+impl MultiPolynomial {
+    pub fn zero() -> MultiPolynomial {
+        MultiPolynomial {
+            terms: BTreeMap::new(),
+        }
+    }
+
+    pub fn constant(c: Rational) -> MultiPolynomial {
+        MultiPolynomial::monomial(Vec::new(), c)
+    }
+
+    /// The variable numbered `index`.
+    pub fn var(index: usize) -> MultiPolynomial {
+        let mut exponents = vec![0; index + 1];
+        exponents[index] = 1;
+        MultiPolynomial::monomial(exponents, Rational::ONE)
+    }
+
+    /// `c` times the product of variable `i` to the power `exponents[i]`.
+    pub fn monomial(mut exponents: Vec<u32>, c: Rational) -> MultiPolynomial {
+        let mut terms = BTreeMap::new();
+        if !c.is_zero() {
+            trim(&mut exponents);
+            terms.insert(exponents, c);
+        }
+        MultiPolynomial { terms }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// The value when no variable occurs, `None` otherwise.
+    pub fn as_constant(&self) -> Option<Rational> {
+        match self.terms.iter().next() {
+            None => Some(Rational::ZERO),
+            Some((exponents, &c)) if exponents.is_empty() && self.terms.len() == 1 => Some(c),
+            _ => None,
+        }
+    }
+
+    /// The monomials as exponents and coefficients, lexicographically smallest first.
+    pub fn terms(&self) -> impl Iterator<Item = (&[u32], Rational)> {
+        self.terms.iter().map(|(e, &c)| (e.as_slice(), c))
+    }
+
+    /// Highest power of variable `var`, 0 for the zero polynomial.
+    pub fn degree(&self, var: usize) -> u32 {
+        self.terms
+            .keys()
+            .map(|e| e.get(var).copied().unwrap_or(0))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Coefficient of the lexicographically largest monomial, zero for the zero polynomial.
+    pub fn leading(&self) -> Rational {
+        self.terms
+            .last_key_value()
+            .map_or(Rational::ZERO, |(_, &c)| c)
+    }
+
+    pub fn checked_add(&self, other: &MultiPolynomial) -> Option<MultiPolynomial> {
+        let mut terms = self.terms.clone();
+        for (exponents, &c) in &other.terms {
+            let sum = match terms.get(exponents) {
+                Some(existing) => existing.checked_add(c)?,
+                None => c,
+            };
+            if sum.is_zero() {
+                terms.remove(exponents);
+            } else {
+                terms.insert(exponents.clone(), sum);
+            }
+        }
+        Some(MultiPolynomial { terms })
+    }
+
+    pub fn checked_sub(&self, other: &MultiPolynomial) -> Option<MultiPolynomial> {
+        self.checked_add(&other.checked_scale(-Rational::ONE)?)
+    }
+
+    pub fn checked_mul(&self, other: &MultiPolynomial) -> Option<MultiPolynomial> {
+        let mut product = MultiPolynomial::zero();
+        for (a, &c) in &self.terms {
+            let mut partial = BTreeMap::new();
+            for (b, &d) in &other.terms {
+                let len = a.len().max(b.len());
+                let exponents = (0..len)
+                    .map(|i| {
+                        let e = a.get(i).copied().unwrap_or(0);
+                        e.checked_add(b.get(i).copied().unwrap_or(0))
+                    })
+                    .collect::<Option<Vec<u32>>>()?;
+                partial.insert(exponents, c.checked_mul(d)?);
+            }
+            product = product.checked_add(&MultiPolynomial { terms: partial })?;
+        }
+        Some(product)
+    }
+
+    pub fn checked_scale(&self, factor: Rational) -> Option<MultiPolynomial> {
+        if factor.is_zero() {
+            return Some(MultiPolynomial::zero());
+        }
+        let terms = self
+            .terms
+            .iter()
+            .map(|(e, c)| Some((e.clone(), c.checked_mul(factor)?)))
+            .collect::<Option<_>>()?;
+        Some(MultiPolynomial { terms })
+    }
+
+    pub fn checked_pow(&self, exp: u32) -> Option<MultiPolynomial> {
+        let mut result = MultiPolynomial::constant(Rational::ONE);
+        for _ in 0..exp {
+            result = result.checked_mul(self)?;
+        }
+        Some(result)
+    }
+
+    /// The quotient `self / divisor` when the division leaves no remainder. `None` when it
+    /// does, when `divisor` is zero or on overflow.
+    pub fn exact_div(&self, divisor: &MultiPolynomial) -> Option<MultiPolynomial> {
+        let (lead, &lead_coefficient) = divisor.terms.last_key_value()?;
+        let mut quotient = MultiPolynomial::zero();
+        let mut rest = self.clone();
+        for _ in 0..MAX_DIVISION_STEPS {
+            let Some((exponents, &c)) = rest.terms.last_key_value() else {
+                return Some(quotient);
+            };
+            if lead.len() > exponents.len() {
+                return None;
+            }
+            let shift = exponents
+                .iter()
+                .enumerate()
+                .map(|(i, &e)| e.checked_sub(lead.get(i).copied().unwrap_or(0)))
+                .collect::<Option<Vec<u32>>>()?;
+            let step = MultiPolynomial::monomial(shift, c.checked_div(lead_coefficient)?);
+            rest = rest.checked_sub(&step.checked_mul(divisor)?)?;
+            quotient = quotient.checked_add(&step)?;
+        }
+        None
+    }
+
+    /// Splits off the content: returns `(c, p)` with `self = c*p`, where `p` has coprime
+    /// integer coefficients and a positive leading coefficient. The zero polynomial has
+    /// content 1.
+    pub fn primitive(&self) -> Option<(Rational, MultiPolynomial)> {
+        if self.is_zero() {
+            return Some((Rational::ONE, self.clone()));
+        }
+        let numer = self
+            .terms
+            .values()
+            .fold(0, |acc, c| gcd(acc, c.numer() as i128));
+        let mut denom = 1i128;
+        for c in self.terms.values() {
+            let d = c.denom() as i128;
+            denom = denom.checked_mul(d / gcd(denom, d))?;
+        }
+        let content = Rational::new(i64::try_from(numer).ok()?, i64::try_from(denom).ok()?)?;
+        let content = if self.leading().is_negative() {
+            -content
+        } else {
+            content
+        };
+        Some((
+            content,
+            self.checked_scale(Rational::ONE.checked_div(content)?)?,
+        ))
+    }
+
+    /// Greatest common divisor, made primitive (see [`MultiPolynomial::primitive`]). Works one
+    /// variable at a time: the contents are handled recursively and the primitive parts by a
+    /// primitive pseudo-remainder sequence. The gcd with zero is the other polynomial.
+    pub fn gcd(&self, other: &MultiPolynomial) -> Option<MultiPolynomial> {
+        if self.is_zero() {
+            return Some(other.primitive()?.1);
+        }
+        if other.is_zero() {
+            return Some(self.primitive()?.1);
+        }
+        let Some(var) = self
+            .first_variable()
+            .into_iter()
+            .chain(other.first_variable())
+            .min()
+        else {
+            return Some(MultiPolynomial::constant(Rational::ONE));
+        };
+        let (content_a, content_b) = (self.content(var)?, other.content(var)?);
+        let content = content_a.gcd(&content_b)?;
+        let mut a = self.exact_div(&content_a)?;
+        let mut b = other.exact_div(&content_b)?;
+        if a.degree(var) < b.degree(var) {
+            (a, b) = (b, a);
+        }
+        while !b.is_zero() {
+            let remainder = a.pseudo_remainder(&b, var)?;
+            a = b;
+            b = if remainder.is_zero() {
+                remainder
+            } else {
+                remainder.exact_div(&remainder.content(var)?)?
+            };
+        }
+        let common = if a.degree(var) == 0 {
+            content
+        } else {
+            content.checked_mul(&a)?
+        };
+        Some(common.primitive()?.1)
+    }
+
+    /// The polynomial with `vars[i]` in place of variable `i`. `None` if a variable has no
+    /// expression or a coefficient doesn't fit an `i32`.
+    pub fn to_expr(&self, vars: &[Expr]) -> Option<Expr> {
+        let terms = self
+            .terms
+            .iter()
+            .map(|(exponents, &c)| {
+                let factors = exponents
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &e)| e > 0)
+                    .map(|(i, &e)| Some((vars.get(i)?.clone(), i32::try_from(e).ok()?)))
+                    .collect::<Option<Vec<_>>>()?;
+                Some(term(c, factors))
+            })
+            .collect::<Option<Vec<Term>>>()?;
+        terms_to_expr(&collect(terms)?)
+    }
+
+    /// Lowest numbered variable that occurs.
+    fn first_variable(&self) -> Option<usize> {
+        self.terms
+            .keys()
+            .filter_map(|e| e.iter().position(|&k| k > 0))
+            .min()
+    }
+
+    /// Coefficients as polynomials in the other variables, keyed by the power of `var`.
+    fn coefficients(&self, var: usize) -> Option<BTreeMap<u32, MultiPolynomial>> {
+        let mut coefficients: BTreeMap<u32, MultiPolynomial> = BTreeMap::new();
+        for (exponents, &c) in &self.terms {
+            let mut rest = exponents.clone();
+            let power = match rest.get_mut(var) {
+                Some(e) => std::mem::take(e),
+                None => 0,
+            };
+            let term = MultiPolynomial::monomial(rest, c);
+            let sum = match coefficients.get(&power) {
+                Some(existing) => existing.checked_add(&term)?,
+                None => term,
+            };
+            coefficients.insert(power, sum);
+        }
+        Some(coefficients)
+    }
+
+    /// Gcd of the coefficients in `var`, free of `var`.
+    fn content(&self, var: usize) -> Option<MultiPolynomial> {
+        let mut content = MultiPolynomial::zero();
+        for coefficient in self.coefficients(var)?.values() {
+            content = content.gcd(coefficient)?;
+        }
+        Some(content)
+    }
+
+    /// Remainder of `lc^k * self` divided by `divisor` as polynomials in `var`, where `lc` is
+    /// the leading coefficient of `divisor` in `var`; it never needs fractions of polynomials.
+    fn pseudo_remainder(&self, divisor: &MultiPolynomial, var: usize) -> Option<MultiPolynomial> {
+        let degree = divisor.degree(var);
+        let lead = divisor.coefficients(var)?.remove(&degree)?;
+        let mut rest = self.clone();
+        while !rest.is_zero() && rest.degree(var) >= degree {
+            let top = rest.degree(var);
+            let rest_lead = rest.coefficients(var)?.remove(&top)?;
+            let mut shift = vec![0; var + 1];
+            shift[var] = top - degree;
+            let step = MultiPolynomial::monomial(shift, Rational::ONE)
+                .checked_mul(&rest_lead)?
+                .checked_mul(divisor)?;
+            rest = rest.checked_mul(&lead)?.checked_sub(&step)?;
+        }
+        Some(rest)
+    }
+}
+
+fn trim(exponents: &mut Vec<u32>) {
+    while exponents.last() == Some(&0) {
+        exponents.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MultiPolynomial;
+    use crate::rational::Rational;
+
+    /// Sum of `c * x^i * y^j` over the terms `(i, j, c)`.
+    fn poly(terms: &[(u32, u32, i64)]) -> MultiPolynomial {
+        terms
+            .iter()
+            .fold(MultiPolynomial::zero(), |sum, &(i, j, c)| {
+                let term = MultiPolynomial::monomial(vec![i, j], Rational::integer(c));
+                sum.checked_add(&term).unwrap()
+            })
+    }
+
+    #[test]
+    fn gcd_of_polynomials_in_two_variables() {
+        // x^2 - y^2 and 2x^2 - 2xy share x - y
+        let a = poly(&[(2, 0, 1), (0, 2, -1)]);
+        let b = poly(&[(2, 0, 2), (1, 1, -2)]);
+        assert_eq!(a.gcd(&b).unwrap(), poly(&[(1, 0, 1), (0, 1, -1)]));
+        // (x+y)^2 (x-1) and (x+y) (y+2)
+        let sum = poly(&[(1, 0, 1), (0, 1, 1)]);
+        let a = sum
+            .checked_pow(2)
+            .unwrap()
+            .checked_mul(&poly(&[(1, 0, 1), (0, 0, -1)]))
+            .unwrap();
+        let b = sum.checked_mul(&poly(&[(0, 1, 1), (0, 0, 2)])).unwrap();
+        assert_eq!(a.gcd(&b).unwrap(), sum);
+    }
+
+    #[test]
+    fn coprime_polynomials_have_gcd_one() {
+        let a = poly(&[(2, 0, 1), (0, 2, 1)]);
+        let b = poly(&[(1, 0, 1), (0, 1, 1)]);
+        let one = MultiPolynomial::constant(Rational::ONE);
+        assert_eq!(a.gcd(&b).unwrap(), one);
+        assert_eq!(poly(&[(0, 0, 6)]).gcd(&poly(&[(0, 0, 4)])).unwrap(), one);
+        assert_eq!(MultiPolynomial::zero().gcd(&b).unwrap(), b);
+    }
+
+    #[test]
+    fn exact_division_needs_a_factor() {
+        let a = poly(&[(2, 0, 1), (0, 2, -1)]);
+        let factor = poly(&[(1, 0, 1), (0, 1, 1)]);
+        assert_eq!(
+            a.exact_div(&factor).unwrap(),
+            poly(&[(1, 0, 1), (0, 1, -1)])
+        );
+        assert_eq!(a.exact_div(&poly(&[(1, 0, 1), (0, 0, 1)])), None);
+    }
+}
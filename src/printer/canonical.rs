@@ -0,0 +1,261 @@
+//! Compact canonical string form of an [`Expr`].
+//!
+//! The canonical form is an S-expression that maps one to one onto the tree, so it survives
+//! storage and transport without any of the parser's implicit rules getting in the way:
+//!
+//! | node                     | canonical form    |
+//! |--------------------------|-------------------|
+//! | `Number(-3)`             | `-3`              |
+//! | `Variable('x')`          | `x`               |
+//! | `Constant('a')`          | `a`               |
+//! | `Symbol("ans")`          | `ans`             |
+//! | `Call { "sin", arg }`    | `(sin arg)`       |
+//! | negation                 | `(- expr)`        |
+//! | binary operation         | `(+ left right)`, likewise `-`, `*`, `/` and `^` |
+//!
+//! Like in the parser a lone `x` is the variable, any other single letter is a constant and
+//! longer words are symbols. The cases that rule can't express are spelled out:
+//! `(var t)`, `(const x)` and `(sym c)`. A call to a function named like one of these
+//! keywords, or `call`, is written `(call var arg)`. Names must not contain whitespace or
+//! parentheses.
+//!
+//! ```
+//! use derivative_calculator::{parse, Expr};
+//!
+//! for input in ["x^2 + 1", "-sin(2x)/ln(x)", "a*x - $1", "(x-1)^-2"] {
+//!     let expr = parse(input)?;
+//!     let canonical = expr.to_canonical();
+//!     assert_eq!(Expr::from_canonical(&canonical), Ok(expr));
+//! }
+//! assert_eq!(parse("x^2 + 1")?.to_canonical(), "(+ (^ x 2) 1)");
+//!
+//! let unusual = Expr::BinaryOp {
+//!     op: derivative_calculator::BinaryOp::Mul,
+//!     left: Box::new(Expr::Variable('t')),
+//!     right: Box::new(Expr::Call { name: "var".into(), arg: Box::new(Expr::Constant('x')) }),
+//! };
+//! assert_eq!(unusual.to_canonical(), "(* (var t) (call var (const x)))");
+//! assert_eq!(Expr::from_canonical(&unusual.to_canonical()), Ok(unusual));
+//! assert!(Expr::from_canonical("(+ x").is_err());
+//! # Ok::<(), derivative_calculator::Error>(())
+//! ```
+
+use std::fmt::Display;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+
+/// Heads with a meaning of their own, calls to functions with these names need `call`.
+const KEYWORDS: [&str; 4] = ["var", "const", "sym", "call"];
+
+/// Reasons a string isn't a valid canonical form.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum CanonicalError {
+    /// The input stopped in the middle of an expression.
+    UnexpectedEnd,
+    /// A token that can't appear at this position, with its index in the input.
+    UnexpectedToken(String, usize),
+    /// A complete expression is followed by more input, starting at this index.
+    TrailingInput(usize),
+}
+
+impl Display for CanonicalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CanonicalError::UnexpectedEnd => f.write_str("unexpected end of input"),
+            CanonicalError::UnexpectedToken(token, idx) => {
+                write!(f, "unexpected '{token}' at position {idx}")
+            }
+            CanonicalError::TrailingInput(idx) => {
+                write!(f, "unexpected input after the expression at position {idx}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CanonicalError {}
+
+impl Expr {
+    /// The canonical form of the expression, see the [module docs](self).
+    pub fn to_canonical(&self) -> String {
+        canonical(self)
+    }
+
+    /// Reads an expression back from its canonical form, see the [module docs](self).
+    pub fn from_canonical(input: &str) -> Result<Expr, CanonicalError> {
+        let mut reader = Reader {
+            chars: input.char_indices().peekable(),
+        };
+        let expr = reader.expr()?;
+        match reader.token() {
+            None => Ok(expr),
+            Some((_, idx)) => Err(CanonicalError::TrailingInput(idx)),
+        }
+    }
+}
+
+fn canonical(expr: &Expr) -> String {
+    match expr {
+        Expr::Number(n) => n.to_string(),
+        Expr::Variable('x') => "x".to_string(),
+        Expr::Variable(c) => format!("(var {c})"),
+        Expr::Constant(c) if *c == 'x' || !c.is_alphabetic() => format!("(const {c})"),
+        Expr::Constant(c) => c.to_string(),
+        Expr::Symbol(name) if name.chars().count() == 1 || KEYWORDS.contains(&name.as_str()) => {
+            format!("(sym {name})")
+        }
+        Expr::Symbol(name) => name.clone(),
+        Expr::Call { name, arg } if KEYWORDS.contains(&name.as_str()) => {
+            format!("(call {name} {})", canonical(arg))
+        }
+        Expr::Call { name, arg } => format!("({name} {})", canonical(arg)),
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => format!("(- {})", canonical(expr)),
+        Expr::BinaryOp { op, left, right } => {
+            let op = match op {
+                BinaryOp::Add => '+',
+                BinaryOp::Sub => '-',
+                BinaryOp::Mul => '*',
+                BinaryOp::Div => '/',
+                BinaryOp::Pow => '^',
+            };
+            format!("({op} {} {})", canonical(left), canonical(right))
+        }
+    }
+}
+
+/// Recursive descent reader over the canonical form.
+struct Reader<'i> {
+    chars: Peekable<CharIndices<'i>>,
+}
+
+impl Reader<'_> {
+    /// Next token: `(`, `)` or a run of other non-whitespace characters, with its start index.
+    fn token(&mut self) -> Option<(String, usize)> {
+        while self.chars.next_if(|(_, chr)| chr.is_whitespace()).is_some() {}
+        let (start, first) = self.chars.next()?;
+        let mut token = first.to_string();
+        if first != '(' && first != ')' {
+            while let Some((_, chr)) = self
+                .chars
+                .next_if(|&(_, chr)| !chr.is_whitespace() && chr != '(' && chr != ')')
+            {
+                token.push(chr);
+            }
+        }
+        Some((token, start))
+    }
+
+    fn expect_token(&mut self) -> Result<(String, usize), CanonicalError> {
+        self.token().ok_or(CanonicalError::UnexpectedEnd)
+    }
+
+    fn expr(&mut self) -> Result<Expr, CanonicalError> {
+        let (token, idx) = self.expect_token()?;
+        match token.as_str() {
+            "(" => self.list(),
+            ")" => Err(CanonicalError::UnexpectedToken(token, idx)),
+            _ => atom(&token).ok_or(CanonicalError::UnexpectedToken(token, idx)),
+        }
+    }
+
+    /// The rest of a parenthesized expression, after its `(`.
+    fn list(&mut self) -> Result<Expr, CanonicalError> {
+        let (head, idx) = self.expect_token()?;
+        let expr = match head.as_str() {
+            "+" | "-" | "*" | "/" | "^" => {
+                let op = match head.as_str() {
+                    "+" => BinaryOp::Add,
+                    "-" => BinaryOp::Sub,
+                    "*" => BinaryOp::Mul,
+                    "/" => BinaryOp::Div,
+                    _ => BinaryOp::Pow,
+                };
+                let left = self.expr()?;
+                if head == "-" && self.close()? {
+                    return Ok(Expr::UnaryOp {
+                        op: UnaryOp::Neg,
+                        expr: Box::new(left),
+                    });
+                }
+                Expr::BinaryOp {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(self.expr()?),
+                }
+            }
+            "var" | "const" => {
+                let (name, idx) = self.expect_token()?;
+                let mut chars = name.chars();
+                let chr = match (chars.next(), chars.next()) {
+                    (Some(chr), None) if chr != '(' && chr != ')' => chr,
+                    _ => return Err(CanonicalError::UnexpectedToken(name, idx)),
+                };
+                if head == "var" {
+                    Expr::Variable(chr)
+                } else {
+                    Expr::Constant(chr)
+                }
+            }
+            "sym" => Expr::Symbol(self.name()?),
+            "call" => Expr::Call {
+                name: self.name()?,
+                arg: Box::new(self.expr()?),
+            },
+            "(" | ")" => return Err(CanonicalError::UnexpectedToken(head, idx)),
+            _ => Expr::Call {
+                name: head,
+                arg: Box::new(self.expr()?),
+            },
+        };
+        if self.close()? {
+            Ok(expr)
+        } else {
+            let (token, idx) = self.expect_token()?;
+            Err(CanonicalError::UnexpectedToken(token, idx))
+        }
+    }
+
+    /// A name token, anything but a parenthesis.
+    fn name(&mut self) -> Result<String, CanonicalError> {
+        match self.expect_token()? {
+            (token, idx) if token == "(" || token == ")" => {
+                Err(CanonicalError::UnexpectedToken(token, idx))
+            }
+            (token, _) => Ok(token),
+        }
+    }
+
+    /// Consumes a `)` if it comes next, fails at the end of the input.
+    fn close(&mut self) -> Result<bool, CanonicalError> {
+        while self.chars.next_if(|(_, chr)| chr.is_whitespace()).is_some() {}
+        match self.chars.peek() {
+            Some((_, ')')) => {
+                self.chars.next();
+                Ok(true)
+            }
+            Some(_) => Ok(false),
+            None => Err(CanonicalError::UnexpectedEnd),
+        }
+    }
+}
+
+/// A number, variable, constant or symbol written without parentheses.
+fn atom(token: &str) -> Option<Expr> {
+    if let Ok(n) = token.parse::<i32>() {
+        return Some(Expr::Number(n));
+    }
+    let mut chars = token.chars();
+    match (chars.next(), chars.next()) {
+        (Some('x'), None) => Some(Expr::Variable('x')),
+        (Some(chr), None) if chr.is_alphabetic() => Some(Expr::Constant(chr)),
+        (Some(chr), Some(_)) if chr != '-' && chr != '+' && !chr.is_ascii_digit() => {
+            Some(Expr::Symbol(token.to_string()))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,64 @@
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+
+impl Expr {
+    /// Renders the expression as LaTeX math (without the surrounding `$`), e.g. `x^2/(x+1)`
+    /// becomes `\frac{x^{2}}{x + 1}`.
+    pub fn to_latex(&self) -> String {
+        latex(self)
+    }
+}
+
+/// LaTeX for `expr`, wrapped in parentheses when it binds looser than `min_prec`.
+fn latex_with_parens(expr: &Expr, min_prec: u8) -> String {
+    let inner = latex(expr);
+    if expr.precedence() < min_prec {
+        format!("\\left({inner}\\right)")
+    } else {
+        inner
+    }
+}
+
+fn latex(expr: &Expr) -> String {
+    match expr {
+        Expr::Number(n) => n.to_string(),
+        Expr::Variable(c) | Expr::Constant(c) => c.to_string(),
+        Expr::Symbol(name) => match name.strip_prefix('$') {
+            Some(number) => format!("\\${number}"),
+            None => format!("\\mathrm{{{name}}}"),
+        },
+        Expr::Call { name, arg } => match name.as_str() {
+            "sqrt" => format!("\\sqrt{{{}}}", latex(arg)),
+            "asin" | "atan" => format!("\\arc{}\\left({}\\right)", &name[1..], latex(arg)),
+            "sin" | "cos" | "tan" | "exp" | "ln" => {
+                format!("\\{name}\\left({}\\right)", latex(arg))
+            }
+            _ if name.len() == 1 => format!("{name}\\left({}\\right)", latex(arg)),
+            _ => format!("\\operatorname{{{name}}}\\left({}\\right)", latex(arg)),
+        },
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => format!("-{}", latex_with_parens(expr, 3)),
+        Expr::BinaryOp { op, left, right } => match op {
+            BinaryOp::Add => format!(
+                "{} + {}",
+                latex_with_parens(left, 1),
+                latex_with_parens(right, 1)
+            ),
+            BinaryOp::Sub => format!(
+                "{} - {}",
+                latex_with_parens(left, 1),
+                latex_with_parens(right, 2)
+            ),
+            BinaryOp::Mul => {
+                let (l, r) = (latex_with_parens(left, 2), latex_with_parens(right, 3));
+                match (&**left, &**right) {
+                    (_, Expr::Number(_)) => format!("{l} \\cdot {r}"),
+                    _ => format!("{l} {r}"),
+                }
+            }
+            BinaryOp::Div => format!("\\frac{{{}}}{{{}}}", latex(left), latex(right)),
+            BinaryOp::Pow => format!("{}^{{{}}}", latex_with_parens(left, 5), latex(right)),
+        },
+    }
+}
@@ -0,0 +1,398 @@
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+
+/// Width used when the terminal size can't be determined.
+const DEFAULT_WIDTH: usize = 80;
+
+/// Width of the terminal: `$COLUMNS` if set, since most shells don't export it unless asked
+/// to, otherwise the size of the terminal on standard output, falling back to 80 columns.
+pub fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse().ok())
+        .filter(|&columns| columns > 0)
+        .or_else(window_width)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Columns of the terminal on standard output, `None` if it isn't one.
+#[cfg(unix)]
+fn window_width() -> Option<usize> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: TIOCGWINSZ only writes a `winsize` through the pointer, which is valid for it
+    let status = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    (status == 0 && size.ws_col > 0).then_some(usize::from(size.ws_col))
+}
+
+#[cfg(not(unix))]
+fn window_width() -> Option<usize> {
+    None
+}
+
+impl Expr {
+    /// Renders the expression as a multi-line drawing: fractions are stacked over a horizontal
+    /// bar, exponents are raised (using superscript characters where possible) and square roots
+    /// get a radical sign. Top level sums are wrapped so that no line is wider than `width`.
+    pub fn pretty_2d(&self, width: usize) -> String {
+        let terms = top_level_terms(self);
+        let mut rows: Vec<Block> = Vec::new();
+        let mut current: Option<Block> = None;
+        for (i, (negative, term)) in terms.iter().enumerate() {
+            // Only the terms after the first carry a sign
+            let sign = match (i, negative) {
+                (0, _) => "",
+                (_, false) => " + ",
+                (_, true) => " - ",
+            };
+            let term = layout_with_parens(term, if i > 0 { 2 } else { 1 });
+            let piece = Block::text(sign).beside(&term);
+            current = Some(match current {
+                None => piece,
+                Some(row) if row.width + piece.width <= width => row.beside(&piece),
+                Some(row) => {
+                    rows.push(row);
+                    // Continuation lines start with the operator so the break is visible.
+                    Block::text(sign.trim_start()).beside(&term)
+                }
+            });
+        }
+        rows.extend(current);
+        rows.iter()
+            .map(Block::render)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Splits a chain of additions and subtractions into its terms, each tagged with whether it is
+/// subtracted.
+fn top_level_terms(expr: &Expr) -> Vec<(bool, &Expr)> {
+    match expr {
+        Expr::BinaryOp {
+            op: op @ (BinaryOp::Add | BinaryOp::Sub),
+            left,
+            right,
+        } => {
+            let mut terms = top_level_terms(left);
+            terms.push((*op == BinaryOp::Sub, right));
+            terms
+        }
+        _ => vec![(false, expr)],
+    }
+}
+
+/// A rectangle of characters with a baseline, the row other blocks line up with when placed
+/// next to it.
+#[derive(Debug, Clone)]
+struct Block {
+    lines: Vec<String>,
+    width: usize,
+    baseline: usize,
+}
+
+impl Block {
+    /// A single line block.
+    fn text(text: &str) -> Block {
+        Block {
+            lines: vec![text.to_string()],
+            width: text.chars().count(),
+            baseline: 0,
+        }
+    }
+
+    fn height(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Places `other` to the right of `self`, lining up both baselines.
+    fn beside(&self, other: &Block) -> Block {
+        let above = self.baseline.max(other.baseline);
+        let below = (self.height() - self.baseline).max(other.height() - other.baseline);
+        let (self_top, other_top) = (above - self.baseline, above - other.baseline);
+        let mut lines = Vec::with_capacity(above + below);
+        for row in 0..above + below {
+            let mut line = self.row(row.checked_sub(self_top));
+            line.push_str(&other.row(row.checked_sub(other_top)));
+            lines.push(line);
+        }
+        Block {
+            lines,
+            width: self.width + other.width,
+            baseline: above,
+        }
+    }
+
+    /// Row `row` padded to the block width; rows outside the block are blank.
+    fn row(&self, row: Option<usize>) -> String {
+        let line = row
+            .and_then(|row| self.lines.get(row))
+            .map(String::as_str)
+            .unwrap_or("");
+        format!("{line:<width$}", width = self.width)
+    }
+
+    /// Centres the block in `width` columns.
+    fn centered(&self, width: usize) -> Block {
+        let left = (width - self.width) / 2;
+        Block {
+            lines: self
+                .lines
+                .iter()
+                .map(|line| format!("{}{line:<w$}", " ".repeat(left), w = width - left))
+                .collect(),
+            width,
+            baseline: self.baseline,
+        }
+    }
+
+    /// `self` stacked over `denominator` with a fraction bar in between.
+    fn over(&self, denominator: &Block) -> Block {
+        let width = self.width.max(denominator.width) + 2;
+        let mut lines = self.centered(width).lines;
+        lines.push("─".repeat(width));
+        lines.extend(denominator.centered(width).lines);
+        Block {
+            lines,
+            width,
+            baseline: self.height(),
+        }
+    }
+
+    /// `self` with `exponent` raised above its right edge.
+    fn raised(&self, exponent: &Block) -> Block {
+        let mut lines: Vec<String> = exponent
+            .lines
+            .iter()
+            .map(|line| format!("{}{line:<w$}", " ".repeat(self.width), w = exponent.width))
+            .collect();
+        lines.extend(
+            self.lines
+                .iter()
+                .map(|line| format!("{line:<w$}", w = self.width + exponent.width)),
+        );
+        Block {
+            lines,
+            width: self.width + exponent.width,
+            baseline: exponent.height() + self.baseline,
+        }
+    }
+
+    /// Wraps the block in parentheses that grow with its height.
+    fn parenthesized(&self) -> Block {
+        let height = self.height();
+        let (left, right): (Vec<&str>, Vec<&str>) = if height == 1 {
+            (vec!["("], vec![")"])
+        } else {
+            (0..height)
+                .map(|row| match row {
+                    0 => ("⎛", "⎞"),
+                    r if r == height - 1 => ("⎝", "⎠"),
+                    _ => ("⎜", "⎟"),
+                })
+                .unzip()
+        };
+        let lines = self
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(row, line)| format!("{}{line:<w$}{}", left[row], right[row], w = self.width))
+            .collect();
+        Block {
+            lines,
+            width: self.width + 2,
+            baseline: self.baseline,
+        }
+    }
+
+    /// Draws a square root sign around the block, with a bar over the top.
+    fn radical(&self) -> Block {
+        let height = self.height();
+        let mut lines = vec![format!(
+            "{}{}",
+            " ".repeat(height + 1),
+            "_".repeat(self.width)
+        )];
+        for (row, line) in self.lines.iter().enumerate() {
+            let mut prefix: Vec<char> = vec![' '; height + 1];
+            prefix[height - row] = '╱';
+            if row == height - 1 {
+                prefix[0] = '╲';
+            }
+            lines.push(format!(
+                "{}{line:<w$}",
+                prefix.into_iter().collect::<String>(),
+                w = self.width
+            ));
+        }
+        Block {
+            lines,
+            width: self.width + height + 1,
+            baseline: self.baseline + 1,
+        }
+    }
+
+    fn render(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| line.trim_end())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Lays out `expr`, wrapped in parentheses when it binds looser than `min_prec`.
+fn layout_with_parens(expr: &Expr, min_prec: u8) -> Block {
+    let block = layout(expr);
+    if expr.precedence() < min_prec {
+        block.parenthesized()
+    } else {
+        block
+    }
+}
+
+fn layout(expr: &Expr) -> Block {
+    match expr {
+        Expr::Number(n) => Block::text(&n.to_string()),
+        Expr::Variable(c) | Expr::Constant(c) => Block::text(&c.to_string()),
+        Expr::Symbol(name) => Block::text(name),
+        Expr::Call { name, arg } if name == "sqrt" => layout(arg).radical(),
+        Expr::Call { name, arg } => Block::text(name).beside(&layout(arg).parenthesized()),
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => Block::text("-").beside(&layout_with_parens(expr, 3)),
+        Expr::BinaryOp { op, left, right } => match op {
+            BinaryOp::Add | BinaryOp::Sub => {
+                let symbol = if *op == BinaryOp::Add { " + " } else { " - " };
+                let right_prec = if *op == BinaryOp::Sub { 2 } else { 1 };
+                layout_with_parens(left, 1)
+                    .beside(&Block::text(symbol))
+                    .beside(&layout_with_parens(right, right_prec))
+            }
+            BinaryOp::Mul => {
+                let left_block = layout_with_parens(left, 2);
+                let right_block = layout_with_parens(right, 3);
+                // Same convention as `Expr::pretty`
+                if Expr::is_implicit_product(left, right) {
+                    left_block.beside(&right_block)
+                } else {
+                    left_block.beside(&Block::text("⋅")).beside(&right_block)
+                }
+            }
+            BinaryOp::Div => layout(left).over(&layout(right)),
+            BinaryOp::Pow => {
+                if is_one_half(right) {
+                    return layout(left).radical();
+                }
+                let base = layout_with_parens(left, 5);
+                match superscript(right) {
+                    Some(exponent) if base.height() == 1 => base.beside(&Block::text(&exponent)),
+                    _ => base.raised(&layout(right)),
+                }
+            }
+        },
+    }
+}
+
+fn is_one_half(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::BinaryOp { op: BinaryOp::Div, left, right }
+            if **left == Expr::Number(1) && **right == Expr::Number(2)
+    )
+}
+
+/// Writes a number or a single letter exponent with Unicode superscript characters, if every
+/// character has a superscript form.
+fn superscript(expr: &Expr) -> Option<String> {
+    let text = match expr {
+        Expr::Number(n) => n.to_string(),
+        Expr::Variable(c) | Expr::Constant(c) => c.to_string(),
+        _ => return None,
+    };
+    text.chars().map(superscript_char).collect()
+}
+
+fn superscript_char(chr: char) -> Option<char> {
+    let mapped = match chr {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '-' => '⁻',
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        _ => return None,
+    };
+    Some(mapped)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+
+    fn drawn(input: &str) -> String {
+        parse(input).unwrap().pretty_2d(80)
+    }
+
+    #[test]
+    fn numbers_are_juxtaposed_only_with_variables_and_their_powers() {
+        assert_eq!(drawn("2x"), "2x");
+        assert_eq!(drawn("3x^2"), "3x²");
+        assert_eq!(drawn("x*3"), "x⋅3");
+        assert_eq!(drawn("2*-x"), "2⋅-x");
+        assert_eq!(drawn("2*(-3)"), "2⋅-3");
+        assert_eq!(drawn("2*3^x"), "2⋅3ˣ");
+    }
+
+    #[test]
+    fn negation_of_powers() {
+        assert_eq!(drawn("-x^2"), "-x²");
+        assert_eq!(drawn("(-x)^2"), "(-x)²");
+    }
+
+    #[test]
+    fn fractions_and_roots_are_stacked() {
+        assert_eq!(drawn("(x+1)/2"), " x + 1\n───────\n   2");
+        assert_eq!(drawn("sqrt(x)/(x^2+1)"), "    _\n  ╲╱x\n────────\n x² + 1");
+    }
+
+    #[test]
+    fn long_sums_wrap_before_an_operator() {
+        let expr = parse("x^5+x^4+x^3+x^2+x").unwrap();
+        assert_eq!(expr.pretty_2d(12), "x⁵ + x⁴ + x³\n+ x² + x");
+    }
+}
@@ -0,0 +1,231 @@
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+
+const MATHML_NS: &str = "http://www.w3.org/1998/Math/MathML";
+
+impl Expr {
+    /// Renders the expression as a presentation MathML document, meant for display.
+    /// e.g. `x^2/(x+1)` becomes a `<mfrac>` with a `<msup>` numerator.
+    pub fn to_mathml(&self) -> String {
+        format!("<math xmlns=\"{MATHML_NS}\">{}</math>", presentation(self))
+    }
+
+    /// Renders the expression as a content MathML document, which keeps the meaning of every
+    /// operation (`<apply><plus/>..</apply>`) so other tools can read it back.
+    pub fn to_content_mathml(&self) -> String {
+        format!("<math xmlns=\"{MATHML_NS}\">{}</math>", content(self))
+    }
+}
+
+/// Presentation markup for `expr`, wrapped in parentheses when it binds looser than `min_prec`.
+fn presentation_with_parens(expr: &Expr, min_prec: u8) -> String {
+    let inner = presentation(expr);
+    if expr.precedence() < min_prec {
+        format!("<mrow><mo>(</mo>{inner}<mo>)</mo></mrow>")
+    } else {
+        inner
+    }
+}
+
+fn presentation(expr: &Expr) -> String {
+    match expr {
+        Expr::Number(n) if *n < 0 => format!("<mrow><mo>-</mo><mn>{}</mn></mrow>", -(*n as i64)),
+        Expr::Number(n) => format!("<mn>{n}</mn>"),
+        Expr::Variable(c) | Expr::Constant(c) => format!("<mi>{c}</mi>"),
+        Expr::Symbol(name) => format!("<mi>{name}</mi>"),
+        Expr::Call { name, arg } if name == "sqrt" => {
+            format!("<msqrt>{}</msqrt>", presentation(arg))
+        }
+        Expr::Call { name, arg } => format!(
+            "<mrow><mi>{name}</mi><mo>&#x2061;</mo><mrow><mo>(</mo>{}<mo>)</mo></mrow></mrow>",
+            presentation(arg)
+        ),
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => format!(
+            "<mrow><mo>-</mo>{}</mrow>",
+            presentation_with_parens(expr, 3)
+        ),
+        Expr::BinaryOp { op, left, right } => match op {
+            BinaryOp::Add | BinaryOp::Sub => {
+                let symbol = if *op == BinaryOp::Add { "+" } else { "-" };
+                // Subtraction is not associative, so `a-(b-c)` keeps its parentheses.
+                let right_prec = if *op == BinaryOp::Sub { 2 } else { 1 };
+                format!(
+                    "<mrow>{}<mo>{symbol}</mo>{}</mrow>",
+                    presentation_with_parens(left, 1),
+                    presentation_with_parens(right, right_prec)
+                )
+            }
+            BinaryOp::Mul => {
+                // A factor starting with a digit or a sign needs a visible sign, or `2*3^x`
+                // would read as `23^x`; anything else reads as `2x`.
+                let symbol = match &**right {
+                    Expr::Number(_) | Expr::UnaryOp { .. } => "&#x00D7;",
+                    Expr::BinaryOp {
+                        op: BinaryOp::Pow,
+                        left: base,
+                        ..
+                    } if matches!(**base, Expr::Number(n) if n >= 0) => "&#x00D7;",
+                    _ => "&#x2062;",
+                };
+                format!(
+                    "<mrow>{}<mo>{symbol}</mo>{}</mrow>",
+                    presentation_with_parens(left, 2),
+                    presentation_with_parens(right, 3)
+                )
+            }
+            BinaryOp::Div => format!(
+                "<mfrac>{}{}</mfrac>",
+                presentation(left),
+                presentation(right)
+            ),
+            BinaryOp::Pow => format!(
+                "<msup>{}{}</msup>",
+                presentation_with_parens(left, 5),
+                presentation(right)
+            ),
+        },
+    }
+}
+
+fn content(expr: &Expr) -> String {
+    match expr {
+        Expr::Number(n) => format!("<cn type=\"integer\">{n}</cn>"),
+        Expr::Variable(c) | Expr::Constant(c) => format!("<ci>{c}</ci>"),
+        Expr::Symbol(name) => format!("<ci>{name}</ci>"),
+        Expr::Call { name, arg } => {
+            let function = match name.as_str() {
+                "sin" | "cos" | "tan" | "exp" | "ln" => format!("<{name}/>"),
+                "asin" | "atan" => format!("<arc{}/>", &name[1..]),
+                "sqrt" => "<root/>".to_string(),
+                _ => format!("<ci type=\"function\">{name}</ci>"),
+            };
+            format!("<apply>{function}{}</apply>", content(arg))
+        }
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => format!("<apply><minus/>{}</apply>", content(expr)),
+        Expr::BinaryOp { op, left, right } => {
+            let operator = match op {
+                BinaryOp::Add => "plus",
+                BinaryOp::Sub => "minus",
+                BinaryOp::Mul => "times",
+                BinaryOp::Div => "divide",
+                BinaryOp::Pow => "power",
+            };
+            format!(
+                "<apply><{operator}/>{}{}</apply>",
+                content(left),
+                content(right)
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+
+    fn presentation(input: &str) -> String {
+        parse(input).unwrap().to_mathml()
+    }
+
+    fn content(input: &str) -> String {
+        parse(input).unwrap().to_content_mathml()
+    }
+
+    fn document(body: &str) -> String {
+        format!("<math xmlns=\"http://www.w3.org/1998/Math/MathML\">{body}</math>")
+    }
+
+    #[test]
+    fn presentation_of_a_fraction_of_powers() {
+        assert_eq!(
+            presentation("x^2/(x+1)"),
+            document(
+                "<mfrac><msup><mi>x</mi><mn>2</mn></msup>\
+                 <mrow><mi>x</mi><mo>+</mo><mn>1</mn></mrow></mfrac>"
+            )
+        );
+    }
+
+    #[test]
+    fn presentation_of_calls_and_roots() {
+        assert_eq!(
+            presentation("sin(x)+sqrt(x)"),
+            document(
+                "<mrow><mrow><mi>sin</mi><mo>&#x2061;</mo>\
+                 <mrow><mo>(</mo><mi>x</mi><mo>)</mo></mrow></mrow>\
+                 <mo>+</mo><msqrt><mi>x</mi></msqrt></mrow>"
+            )
+        );
+    }
+
+    #[test]
+    fn presentation_of_negation_and_subtraction() {
+        assert_eq!(
+            presentation("-x^2-(x-1)"),
+            document(
+                "<mrow><mrow><mo>-</mo><msup><mi>x</mi><mn>2</mn></msup></mrow><mo>-</mo>\
+                 <mrow><mo>(</mo><mrow><mi>x</mi><mo>-</mo><mn>1</mn></mrow><mo>)</mo></mrow>\
+                 </mrow>"
+            )
+        );
+        assert_eq!(
+            presentation("-3"),
+            document("<mrow><mo>-</mo><mn>3</mn></mrow>")
+        );
+    }
+
+    #[test]
+    fn products_use_invisible_times_only_where_nothing_runs_together() {
+        assert_eq!(
+            presentation("2x"),
+            document("<mrow><mn>2</mn><mo>&#x2062;</mo><mi>x</mi></mrow>")
+        );
+        assert_eq!(
+            presentation("2*3"),
+            document("<mrow><mn>2</mn><mo>&#x00D7;</mo><mn>3</mn></mrow>")
+        );
+        assert_eq!(
+            presentation("2*3^x"),
+            document("<mrow><mn>2</mn><mo>&#x00D7;</mo><msup><mn>3</mn><mi>x</mi></msup></mrow>")
+        );
+        assert_eq!(
+            presentation("2*-x"),
+            document("<mrow><mn>2</mn><mo>&#x00D7;</mo><mrow><mo>-</mo><mi>x</mi></mrow></mrow>")
+        );
+    }
+
+    #[test]
+    fn content_markup_keeps_every_operation() {
+        assert_eq!(
+            content("2x^3-ln(x)"),
+            document(
+                "<apply><minus/><apply><times/><cn type=\"integer\">2</cn>\
+                 <apply><power/><ci>x</ci><cn type=\"integer\">3</cn></apply></apply>\
+                 <apply><ln/><ci>x</ci></apply></apply>"
+            )
+        );
+    }
+
+    #[test]
+    fn content_markup_of_functions() {
+        assert_eq!(
+            content("atan(x)/sqrt(x)"),
+            document(
+                "<apply><divide/><apply><arctan/><ci>x</ci></apply>\
+                 <apply><root/><ci>x</ci></apply></apply>"
+            )
+        );
+        let call = crate::parse_with_functions("-f(x)", ['f'].into()).unwrap();
+        assert_eq!(
+            call.to_content_mathml(),
+            document(
+                "<apply><minus/><apply><ci type=\"function\">f</ci><ci>x</ci></apply></apply>"
+            )
+        );
+    }
+}
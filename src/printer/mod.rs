@@ -0,0 +1,62 @@
+pub mod canonical;
+pub mod latex;
+pub mod layout;
+pub mod mathml;
+pub mod tree;
+
+use crate::parser::ast::Expr;
+
+/// Formats an [`Expr`] can be rendered to.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[non_exhaustive]
+pub enum OutputFormat {
+    /// Single line plain text, see [`Expr::pretty`].
+    #[default]
+    Pretty,
+    /// LaTeX math, see [`Expr::to_latex`].
+    Latex,
+    /// Presentation MathML, see [`Expr::to_mathml`].
+    MathMl,
+    /// Content MathML, see [`Expr::to_content_mathml`].
+    ContentMathMl,
+    /// Multi-line drawing sized to the terminal, see [`Expr::pretty_2d`].
+    Unicode,
+}
+
+impl OutputFormat {
+    /// Every available format, in the order they are listed to the user.
+    pub const ALL: [OutputFormat; 5] = [
+        OutputFormat::Pretty,
+        OutputFormat::Unicode,
+        OutputFormat::Latex,
+        OutputFormat::MathMl,
+        OutputFormat::ContentMathMl,
+    ];
+
+    /// Name used to select the format from the REPL or the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Pretty => "pretty",
+            OutputFormat::Latex => "latex",
+            OutputFormat::MathMl => "mathml",
+            OutputFormat::ContentMathMl => "content-mathml",
+            OutputFormat::Unicode => "unicode",
+        }
+    }
+
+    /// Looks up a format by its [`OutputFormat::name`].
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        Self::ALL.into_iter().find(|format| format.name() == name)
+    }
+}
+
+/// Renders `expr` in the given `format`.
+pub fn render(expr: &Expr, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Pretty => expr.pretty(),
+        OutputFormat::Latex => expr.to_latex(),
+        OutputFormat::MathMl => expr.to_mathml(),
+        OutputFormat::ContentMathMl => expr.to_content_mathml(),
+        OutputFormat::Unicode => expr.pretty_2d(layout::terminal_width()),
+    }
+}
@@ -0,0 +1,51 @@
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+
+impl Expr {
+    /// Draws the syntax tree with one node per line, e.g. `x^2+1` becomes
+    /// ```text
+    /// Add
+    /// ├── Pow
+    /// │   ├── Variable x
+    /// │   └── Number 2
+    /// └── Number 1
+    /// ```
+    pub fn tree(&self) -> String {
+        let mut lines = Vec::new();
+        draw(self, "", "", &mut lines);
+        lines.join("\n")
+    }
+}
+
+/// Appends `expr` and its children to `lines`. `first` prefixes the node itself and `rest`
+/// prefixes everything below it.
+fn draw(expr: &Expr, first: &str, rest: &str, lines: &mut Vec<String>) {
+    let (label, children): (String, Vec<&Expr>) = match expr {
+        Expr::Number(n) => (format!("Number {n}"), vec![]),
+        Expr::Variable(c) => (format!("Variable {c}"), vec![]),
+        Expr::Constant(c) => (format!("Constant {c}"), vec![]),
+        Expr::Symbol(name) => (format!("Symbol {name}"), vec![]),
+        Expr::Call { name, arg } => (format!("Call {name}"), vec![arg]),
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => ("Neg".to_string(), vec![expr]),
+        Expr::BinaryOp { op, left, right } => {
+            let name = match op {
+                BinaryOp::Add => "Add",
+                BinaryOp::Sub => "Sub",
+                BinaryOp::Mul => "Mul",
+                BinaryOp::Div => "Div",
+                BinaryOp::Pow => "Pow",
+            };
+            (name.to_string(), vec![left, right])
+        }
+    };
+    lines.push(format!("{first}{label}"));
+    for (i, child) in children.iter().enumerate() {
+        if i + 1 == children.len() {
+            draw(child, &format!("{rest}└── "), &format!("{rest}    "), lines);
+        } else {
+            draw(child, &format!("{rest}├── "), &format!("{rest}│   "), lines);
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::evaluator::compiled::CompiledExpr;
+use crate::evaluator::EvalError;
+use crate::parser::ast::Expr;
+
+/// Nodes of the 15 point Kronrod rule on `[-1, 1]`, from the outside in; the odd ones are the
+/// nodes of the embedded 7 point Gauss rule. Only the non-negative half, the rule is symmetric.
+const KRONROD_NODES: [f64; 8] = [
+    0.991455371120813,
+    0.949107912342759,
+    0.864864423359769,
+    0.741531185599394,
+    0.586087235467691,
+    0.405845151377397,
+    0.207784955007898,
+    0.0,
+];
+const KRONROD_WEIGHTS: [f64; 8] = [
+    0.022935322010529,
+    0.063092092629979,
+    0.104790010322250,
+    0.140653259715525,
+    0.169004726639268,
+    0.190350578064785,
+    0.204432940075299,
+    0.209482141084728,
+];
+/// Weights of the Gauss rule for `KRONROD_NODES[1]`, `[3]`, `[5]` and `[7]`.
+const GAUSS_WEIGHTS: [f64; 4] = [
+    0.129484966168870,
+    0.279705391489277,
+    0.381830050505119,
+    0.417959183673469,
+];
+
+/// Accuracy goals and limits of [`definite_integral`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuadratureOptions {
+    /// Absolute error that's good enough.
+    pub abs_tolerance: f64,
+    /// Error relative to the value that's good enough.
+    pub rel_tolerance: f64,
+    /// Largest number of subintervals before giving up.
+    pub max_intervals: usize,
+}
+
+impl Default for QuadratureOptions {
+    fn default() -> Self {
+        QuadratureOptions {
+            abs_tolerance: 1e-10,
+            rel_tolerance: 1e-10,
+            max_intervals: 1000,
+        }
+    }
+}
+
+/// Value of a definite integral with an estimate of its error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quadrature {
+    pub value: f64,
+    /// Estimated absolute error of `value`.
+    pub error: f64,
+    /// Number of subintervals the range ended up split into.
+    pub intervals: usize,
+    /// Number of times the integrand was evaluated.
+    pub evaluations: usize,
+}
+
+/// Reasons a definite integral can't be computed.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum QuadratureError {
+    /// The integrand can't be evaluated.
+    Eval(EvalError),
+    /// A bound is NaN.
+    InvalidBound,
+    /// The integrand is infinite or undefined at this point inside the range.
+    NotFinite(f64),
+    /// The tolerance isn't reached within the subdivision limit, which happens with divergent
+    /// integrals. Holds the best estimate.
+    NotConverged(Quadrature),
+}
+
+impl Display for QuadratureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            QuadratureError::Eval(e) => write!(f, "{e}"),
+            QuadratureError::InvalidBound => f.write_str("bounds must be numbers or infinities"),
+            QuadratureError::NotFinite(x) => {
+                write!(f, "integrand is not finite at {x} inside the range")
+            }
+            QuadratureError::NotConverged(estimate) => write!(
+                f,
+                "no convergence after {} subintervals, the integral may diverge (last estimate {} with estimated error {:.1e})",
+                estimate.intervals, estimate.value, estimate.error
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QuadratureError {}
+
+impl From<EvalError> for QuadratureError {
+    fn from(e: EvalError) -> Self {
+        QuadratureError::Eval(e)
+    }
+}
+
+impl Expr {
+    /// Numerical value of the integral over `var` from `a` to `b`, see [`definite_integral`].
+    /// Every other symbol must be bound in `values`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use derivative_calculator::parse;
+    ///
+    /// let none = HashMap::new();
+    /// let gaussian = parse("exp(-(x^2))")?;
+    /// let result = gaussian.definite_integral('x', 0.0, f64::INFINITY, &none).unwrap();
+    /// assert!((result.value - std::f64::consts::PI.sqrt() / 2.0).abs() < 1e-9);
+    ///
+    /// // Endpoint singularity
+    /// let result = parse("1/sqrt(x)")?.definite_integral('x', 0.0, 1.0, &none).unwrap();
+    /// assert!((result.value - 2.0).abs() < 1e-9);
+    ///
+    /// assert!(parse("1/x")?.definite_integral('x', 0.0, 1.0, &none).is_err());
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn definite_integral(
+        &self,
+        var: char,
+        a: f64,
+        b: f64,
+        values: &HashMap<char, f64>,
+    ) -> Result<Quadrature, QuadratureError> {
+        definite_integral(self, var, a, b, values, &QuadratureOptions::default())
+    }
+}
+
+/// Integrates `expr` over `var` from `a` to `b` by adaptive 15 point Gauss–Kronrod
+/// quadrature, always splitting the subinterval with the largest error estimate until the
+/// total meets the tolerance.
+///
+/// Either bound may be infinite: `[a, ∞)` is mapped onto `[0, 1)` by `x = a + t/(1-t)`, and
+/// the whole real line onto `(-1, 1)` by `x = t/(1-t^2)`. When the integrand is undefined at a
+/// finite bound, `x = a + (b-a)(3t^2 - 2t^3)` smooths out integrable singularities such as that
+/// of `1/sqrt(x)` at `0`. The integrand is never evaluated at the bounds themselves.
+pub fn definite_integral(
+    expr: &Expr,
+    var: char,
+    a: f64,
+    b: f64,
+    values: &HashMap<char, f64>,
+    options: &QuadratureOptions,
+) -> Result<Quadrature, QuadratureError> {
+    if a.is_nan() || b.is_nan() {
+        return Err(QuadratureError::InvalidBound);
+    }
+    if a == b {
+        return Ok(Quadrature {
+            value: 0.0,
+            error: 0.0,
+            intervals: 0,
+            evaluations: 0,
+        });
+    }
+    if a > b {
+        let negate = |mut quadrature: Quadrature| {
+            quadrature.value = -quadrature.value;
+            quadrature
+        };
+        return match definite_integral(expr, var, b, a, values, options) {
+            Ok(quadrature) => Ok(negate(quadrature)),
+            Err(QuadratureError::NotConverged(estimate)) => {
+                Err(QuadratureError::NotConverged(negate(estimate)))
+            }
+            Err(e) => Err(e),
+        };
+    }
+    let f = expr.compile(var, values)?;
+    let mapping = match (a.is_finite(), b.is_finite()) {
+        (true, true) if !f.eval(a).is_finite() || !f.eval(b).is_finite() => {
+            Mapping::Smooth { a, width: b - a }
+        }
+        (true, true) => Mapping::Identity,
+        (true, false) => Mapping::Above(a),
+        (false, true) => Mapping::Below(b),
+        (false, false) => Mapping::Whole,
+    };
+    Integrand { f, mapping }.adaptive(options, mapping.range(a, b))
+}
+
+/// Substitution `x = x(t)` applied before integrating over `t`.
+#[derive(Debug, Clone, Copy)]
+enum Mapping {
+    Identity,
+    /// `x = a + width*(3t^2 - 2t^3)` over `[0, 1]`.
+    Smooth {
+        a: f64,
+        width: f64,
+    },
+    /// `x = a + t/(1-t)` over `[0, 1)`.
+    Above(f64),
+    /// `x = b - t/(1-t)` over `[0, 1)`.
+    Below(f64),
+    /// `x = t/(1-t^2)` over `(-1, 1)`.
+    Whole,
+}
+
+impl Mapping {
+    /// The range of `t`.
+    fn range(&self, a: f64, b: f64) -> (f64, f64) {
+        match self {
+            Mapping::Identity => (a, b),
+            Mapping::Whole => (-1.0, 1.0),
+            _ => (0.0, 1.0),
+        }
+    }
+
+    /// `x(t)` and `dx/dt`.
+    fn apply(&self, t: f64) -> (f64, f64) {
+        match *self {
+            Mapping::Identity => (t, 1.0),
+            Mapping::Smooth { a, width } => (
+                a + width * t * t * (3.0 - 2.0 * t),
+                6.0 * width * t * (1.0 - t),
+            ),
+            Mapping::Above(a) => (a + t / (1.0 - t), 1.0 / ((1.0 - t) * (1.0 - t))),
+            Mapping::Below(b) => (b - t / (1.0 - t), 1.0 / ((1.0 - t) * (1.0 - t))),
+            Mapping::Whole => {
+                let s = 1.0 - t * t;
+                (t / s, (1.0 + t * t) / (s * s))
+            }
+        }
+    }
+}
+
+/// A subinterval of the `t` range with its Kronrod estimate and error.
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    lo: f64,
+    hi: f64,
+    value: f64,
+    error: f64,
+}
+
+struct Integrand {
+    f: CompiledExpr,
+    mapping: Mapping,
+}
+
+impl Integrand {
+    /// `f(x(t)) * x'(t)`, failing where `f` isn't finite.
+    fn at(&self, t: f64) -> Result<f64, QuadratureError> {
+        let (x, dx) = self.mapping.apply(t);
+        let fx = self.f.eval(x);
+        if !fx.is_finite() {
+            return Err(QuadratureError::NotFinite(x));
+        }
+        // Far out in an infinite range f vanishes while dx/dt overflows
+        Ok(if fx == 0.0 { 0.0 } else { fx * dx })
+    }
+
+    fn adaptive(
+        &self,
+        options: &QuadratureOptions,
+        (lo, hi): (f64, f64),
+    ) -> Result<Quadrature, QuadratureError> {
+        // Splitting further than this only chases a non-integrable singularity
+        let min_width = 1e-12 * (hi - lo);
+        let mut segments = vec![self.kronrod(lo, hi)?];
+        loop {
+            let value: f64 = segments.iter().map(|s| s.value).sum();
+            let error: f64 = segments.iter().map(|s| s.error).sum();
+            let result = Quadrature {
+                value,
+                error,
+                intervals: segments.len(),
+                evaluations: 15 * (2 * segments.len() - 1),
+            };
+            if error
+                <= options
+                    .abs_tolerance
+                    .max(options.rel_tolerance * value.abs())
+            {
+                return Ok(result);
+            }
+            let worst = segments
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.error.total_cmp(&b.error))
+                .map(|(idx, _)| idx)
+                .unwrap_or(0);
+            let Segment { lo, hi, .. } = segments[worst];
+            if segments.len() >= options.max_intervals || hi - lo < min_width {
+                return Err(QuadratureError::NotConverged(result));
+            }
+            let mid = 0.5 * (lo + hi);
+            segments[worst] = self.kronrod(lo, mid)?;
+            segments.push(self.kronrod(mid, hi)?);
+        }
+    }
+
+    /// The 15 point Kronrod estimate over `[lo, hi]`, with its difference from the embedded
+    /// 7 point Gauss estimate as the error.
+    fn kronrod(&self, lo: f64, hi: f64) -> Result<Segment, QuadratureError> {
+        let center = 0.5 * (lo + hi);
+        let half = 0.5 * (hi - lo);
+        let mut kronrod = 0.0;
+        let mut gauss = 0.0;
+        for (idx, (node, weight)) in KRONROD_NODES.iter().zip(KRONROD_WEIGHTS).enumerate() {
+            let sum = if *node == 0.0 {
+                self.at(center)?
+            } else {
+                self.at(center - half * node)? + self.at(center + half * node)?
+            };
+            kronrod += weight * sum;
+            if idx % 2 == 1 {
+                gauss += GAUSS_WEIGHTS[idx / 2] * sum;
+            }
+        }
+        Ok(Segment {
+            lo,
+            hi,
+            value: kronrod * half,
+            error: ((kronrod - gauss) * half).abs(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::f64::consts::PI;
+
+    use super::QuadratureError;
+    use crate::parse;
+
+    fn integral(input: &str, a: f64, b: f64) -> Result<f64, QuadratureError> {
+        let values = HashMap::from([('a', 3.0)]);
+        Ok(parse(input)
+            .unwrap()
+            .definite_integral('x', a, b, &values)?
+            .value)
+    }
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!((value - expected).abs() < 1e-9, "{value} != {expected}");
+    }
+
+    #[test]
+    fn finite_ranges() {
+        assert_close(integral("sin(x)", 0.0, PI).unwrap(), 2.0);
+        assert_close(integral("sin(x)", PI, 0.0).unwrap(), -2.0);
+        assert_close(integral("a*x^2", 0.0, 1.0).unwrap(), 1.0);
+        assert_eq!(integral("x", 1.0, 1.0), Ok(0.0));
+    }
+
+    #[test]
+    fn infinite_ranges() {
+        assert_close(
+            integral("exp(-(x^2))", f64::NEG_INFINITY, f64::INFINITY).unwrap(),
+            PI.sqrt(),
+        );
+        assert_close(integral("1/(1+x^2)", 0.0, f64::INFINITY).unwrap(), PI / 2.0);
+        assert_close(integral("exp(x)", f64::NEG_INFINITY, 0.0).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn endpoint_singularities() {
+        assert_close(integral("ln(x)", 0.0, 1.0).unwrap(), -1.0);
+        assert_close(integral("1/sqrt(1-x^2)", -1.0, 1.0).unwrap(), PI);
+    }
+
+    #[test]
+    fn divergent_and_undefined_integrals() {
+        assert!(matches!(
+            integral("1/x", 0.0, 1.0),
+            Err(QuadratureError::NotConverged(_))
+        ));
+        assert_eq!(
+            integral("x", f64::NAN, 1.0),
+            Err(QuadratureError::InvalidBound)
+        );
+        assert!(matches!(
+            integral("1/(x-1/2)", 0.0, 1.0),
+            Err(QuadratureError::NotFinite(_))
+        ));
+        assert!(matches!(
+            integral("b*x", 0.0, 1.0),
+            Err(QuadratureError::Eval(_))
+        ));
+    }
+}
@@ -0,0 +1,207 @@
+use std::fmt::Display;
+
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+
+/// Exact fraction in lowest terms with a positive denominator.
+///
+/// Arithmetic is checked: every operation returns `None` instead of overflowing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numer: i64,
+    denom: i64,
+}
+
+impl Rational {
+    pub const ZERO: Rational = Rational { numer: 0, denom: 1 };
+    pub const ONE: Rational = Rational { numer: 1, denom: 1 };
+
+    /// `numer / denom` reduced to lowest terms, `None` if `denom` is zero.
+    pub fn new(numer: i64, denom: i64) -> Option<Rational> {
+        Self::reduce(numer as i128, denom as i128)
+    }
+
+    /// The integer `n`.
+    pub fn integer(n: i64) -> Rational {
+        Rational { numer: n, denom: 1 }
+    }
+
+    pub fn numer(&self) -> i64 {
+        self.numer
+    }
+
+    pub fn denom(&self) -> i64 {
+        self.denom
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.numer == 0
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denom == 1
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.numer < 0
+    }
+
+    pub fn abs(&self) -> Rational {
+        Rational {
+            numer: self.numer.abs(),
+            denom: self.denom,
+        }
+    }
+
+    pub fn checked_add(self, other: Rational) -> Option<Rational> {
+        let numer =
+            self.numer as i128 * other.denom as i128 + other.numer as i128 * self.denom as i128;
+        Self::reduce(numer, self.denom as i128 * other.denom as i128)
+    }
+
+    pub fn checked_sub(self, other: Rational) -> Option<Rational> {
+        self.checked_add(-other)
+    }
+
+    pub fn checked_mul(self, other: Rational) -> Option<Rational> {
+        Self::reduce(
+            self.numer as i128 * other.numer as i128,
+            self.denom as i128 * other.denom as i128,
+        )
+    }
+
+    /// `None` when dividing by zero or on overflow.
+    pub fn checked_div(self, other: Rational) -> Option<Rational> {
+        Self::reduce(
+            self.numer as i128 * other.denom as i128,
+            self.denom as i128 * other.numer as i128,
+        )
+    }
+
+    /// Integer power, negative exponents invert. `None` for `0^-n` or on overflow.
+    pub fn checked_pow(self, exp: i32) -> Option<Rational> {
+        let mut result = Rational::ONE;
+        for _ in 0..exp.unsigned_abs() {
+            result = result.checked_mul(self)?;
+        }
+        if exp < 0 {
+            Rational::ONE.checked_div(result)
+        } else {
+            Some(result)
+        }
+    }
+
+    /// Exact square root, `None` if the number is negative or not the square of a rational.
+    pub fn sqrt(&self) -> Option<Rational> {
+        if self.numer < 0 {
+            return None;
+        }
+        let (numer, denom) = (self.numer.isqrt(), self.denom.isqrt());
+        (numer * numer == self.numer && denom * denom == self.denom)
+            .then_some(Rational { numer, denom })
+    }
+
+    /// `n!` as a rational, `None` if it doesn't fit.
+    pub fn factorial(n: u32) -> Option<Rational> {
+        (1..=n as i64).try_fold(Rational::ONE, |acc, k| {
+            acc.checked_mul(Rational::integer(k))
+        })
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.numer as f64 / self.denom as f64
+    }
+
+    /// Exact value of an expression built from numbers and `+ - * / ^` with integer exponents,
+    /// `None` if it contains anything else, divides by zero or overflows.
+    pub fn from_expr(expr: &Expr) -> Option<Rational> {
+        match expr {
+            Expr::Number(n) => Some(Rational::integer(*n as i64)),
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+            } => Some(-Rational::from_expr(expr)?),
+            Expr::BinaryOp { op, left, right } => {
+                let left = Rational::from_expr(left)?;
+                let right = Rational::from_expr(right)?;
+                match op {
+                    BinaryOp::Add => left.checked_add(right),
+                    BinaryOp::Sub => left.checked_sub(right),
+                    BinaryOp::Mul => left.checked_mul(right),
+                    BinaryOp::Div => left.checked_div(right),
+                    BinaryOp::Pow if right.is_integer() => {
+                        left.checked_pow(i32::try_from(right.numer).ok()?)
+                    }
+                    BinaryOp::Pow => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The number as an expression: `Number(n)` for integers, otherwise `n/d` with the sign on
+    /// the numerator. `None` if a part doesn't fit an `i32`.
+    pub fn to_expr(&self) -> Option<Expr> {
+        let numer = Expr::Number(i32::try_from(self.numer).ok()?);
+        if self.is_integer() {
+            return Some(numer);
+        }
+        Some(Expr::BinaryOp {
+            op: BinaryOp::Div,
+            left: Box::new(numer),
+            right: Box::new(Expr::Number(i32::try_from(self.denom).ok()?)),
+        })
+    }
+
+    fn reduce(numer: i128, denom: i128) -> Option<Rational> {
+        if denom == 0 {
+            return None;
+        }
+        let divisor = gcd(numer, denom) * denom.signum();
+        Some(Rational {
+            numer: i64::try_from(numer / divisor).ok()?,
+            denom: i64::try_from(denom / divisor).ok()?,
+        })
+    }
+}
+
+impl std::ops::Neg for Rational {
+    type Output = Rational;
+
+    fn neg(self) -> Rational {
+        Rational {
+            numer: -self.numer,
+            denom: self.denom,
+        }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.numer as i128 * other.denom as i128).cmp(&(other.numer as i128 * self.denom as i128))
+    }
+}
+
+impl Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.numer)
+        } else {
+            write!(f, "{}/{}", self.numer, self.denom)
+        }
+    }
+}
+
+/// Greatest common divisor, taken to be 1 when both are zero so it's always safe to divide by.
+pub(crate) fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.max(1)
+}
@@ -0,0 +1,1111 @@
+use std::collections::HashMap;
+
+use super::session::is_name;
+use super::{Flow, Repl};
+use derivative_calculator::analysis::{AnalysisOptions, Point, Trend};
+use derivative_calculator::assumptions::{Assumption, Unproven};
+use derivative_calculator::domain::Domain;
+use derivative_calculator::identities::{Family, Identities, Strategy};
+use derivative_calculator::limit::{Direction, Limit, LimitResult, Target};
+use derivative_calculator::rewrite::egraph::SaturationOptions;
+use derivative_calculator::rewrite::RuleSet;
+use derivative_calculator::roots::{solve_system, NewtonOptions, Start, StepKind};
+use derivative_calculator::{
+    differentiator, printer, BinaryOp, Error, Expr, OutputFormat, SimplifyLevel, BUILTIN_FUNCTIONS,
+};
+
+/// Description of a REPL command, used both for dispatching and for the help text.
+pub(crate) struct CommandSpec {
+    /// Name including the leading `:`.
+    pub name: &'static str,
+    /// Other spellings accepted for the same command.
+    pub aliases: &'static [&'static str],
+    /// Arguments, as shown in the help text.
+    pub args: &'static str,
+    pub help: &'static str,
+    run: fn(&mut Repl, &str) -> Flow,
+}
+
+/// Every command the REPL understands.
+pub(crate) const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: ":help",
+        aliases: &["help", "\\h"],
+        args: "",
+        help: "display this help",
+        run: help,
+    },
+    CommandSpec {
+        name: ":exit",
+        aliases: &["exit", "\\e"],
+        args: "",
+        help: "quit the calculator",
+        run: |_, _| Flow::Exit,
+    },
+    CommandSpec {
+        name: ":clear",
+        aliases: &["clear", "\\c"],
+        args: "",
+        help: "clear the screen",
+        run: clear,
+    },
+    CommandSpec {
+        name: ":format",
+        aliases: &["format", "\\f"],
+        args: "[name]",
+        help: "show or choose how results are printed",
+        run: format,
+    },
+    CommandSpec {
+        name: ":level",
+        aliases: &["level"],
+        args: "[name]",
+        help: "show or choose how derivatives are simplified, e.g. expand or factor",
+        run: level,
+    },
+    CommandSpec {
+        name: ":vars",
+        aliases: &[":list"],
+        args: "",
+        help: "list the names defined with 'name := expr'",
+        run: vars,
+    },
+    CommandSpec {
+        name: ":show",
+        aliases: &[],
+        args: "<name>",
+        help: "show a binding or a result such as 'ans' or '$2'",
+        run: show,
+    },
+    CommandSpec {
+        name: ":del",
+        aliases: &[":delete"],
+        args: "<name>",
+        help: "delete a binding",
+        run: delete,
+    },
+    CommandSpec {
+        name: ":history",
+        aliases: &[],
+        args: "[count]",
+        help: "show the latest history entries (default 20)",
+        run: history,
+    },
+    CommandSpec {
+        name: ":search",
+        aliases: &[],
+        args: "<text>",
+        help: "list history entries containing text (Ctrl-R searches interactively)",
+        run: search,
+    },
+    CommandSpec {
+        name: ":tokens",
+        aliases: &[],
+        args: "<expr>",
+        help: "show the tokens and their positions",
+        run: tokens,
+    },
+    CommandSpec {
+        name: ":ast",
+        aliases: &[],
+        args: "<expr>",
+        help: "show the parsed syntax tree as Rust values",
+        run: ast,
+    },
+    CommandSpec {
+        name: ":tree",
+        aliases: &[],
+        args: "<expr>",
+        help: "draw the parsed syntax tree",
+        run: tree,
+    },
+    CommandSpec {
+        name: ":simplify",
+        aliases: &[],
+        args: "<expr>",
+        help: "simplify an expression without differentiating it, listing the conditions it needed",
+        run: simplify,
+    },
+    CommandSpec {
+        name: ":domain",
+        aliases: &["domain"],
+        args: "<expr>",
+        help: "show where an expression and its derivative are defined, as intervals of x when possible",
+        run: domain,
+    },
+    CommandSpec {
+        name: ":assume",
+        aliases: &["assume"],
+        args: "[<fact> | forget <expr> | clear]",
+        help: "list or declare facts the simplifier may use, e.g. 'assume x > 0' or 'assume n integer'",
+        run: assume,
+    },
+    CommandSpec {
+        name: ":rules",
+        aliases: &["rules"],
+        args: "[load <file> | add <rule> | clear]",
+        help: "list, load, add or drop the rewrite rules applied to results, e.g. 'rules add sin(a)^2 + cos(a)^2 => 1'",
+        run: rules,
+    },
+    CommandSpec {
+        name: ":rewrite",
+        aliases: &["rewrite"],
+        args: "<expr>",
+        help: "apply the built-in and loaded rewrite rules",
+        run: rewrite,
+    },
+    CommandSpec {
+        name: ":cancel",
+        aliases: &["cancel"],
+        args: "<expr>",
+        help: "put over a common denominator and cancel common factors",
+        run: cancel,
+    },
+    CommandSpec {
+        name: ":expand",
+        aliases: &["expand"],
+        args: "<expr>",
+        help: "multiply out products and integer powers",
+        run: expand,
+    },
+    CommandSpec {
+        name: ":factor",
+        aliases: &["factor"],
+        args: "<expr>",
+        help: "factor out common factors and rational roots",
+        run: factor,
+    },
+    CommandSpec {
+        name: ":identities",
+        aliases: &["identities"],
+        args: "[contract|expand] [family...] <expr>",
+        help: "apply trig, exp and log identities, e.g. 'identities expand log double-angle ln(x^2)*sin(2x)'",
+        run: identities,
+    },
+    CommandSpec {
+        name: ":saturate",
+        aliases: &["saturate"],
+        args: "<expr>",
+        help: "simplify by equality saturation, keeping every equal form and picking the smallest",
+        run: saturate,
+    },
+    CommandSpec {
+        name: ":diff",
+        aliases: &[],
+        args: "<expr>",
+        help: "differentiate without simplifying the result",
+        run: diff,
+    },
+    CommandSpec {
+        name: ":eval",
+        aliases: &[],
+        args: "<expr>[, x=value...]",
+        help: "evaluate numerically, e.g. ':eval x^2+a, x=3, a=1/2'",
+        run: eval,
+    },
+    CommandSpec {
+        name: ":subs",
+        aliases: &["subs"],
+        args: "[<expr>,] name=expr...",
+        help: "substitute symbols, in ans by default, e.g. 'subs x = 2*t+1'",
+        run: subs,
+    },
+    CommandSpec {
+        name: ":series",
+        aliases: &["series"],
+        args: "<expr> [at <a>] [order <n>]",
+        help: "Taylor series about x = a (default 0) up to O((x-a)^n), n = 6 by default",
+        run: series,
+    },
+    CommandSpec {
+        name: ":limit",
+        aliases: &["limit"],
+        args: "<expr> at <a>[+|-]",
+        help: "limit as x -> a, from one side with + or -; a can be inf or -inf",
+        run: limit,
+    },
+    CommandSpec {
+        name: ":integrate",
+        aliases: &["integrate"],
+        args: "<expr> [from <a> to <b>]",
+        help: "antiderivative in x, checked by differentiating it back, or with bounds (which can be inf or -inf) the numerical value",
+        run: integrate,
+    },
+    CommandSpec {
+        name: ":analyze",
+        aliases: &["analyze"],
+        args: "<expr> [from <a> to <b>]",
+        help: "critical points with their kind, intervals of increase and decrease and inflection points; roots that can't be solved for exactly are searched for between a and b (default -10 to 10)",
+        run: analyze,
+    },
+    CommandSpec {
+        name: ":newton",
+        aliases: &["newton"],
+        args: "<expr> at <x0> | <expr> from <a> to <b> | <expr>, ... at x=<x0>, y=<y0>, ...",
+        help: "root by Newton's method from a guess, damped when steps overshoot, or within an interval where the sign changes, falling back to bisection; with several equations and named starting values solves the system using its Jacobian",
+        run: newton,
+    },
+];
+
+/// Finds the command `line` starts with and returns it with the rest of the line.
+pub(crate) fn lookup(line: &str) -> Option<(&'static CommandSpec, &str)> {
+    let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    COMMANDS
+        .iter()
+        .find(|command| command.name == word || command.aliases.contains(&word))
+        .map(|command| (command, rest.trim()))
+}
+
+/// Runs `command` with its argument string.
+pub(crate) fn execute(repl: &mut Repl, command: &CommandSpec, args: &str) -> Flow {
+    (command.run)(repl, args)
+}
+
+fn help(_: &mut Repl, _: &str) -> Flow {
+    println!("Type a mathematical expression to differentiate it with respect to x.");
+    println!("You can enter expressions using numbers, variables (like x), operators (+, -, *, /, ^), and parentheses.");
+    println!("Examples:");
+    println!("  2*x + 3");
+    println!("  (x^2+2*x+1)/(x+1) ");
+    println!("  f := x^3 + a*x, then f(2*x), ans or $1");
+    println!("Built-in functions: {}", BUILTIN_FUNCTIONS.join(", "));
+    println!("Commands:");
+    let width = COMMANDS
+        .iter()
+        .map(|command| command.name.len() + command.args.len() + 3)
+        .max()
+        .unwrap_or(0);
+    for command in COMMANDS {
+        let usage = format!("{} {}", command.name, command.args);
+        let aliases = if command.aliases.is_empty() {
+            String::new()
+        } else {
+            format!(" (also {})", command.aliases.join(", "))
+        };
+        println!("  {usage:<width$}{}{aliases}", command.help);
+    }
+    let names: Vec<&str> = OutputFormat::ALL.iter().map(|f| f.name()).collect();
+    println!("Available formats: {}", names.join(", "));
+    let names: Vec<&str> = SimplifyLevel::ALL.iter().map(|l| l.name()).collect();
+    println!("Available levels: {}", names.join(", "));
+    Flow::Continue
+}
+
+fn clear(repl: &mut Repl, _: &str) -> Flow {
+    if let Err(e) = repl.editor.clear_screen() {
+        println!("Error: {e}");
+    }
+    Flow::Continue
+}
+
+fn format(repl: &mut Repl, name: &str) -> Flow {
+    if name.is_empty() {
+        println!("Output format is {}.", repl.format.name());
+        return Flow::Continue;
+    }
+    match OutputFormat::from_name(name) {
+        Some(selected) => {
+            repl.format = selected;
+            println!("Output format set to {}.", selected.name());
+        }
+        None => println!("Unknown format '{name}'."),
+    }
+    Flow::Continue
+}
+
+fn level(repl: &mut Repl, name: &str) -> Flow {
+    if name.is_empty() {
+        println!("Simplification level is {}.", repl.level.name());
+        return Flow::Continue;
+    }
+    match SimplifyLevel::from_name(name) {
+        Some(selected) => {
+            repl.level = selected;
+            println!("Simplification level set to {}.", selected.name());
+        }
+        None => println!("Unknown level '{name}'."),
+    }
+    Flow::Continue
+}
+
+fn vars(repl: &mut Repl, _: &str) -> Flow {
+    let mut empty = true;
+    for (name, expr) in repl.session.bindings() {
+        println!(
+            "{}",
+            render_labelled(&format!("{name} :="), expr, repl.format)
+        );
+        empty = false;
+    }
+    if empty {
+        println!("Nothing defined yet. Use 'name := expr' to define something.");
+    }
+    match repl.session.result_count() {
+        0 => {}
+        1 => println!("1 result, available as ans or $1."),
+        n => println!("{n} results, available as ans and $1 to ${n}."),
+    }
+    Flow::Continue
+}
+
+fn show(repl: &mut Repl, name: &str) -> Flow {
+    match repl.session.get(name) {
+        Ok(expr) => println!(
+            "{}",
+            render_labelled(&format!("{name}:"), expr, repl.format)
+        ),
+        Err(e) => println!("{e}"),
+    }
+    Flow::Continue
+}
+
+fn delete(repl: &mut Repl, name: &str) -> Flow {
+    if repl.session.remove(name) {
+        println!("Deleted '{name}'.");
+    } else {
+        println!("'{name}' is not defined.");
+    }
+    Flow::Continue
+}
+
+fn history(repl: &mut Repl, count: &str) -> Flow {
+    let count = if count.is_empty() {
+        20
+    } else {
+        match count.parse::<usize>() {
+            Ok(count) => count,
+            Err(_) => {
+                println!("'{count}' is not a number.");
+                return Flow::Continue;
+            }
+        }
+    };
+    let entries: Vec<&String> = repl.editor.history().iter().collect();
+    let start = entries.len().saturating_sub(count);
+    for (idx, entry) in entries.iter().enumerate().skip(start) {
+        println!("{:>5}  {entry}", idx + 1);
+    }
+    match &repl.history.path {
+        Ok(path) => println!("History file: {}", path.display()),
+        Err(reason) => println!("History is not saved ({reason})."),
+    }
+    Flow::Continue
+}
+
+fn search(repl: &mut Repl, text: &str) -> Flow {
+    if text.is_empty() {
+        println!("Usage: :search <text>");
+        return Flow::Continue;
+    }
+    let mut found = false;
+    for (idx, entry) in repl.editor.history().iter().enumerate() {
+        if entry.contains(text) {
+            println!("{:>5}  {entry}", idx + 1);
+            found = true;
+        }
+    }
+    if !found {
+        println!("No history entry contains '{text}'.");
+    }
+    Flow::Continue
+}
+
+fn tokens(_: &mut Repl, input: &str) -> Flow {
+    for lexeme in derivative_calculator::lex(input) {
+        match lexeme {
+            Ok((position, lexeme)) => println!("{position:>4}  {lexeme:?}"),
+            Err(e) => println!("{}", Error::Tokenize(e)),
+        }
+    }
+    Flow::Continue
+}
+
+fn ast(repl: &mut Repl, input: &str) -> Flow {
+    if let Some(expr) = repl.parse_or_report(input) {
+        println!("{expr:#?}");
+    }
+    Flow::Continue
+}
+
+fn tree(repl: &mut Repl, input: &str) -> Flow {
+    if let Some(expr) = repl.parse_or_report(input) {
+        println!("{}", expr.tree());
+    }
+    Flow::Continue
+}
+
+fn simplify(repl: &mut Repl, input: &str) -> Flow {
+    if let Some(expr) = repl.parse_or_report(input) {
+        let simplified = expr
+            .simplify()
+            .simplify_assuming(&repl.assumptions, Unproven::Record);
+        repl.print_result("Simplified", &simplified.expr);
+        for condition in &simplified.side_conditions {
+            println!("  if {condition}");
+        }
+    }
+    Flow::Continue
+}
+
+fn domain(repl: &mut Repl, input: &str) -> Flow {
+    let Some(expr) = repl.parse_or_report(input) else {
+        return Flow::Continue;
+    };
+    let domain = expr.domain();
+    println!("Expression {}", describe_domain(&domain, 'x'));
+    match expr.diff('x') {
+        Ok(derivative) => {
+            let domain = domain.and(derivative.simplify().domain());
+            println!("Derivative {}", describe_domain(&domain, 'x'));
+        }
+        Err(e) => println!("{}", Error::Differentiate(e)),
+    }
+    Flow::Continue
+}
+
+fn assume(repl: &mut Repl, input: &str) -> Flow {
+    let (action, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    match (action, rest.trim()) {
+        ("", _) => {
+            for fact in repl.assumptions.iter() {
+                println!("  {fact}");
+            }
+            if repl.assumptions.is_empty() {
+                println!("Nothing assumed yet. Use e.g. ':assume x > 0'.");
+            }
+        }
+        ("clear", "") => {
+            repl.assumptions.clear();
+            println!("Assumptions cleared.");
+        }
+        ("forget", expr) if !expr.is_empty() => {
+            if let Some(expr) = repl.parse_or_report(expr) {
+                if repl.assumptions.forget(&expr) {
+                    println!("Forgot the assumptions about {}.", expr.pretty());
+                } else {
+                    println!("Nothing is assumed about {}.", expr.pretty());
+                }
+            }
+        }
+        _ => match input.parse::<Assumption>() {
+            Ok(fact) => {
+                let shown = fact.to_string();
+                match repl.assumptions.add(fact) {
+                    Ok(()) => println!("Assuming {shown}."),
+                    Err(e) => println!("Can't assume {shown}: {e}"),
+                }
+            }
+            Err(e) => println!("{e}"),
+        },
+    }
+    Flow::Continue
+}
+
+fn rules(repl: &mut Repl, input: &str) -> Flow {
+    let (action, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    let loaded = match (action, rest.trim()) {
+        ("", _) => {
+            for rule in repl.rules.iter() {
+                println!("  {rule}");
+            }
+            println!(
+                "{} loaded rule(s), applied after the {} built-in ones.",
+                repl.rules.len(),
+                RuleSet::builtin().len()
+            );
+            return Flow::Continue;
+        }
+        ("clear", "") => {
+            repl.rules = RuleSet::default();
+            println!("Loaded rules cleared.");
+            return Flow::Continue;
+        }
+        ("load", path) if !path.is_empty() => RuleSet::load(path),
+        ("add", rule) if !rule.is_empty() => RuleSet::parse(rule),
+        _ => {
+            println!("Usage: :rules [load <file> | add <rule> | clear]");
+            return Flow::Continue;
+        }
+    };
+    match loaded {
+        Ok(rules) => {
+            println!("Added {} rule(s).", rules.len());
+            repl.rules.extend(rules);
+        }
+        Err(e) => println!("Rule error: {e}"),
+    }
+    Flow::Continue
+}
+
+fn rewrite(repl: &mut Repl, input: &str) -> Flow {
+    if let Some(expr) = repl.parse_or_report(input) {
+        let rewritten = repl.rewrite(&expr.simplify());
+        repl.print_result("Rewritten", &rewritten);
+    }
+    Flow::Continue
+}
+
+fn cancel(repl: &mut Repl, input: &str) -> Flow {
+    if let Some(expr) = repl.parse_or_report(input) {
+        repl.print_result("Cancelled", &expr.simplify().cancel());
+    }
+    Flow::Continue
+}
+
+fn expand(repl: &mut Repl, input: &str) -> Flow {
+    if let Some(expr) = repl.parse_or_report(input) {
+        repl.print_result("Expanded", &expr.simplify().expand());
+    }
+    Flow::Continue
+}
+
+fn factor(repl: &mut Repl, input: &str) -> Flow {
+    if let Some(expr) = repl.parse_or_report(input) {
+        match expr.simplify().try_factor() {
+            Ok(factored) => repl.print_result("Factored", &factored),
+            Err(e) => println!("Factor error: {e}"),
+        }
+    }
+    Flow::Continue
+}
+
+fn identities(repl: &mut Repl, input: &str) -> Flow {
+    let mut identities = Identities {
+        families: Vec::new(),
+        ..Identities::default()
+    };
+    let mut rest = input;
+    while let Some((word, tail)) = rest.split_once(char::is_whitespace) {
+        if let Some(strategy) = Strategy::from_name(word) {
+            identities.strategy = strategy;
+        } else if let Some(family) = Family::from_name(word) {
+            identities.families.push(family);
+        } else {
+            break;
+        }
+        rest = tail.trim_start();
+    }
+    if identities.families.is_empty() {
+        identities.families = Family::ALL.to_vec();
+    }
+    if let Some(expr) = repl.parse_or_report(rest) {
+        repl.print_result("Simplified", &expr.simplify_identities(&identities));
+    }
+    Flow::Continue
+}
+
+fn saturate(repl: &mut Repl, input: &str) -> Flow {
+    if let Some(expr) = repl.parse_or_report(input) {
+        let saturation = expr.saturate(&SaturationOptions::default());
+        repl.print_result("Saturated", &saturation.expr);
+        println!(
+            "  {} nodes after {} iteration(s), {}",
+            saturation.nodes, saturation.iterations, saturation.stop
+        );
+    }
+    Flow::Continue
+}
+
+fn diff(repl: &mut Repl, input: &str) -> Flow {
+    if let Some(expr) = repl.parse_or_report(input) {
+        match differentiator::differentiate(&expr, 'x') {
+            Ok(derivative) => repl.print_result("Derivative", &derivative),
+            Err(e) => println!("{}", Error::Differentiate(e)),
+        }
+    }
+    Flow::Continue
+}
+
+fn eval(repl: &mut Repl, input: &str) -> Flow {
+    let mut parts = input.split(',');
+    let Some(expr) = parts.next().and_then(|expr| repl.parse_or_report(expr)) else {
+        return Flow::Continue;
+    };
+    let mut values = HashMap::new();
+    for binding in parts {
+        let Some((name, value)) = binding.split_once('=') else {
+            println!("Expected 'symbol=value', found '{}'.", binding.trim());
+            return Flow::Continue;
+        };
+        let mut chars = name.trim().chars();
+        let (Some(symbol), None) = (chars.next(), chars.next()) else {
+            println!("'{}' is not a single letter symbol.", name.trim());
+            return Flow::Continue;
+        };
+        let Some(value) = repl.parse_or_report(value) else {
+            return Flow::Continue;
+        };
+        match value.eval(&HashMap::new()) {
+            Ok(value) => values.insert(symbol, value),
+            Err(e) => {
+                println!("Evaluation error: {e}");
+                return Flow::Continue;
+            }
+        };
+    }
+    match expr.eval(&values) {
+        Ok(value) => println!("Value: {value}"),
+        Err(e) => println!("Evaluation error: {e}"),
+    }
+    Flow::Continue
+}
+
+fn subs(repl: &mut Repl, input: &str) -> Flow {
+    let mut parts: Vec<&str> = input.split(',').collect();
+    let target = match parts.first() {
+        Some(first) if !first.contains('=') && !first.trim().is_empty() => parts.remove(0),
+        _ => "ans",
+    };
+    if parts.is_empty() {
+        println!("Usage: :subs [<expr>,] name=expr...");
+        return Flow::Continue;
+    }
+    let Some(expr) = repl.parse_names_or_report(target) else {
+        return Flow::Continue;
+    };
+    let mut substitutions = HashMap::new();
+    for binding in parts {
+        let Some((name, value)) = binding.split_once('=') else {
+            println!("Expected 'name=expr', found '{}'.", binding.trim());
+            return Flow::Continue;
+        };
+        let name = name.trim();
+        if !is_name(name) {
+            println!("'{name}' is not a name.");
+            return Flow::Continue;
+        }
+        let Some(value) = repl.parse_or_report(value) else {
+            return Flow::Continue;
+        };
+        substitutions.insert(name.to_string(), value);
+    }
+    // Bound names are replaced before they're resolved to their values, the others after,
+    // within the values of `ans` and the bound names
+    let (bound, free): (HashMap<_, _>, HashMap<_, _>) = substitutions
+        .into_iter()
+        .partition(|(name, _)| repl.session.is_bound(name));
+    let Some(expr) = repl.resolve_or_report(&expr.substitute(&bound)) else {
+        return Flow::Continue;
+    };
+    repl.print_result("Substituted", &expr.substitute(&free).simplify());
+    Flow::Continue
+}
+
+fn series(repl: &mut Repl, input: &str) -> Flow {
+    let (input, order) = match input.rsplit_once(" order ") {
+        Some((input, order)) => match order.trim().parse::<u32>() {
+            Ok(order) => (input, order),
+            Err(_) => {
+                println!("'{}' is not a number.", order.trim());
+                return Flow::Continue;
+            }
+        },
+        None => (input, 6),
+    };
+    let (input, point) = match input.rsplit_once(" at ") {
+        Some((input, point)) => (input, repl.parse_or_report(point)),
+        None => (input, Some(Expr::Number(0))),
+    };
+    let (Some(expr), Some(point)) = (repl.parse_or_report(input), point) else {
+        return Flow::Continue;
+    };
+    match expr.series('x', &point, order) {
+        Ok(series) => {
+            let number = repl.session.push_result(series.polynomial());
+            let label = format!("Series (${number}):");
+            println!(
+                "{}",
+                render_labelled(&label, &series.to_expr(), repl.format)
+            );
+            if series.order < order {
+                println!(
+                    "Stopped at order {}, the next coefficient doesn't fit a fraction of 64-bit integers.",
+                    series.order
+                );
+            }
+        }
+        Err(e) => println!("Series error: {e}"),
+    }
+    Flow::Continue
+}
+
+fn limit(repl: &mut Repl, input: &str) -> Flow {
+    let Some((input, point)) = input.rsplit_once(" at ") else {
+        println!("Usage: :limit <expr> at <a>[+|-]");
+        return Flow::Continue;
+    };
+    let point = point.trim();
+    let (point, direction) = if let Some(point) = point.strip_suffix('+') {
+        (point, Direction::Above)
+    } else if let Some(point) = point.strip_suffix('-') {
+        (point, Direction::Below)
+    } else {
+        (point, Direction::Both)
+    };
+    let target = match point.trim() {
+        "inf" | "+inf" => Target::PosInfinity,
+        "-inf" => Target::NegInfinity,
+        point => match repl.parse_or_report(point) {
+            Some(point) => Target::Point(point),
+            None => return Flow::Continue,
+        },
+    };
+    let Some(expr) = repl.parse_or_report(input) else {
+        return Flow::Continue;
+    };
+    match expr.limit('x', &target, direction) {
+        Ok(LimitResult {
+            limit: Limit::Finite(value),
+            strategy,
+        }) => {
+            let number = repl.session.push_result(value.clone());
+            let label = format!("Limit (${number}, by {strategy}):");
+            println!("{}", render_labelled(&label, &value, repl.format));
+        }
+        Ok(LimitResult { limit, strategy }) => println!("Limit (by {strategy}): {limit}"),
+        Err(e) => println!("Limit error: {e}"),
+    }
+    Flow::Continue
+}
+
+fn integrate(repl: &mut Repl, input: &str) -> Flow {
+    if let Some((input, bounds)) = input.rsplit_once(" from ") {
+        return definite_integral(repl, input, bounds);
+    }
+    let Some(expr) = repl.parse_or_report(input) else {
+        return Flow::Continue;
+    };
+    match expr.integrate('x') {
+        Ok(antiderivative) => {
+            let number = repl.session.push_result(antiderivative.clone());
+            let label = format!("Integral (${number}):");
+            let with_constant = Expr::BinaryOp {
+                op: BinaryOp::Add,
+                left: Box::new(antiderivative),
+                right: Box::new(Expr::Constant('C')),
+            };
+            println!("{}", render_labelled(&label, &with_constant, repl.format));
+        }
+        Err(e) => println!("Integration error: {e}"),
+    }
+    Flow::Continue
+}
+
+fn definite_integral(repl: &mut Repl, input: &str, bounds: &str) -> Flow {
+    let Some((a, b)) = bounds.split_once(" to ") else {
+        println!("Usage: :integrate <expr> from <a> to <b>");
+        return Flow::Continue;
+    };
+    let (Some(expr), Some(a), Some(b)) = (
+        repl.parse_or_report(input),
+        bound_or_report(repl, a),
+        bound_or_report(repl, b),
+    ) else {
+        return Flow::Continue;
+    };
+    match expr.definite_integral('x', a, b, &HashMap::new()) {
+        Ok(result) => println!(
+            "Value: {} (estimated error {:.1e})",
+            result.value, result.error
+        ),
+        Err(e) => println!("Integration error: {e}"),
+    }
+    Flow::Continue
+}
+
+fn analyze(repl: &mut Repl, input: &str) -> Flow {
+    let mut options = AnalysisOptions::default();
+    let input = match input.rsplit_once(" from ") {
+        Some((input, bounds)) => {
+            let Some((a, b)) = bounds.split_once(" to ") else {
+                println!("Usage: :analyze <expr> [from <a> to <b>]");
+                return Flow::Continue;
+            };
+            let (Some(a), Some(b)) = (bound_or_report(repl, a), bound_or_report(repl, b)) else {
+                return Flow::Continue;
+            };
+            if !(a.is_finite() && b.is_finite() && a < b) {
+                println!("The bounds must be finite, the first below the second.");
+                return Flow::Continue;
+            }
+            options.range = (a, b);
+            input
+        }
+        None => input,
+    };
+    let Some(expr) = repl.parse_or_report(input) else {
+        return Flow::Continue;
+    };
+    let analysis = match expr.analyze('x', &options) {
+        Ok(analysis) => analysis,
+        Err(e) => {
+            println!("Analysis error: {e}");
+            return Flow::Continue;
+        }
+    };
+    repl.print_result("Derivative", &analysis.derivative);
+    println!(
+        "{}",
+        render_labelled(
+            "Second derivative:",
+            &analysis.second_derivative,
+            repl.format
+        )
+    );
+    if analysis.critical_points.is_empty() {
+        println!("No critical points.");
+    } else {
+        println!("Critical points:");
+        for (point, extremum) in &analysis.critical_points {
+            println!(
+                "  {}: {extremum}, f = {}",
+                at(point),
+                approximate(point.value)
+            );
+        }
+    }
+    for trend in [Trend::Increasing, Trend::Decreasing, Trend::Constant] {
+        let intervals: Vec<String> = analysis
+            .monotonicity
+            .iter()
+            .filter(|(_, t)| *t == trend)
+            .map(|(interval, _)| interval.to_string())
+            .collect();
+        if !intervals.is_empty() {
+            let trend = trend.to_string();
+            let (first, rest) = trend.split_at(1);
+            println!(
+                "{}{rest} on {}",
+                first.to_uppercase(),
+                intervals.join(" U ")
+            );
+        }
+    }
+    if analysis.inflection_points.is_empty() {
+        println!("No inflection points.");
+    }
+    for point in &analysis.inflection_points {
+        println!(
+            "Inflection point at {}, f = {}",
+            at(point),
+            approximate(point.value)
+        );
+    }
+    if let Some((a, b)) = analysis.range {
+        println!("Searched numerically between {a} and {b}.");
+    }
+    Flow::Continue
+}
+
+fn newton(repl: &mut Repl, input: &str) -> Flow {
+    let start = if let Some((input, guess)) = input.rsplit_once(" at ") {
+        if guess.contains('=') {
+            return newton_system(repl, input, guess);
+        }
+        bound_or_report(repl, guess).map(|x0| (input, Start::Guess(x0)))
+    } else if let Some((input, bounds)) = input.rsplit_once(" from ") {
+        match bounds.split_once(" to ") {
+            Some((a, b)) => match (bound_or_report(repl, a), bound_or_report(repl, b)) {
+                (Some(a), Some(b)) => Some((input, Start::Bracket(a, b))),
+                _ => None,
+            },
+            None => {
+                println!("Usage: :newton <expr> from <a> to <b>");
+                None
+            }
+        }
+    } else {
+        println!("Usage: :newton <expr> at <x0> or :newton <expr> from <a> to <b>");
+        None
+    };
+    let Some((input, start)) = start else {
+        return Flow::Continue;
+    };
+    let Some(expr) = repl.parse_or_report(input) else {
+        return Flow::Continue;
+    };
+    let root = match expr.find_root('x', start, &HashMap::new()) {
+        Ok(root) => root,
+        Err(e) => {
+            println!("Root finding error: {e}");
+            return Flow::Continue;
+        }
+    };
+    repl.print_result("Derivative", &root.derivative);
+    for (i, iteration) in root.iterations.iter().enumerate() {
+        println!(
+            "  {:>3}  x = {:<19.15} f = {:<10.3e} step {:.3e} ({})",
+            i + 1,
+            iteration.x,
+            iteration.residual,
+            iteration.step,
+            iteration.kind
+        );
+    }
+    println!(
+        "Root: x ≈ {} (f = {:.1e})",
+        approximate(root.x),
+        root.residual
+    );
+    let mut summary = format!(
+        "Converged in {} iterations: {} Newton, {} damped, {} bisection",
+        root.iterations.len(),
+        root.count(StepKind::Newton),
+        root.count(StepKind::Damped(0)),
+        root.count(StepKind::Bisection)
+    );
+    if let Some(order) = root.order() {
+        summary.push_str(&format!(", order of convergence ≈ {order:.1}"));
+    }
+    println!("{summary}.");
+    Flow::Continue
+}
+
+/// `:newton` with equations separated by commas and starting values like `x=1, y=2`.
+fn newton_system(repl: &mut Repl, input: &str, guess: &str) -> Flow {
+    let mut vars = Vec::new();
+    let mut start = Vec::new();
+    for binding in guess.split(',') {
+        let Some((var, value)) = binding.split_once('=') else {
+            println!("Starting values look like x=1, y=2.");
+            return Flow::Continue;
+        };
+        let mut chars = var.trim().chars();
+        let (Some(var), None) = (chars.next(), chars.next()) else {
+            println!("'{}' is not a single-letter variable.", var.trim());
+            return Flow::Continue;
+        };
+        let Some(value) = bound_or_report(repl, value) else {
+            return Flow::Continue;
+        };
+        vars.push(var);
+        start.push(value);
+    }
+    let Some(equations) = input
+        .split(',')
+        .map(|equation| repl.parse_or_report(equation))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Flow::Continue;
+    };
+    let options = NewtonOptions::default();
+    match solve_system(&equations, &vars, &start, &HashMap::new(), &options) {
+        Ok(root) => {
+            println!("Jacobian:");
+            for row in &root.jacobian {
+                let row: Vec<String> = row.iter().map(|entry| entry.pretty()).collect();
+                println!("  [{}]", row.join(", "));
+            }
+            let values: Vec<String> = vars
+                .iter()
+                .zip(&root.values)
+                .map(|(var, value)| format!("{var} ≈ {}", approximate(*value)))
+                .collect();
+            println!("Solution: {}", values.join(", "));
+            let largest = root.residuals.iter().fold(0f64, |m, r| m.max(r.abs()));
+            println!(
+                "Converged in {} iterations with {} step halvings, largest residual {largest:.1e}.",
+                root.iterations, root.halvings
+            );
+        }
+        Err(e) => println!("Root finding error: {e}"),
+    }
+    Flow::Continue
+}
+
+/// `x = 1` or, for points only found numerically, `x ≈ 1.570796`.
+fn at(point: &Point) -> String {
+    match point.exact {
+        Some(_) => format!("x = {point}"),
+        None => format!("x ≈ {point}"),
+    }
+}
+
+/// `value` to six decimals at most.
+fn approximate(value: f64) -> String {
+    let text = format!("{value:.6}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" { "0" } else { text }.to_string()
+}
+
+/// An integration bound: `inf`, `-inf` or an expression with a numerical value. Prints the
+/// error if it is neither.
+fn bound_or_report(repl: &Repl, input: &str) -> Option<f64> {
+    match input.trim() {
+        "inf" | "+inf" => return Some(f64::INFINITY),
+        "-inf" => return Some(f64::NEG_INFINITY),
+        _ => {}
+    }
+    repl.parse_or_report(input)?
+        .eval(&HashMap::new())
+        .map_err(|e| println!("Evaluation error: {e}"))
+        .ok()
+}
+
+/// Where `domain` holds, e.g. `defined where x > 0, i.e. x in (0, inf)`.
+pub(crate) fn describe_domain(domain: &Domain, var: char) -> String {
+    let mut text = if domain.is_everywhere() {
+        "defined everywhere".to_string()
+    } else {
+        format!("defined where {domain}")
+    };
+    match domain.intervals(var) {
+        Some(intervals) if intervals.is_empty() => text.push_str(", i.e. nowhere"),
+        Some(intervals) if !domain.is_everywhere() => {
+            let intervals: Vec<String> = intervals.iter().map(|i| i.to_string()).collect();
+            text.push_str(&format!(", i.e. {var} in {}", intervals.join(" U ")));
+        }
+        _ => {}
+    }
+    text
+}
+
+/// Renders `expr` in the selected format after `label` (e.g. `"Derivative:"`), putting
+/// multi-line output below the label.
+pub(crate) fn render_labelled(label: &str, expr: &Expr, format: OutputFormat) -> String {
+    let rendered = printer::render(expr, format);
+    if rendered.contains('\n') {
+        format!("{label}\n{rendered}")
+    } else {
+        format!("{label} {rendered}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{describe_domain, lookup};
+    use derivative_calculator::parse;
+
+    #[test]
+    fn lookup_matches_names_and_aliases() {
+        let (command, rest) = lookup(":format  latex ").unwrap();
+        assert_eq!((command.name, rest), (":format", "latex"));
+        assert_eq!(lookup("\\f").unwrap().0.name, ":format");
+        assert_eq!(lookup(":list").unwrap().0.name, ":vars");
+        let (command, rest) = lookup("exit").unwrap();
+        assert_eq!(command.name, ":exit");
+        assert_eq!(rest, "");
+    }
+
+    #[test]
+    fn lookup_rejects_other_words() {
+        assert!(lookup(":nope").is_none());
+        assert!(lookup(":form").is_none());
+        assert!(lookup("x^2").is_none());
+    }
+
+    #[test]
+    fn domains_are_described_with_their_intervals() {
+        let describe = |input: &str| describe_domain(&parse(input).unwrap().domain(), 'x');
+        assert_eq!(describe("x^2+1"), "defined everywhere");
+        assert_eq!(
+            describe("ln(x-1)"),
+            "defined where x-1 > 0, i.e. x in (1, inf)"
+        );
+        assert_eq!(
+            describe("ln(-x^2-1)"),
+            "defined where -x^2-1 > 0, i.e. nowhere"
+        );
+        assert_eq!(describe("tan(x)"), "defined where cos(x) != 0");
+    }
+}
@@ -0,0 +1,452 @@
+use std::borrow::Cow;
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Helper};
+
+use super::commands::{self, COMMANDS};
+use super::session::Session;
+use derivative_calculator::{lex, Lexeme, OutputFormat, SimplifyLevel, BUILTIN_FUNCTIONS};
+
+const RESET: &str = "\x1b[0m";
+const NUMBER: &str = "\x1b[33m";
+const VARIABLE: &str = "\x1b[1;36m";
+const CONSTANT: &str = "\x1b[35m";
+const FUNCTION: &str = "\x1b[34m";
+const NAME: &str = "\x1b[32m";
+const OPERATOR: &str = "\x1b[1m";
+const COMMAND: &str = "\x1b[1;34m";
+const MATCHING_PAREN: &str = "\x1b[1;4;32m";
+const UNMATCHED_PAREN: &str = "\x1b[1;31m";
+const HINT: &str = "\x1b[2m";
+
+/// Line editor integration: colours the input using [`lex`], keeps reading while
+/// parentheses are open, shows the derivative of the current line as a hint and completes
+/// commands, functions and session names.
+pub(crate) struct ReplHelper {
+    /// Copy of the REPL session, refreshed before every line, so hints can resolve names.
+    pub session: Session,
+    /// Disabled when `NO_COLOR` is set.
+    colors: bool,
+}
+
+impl ReplHelper {
+    pub fn new() -> Self {
+        Self {
+            session: Session::default(),
+            colors: std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+        }
+    }
+}
+
+impl Helper for ReplHelper {}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let trimmed = before.trim_start();
+        let indent = before.len() - trimmed.len();
+
+        // Until a space follows it the command name itself is being completed
+        let command = trimmed
+            .contains(char::is_whitespace)
+            .then(|| commands::lookup(trimmed))
+            .flatten();
+        if let Some((command, _)) = command {
+            // The arguments as typed, since `lookup` trims them
+            let args_start = indent + trimmed.find(char::is_whitespace).unwrap_or(0);
+            let args = &before[args_start..];
+            let word_start = pos - args.rsplit(char::is_whitespace).next().unwrap_or("").len();
+            let word = &before[word_start..];
+            let candidates = match command.name {
+                ":format" => candidates(OutputFormat::ALL.iter().map(|f| f.name()), word, ""),
+                ":show" | ":del" => candidates(
+                    self.session.bindings().map(|(name, _)| name.as_str()),
+                    word,
+                    "",
+                ),
+                ":help" | ":exit" | ":clear" | ":vars" | ":history" | ":search" => Vec::new(),
+                _ => return Ok(self.complete_expression(before, args_start)),
+            };
+            return Ok((word_start, candidates));
+        }
+        if trimmed.starts_with(':') || trimmed.starts_with('\\') {
+            let names = COMMANDS
+                .iter()
+                .flat_map(|command| {
+                    std::iter::once(command.name).chain(command.aliases.iter().copied())
+                })
+                .filter(|name| name.starts_with(trimmed.chars().next().unwrap_or(':')));
+            return Ok((indent, candidates(names, trimmed, " ")));
+        }
+        let expression_start = before.find(":=").map_or(0, |idx| idx + 2);
+        Ok(self.complete_expression(before, expression_start))
+    }
+}
+
+impl ReplHelper {
+    /// Completes the name being typed at the end of `before[start..]`, an expression, using the
+    /// tokenizer to find where that name begins.
+    fn complete_expression(&self, before: &str, start: usize) -> (usize, Vec<Pair>) {
+        let text = &before[start..];
+        // `$` on its own isn't a valid token yet, so result references are handled first.
+        let digits = text.trim_end_matches(|chr: char| chr.is_ascii_digit());
+        if digits.ends_with('$') {
+            let word_start = start + digits.len() - 1;
+            let references: Vec<String> = (1..=self.session.result_count())
+                .map(|n| format!("${n}"))
+                .collect();
+            let references = references.iter().map(String::as_str);
+            return (
+                word_start,
+                candidates(references, &before[word_start..], ""),
+            );
+        }
+
+        let Ok(lexemes) = lex(text).into_iter().collect::<Result<Vec<_>, _>>() else {
+            return (before.len(), Vec::new());
+        };
+        let word = match lexemes.last() {
+            Some((_, Lexeme::Name(name))) => name.clone(),
+            Some((_, Lexeme::Constant(c) | Lexeme::Variable(c))) => c.to_string(),
+            _ => String::new(),
+        };
+        // The word has to end at the cursor, `sin ` is finished.
+        if !text.ends_with(&word) {
+            return (before.len(), Vec::new());
+        }
+
+        let mut names: Vec<(String, &str)> = BUILTIN_FUNCTIONS
+            .iter()
+            .map(|name| (name.to_string(), "("))
+            .collect();
+        // Bindings that depend on `x` are usually called.
+        names.extend(self.session.bindings().map(|(name, expr)| {
+            let suffix = if expr.contains_variable('x') { "(" } else { "" };
+            (name.clone(), suffix)
+        }));
+        if self.session.result_count() > 0 {
+            names.push(("ans".to_string(), ""));
+        }
+        let candidates = names
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(&word))
+            .map(|(name, suffix)| Pair {
+                replacement: format!("{name}{suffix}"),
+                display: name,
+            })
+            .collect();
+        (before.len() - word.len(), candidates)
+    }
+}
+
+/// Every name in `names` starting with `prefix`, replaced with `suffix` appended.
+fn candidates<'a>(names: impl Iterator<Item = &'a str>, prefix: &str, suffix: &str) -> Vec<Pair> {
+    names
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| Pair {
+            display: name.to_string(),
+            replacement: format!("{name}{suffix}"),
+        })
+        .collect()
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if !self.colors || line.is_empty() {
+            return Cow::Borrowed(line);
+        }
+        let parens = paren_colors(line, pos);
+        let mut out = String::with_capacity(line.len() * 2);
+
+        // Commands and assignments start with something the tokenizer doesn't know about.
+        let mut rest_start = 0;
+        if line.starts_with(':') || line.starts_with('\\') {
+            let end = line.find(char::is_whitespace).unwrap_or(line.len());
+            out.push_str(&format!("{COMMAND}{}{RESET}", &line[..end]));
+            rest_start = end;
+        } else if let Some(idx) = line.find(":=") {
+            out.push_str(&format!("{NAME}{}{RESET}{OPERATOR}:={RESET}", &line[..idx]));
+            rest_start = idx + 2;
+        }
+        highlight_expression(line, rest_start, &parens, &mut out);
+        Cow::Owned(out)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        if self.colors {
+            Cow::Owned(format!("{HINT}{hint}{RESET}"))
+        } else {
+            Cow::Borrowed(hint)
+        }
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _kind: CmdKind) -> bool {
+        // Bracket matching depends on the cursor, so every movement needs a redraw.
+        self.colors
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        let trimmed = line.trim();
+        if pos < line.len()
+            || trimmed.is_empty()
+            || trimmed.starts_with(':')
+            || trimmed.starts_with('\\')
+            || trimmed.contains(":=")
+            || paren_depth(trimmed) != 0
+        {
+            return None;
+        }
+        let expr = derivative_calculator::parse_with_names(
+            trimmed,
+            self.session.functions(),
+            self.session.names(),
+        )
+        .ok()?;
+        let expr = self.session.resolve(&expr).ok()?;
+        let derivative =
+            derivative_calculator::derivative(&expr, 'x', 1, SimplifyLevel::Basic).ok()?;
+        Some(format!("   d/dx = {}", derivative.pretty()))
+    }
+}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if paren_depth(ctx.input()) > 0 {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+/// Number of parentheses left open at the end of `input`.
+fn paren_depth(input: &str) -> i32 {
+    input.chars().fold(0, |depth, chr| match chr {
+        '(' => depth + 1,
+        ')' => depth - 1,
+        _ => depth,
+    })
+}
+
+/// Colour for every parenthesis, keyed by byte index: the pair next to the cursor is
+/// highlighted and parentheses without a partner are flagged.
+fn paren_colors(line: &str, pos: usize) -> Vec<(usize, &'static str)> {
+    let mut pairs = Vec::new();
+    let mut open = Vec::new();
+    let mut colors = Vec::new();
+    for (idx, chr) in line.char_indices() {
+        match chr {
+            '(' => open.push(idx),
+            ')' => match open.pop() {
+                Some(start) => pairs.push((start, idx)),
+                None => colors.push((idx, UNMATCHED_PAREN)),
+            },
+            _ => {}
+        }
+    }
+    colors.extend(open.into_iter().map(|idx| (idx, UNMATCHED_PAREN)));
+    // The cursor is either on a parenthesis or right after one.
+    let at_cursor = |idx: usize| idx == pos || idx + 1 == pos;
+    if let Some(&(start, end)) = pairs
+        .iter()
+        .find(|(start, end)| at_cursor(*start) || at_cursor(*end))
+    {
+        colors.push((start, MATCHING_PAREN));
+        colors.push((end, MATCHING_PAREN));
+    }
+    colors
+}
+
+/// Appends `line[start..]` to `out` with every token coloured. Characters the tokenizer rejects
+/// are copied as they are and tokenizing resumes after them.
+fn highlight_expression(line: &str, start: usize, parens: &[(usize, &str)], out: &mut String) {
+    let text = &line[start..];
+    // Byte offset in `line` of every character of `text` (the tokenizer counts characters).
+    let offsets: Vec<usize> = text
+        .char_indices()
+        .map(|(idx, _)| start + idx)
+        .chain(std::iter::once(line.len()))
+        .collect();
+
+    let mut tokens = Vec::new();
+    let mut error_at = None;
+    for lexeme in lex(text) {
+        match lexeme {
+            Ok(lexeme) => tokens.push(lexeme),
+            Err(e) => error_at = Some(e.position()),
+        }
+    }
+
+    let end = error_at.unwrap_or(offsets.len() - 1);
+    for (i, (from, token)) in tokens.iter().enumerate() {
+        let to = tokens.get(i + 1).map_or(end, |(next, _)| *next);
+        let (from, to) = (offsets[*from], offsets[to]);
+        let color = match token {
+            Lexeme::Number(_) => NUMBER,
+            Lexeme::Variable(_) => VARIABLE,
+            Lexeme::Constant(_) => CONSTANT,
+            Lexeme::Name(name) if BUILTIN_FUNCTIONS.contains(&name.as_str()) => FUNCTION,
+            Lexeme::Name(_) => NAME,
+            Lexeme::Operator(_) => OPERATOR,
+            Lexeme::LeftParen | Lexeme::RightParen => parens
+                .iter()
+                .find(|(idx, _)| *idx == from)
+                .map_or("", |(_, color)| color),
+            _ => "",
+        };
+        if color.is_empty() {
+            out.push_str(&line[from..to]);
+        } else {
+            out.push_str(&format!("{color}{}{RESET}", &line[from..to]));
+        }
+    }
+
+    if let Some(idx) = error_at {
+        let (bad, resume) = (offsets[idx], offsets[(idx + 1).min(offsets.len() - 1)]);
+        out.push_str(&line[bad..resume]);
+        if resume < line.len() {
+            highlight_expression(line, resume, parens, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustyline::highlight::Highlighter;
+
+    use super::*;
+
+    fn helper() -> ReplHelper {
+        ReplHelper {
+            session: Session::default(),
+            colors: true,
+        }
+    }
+
+    fn colored(color: &str, text: &str) -> String {
+        format!("{color}{text}{RESET}")
+    }
+
+    #[test]
+    fn tokens_are_colored_by_kind() {
+        let line = "2x+sin(x)";
+        let expected = [
+            colored(NUMBER, "2"),
+            colored(VARIABLE, "x"),
+            colored(OPERATOR, "+"),
+            colored(FUNCTION, "sin"),
+            colored(MATCHING_PAREN, "("),
+            colored(VARIABLE, "x"),
+            colored(MATCHING_PAREN, ")"),
+        ]
+        .concat();
+        assert_eq!(helper().highlight(line, line.len()), expected);
+    }
+
+    #[test]
+    fn commands_and_rejected_characters() {
+        let line = ":diff a#b";
+        let expected = [
+            colored(COMMAND, ":diff"),
+            " ".to_string(),
+            colored(CONSTANT, "a"),
+            "#".to_string(),
+            colored(CONSTANT, "b"),
+        ]
+        .concat();
+        assert_eq!(helper().highlight(line, 0), expected);
+    }
+
+    #[test]
+    fn unmatched_parentheses_are_flagged() {
+        assert_eq!(paren_depth("sin((x)"), 1);
+        assert_eq!(paren_depth("x)"), -1);
+        assert_eq!(paren_colors("(x", 2), [(0, UNMATCHED_PAREN)]);
+        assert_eq!(paren_colors("x)", 0), [(1, UNMATCHED_PAREN)]);
+        assert_eq!(
+            paren_colors("(x)+(y)", 1),
+            [(0, MATCHING_PAREN), (2, MATCHING_PAREN)]
+        );
+    }
+
+    fn completions(helper: &ReplHelper, line: &str) -> (usize, Vec<String>) {
+        let history = rustyline::history::DefaultHistory::new();
+        let (start, pairs) = helper
+            .complete(line, line.len(), &Context::new(&history))
+            .unwrap();
+        (
+            start,
+            pairs.into_iter().map(|pair| pair.replacement).collect(),
+        )
+    }
+
+    #[test]
+    fn completes_functions_bindings_and_results() {
+        let mut helper = helper();
+        assert_eq!(completions(&helper, "2+si"), (2, vec!["sin(".to_string()]));
+        let area = derivative_calculator::parse("x^2").unwrap();
+        helper.session.define("area", &area).unwrap();
+        helper.session.push_result(area);
+        assert_eq!(completions(&helper, "ar"), (0, vec!["area(".to_string()]));
+        assert_eq!(completions(&helper, "an"), (0, vec!["ans".to_string()]));
+        assert_eq!(completions(&helper, "1+$"), (2, vec!["$1".to_string()]));
+    }
+
+    #[test]
+    fn completes_commands_and_their_arguments() {
+        let helper = helper();
+        assert_eq!(
+            completions(&helper, ":form"),
+            (0, vec![":format ".to_string()])
+        );
+        assert_eq!(
+            completions(&helper, ":format la"),
+            (8, vec!["latex".to_string()])
+        );
+        assert_eq!(
+            completions(&helper, ":diff co"),
+            (6, vec!["cos(".to_string()])
+        );
+        assert_eq!(
+            completions(&helper, ":diff  x+co"),
+            (9, vec!["cos(".to_string()])
+        );
+    }
+
+    #[test]
+    fn command_names_complete_until_a_space() {
+        let helper = helper();
+        assert_eq!(
+            completions(&helper, ":format"),
+            (0, vec![":format ".to_string()])
+        );
+        assert_eq!(completions(&helper, "  :vars").0, 2);
+        assert_eq!(
+            completions(&helper, ":format ").1.len(),
+            OutputFormat::ALL.len()
+        );
+    }
+
+    #[test]
+    fn no_color_leaves_the_line_alone() {
+        let helper = ReplHelper {
+            colors: false,
+            ..helper()
+        };
+        assert_eq!(helper.highlight("2x", 2), "2x");
+    }
+}
@@ -0,0 +1,128 @@
+use std::env;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+/// Environment variable overriding the history file; empty or `off` disables history.
+pub(crate) const HISTORY_FILE_VAR: &str = "DERIVATIVE_CALCULATOR_HISTORY";
+/// Environment variable overriding the number of entries kept.
+pub(crate) const HISTORY_SIZE_VAR: &str = "DERIVATIVE_CALCULATOR_HISTORY_SIZE";
+
+/// Entries kept when [`HISTORY_SIZE_VAR`] isn't set.
+const DEFAULT_SIZE: usize = 1000;
+
+/// Where the REPL history is stored and how much of it is kept.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HistorySettings {
+    /// File the history is loaded from and saved to, or why history isn't persisted.
+    pub path: Result<PathBuf, NotSaved>,
+    /// Maximum number of entries, older ones are dropped first.
+    pub max_size: usize,
+}
+
+/// Why the history isn't saved to a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum NotSaved {
+    /// [`HISTORY_FILE_VAR`] is empty or `off`.
+    Disabled,
+    /// Neither [`HISTORY_FILE_VAR`], `$XDG_STATE_HOME` nor `$HOME` gives a location.
+    NoLocation,
+}
+
+impl Display for NotSaved {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NotSaved::Disabled => write!(f, "disabled with ${HISTORY_FILE_VAR}"),
+            NotSaved::NoLocation => write!(
+                f,
+                "no home directory, set $HOME or ${HISTORY_FILE_VAR} to keep it"
+            ),
+        }
+    }
+}
+
+impl HistorySettings {
+    /// Reads the settings from [`HISTORY_FILE_VAR`] and [`HISTORY_SIZE_VAR`], defaulting to
+    /// `$XDG_STATE_HOME/derivative_calculator/history` (`~/.local/state/...` when unset).
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| env::var_os(name))
+    }
+
+    /// [`HistorySettings::from_env`] with the environment variables looked up by `var`.
+    fn from_vars(var: impl Fn(&str) -> Option<OsString>) -> Self {
+        let path = match var(HISTORY_FILE_VAR) {
+            Some(value) if value.is_empty() || value == "off" => Err(NotSaved::Disabled),
+            Some(value) => Ok(PathBuf::from(value)),
+            None => default_path(&var).ok_or(NotSaved::NoLocation),
+        };
+        let max_size = var(HISTORY_SIZE_VAR)
+            .and_then(|size| size.to_str()?.trim().parse().ok())
+            .unwrap_or(DEFAULT_SIZE);
+        Self { path, max_size }
+    }
+}
+
+/// The XDG state directory location, if a home directory can be found.
+fn default_path(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    let state_home = var("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    Some(state_home.join("derivative_calculator").join("history"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+
+    use super::{HistorySettings, NotSaved, DEFAULT_SIZE, HISTORY_FILE_VAR, HISTORY_SIZE_VAR};
+
+    fn settings(vars: &[(&str, &str)]) -> HistorySettings {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        HistorySettings::from_vars(|name| vars.get(name).map(OsString::from))
+    }
+
+    fn path(text: &str) -> Result<PathBuf, NotSaved> {
+        Ok(PathBuf::from(text))
+    }
+
+    #[test]
+    fn the_file_is_in_the_state_directory() {
+        let home = [("HOME", "/home/ada")];
+        assert_eq!(
+            settings(&home).path,
+            path("/home/ada/.local/state/derivative_calculator/history")
+        );
+        let xdg = [("HOME", "/home/ada"), ("XDG_STATE_HOME", "/var/state")];
+        assert_eq!(
+            settings(&xdg).path,
+            path("/var/state/derivative_calculator/history")
+        );
+        // A relative XDG directory is ignored, as the specification asks
+        let relative = [("HOME", "/home/ada"), ("XDG_STATE_HOME", "state")];
+        assert_eq!(settings(&relative).path, settings(&home).path);
+        assert_eq!(settings(&[]).path, Err(NotSaved::NoLocation));
+    }
+
+    #[test]
+    fn the_variable_overrides_or_disables_the_file() {
+        let custom = [(HISTORY_FILE_VAR, "/tmp/calc"), ("HOME", "/home/ada")];
+        assert_eq!(settings(&custom).path, path("/tmp/calc"));
+        for off in ["off", ""] {
+            let disabled = [(HISTORY_FILE_VAR, off), ("HOME", "/home/ada")];
+            assert_eq!(settings(&disabled).path, Err(NotSaved::Disabled));
+        }
+    }
+
+    #[test]
+    fn the_size_can_be_set() {
+        assert_eq!(settings(&[]).max_size, DEFAULT_SIZE);
+        assert_eq!(settings(&[(HISTORY_SIZE_VAR, " 50 ")]).max_size, 50);
+        assert_eq!(
+            settings(&[(HISTORY_SIZE_VAR, "many")]).max_size,
+            DEFAULT_SIZE
+        );
+    }
+}
@@ -0,0 +1,206 @@
+mod commands;
+mod helper;
+mod history;
+mod session;
+
+use std::process::ExitCode;
+
+use rustyline::history::DefaultHistory;
+use rustyline::{error::ReadlineError, Config, Editor};
+
+use derivative_calculator::assumptions::{Assumptions, Unproven};
+use derivative_calculator::rewrite::RuleSet;
+use derivative_calculator::{Expr, OutputFormat, SimplifyLevel};
+use helper::ReplHelper;
+use history::HistorySettings;
+use session::Session;
+
+/// Whether the REPL keeps reading after a line.
+pub(crate) enum Flow {
+    Continue,
+    Exit,
+}
+
+/// State of an interactive session.
+pub(crate) struct Repl {
+    editor: Editor<ReplHelper, DefaultHistory>,
+    /// How results are printed, chosen with `:format`.
+    format: OutputFormat,
+    /// How derivatives are simplified, chosen with `:level`.
+    level: SimplifyLevel,
+    /// Rewrite rules added with `:rules`, applied to results after the built-in ones.
+    rules: RuleSet,
+    /// Facts about symbols declared with `:assume`, consulted by the rules.
+    assumptions: Assumptions,
+    /// Names and previous results the user can refer to.
+    session: Session,
+    history: HistorySettings,
+}
+
+impl Repl {
+    pub fn new() -> rustyline::Result<Self> {
+        let history = HistorySettings::from_env();
+        let config = Config::builder()
+            .max_history_size(history.max_size)?
+            .history_ignore_dups(true)?
+            .build();
+        let mut editor = Editor::with_config(config)?;
+        editor.set_helper(Some(ReplHelper::new()));
+        Ok(Self {
+            editor,
+            format: OutputFormat::default(),
+            level: SimplifyLevel::default(),
+            rules: RuleSet::default(),
+            assumptions: Assumptions::default(),
+            session: Session::default(),
+            history,
+        })
+    }
+
+    /// Reads and handles lines until the user exits.
+    pub fn run(&mut self) -> rustyline::Result<ExitCode> {
+        if let Ok(path) = &self.history.path {
+            if self.editor.load_history(path).is_err() {
+                println!("No previous history found.");
+            }
+        }
+
+        println!("\n====Symbolic Derivative Calculator====\n");
+        let exit_code = self.read_lines();
+        self.save_history();
+        exit_code
+    }
+
+    /// Writes the history file, creating its directory if needed.
+    fn save_history(&mut self) {
+        let Ok(path) = &self.history.path else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Err(e) = self.editor.save_history(path) {
+            println!("Could not save history to {}: {e}", path.display());
+        }
+    }
+
+    /// The read-eval-print loop itself.
+    fn read_lines(&mut self) -> rustyline::Result<ExitCode> {
+        loop {
+            if let Some(helper) = self.editor.helper_mut() {
+                helper.session = self.session.clone();
+            }
+            match self.editor.readline("Expr> ") {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        let _ = self.editor.add_history_entry(line.clone());
+                    }
+                    if let Flow::Exit = self.handle_line(line.trim()) {
+                        println!("Exiting...");
+                        println!("Bye!!");
+                        break Ok(ExitCode::SUCCESS);
+                    }
+                }
+
+                Err(e) => {
+                    match e {
+                        ReadlineError::Interrupted => println!("CTRL-C detected!!"),
+                        ReadlineError::Eof => println!("CTRL-D detected!!"),
+                        other => println!("Error: {other:?}"),
+                    }
+                    break Ok(ExitCode::SUCCESS);
+                }
+            };
+        }
+    }
+
+    /// Runs a command, or differentiates the line if it isn't one.
+    fn handle_line(&mut self, line: &str) -> Flow {
+        if line.is_empty() {
+            return Flow::Continue;
+        }
+        if let Some((command, args)) = commands::lookup(line) {
+            return commands::execute(self, command, args);
+        }
+        if line.starts_with(':') {
+            println!("Unknown command '{line}'. Type ':help' for a list of commands.");
+            return Flow::Continue;
+        }
+
+        if let Some((name, definition)) = line.split_once(":=") {
+            self.assign(name.trim(), definition);
+            return Flow::Continue;
+        }
+
+        let Some(ast) = self.parse_or_report(line) else {
+            return Flow::Continue;
+        };
+        match derivative_calculator::derivative(&ast, 'x', 1, self.level) {
+            Ok(simplified) => {
+                let rewritten = self.rewrite(&simplified);
+                self.print_result("Derivative", &rewritten);
+                let domain = ast.domain().and(rewritten.domain());
+                if !domain.is_everywhere() {
+                    println!("  {}", commands::describe_domain(&domain, 'x'));
+                }
+            }
+            Err(e) => println!("{e}"),
+        }
+        Flow::Continue
+    }
+
+    /// Handles `name := definition`.
+    fn assign(&mut self, name: &str, definition: &str) {
+        let Some(expr) = self.parse_or_report(definition) else {
+            return;
+        };
+        match self.session.define(name, &expr) {
+            Ok(value) => println!(
+                "{}",
+                commands::render_labelled(&format!("{name} :="), &value, self.format)
+            ),
+            Err(e) => println!("{e}"),
+        }
+    }
+
+    /// Parses `input` and resolves the session names in it, printing the error if either fails.
+    fn parse_or_report(&self, input: &str) -> Option<Expr> {
+        let expr = self.parse_names_or_report(input)?;
+        self.resolve_or_report(&expr)
+    }
+
+    /// Parses `input` with the session names kept whole but not yet resolved.
+    fn parse_names_or_report(&self, input: &str) -> Option<Expr> {
+        derivative_calculator::parse_with_names(
+            input,
+            self.session.functions(),
+            self.session.names(),
+        )
+        .map_err(|e| println!("{e}"))
+        .ok()
+    }
+
+    fn resolve_or_report(&self, expr: &Expr) -> Option<Expr> {
+        self.session.resolve(expr).map_err(|e| println!("{e}")).ok()
+    }
+
+    /// `expr` rewritten with the built-in rules followed by the loaded ones under the
+    /// assumptions, as it is when there are neither rules nor assumptions.
+    fn rewrite(&self, expr: &Expr) -> Expr {
+        if self.rules.is_empty() && self.assumptions.is_empty() {
+            return expr.clone();
+        }
+        let mut rules = RuleSet::builtin().clone();
+        rules.extend(self.rules.clone());
+        rules
+            .rewrite_assuming(expr, &self.assumptions, Unproven::Skip)
+            .expr
+    }
+
+    /// Prints `expr` in the selected output format and records it as the latest result.
+    fn print_result(&mut self, label: &str, expr: &Expr) {
+        let number = self.session.push_result(expr.clone());
+        let label = format!("{label} (${number}):");
+        println!("{}", commands::render_labelled(&label, expr, self.format));
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+
+use derivative_calculator::{Expr, BUILTIN_FUNCTIONS};
+
+/// Name that always refers to the latest result.
+const LAST_RESULT: &str = "ans";
+
+/// Problems with the names used in a REPL line.
+#[derive(Debug, PartialEq)]
+pub(crate) enum SessionError {
+    /// `ans` or `$n` used before there is such a result.
+    NoSuchResult(String),
+    /// The name is not bound and isn't a built-in function.
+    UnknownName(String),
+    /// The name can't be assigned to, e.g. `x`, `ans` or `sin`.
+    ReservedName(String),
+}
+
+impl Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SessionError::NoSuchResult(name) => write!(f, "there is no result '{name}' yet"),
+            SessionError::UnknownName(name) => write!(f, "'{name}' is not defined"),
+            SessionError::ReservedName(name) => write!(f, "'{name}' can't be assigned to"),
+        }
+    }
+}
+
+/// Bindings and results that live for the duration of a REPL session.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Session {
+    /// Names assigned with `name := expr`, already resolved.
+    bindings: BTreeMap<String, Expr>,
+    /// Every printed result, `$1` being the first.
+    results: Vec<Expr>,
+}
+
+impl Session {
+    /// Single letter bindings that depend on `x`, which makes `f(...)` a call instead of a
+    /// multiplication.
+    pub fn functions(&self) -> HashSet<char> {
+        self.bindings
+            .iter()
+            .filter(|(_, expr)| expr.contains_variable('x'))
+            .filter_map(|(name, _)| {
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(c),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Multi-letter names the parser must keep whole: every such binding and `ans`.
+    pub fn names(&self) -> HashSet<String> {
+        self.bindings
+            .keys()
+            .filter(|name| name.chars().nth(1).is_some())
+            .cloned()
+            .chain([LAST_RESULT.to_string()])
+            .collect()
+    }
+
+    /// Binds `name` to `expr`. Names in `expr` are resolved right away, so later changes to the
+    /// names it uses don't affect it.
+    pub fn define(&mut self, name: &str, expr: &Expr) -> Result<Expr, SessionError> {
+        if !is_name(name) || name == "x" || name == LAST_RESULT || BUILTIN_FUNCTIONS.contains(&name)
+        {
+            return Err(SessionError::ReservedName(name.to_string()));
+        }
+        let value = self.resolve(expr)?;
+        self.bindings.insert(name.to_string(), value.clone());
+        Ok(value)
+    }
+
+    /// Whether `name` is bound with `name := expr`.
+    pub fn is_bound(&self, name: &str) -> bool {
+        self.bindings.contains_key(name)
+    }
+
+    /// Removes a binding, returning whether it existed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.bindings.remove(name).is_some()
+    }
+
+    /// All bindings, sorted by name.
+    pub fn bindings(&self) -> impl Iterator<Item = (&String, &Expr)> {
+        self.bindings.iter()
+    }
+
+    /// Number of results recorded so far.
+    pub fn result_count(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Records a result and returns its number, usable as `$n`.
+    pub fn push_result(&mut self, expr: Expr) -> usize {
+        self.results.push(expr);
+        self.results.len()
+    }
+
+    /// Value of a binding, `ans` or `$n`.
+    pub fn get(&self, name: &str) -> Result<&Expr, SessionError> {
+        let result = if name == LAST_RESULT {
+            self.results.last()
+        } else if let Some(number) = name.strip_prefix('$') {
+            number
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| n.checked_sub(1))
+                .and_then(|idx| self.results.get(idx))
+        } else {
+            return self
+                .bindings
+                .get(name)
+                .ok_or_else(|| SessionError::UnknownName(name.to_string()));
+        };
+        result.ok_or_else(|| SessionError::NoSuchResult(name.to_string()))
+    }
+
+    /// Replaces every bound name in `expr` with its value and expands calls to user-defined
+    /// functions by substituting the argument for `x`.
+    pub fn resolve(&self, expr: &Expr) -> Result<Expr, SessionError> {
+        let resolved = match expr {
+            Expr::Constant(c) => match self.bindings.get(&c.to_string()) {
+                Some(value) => value.clone(),
+                None => expr.clone(),
+            },
+            Expr::Symbol(name) => self.get(name)?.clone(),
+            Expr::Call { name, arg } => {
+                let arg = self.resolve(arg)?;
+                match self.bindings.get(name) {
+                    Some(body) => body.substitute(&HashMap::from([("x".to_string(), arg)])),
+                    None if BUILTIN_FUNCTIONS.contains(&name.as_str()) => Expr::Call {
+                        name: name.clone(),
+                        arg: Box::new(arg),
+                    },
+                    None => return Err(SessionError::UnknownName(name.clone())),
+                }
+            }
+            Expr::UnaryOp { op, expr } => Expr::UnaryOp {
+                op: op.clone(),
+                expr: Box::new(self.resolve(expr)?),
+            },
+            Expr::BinaryOp { op, left, right } => Expr::BinaryOp {
+                op: op.clone(),
+                left: Box::new(self.resolve(left)?),
+                right: Box::new(self.resolve(right)?),
+            },
+            _ => expr.clone(),
+        };
+        Ok(resolved)
+    }
+}
+
+/// Whether `name` is a letter or a word that can name a value: lowercase letters, digits and
+/// underscores, starting with a letter.
+pub(crate) fn is_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(session: &Session, input: &str) -> Expr {
+        let expr =
+            derivative_calculator::parse_with_names(input, session.functions(), session.names())
+                .unwrap();
+        session.resolve(&expr).unwrap()
+    }
+
+    #[test]
+    fn multi_letter_bindings_are_kept_whole() {
+        let mut session = Session::default();
+        session.define("area", &parse(&session, "3x^2")).unwrap();
+        assert_eq!(parse(&session, "2area").pretty(), "2*(3x^2)");
+        // Unbound words are still products of letters
+        assert_eq!(parse(&session, "ab").pretty(), "a*b");
+    }
+
+    #[test]
+    fn results_are_referenced_by_ans_and_number() {
+        let mut session = Session::default();
+        session.push_result(Expr::Number(1));
+        session.push_result(Expr::Number(2));
+        assert_eq!(parse(&session, "ans+$1").pretty(), "2+1");
+        assert_eq!(
+            session.resolve(&Expr::Symbol("$3".to_string())),
+            Err(SessionError::NoSuchResult("$3".to_string()))
+        );
+    }
+
+    #[test]
+    fn functions_substitute_their_argument() {
+        let mut session = Session::default();
+        session.define("f", &parse(&session, "x^2+1")).unwrap();
+        assert_eq!(parse(&session, "f(2x)").pretty(), "(2x)^2+1");
+    }
+
+    #[test]
+    fn names_are_letters_and_words() {
+        assert!(is_name("a") && is_name("area") && is_name("r_2"));
+        assert!(!is_name("") && !is_name("2r") && !is_name("$1") && !is_name("A"));
+        let mut session = Session::default();
+        assert_eq!(
+            session.define("x", &Expr::Number(1)),
+            Err(SessionError::ReservedName("x".to_string()))
+        );
+        assert!(session.define("area", &Expr::Number(1)).is_ok());
+        assert!(session.is_bound("area") && !session.is_bound("r"));
+    }
+}
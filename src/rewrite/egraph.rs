@@ -0,0 +1,810 @@
+//! Simplification by equality saturation.
+//!
+//! An e-graph stores many equal forms of an expression at once: each e-class is a set of
+//! equal expressions, given as nodes whose operands are again e-classes. Rules only ever add
+//! equal forms, so unlike greedy rewriting no rule can lead into a dead end, and once no rule
+//! adds anything new (or a limit is hit) the cheapest form is extracted.
+//!
+//! Rules use the syntax of [`RuleSet`], but their patterns match structurally; commutativity
+//! and associativity are rules of their own, see [`ALGEBRA_RULES`](super::ALGEBRA_RULES).
+//! The e-graph folds arithmetic on numbers by itself, so `fold(e)` in a replacement is just
+//! `e`.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use super::{Condition, Predicate, Rule, RuleSet};
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+use crate::rational::Rational;
+
+/// Matches of one rule in one iteration past which the rule is left out for a while, since
+/// rules such as commutativity match everywhere and would swamp the others.
+const MATCH_LIMIT: usize = 1000;
+/// Iterations a rule is left out the first time it exceeds its limit; the ban and the limit
+/// double every further time.
+const BAN_LENGTH: usize = 2;
+/// Steps of matching allowed per match of [`MATCH_LIMIT`], for patterns that take many steps
+/// to find few matches.
+const WORK_PER_MATCH: usize = 50;
+
+/// What extraction minimizes, summed over the nodes of an expression. Ties go to the
+/// expression with fewer nodes. A negative number counts as a negated one, as it's written.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum Cost {
+    /// Every number, name, operation and function call counts 1.
+    NodeCount,
+    /// Operations and function calls count 1, numbers and names are free.
+    OperationCount,
+    /// The cost of each node, given without its operands.
+    Custom(fn(&Head) -> u32),
+}
+
+/// A node of an expression without its operands, as seen by [`Cost::Custom`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Head<'a> {
+    Number(i32),
+    /// A variable, constant or named symbol.
+    Name,
+    Neg,
+    Binary(BinaryOp),
+    Call(&'a str),
+}
+
+/// Limits and cost function of [`saturate`].
+#[derive(Debug, Clone, Copy)]
+pub struct SaturationOptions {
+    pub cost: Cost,
+    /// Most rounds of applying every rule everywhere.
+    pub max_iterations: usize,
+    /// Size of the e-graph, in nodes, after which no more rules are applied.
+    pub max_nodes: usize,
+}
+
+impl Default for SaturationOptions {
+    fn default() -> Self {
+        SaturationOptions {
+            cost: Cost::NodeCount,
+            max_iterations: 30,
+            max_nodes: 2_000,
+        }
+    }
+}
+
+/// Why [`saturate`] stopped applying rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StopReason {
+    /// No rule added anything new, every form the rules can reach is in the e-graph.
+    Saturated,
+    IterationLimit,
+    NodeLimit,
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            StopReason::Saturated => "saturated",
+            StopReason::IterationLimit => "iteration limit reached",
+            StopReason::NodeLimit => "node limit reached",
+        })
+    }
+}
+
+/// The cheapest form found, with how the search went.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Saturation {
+    pub expr: Expr,
+    /// Cost of `expr` under the chosen cost function.
+    pub cost: u32,
+    /// Rounds of rule applications.
+    pub iterations: usize,
+    /// Size of the e-graph at the end, in nodes.
+    pub nodes: usize,
+    pub stop: StopReason,
+}
+
+impl Expr {
+    /// The cheapest form of the expression reachable with the
+    /// [algebraic identities](RuleSet::algebra), see [`saturate`].
+    ///
+    /// ```
+    /// use derivative_calculator::parse;
+    /// use derivative_calculator::rewrite::egraph::{Cost, SaturationOptions};
+    ///
+    /// let options = SaturationOptions::default();
+    /// assert_eq!(parse("2x + 3x - x*5")?.saturate(&options).expr.pretty(), "0");
+    /// assert_eq!(parse("x*x*x*2 + x^3")?.saturate(&options).expr.pretty(), "3x^3");
+    ///
+    /// // A distribution that makes the expression bigger before it gets smaller
+    /// let expr = parse("(x+1)*(x+1) - (x+1)^2")?;
+    /// assert_eq!(expr.simplify_full().pretty(), "(x+1)*(x+1)-(x+1)^2");
+    /// assert_eq!(expr.saturate(&options).expr.pretty(), "0");
+    ///
+    /// let options = SaturationOptions { cost: Cost::OperationCount, ..options };
+    /// assert_eq!(parse("a*b + a*c")?.saturate(&options).expr.pretty(), "a*(b+c)");
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn saturate(&self, options: &SaturationOptions) -> Saturation {
+        saturate(self, RuleSet::algebra(), options)
+    }
+}
+
+/// Applies every rule of `rules` everywhere, keeping all the forms, until none adds anything
+/// new or a limit of `options` is reached, and then returns the form of `expr` with the lowest
+/// cost, tidied up by [`Expr::simplify`]. That's never more than the cost of `expr` itself.
+pub fn saturate(expr: &Expr, rules: &RuleSet, options: &SaturationOptions) -> Saturation {
+    let mut graph = EGraph::default();
+    let root = graph.add_expr(expr);
+    graph.rebuild();
+    let mut schedule = vec![Backoff::default(); rules.len()];
+    let mut iterations = 0;
+    let stop = loop {
+        if graph.node_count() >= options.max_nodes {
+            break StopReason::NodeLimit;
+        }
+        if iterations == options.max_iterations {
+            break StopReason::IterationLimit;
+        }
+        iterations += 1;
+        let mut matches = Vec::new();
+        for (rule, backoff) in rules.iter().zip(&mut schedule) {
+            if backoff.banned_until > iterations {
+                continue;
+            }
+            let limit = MATCH_LIMIT << backoff.times_banned;
+            match graph.search(rule, limit) {
+                Some(found) => matches.extend(found.into_iter().map(|(id, s)| (rule, id, s))),
+                None => {
+                    backoff.banned_until = iterations + (BAN_LENGTH << backoff.times_banned);
+                    backoff.times_banned += 1;
+                }
+            }
+        }
+        let mut changed = false;
+        for (rule, id, subst) in matches {
+            if graph.node_count() >= options.max_nodes {
+                break;
+            }
+            let rewritten = graph.instantiate(&rule.replacement, &subst);
+            changed |= graph.union(id, rewritten);
+        }
+        changed |= graph.rebuild();
+        if !changed {
+            // Banned rules may still have something to add.
+            if schedule.iter().all(|b| b.banned_until <= iterations + 1) {
+                break StopReason::Saturated;
+            }
+            for backoff in &mut schedule {
+                backoff.banned_until = 0;
+            }
+        }
+    };
+    let expr = graph.extract(root, options.cost).simplify();
+    Saturation {
+        cost: options.cost.total(&expr),
+        expr,
+        iterations,
+        nodes: graph.node_count(),
+        stop,
+    }
+}
+
+/// When a rule is left out of the iterations, see [`MATCH_LIMIT`].
+#[derive(Debug, Clone, Copy, Default)]
+struct Backoff {
+    banned_until: usize,
+    times_banned: u32,
+}
+
+/// Index of an e-class. Merged classes live on under the index of one of them, see
+/// [`EGraph::find`].
+type Id = usize;
+
+/// Pattern variables with the e-classes they stand for.
+type Subst = Vec<(char, Id)>;
+
+/// An expression node with e-classes for operands.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Node {
+    Number(i32),
+    Variable(char),
+    Constant(char),
+    Symbol(String),
+    Call(String, Id),
+    Neg(Id),
+    Add(Id, Id),
+    Sub(Id, Id),
+    Mul(Id, Id),
+    Div(Id, Id),
+    Pow(Id, Id),
+}
+
+impl Node {
+    fn binary(op: &BinaryOp, left: Id, right: Id) -> Node {
+        match op {
+            BinaryOp::Add => Node::Add(left, right),
+            BinaryOp::Sub => Node::Sub(left, right),
+            BinaryOp::Mul => Node::Mul(left, right),
+            BinaryOp::Div => Node::Div(left, right),
+            BinaryOp::Pow => Node::Pow(left, right),
+        }
+    }
+
+    /// The operator and operands of a binary node.
+    fn as_binary(&self) -> Option<(BinaryOp, Id, Id)> {
+        match *self {
+            Node::Add(l, r) => Some((BinaryOp::Add, l, r)),
+            Node::Sub(l, r) => Some((BinaryOp::Sub, l, r)),
+            Node::Mul(l, r) => Some((BinaryOp::Mul, l, r)),
+            Node::Div(l, r) => Some((BinaryOp::Div, l, r)),
+            Node::Pow(l, r) => Some((BinaryOp::Pow, l, r)),
+            _ => None,
+        }
+    }
+
+    fn children(&self) -> Vec<Id> {
+        match self {
+            Node::Call(_, a) | Node::Neg(a) => vec![*a],
+            _ => self.as_binary().map_or(Vec::new(), |(_, l, r)| vec![l, r]),
+        }
+    }
+
+    fn map_children(&self, f: impl Fn(Id) -> Id) -> Node {
+        match self {
+            Node::Call(name, a) => Node::Call(name.clone(), f(*a)),
+            Node::Neg(a) => Node::Neg(f(*a)),
+            _ => match self.as_binary() {
+                Some((op, l, r)) => Node::binary(&op, f(l), f(r)),
+                None => self.clone(),
+            },
+        }
+    }
+
+    /// Position of the variant, which the derived order sorts by first.
+    fn kind(&self) -> u8 {
+        match self {
+            Node::Number(_) => 0,
+            Node::Variable(_) => 1,
+            Node::Constant(_) => 2,
+            Node::Symbol(_) => 3,
+            Node::Call(..) => 4,
+            Node::Neg(_) => 5,
+            Node::Add(..) => 6,
+            Node::Sub(..) => 7,
+            Node::Mul(..) => 8,
+            Node::Div(..) => 9,
+            Node::Pow(..) => 10,
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        matches!(
+            self,
+            Node::Number(_) | Node::Variable(_) | Node::Constant(_) | Node::Symbol(_)
+        )
+    }
+
+    fn head(&self) -> Head<'_> {
+        match self {
+            Node::Number(n) => Head::Number(*n),
+            Node::Variable(_) | Node::Constant(_) | Node::Symbol(_) => Head::Name,
+            Node::Call(name, _) => Head::Call(name),
+            Node::Neg(_) => Head::Neg,
+            _ => Head::Binary(self.as_binary().expect("the other nodes are binary").0),
+        }
+    }
+}
+
+impl Cost {
+    /// Cost of a whole expression.
+    pub fn total(&self, expr: &Expr) -> u32 {
+        let (head, operands) = match expr {
+            Expr::Number(n) => (Head::Number(*n), vec![]),
+            Expr::Call { name, arg } => (Head::Call(name), vec![arg.as_ref()]),
+            Expr::UnaryOp { expr, .. } => (Head::Neg, vec![expr.as_ref()]),
+            Expr::BinaryOp { op, left, right } => (
+                Head::Binary(op.clone()),
+                vec![left.as_ref(), right.as_ref()],
+            ),
+            _ => (Head::Name, vec![]),
+        };
+        operands
+            .into_iter()
+            .fold(self.of(&head), |sum, e| sum.saturating_add(self.total(e)))
+    }
+
+    fn of(&self, head: &Head) -> u32 {
+        if let Head::Number(n) = head {
+            if *n < 0 {
+                let magnitude = Head::Number(n.saturating_neg());
+                return self.of(&Head::Neg).saturating_add(self.of(&magnitude));
+            }
+        }
+        match (self, head) {
+            (Cost::Custom(cost), _) => cost(head),
+            (Cost::OperationCount, Head::Number(_) | Head::Name) => 0,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Class {
+    /// Sorted, except between [`EGraph::union`] and the next [`EGraph::rebuild`].
+    nodes: Vec<Node>,
+    /// Value of the class when it's a number.
+    constant: Option<Rational>,
+}
+
+impl Class {
+    /// The nodes of the same kind as `probe`, which are next to each other in sorted order.
+    fn nodes_like(&self, probe: &Node) -> &[Node] {
+        let kind = probe.kind();
+        let start = self.nodes.partition_point(|node| node.kind() < kind);
+        let len = self.nodes[start..].partition_point(|node| node.kind() == kind);
+        &self.nodes[start..start + len]
+    }
+}
+
+#[derive(Debug, Default)]
+struct EGraph {
+    /// Union-find forest over the classes.
+    parent: Vec<Id>,
+    /// Indexed by [`Id`]; only classes that are their own parent are in use.
+    classes: Vec<Class>,
+    /// Class of every node, with operands up to date as of the last [`EGraph::rebuild`].
+    memo: HashMap<Node, Id>,
+}
+
+impl EGraph {
+    fn find(&self, mut id: Id) -> Id {
+        while self.parent[id] != id {
+            id = self.parent[id];
+        }
+        id
+    }
+
+    fn roots(&self) -> impl Iterator<Item = Id> + '_ {
+        (0..self.parent.len()).filter(|&id| self.parent[id] == id)
+    }
+
+    fn node_count(&self) -> usize {
+        self.memo.len()
+    }
+
+    fn canonical(&self, node: &Node) -> Node {
+        node.map_children(|id| self.find(id))
+    }
+
+    /// Class of `node`, adding a new one if the node is new.
+    fn add(&mut self, node: Node) -> Id {
+        let node = self.canonical(&node);
+        if let Some(&id) = self.memo.get(&node) {
+            return self.find(id);
+        }
+        let id = self.parent.len();
+        self.parent.push(id);
+        let constant = self.evaluate(&node);
+        self.classes.push(Class {
+            nodes: vec![node.clone()],
+            constant,
+        });
+        self.memo.insert(node, id);
+        id
+    }
+
+    fn add_expr(&mut self, expr: &Expr) -> Id {
+        let node = match expr {
+            Expr::Number(n) => Node::Number(*n),
+            Expr::Variable(c) => Node::Variable(*c),
+            Expr::Constant(c) => Node::Constant(*c),
+            Expr::Symbol(name) => Node::Symbol(name.clone()),
+            Expr::Call { name, arg } => Node::Call(name.clone(), self.add_expr(arg)),
+            Expr::UnaryOp { expr, .. } => Node::Neg(self.add_expr(expr)),
+            Expr::BinaryOp { op, left, right } => {
+                let left = self.add_expr(left);
+                Node::binary(op, left, self.add_expr(right))
+            }
+        };
+        self.add(node)
+    }
+
+    /// Merges two classes, returning whether they were different. Classes with different
+    /// values stay apart: they only meet through an identity that fails for these operands,
+    /// such as `x*x^-1 = 1` when `x` is 0.
+    fn union(&mut self, a: Id, b: Id) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        let values = (self.classes[a].constant, self.classes[b].constant);
+        if a == b || matches!(values, (Some(u), Some(v)) if u != v) {
+            return false;
+        }
+        let (root, child) = if self.classes[a].nodes.len() >= self.classes[b].nodes.len() {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        self.parent[child] = root;
+        let merged = std::mem::take(&mut self.classes[child]);
+        let class = &mut self.classes[root];
+        class.nodes.extend(merged.nodes);
+        class.constant = class.constant.or(merged.constant);
+        true
+    }
+
+    /// Restores the invariants after unions: merges classes whose nodes became the same
+    /// (`f(a)` and `f(b)` once `a` and `b` are merged), works out which classes are numbers
+    /// and adds the numbers to them. Returns whether anything changed.
+    fn rebuild(&mut self) -> bool {
+        let mut changed = false;
+        loop {
+            for id in 0..self.parent.len() {
+                self.parent[id] = self.find(id);
+            }
+            self.memo.clear();
+            let mut merges = Vec::new();
+            for id in 0..self.classes.len() {
+                if self.parent[id] != id {
+                    continue;
+                }
+                let mut nodes: Vec<Node> = self.classes[id]
+                    .nodes
+                    .iter()
+                    .map(|node| self.canonical(node))
+                    .collect();
+                nodes.sort();
+                nodes.dedup();
+                for node in &nodes {
+                    match self.memo.get(node) {
+                        Some(&other) if other != id => merges.push((id, other)),
+                        Some(_) => {}
+                        None => {
+                            self.memo.insert(node.clone(), id);
+                        }
+                    }
+                }
+                self.classes[id].nodes = nodes;
+            }
+            let mut progress = false;
+            for id in 0..self.classes.len() {
+                if self.parent[id] != id {
+                    continue;
+                }
+                if self.classes[id].constant.is_none() {
+                    let constant = self.classes[id]
+                        .nodes
+                        .iter()
+                        .find_map(|node| self.evaluate(node));
+                    progress |= constant.is_some();
+                    self.classes[id].constant = constant;
+                }
+                if let Some(number) = self.classes[id].constant.and_then(|c| c.to_expr()) {
+                    merges.push((id, self.add_expr(&number)));
+                }
+            }
+            for (a, b) in merges {
+                progress |= self.union(a, b);
+            }
+            if !progress {
+                return changed;
+            }
+            changed = true;
+        }
+    }
+
+    /// Value of `node` when it's arithmetic on numbers.
+    fn evaluate(&self, node: &Node) -> Option<Rational> {
+        let value = |id: Id| self.classes[self.find(id)].constant;
+        match *node {
+            Node::Number(n) => Some(Rational::integer(n as i64)),
+            Node::Neg(a) => Some(-value(a)?),
+            Node::Add(a, b) => value(a)?.checked_add(value(b)?),
+            Node::Sub(a, b) => value(a)?.checked_sub(value(b)?),
+            Node::Mul(a, b) => value(a)?.checked_mul(value(b)?),
+            Node::Div(a, b) => value(a)?.checked_div(value(b)?),
+            Node::Pow(a, b) => {
+                let exp = value(b)?;
+                if !exp.is_integer() {
+                    return None;
+                }
+                value(a)?.checked_pow(i32::try_from(exp.numer()).ok()?)
+            }
+            _ => None,
+        }
+    }
+
+    /// Where and how `rule` matches, `None` if it does in more than `limit` ways or it takes
+    /// too long to tell.
+    fn search(&self, rule: &Rule, limit: usize) -> Option<Vec<(Id, Subst)>> {
+        let mut found = Vec::new();
+        let mut work = limit * WORK_PER_MATCH;
+        for id in self.roots() {
+            let mut matches = Vec::new();
+            self.ematch(&rule.pattern, id, Subst::new(), &mut matches, &mut work);
+            found.extend(
+                matches
+                    .into_iter()
+                    .filter(|subst| rule.conditions.iter().all(|c| self.holds(c, subst)))
+                    .map(|subst| (id, subst)),
+            );
+            if found.len() > limit || work == 0 {
+                return None;
+            }
+        }
+        Some(found)
+    }
+
+    /// Adds the ways `pattern` matches class `id` that agree with `subst`, giving up once
+    /// `work` steps are taken.
+    fn ematch(
+        &self,
+        pattern: &Expr,
+        id: Id,
+        subst: Subst,
+        matches: &mut Vec<Subst>,
+        work: &mut usize,
+    ) {
+        if *work == 0 {
+            return;
+        }
+        *work -= 1;
+        let class = &self.classes[id];
+        match pattern {
+            Expr::Variable(c) | Expr::Constant(c) => match subst.iter().find(|(var, _)| var == c) {
+                Some(&(_, bound)) if self.find(bound) != id => {}
+                Some(_) => matches.push(subst),
+                None => {
+                    let mut subst = subst;
+                    subst.push((*c, id));
+                    matches.push(subst);
+                }
+            },
+            Expr::Number(n) => {
+                if class.constant == Some(Rational::integer(*n as i64)) {
+                    matches.push(subst);
+                }
+            }
+            Expr::Symbol(name) => {
+                if class.nodes.contains(&Node::Symbol(name.clone())) {
+                    matches.push(subst);
+                }
+            }
+            Expr::Call { name, arg } => {
+                for node in class.nodes_like(&Node::Call(String::new(), 0)) {
+                    if let Node::Call(called, a) = node {
+                        if called == name {
+                            self.ematch(arg, self.find(*a), subst.clone(), matches, work);
+                        }
+                    }
+                }
+            }
+            Expr::UnaryOp { expr, .. } => {
+                for node in class.nodes_like(&Node::Neg(0)) {
+                    if let Node::Neg(a) = node {
+                        self.ematch(expr, self.find(*a), subst.clone(), matches, work);
+                    }
+                }
+            }
+            Expr::BinaryOp { op, left, right } => {
+                for node in class.nodes_like(&Node::binary(op, 0, 0)) {
+                    let Some((_, l, r)) = node.as_binary() else {
+                        continue;
+                    };
+                    let mut partial = Vec::new();
+                    self.ematch(left, self.find(l), subst.clone(), &mut partial, work);
+                    for subst in partial {
+                        self.ematch(right, self.find(r), subst, matches, work);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether `condition` holds for the classes in `subst`. Number conditions only hold for
+    /// classes known to be numbers, so `not number(a)` holds for anything else.
+    fn holds(&self, condition: &Condition, subst: &Subst) -> bool {
+        let class = |var: char| {
+            let id = subst.iter().find(|(v, _)| *v == var).map(|&(_, id)| id);
+            self.find(id.expect("conditions only use pattern variables"))
+        };
+        let a = class(condition.args[0]);
+        let value = self.classes[a].constant;
+        let integer = value.filter(Rational::is_integer).map(|v| v.numer());
+        let result = match condition.predicate {
+            Predicate::Number => value.is_some(),
+            Predicate::Integer => integer.is_some(),
+            Predicate::Positive => value.is_some_and(|v| v > Rational::ZERO),
+            Predicate::Negative => value.is_some_and(|v| v.is_negative()),
+            Predicate::Nonzero => value.is_some_and(|v| !v.is_zero()),
+            Predicate::Nonnegative => value.is_some_and(|v| !v.is_negative()),
+            Predicate::Real => value.is_some(),
+            Predicate::Even => integer.is_some_and(|n| n % 2 == 0),
+            Predicate::Odd => integer.is_some_and(|n| n % 2 != 0),
+            Predicate::Atom => value.is_some() || self.classes[a].nodes.iter().any(Node::is_leaf),
+            Predicate::Free => !self.reaches(a, class(condition.args[1])),
+        };
+        result != condition.negated
+    }
+
+    /// Whether some form of class `from` contains class `to`.
+    fn reaches(&self, from: Id, to: Id) -> bool {
+        let mut seen = HashSet::new();
+        let mut stack = vec![from];
+        while let Some(id) = stack.pop() {
+            if id == to {
+                return true;
+            }
+            if seen.insert(id) {
+                for node in &self.classes[id].nodes {
+                    stack.extend(node.children().into_iter().map(|c| self.find(c)));
+                }
+            }
+        }
+        false
+    }
+
+    /// Adds the replacement of a rule with the pattern variables bound by `subst`.
+    fn instantiate(&mut self, replacement: &Expr, subst: &Subst) -> Id {
+        let node = match replacement {
+            Expr::Variable(c) | Expr::Constant(c) => {
+                let bound = subst.iter().find(|(var, _)| var == c);
+                return self.find(bound.expect("rules only use pattern variables").1);
+            }
+            Expr::Call { name, arg } if name == "fold" => return self.instantiate(arg, subst),
+            Expr::Call { name, arg } => Node::Call(name.clone(), self.instantiate(arg, subst)),
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+            } => Node::Neg(self.instantiate(expr, subst)),
+            Expr::BinaryOp { op, left, right } => {
+                let left = self.instantiate(left, subst);
+                Node::binary(op, left, self.instantiate(right, subst))
+            }
+            leaf => return self.add_expr(leaf),
+        };
+        self.add(node)
+    }
+
+    /// The cheapest expression in class `root`, found by lowering the cost of every class
+    /// until none gets any cheaper.
+    fn extract(&self, root: Id, cost: Cost) -> Expr {
+        // Cost, node count and the node of the cheapest expression of each class.
+        let mut best: HashMap<Id, (u32, u32, &Node)> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in self.roots() {
+                for node in &self.classes[id].nodes {
+                    let size: u32 = if matches!(node, Node::Number(n) if *n < 0) {
+                        2
+                    } else {
+                        1
+                    };
+                    let mut total = (cost.of(&node.head()), size);
+                    let mut known = true;
+                    for child in node.children() {
+                        match best.get(&self.find(child)) {
+                            Some(&(c, n, _)) => {
+                                total = (total.0.saturating_add(c), total.1.saturating_add(n));
+                            }
+                            None => known = false,
+                        }
+                    }
+                    let better = best.get(&id).is_none_or(|&(c, n, _)| total < (c, n));
+                    if known && better {
+                        best.insert(id, (total.0, total.1, node));
+                        changed = true;
+                    }
+                }
+            }
+        }
+        self.build(root, &best)
+    }
+
+    fn build(&self, id: Id, best: &HashMap<Id, (u32, u32, &Node)>) -> Expr {
+        let node = best[&self.find(id)].2;
+        let child = |id: Id| Box::new(self.build(id, best));
+        match node {
+            Node::Number(n) => Expr::Number(*n),
+            Node::Variable(c) => Expr::Variable(*c),
+            Node::Constant(c) => Expr::Constant(*c),
+            Node::Symbol(name) => Expr::Symbol(name.clone()),
+            Node::Call(name, a) => Expr::Call {
+                name: name.clone(),
+                arg: child(*a),
+            },
+            Node::Neg(a) => Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr: child(*a),
+            },
+            _ => {
+                let (op, l, r) = node.as_binary().expect("the other nodes are binary");
+                Expr::BinaryOp {
+                    op,
+                    left: child(l),
+                    right: child(r),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{saturate, Cost, Head, Saturation, SaturationOptions, StopReason};
+    use crate::parse;
+    use crate::parser::ast::BinaryOp;
+    use crate::rewrite::RuleSet;
+
+    /// Commutativity and distribution, which never run out of new forms on a long product.
+    const EXPANDING: &str = "a+b => b+a\na*b => b*a\na*(b+c) => a*b+a*c";
+
+    fn saturated(rules: &str, input: &str, options: SaturationOptions) -> Saturation {
+        let rules = RuleSet::parse(rules).unwrap();
+        saturate(&parse(input).unwrap(), &rules, &options)
+    }
+
+    #[test]
+    fn stops_once_nothing_new_is_found() {
+        let options = SaturationOptions::default();
+        let found = saturated("a+0 => a", "x+0", options);
+        assert_eq!(found.expr.pretty(), "x");
+        assert_eq!(found.stop, StopReason::Saturated);
+        assert!(found.iterations < options.max_iterations);
+        let found = saturated(EXPANDING, "(x+y)*(z+w)", options);
+        assert_eq!(found.stop, StopReason::Saturated);
+    }
+
+    #[test]
+    fn limits_end_the_search() {
+        let product = "(x+y)*(z+w)*(u+v)";
+        let options = SaturationOptions {
+            max_nodes: 50,
+            ..SaturationOptions::default()
+        };
+        let found = saturated(EXPANDING, product, options);
+        assert_eq!(found.stop, StopReason::NodeLimit);
+        assert!(found.nodes >= 50);
+        let options = SaturationOptions {
+            max_iterations: 1,
+            ..SaturationOptions::default()
+        };
+        let found = saturated(EXPANDING, product, options);
+        assert_eq!(
+            (found.stop, found.iterations),
+            (StopReason::IterationLimit, 1)
+        );
+        // Never worse than the input
+        assert_eq!(found.expr.pretty(), product);
+    }
+
+    #[test]
+    fn extraction_picks_the_cheapest_form() {
+        let factor = "a*b+a*c => a*(b+c)\na*(b+c) => a*b+a*c";
+        let options = SaturationOptions::default();
+        let found = saturated(factor, "x*y+x*z", options);
+        assert_eq!(
+            (found.expr.pretty(), found.cost),
+            ("x*(y+z)".to_string(), 5)
+        );
+
+        let squares = "a^2 => a*a\na*a => a^2";
+        let powers_cost = |head: &Head| match head {
+            Head::Binary(BinaryOp::Pow) => 10,
+            _ => 1,
+        };
+        let products_cost = |head: &Head| match head {
+            Head::Binary(BinaryOp::Mul) => 10,
+            _ => 1,
+        };
+        let custom = |cost| SaturationOptions {
+            cost: Cost::Custom(cost),
+            ..options
+        };
+        let found = saturated(squares, "y^2", custom(powers_cost));
+        assert_eq!((found.expr.pretty(), found.cost), ("y*y".to_string(), 3));
+        let found = saturated(squares, "y*y", custom(products_cost));
+        assert_eq!((found.expr.pretty(), found.cost), ("y^2".to_string(), 3));
+    }
+}
@@ -0,0 +1,584 @@
+//! Matching patterns against expressions and rewriting with rules.
+
+use std::borrow::Cow;
+
+use super::{Condition, Predicate, Rule, RuleSet};
+use crate::algebra::node_count;
+use crate::assumptions::{Assumption, Assumptions, Property, Simplified, Unproven};
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+use crate::rational::Rational;
+
+/// Most rewrites a single [`RuleSet::rewrite`] performs.
+const MAX_REWRITES: usize = 10_000;
+/// How many nodes a rewrite may add to the expression, so rules that keep growing it, like
+/// `f(a) => f(a+1)`, stop early.
+const MAX_GROWTH: usize = 500;
+/// Most ways of matching one pattern that are looked at before giving up on it.
+const MAX_MATCHES: usize = 256;
+
+/// Pattern variables with the expressions they stand for, mostly parts of the matched
+/// expression.
+type Bindings<'a> = Vec<(char, Cow<'a, Expr>)>;
+
+/// What conditions may rely on while rewriting, see [`RuleSet::rewrite_assuming`].
+#[derive(Clone, Copy)]
+pub(super) struct Context<'c> {
+    pub assumptions: &'c Assumptions,
+    pub unproven: Unproven,
+}
+
+/// The outcome of a condition for some bindings.
+enum Verdict {
+    Holds,
+    Fails,
+    /// Holds if the assumption does, which isn't known.
+    Needs(Assumption),
+}
+
+pub(super) fn rewrite(
+    rules: &RuleSet,
+    expr: &Expr,
+    assumptions: &Assumptions,
+    unproven: Unproven,
+) -> Simplified {
+    let context = Context {
+        assumptions,
+        unproven,
+    };
+    let mut budget = Budget {
+        rewrites: MAX_REWRITES,
+        max_nodes: node_count(expr) + MAX_GROWTH,
+    };
+    let mut side_conditions = Vec::new();
+    let expr = rewrite_node(rules, expr, context, &mut budget, &mut side_conditions);
+    Simplified {
+        expr,
+        side_conditions,
+    }
+}
+
+/// What is left of the work a rewrite may do.
+struct Budget {
+    rewrites: usize,
+    /// Size of the largest subexpression a rule may produce.
+    max_nodes: usize,
+}
+
+/// Rewrites the operands of `expr` and then `expr` itself, starting over on the result of
+/// every rule that applies, and adds the assumptions the rewrites needed to `needed`.
+fn rewrite_node(
+    rules: &RuleSet,
+    expr: &Expr,
+    context: Context,
+    budget: &mut Budget,
+    needed: &mut Vec<Assumption>,
+) -> Expr {
+    let mut expr = Cow::Borrowed(expr);
+    // A loop rather than recursion, so rules that never settle use up the budget and not
+    // the stack
+    loop {
+        let mut rewrite =
+            |expr: &Expr| Box::new(rewrite_node(rules, expr, context, budget, needed));
+        let current = normalize(match expr.as_ref() {
+            Expr::Call { name, arg } => Expr::Call {
+                name: name.clone(),
+                arg: rewrite(arg),
+            },
+            Expr::UnaryOp { op, expr } => Expr::UnaryOp {
+                op: op.clone(),
+                expr: rewrite(expr),
+            },
+            Expr::BinaryOp { op, left, right } => Expr::BinaryOp {
+                op: op.clone(),
+                left: rewrite(left),
+                right: rewrite(right),
+            },
+            other => other.clone(),
+        });
+        if budget.rewrites == 0 {
+            return current;
+        }
+        let Some((rewritten, assumptions)) =
+            rules.iter().find_map(|rule| apply(rule, &current, context))
+        else {
+            return current;
+        };
+        if node_count(&rewritten) > budget.max_nodes {
+            budget.rewrites = 0;
+            return current;
+        }
+        budget.rewrites -= 1;
+        for assumption in assumptions {
+            if !needed.contains(&assumption) {
+                needed.push(assumption);
+            }
+        }
+        expr = Cow::Owned(rewritten);
+    }
+}
+
+/// `expr` rewritten by `rule` at its root, with the assumptions that took. A rule that
+/// leaves the expression as it is doesn't count as applying.
+pub(super) fn apply(rule: &Rule, expr: &Expr, context: Context) -> Option<(Expr, Vec<Assumption>)> {
+    if !same_head(&rule.pattern, expr) {
+        return None;
+    }
+    let matcher = Matcher {
+        conditions: &rule.conditions,
+        context,
+    };
+    let rewritten = match &rule.pattern {
+        Expr::BinaryOp {
+            op: op @ (BinaryOp::Add | BinaryOp::Mul),
+            ..
+        } => {
+            let operands = operands(op, expr);
+            let mut matches = Vec::new();
+            matcher.match_operands(
+                op,
+                &ordered(self::operands(op, &rule.pattern)),
+                &operands,
+                &mut vec![false; operands.len()],
+                &mut Vec::new(),
+                &Bindings::new(),
+                true,
+                &mut matches,
+            );
+            matches.into_iter().find_map(|(bindings, chosen)| {
+                let (replacement, needed) = instantiate(rule, &bindings, context)?;
+                // The operands left out of the match stay where they were
+                let first = *chosen.iter().min()?;
+                let rest = operands.iter().enumerate().filter_map(|(idx, operand)| {
+                    if idx == first {
+                        Some(replacement.clone())
+                    } else if chosen.contains(&idx) {
+                        None
+                    } else {
+                        Some((*operand).clone())
+                    }
+                });
+                Some((chain(op, rest.collect()), needed))
+            })
+        }
+        pattern => {
+            let mut matches = Vec::new();
+            matcher.match_expr(pattern, expr, &Bindings::new(), &mut matches);
+            matches
+                .into_iter()
+                .find_map(|bindings| instantiate(rule, &bindings, context))
+        }
+    }?;
+    let (rewritten, needed) = rewritten;
+    let rewritten = normalize(rewritten);
+    (rewritten != *expr).then_some((rewritten, needed))
+}
+
+/// Whether `pattern` and `expr` have the same outermost operation, a quick test before
+/// matching.
+fn same_head(pattern: &Expr, expr: &Expr) -> bool {
+    match (pattern, expr) {
+        (Expr::Variable(_) | Expr::Constant(_), _) => true,
+        (Expr::Call { name, .. }, Expr::Call { name: other, .. }) => name == other,
+        (Expr::UnaryOp { op, .. }, Expr::UnaryOp { op: other, .. }) => op == other,
+        (Expr::BinaryOp { op, .. }, Expr::BinaryOp { op: other, .. }) => op == other,
+        (Expr::Call { .. } | Expr::UnaryOp { .. } | Expr::BinaryOp { .. }, _) => false,
+        (pattern, expr) => pattern == expr,
+    }
+}
+
+/// The replacement for these bindings with the assumptions it needs, `None` if a condition
+/// fails or a `fold` can't be evaluated.
+fn instantiate(
+    rule: &Rule,
+    bindings: &Bindings,
+    context: Context,
+) -> Option<(Expr, Vec<Assumption>)> {
+    let mut needed = Vec::new();
+    for condition in &rule.conditions {
+        match holds(condition, bindings, context)? {
+            Verdict::Holds => {}
+            Verdict::Fails => return None,
+            Verdict::Needs(_) if context.unproven == Unproven::Skip => return None,
+            Verdict::Needs(assumption) => needed.push(assumption),
+        }
+    }
+    Some((substitute(&rule.replacement, bindings)?, needed))
+}
+
+fn substitute(expr: &Expr, bindings: &Bindings) -> Option<Expr> {
+    Some(match expr {
+        Expr::Variable(c) | Expr::Constant(c) => lookup(bindings, *c)?.clone(),
+        Expr::Call { name, arg } if name == "fold" => {
+            fold(&substitute(arg, bindings)?)?.to_expr()?
+        }
+        Expr::Call { name, arg } => Expr::Call {
+            name: name.clone(),
+            arg: Box::new(substitute(arg, bindings)?),
+        },
+        Expr::UnaryOp { op, expr } => Expr::UnaryOp {
+            op: op.clone(),
+            expr: Box::new(substitute(expr, bindings)?),
+        },
+        Expr::BinaryOp { op, left, right } => Expr::BinaryOp {
+            op: op.clone(),
+            left: Box::new(substitute(left, bindings)?),
+            right: Box::new(substitute(right, bindings)?),
+        },
+        _ => expr.clone(),
+    })
+}
+
+/// Exact value of arithmetic on numbers, including square roots of perfect squares.
+fn fold(expr: &Expr) -> Option<Rational> {
+    match expr {
+        Expr::Call { name, arg } if name == "sqrt" => fold(arg)?.sqrt(),
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => Some(-fold(expr)?),
+        Expr::BinaryOp { op, left, right } => {
+            let (left, right) = (fold(left)?, fold(right)?);
+            match op {
+                BinaryOp::Add => left.checked_add(right),
+                BinaryOp::Sub => left.checked_sub(right),
+                BinaryOp::Mul => left.checked_mul(right),
+                BinaryOp::Div => left.checked_div(right),
+                BinaryOp::Pow if right.is_integer() => {
+                    left.checked_pow(i32::try_from(right.numer()).ok()?)
+                }
+                BinaryOp::Pow => None,
+            }
+        }
+        expr => Rational::from_expr(expr),
+    }
+}
+
+fn lookup<'a>(bindings: &'a Bindings, var: char) -> Option<&'a Expr> {
+    bindings
+        .iter()
+        .find(|(c, _)| *c == var)
+        .map(|(_, expr)| expr.as_ref())
+}
+
+/// Whether the condition holds, `None` while one of its variables is unbound. Sign
+/// conditions the assumptions don't settle are needed as assumptions themselves, unless
+/// negated: `not positive(a)` only holds where `a` is known not to be positive.
+fn holds(condition: &Condition, bindings: &Bindings, context: Context) -> Option<Verdict> {
+    let args = condition
+        .args
+        .iter()
+        .map(|c| lookup(bindings, *c))
+        .collect::<Option<Vec<_>>>()?;
+    let check = |property| {
+        let known = context.assumptions.check(args[0], property);
+        match known {
+            Some(result) if result != condition.negated => Verdict::Holds,
+            None if !condition.negated => Verdict::Needs(Assumption {
+                expr: args[0].clone(),
+                property,
+            }),
+            _ => Verdict::Fails,
+        }
+    };
+    let result = match condition.predicate {
+        Predicate::Positive => return Some(check(Property::Positive)),
+        Predicate::Negative => return Some(check(Property::Negative)),
+        Predicate::Nonzero => return Some(check(Property::Nonzero)),
+        Predicate::Nonnegative => return Some(check(Property::Nonnegative)),
+        Predicate::Real => return Some(check(Property::Real)),
+        Predicate::Number => Rational::from_expr(args[0]).is_some(),
+        Predicate::Integer => {
+            matches!(args[0], Expr::Number(_))
+                || (Rational::from_expr(args[0]).is_none()
+                    && context.assumptions.check(args[0], Property::Integer) == Some(true))
+        }
+        Predicate::Even => matches!(args[0], Expr::Number(n) if n % 2 == 0),
+        Predicate::Odd => matches!(args[0], Expr::Number(n) if n % 2 != 0),
+        Predicate::Atom => matches!(
+            args[0],
+            Expr::Number(_) | Expr::Variable(_) | Expr::Constant(_) | Expr::Symbol(_)
+        ),
+        Predicate::Free => !occurs(args[1], args[0]),
+    };
+    Some(if result != condition.negated {
+        Verdict::Holds
+    } else {
+        Verdict::Fails
+    })
+}
+
+/// Whether `part` is a subexpression of `expr`.
+fn occurs(part: &Expr, expr: &Expr) -> bool {
+    part == expr
+        || match expr {
+            Expr::Call { arg, .. } => occurs(part, arg),
+            Expr::UnaryOp { expr, .. } => occurs(part, expr),
+            Expr::BinaryOp { left, right, .. } => occurs(part, left) || occurs(part, right),
+            _ => false,
+        }
+}
+
+/// Finds the ways a pattern matches, dropping partial matches as soon as they break one of
+/// the conditions.
+struct Matcher<'r> {
+    conditions: &'r [Condition],
+    context: Context<'r>,
+}
+
+impl Matcher<'_> {
+    /// `bindings` with `var` bound to `expr`, `None` if that breaks a condition.
+    fn bind<'a>(
+        &self,
+        bindings: &Bindings<'a>,
+        var: char,
+        expr: Cow<'a, Expr>,
+    ) -> Option<Bindings<'a>> {
+        let mut bindings = bindings.clone();
+        bindings.push((var, expr));
+        let broken = self
+            .conditions
+            .iter()
+            .filter(|c| c.args.contains(&var))
+            .any(|c| match holds(c, &bindings, self.context) {
+                Some(Verdict::Fails) => true,
+                Some(Verdict::Needs(_)) => self.context.unproven == Unproven::Skip,
+                _ => false,
+            });
+        (!broken).then_some(bindings)
+    }
+
+    /// Adds to `out` every way `pattern` matches `expr` that agrees with `bindings`.
+    fn match_expr<'a>(
+        &self,
+        pattern: &Expr,
+        expr: &'a Expr,
+        bindings: &Bindings<'a>,
+        out: &mut Vec<Bindings<'a>>,
+    ) {
+        if out.len() >= MAX_MATCHES || !same_head(pattern, expr) {
+            return;
+        }
+        match (pattern, expr) {
+            (Expr::Variable(c) | Expr::Constant(c), _) => match lookup(bindings, *c) {
+                Some(bound) if bound == expr => out.push(bindings.clone()),
+                Some(_) => {}
+                None => out.extend(self.bind(bindings, *c, Cow::Borrowed(expr))),
+            },
+            (Expr::Call { arg, .. }, Expr::Call { arg: expr_arg, .. }) => {
+                self.match_expr(arg, expr_arg, bindings, out)
+            }
+            (Expr::UnaryOp { expr: inner, .. }, Expr::UnaryOp { expr, .. }) => {
+                self.match_expr(inner, expr, bindings, out)
+            }
+            (
+                Expr::BinaryOp {
+                    op: op @ (BinaryOp::Add | BinaryOp::Mul),
+                    ..
+                },
+                _,
+            ) => {
+                let mut matches = Vec::new();
+                let operands = operands(op, expr);
+                self.match_operands(
+                    op,
+                    &ordered(self::operands(op, pattern)),
+                    &operands,
+                    &mut vec![false; operands.len()],
+                    &mut Vec::new(),
+                    bindings,
+                    false,
+                    &mut matches,
+                );
+                out.extend(matches.into_iter().map(|(bindings, _)| bindings));
+            }
+            (
+                Expr::BinaryOp { left, right, .. },
+                Expr::BinaryOp {
+                    left: expr_left,
+                    right: expr_right,
+                    ..
+                },
+            ) => {
+                let mut partial = Vec::new();
+                self.match_expr(left, expr_left, bindings, &mut partial);
+                for bindings in partial {
+                    self.match_expr(right, expr_right, &bindings, out);
+                }
+            }
+            _ => out.push(bindings.clone()),
+        }
+    }
+
+    /// Matches each of `patterns` to a different one of the `operands` of a sum or product,
+    /// in any order, recording the bindings with the indices of the operands used. Unless
+    /// `partial` every operand must be used, with a variable as the last pattern taking all
+    /// the operands left over.
+    #[allow(clippy::too_many_arguments)]
+    fn match_operands<'a>(
+        &self,
+        op: &BinaryOp,
+        patterns: &[&Expr],
+        operands: &[&'a Expr],
+        used: &mut Vec<bool>,
+        chosen: &mut Vec<usize>,
+        bindings: &Bindings<'a>,
+        partial: bool,
+        out: &mut Vec<(Bindings<'a>, Vec<usize>)>,
+    ) {
+        if out.len() >= MAX_MATCHES {
+            return;
+        }
+        let Some((pattern, rest)) = patterns.split_first() else {
+            if partial || used.iter().all(|&u| u) {
+                out.push((bindings.clone(), chosen.clone()));
+            }
+            return;
+        };
+        let left: Vec<usize> = (0..operands.len()).filter(|&i| !used[i]).collect();
+        if let (true, Expr::Variable(c) | Expr::Constant(c)) =
+            (rest.is_empty() && !partial, pattern)
+        {
+            if left.len() > 1 && lookup(bindings, *c).is_none() {
+                let absorbed = left.iter().map(|&i| operands[i].clone()).collect();
+                if let Some(bindings) = self.bind(bindings, *c, Cow::Owned(chain(op, absorbed))) {
+                    let mut chosen = chosen.clone();
+                    chosen.extend(&left);
+                    out.push((bindings, chosen));
+                }
+                return;
+            }
+        }
+        for idx in left {
+            let mut matches = Vec::new();
+            self.match_expr(pattern, operands[idx], bindings, &mut matches);
+            used[idx] = true;
+            chosen.push(idx);
+            for bindings in matches {
+                self.match_operands(op, rest, operands, used, chosen, &bindings, partial, out);
+            }
+            chosen.pop();
+            used[idx] = false;
+        }
+    }
+}
+
+/// Patterns with the variables last, so that the others narrow down the search first and a
+/// variable can take what's left over.
+fn ordered(patterns: Vec<&Expr>) -> Vec<&Expr> {
+    let (variables, others): (Vec<&Expr>, Vec<&Expr>) = patterns
+        .into_iter()
+        .partition(|p| matches!(p, Expr::Variable(_) | Expr::Constant(_)));
+    others.into_iter().chain(variables).collect()
+}
+
+/// The operands of a chain of `op`, whatever the grouping: `a*(b*c)` gives `[a, b, c]`.
+fn operands<'a>(op: &BinaryOp, expr: &'a Expr) -> Vec<&'a Expr> {
+    match expr {
+        Expr::BinaryOp {
+            op: expr_op,
+            left,
+            right,
+        } if expr_op == op => {
+            let mut all = operands(op, left);
+            all.extend(operands(op, right));
+            all
+        }
+        expr => vec![expr],
+    }
+}
+
+/// Left-grouped chain of `op` over `operands`, which mustn't be empty.
+fn chain(op: &BinaryOp, operands: Vec<Expr>) -> Expr {
+    operands
+        .into_iter()
+        .reduce(|left, right| Expr::BinaryOp {
+            op: op.clone(),
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+        .unwrap_or(Expr::Number(0))
+}
+
+/// Regroups sums and products to the left and puts the integer factors of a product first,
+/// the form rules produce and [`Expr::simplify`] has always returned.
+fn normalize(expr: Expr) -> Expr {
+    let Expr::BinaryOp {
+        op: op @ (BinaryOp::Add | BinaryOp::Mul),
+        ..
+    } = &expr
+    else {
+        return expr;
+    };
+    let operands = operands(op, &expr);
+    let numbers_first = *op == BinaryOp::Add
+        || operands.is_sorted_by_key(|operand| !matches!(operand, Expr::Number(_)));
+    if numbers_first && left_grouped(op, &expr) {
+        return expr;
+    }
+    let mut operands: Vec<Expr> = operands.into_iter().cloned().collect();
+    if *op == BinaryOp::Mul {
+        operands.sort_by_key(|operand| !matches!(operand, Expr::Number(_)));
+    }
+    chain(op, operands)
+}
+
+/// Whether no operand of the chain of `op` is itself a chain of `op` grouped to the right.
+fn left_grouped(op: &BinaryOp, expr: &Expr) -> bool {
+    match expr {
+        Expr::BinaryOp {
+            op: expr_op,
+            left,
+            right,
+        } if expr_op == op => {
+            !matches!(right.as_ref(), Expr::BinaryOp { op: right_op, .. } if right_op == op)
+                && left_grouped(op, left)
+        }
+        _ => true,
+    }
+}
+
+/// `pattern` with negated numbers written as negative numbers, the way they are matched.
+pub(super) fn canonical_numbers(pattern: &Expr) -> Expr {
+    match pattern {
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => match canonical_numbers(expr) {
+            Expr::Number(n) => Expr::Number(-n),
+            expr => Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr: Box::new(expr),
+            },
+        },
+        Expr::Call { name, arg } => Expr::Call {
+            name: name.clone(),
+            arg: Box::new(canonical_numbers(arg)),
+        },
+        Expr::BinaryOp { op, left, right } => Expr::BinaryOp {
+            op: op.clone(),
+            left: Box::new(canonical_numbers(left)),
+            right: Box::new(canonical_numbers(right)),
+        },
+        _ => pattern.clone(),
+    }
+}
+
+/// The single letters in `expr`, i.e. its pattern variables.
+pub(super) fn pattern_variables(expr: &Expr) -> Vec<char> {
+    let mut variables = Vec::new();
+    collect_variables(expr, &mut variables);
+    variables
+}
+
+fn collect_variables(expr: &Expr, variables: &mut Vec<char>) {
+    match expr {
+        Expr::Variable(c) | Expr::Constant(c) if !variables.contains(c) => variables.push(*c),
+        Expr::Call { arg, .. } => collect_variables(arg, variables),
+        Expr::UnaryOp { expr, .. } => collect_variables(expr, variables),
+        Expr::BinaryOp { left, right, .. } => {
+            collect_variables(left, variables);
+            collect_variables(right, variables);
+        }
+        _ => {}
+    }
+}
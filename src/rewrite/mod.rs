@@ -0,0 +1,410 @@
+pub mod egraph;
+mod engine;
+
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::assumptions::{Assumptions, Simplified, Unproven};
+use crate::parser::ast::Expr;
+use crate::Error;
+
+/// Text of the rules behind [`Expr::simplify`], see [`RuleSet::builtin`].
+pub const DEFAULT_RULES: &str = include_str!("default.rules");
+/// Text of the identities [`Expr::saturate`] works with, see [`RuleSet::algebra`].
+pub const ALGEBRA_RULES: &str = include_str!("algebra.rules");
+
+/// Reasons a rule or a rules file can't be read.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum RuleError {
+    /// The line isn't of the form `pattern => replacement [if conditions]`.
+    Syntax { line: usize, message: String },
+    /// The pattern or the replacement isn't a valid expression.
+    Parse { line: usize, error: Error },
+    /// The rules file can't be read.
+    Io(String),
+}
+
+impl Display for RuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RuleError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            RuleError::Parse { line, error } => write!(f, "line {line}: {error}"),
+            RuleError::Io(message) => write!(f, "can't read the rules: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+/// A rewrite rule such as `a*x + b*x => (a+b)*x if free(a, x)`.
+///
+/// Single letters in the pattern are variables that match any expression, the same letter
+/// matching equal expressions; numbers, names and function calls match themselves. Sums and
+/// products match whatever the order and grouping of their operands, and a pattern that is a
+/// sum or product also matches part of a larger one, e.g. `x + x => 2x` rewrites `x+y+x` to
+/// `2x+y`. In the replacement, `fold(...)` stands for the exact value of arithmetic on numbers.
+///
+/// Conditions are joined with `and`, each one a predicate of pattern variables, optionally
+/// preceded by `not`: `number(a)` (a rational number), `integer(a)`, `positive(a)`,
+/// `negative(a)`, `nonzero(a)`, `nonnegative(a)`, `real(a)`, `even(a)`, `odd(a)`, `atom(a)`
+/// (a number or a name) and `free(a, b)` (`b` doesn't occur in `a`). The sign conditions and
+/// `real` hold where the number or the [`Assumptions`] prove them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pattern: Expr,
+    replacement: Expr,
+    conditions: Vec<Condition>,
+    /// The rule as written.
+    source: String,
+}
+
+/// A predicate of the expressions bound to pattern variables.
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    negated: bool,
+    predicate: Predicate,
+    args: Vec<char>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Predicate {
+    Number,
+    Integer,
+    Positive,
+    Negative,
+    Nonzero,
+    Nonnegative,
+    Real,
+    Even,
+    Odd,
+    Atom,
+    Free,
+}
+
+impl Predicate {
+    const ALL: [(&'static str, Predicate, usize); 11] = [
+        ("number", Predicate::Number, 1),
+        ("integer", Predicate::Integer, 1),
+        ("positive", Predicate::Positive, 1),
+        ("negative", Predicate::Negative, 1),
+        ("nonzero", Predicate::Nonzero, 1),
+        ("nonnegative", Predicate::Nonnegative, 1),
+        ("real", Predicate::Real, 1),
+        ("even", Predicate::Even, 1),
+        ("odd", Predicate::Odd, 1),
+        ("atom", Predicate::Atom, 1),
+        ("free", Predicate::Free, 2),
+    ];
+}
+
+impl Rule {
+    /// Reads a rule, reporting errors at line `line`.
+    fn parse(text: &str, line: usize) -> Result<Rule, RuleError> {
+        let syntax = |message: String| RuleError::Syntax { line, message };
+        let (pattern, rest) = text
+            .split_once("=>")
+            .ok_or_else(|| syntax("expected 'pattern => replacement'".to_string()))?;
+        let (replacement, conditions) = match rest.split_once(" if ") {
+            Some((replacement, conditions)) => (replacement, Some(conditions)),
+            None => (rest, None),
+        };
+        // Every word is a name in a rule, where single letters are the pattern variables
+        let names: HashSet<String> = text
+            .split(|c: char| !(c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'))
+            .filter(|word| word.len() > 1)
+            .map(str::to_string)
+            .collect();
+        let parse = |text: &str| {
+            crate::parse_with_names(text.trim(), HashSet::new(), names.clone())
+                .map_err(|error| RuleError::Parse { line, error })
+        };
+        let pattern = engine::canonical_numbers(&parse(pattern)?);
+        let replacement = parse(replacement)?;
+        if matches!(pattern, Expr::Variable(_) | Expr::Constant(_)) {
+            return Err(syntax("the pattern can't be a lone variable".to_string()));
+        }
+        let bound = engine::pattern_variables(&pattern);
+        let conditions = conditions
+            .map(|text| {
+                text.split(" and ")
+                    .map(|condition| Condition::parse(condition.trim(), line))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        let used = engine::pattern_variables(&replacement)
+            .into_iter()
+            .chain(conditions.iter().flat_map(|c| c.args.iter().copied()));
+        for var in used {
+            if !bound.contains(&var) {
+                return Err(syntax(format!("'{var}' doesn't occur in the pattern")));
+            }
+        }
+        Ok(Rule {
+            pattern,
+            replacement,
+            conditions,
+            source: text.trim().to_string(),
+        })
+    }
+
+    /// `expr` rewritten by this rule at its root, `None` if the rule doesn't apply.
+    pub fn apply(&self, expr: &Expr) -> Option<Expr> {
+        let context = engine::Context {
+            assumptions: &Assumptions::default(),
+            unproven: Unproven::Skip,
+        };
+        engine::apply(self, expr, context).map(|(expr, _)| expr)
+    }
+}
+
+impl FromStr for Rule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rule::parse(s, 1)
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Condition {
+    fn parse(text: &str, line: usize) -> Result<Condition, RuleError> {
+        let syntax = |message: String| RuleError::Syntax { line, message };
+        let (negated, text) = match text.strip_prefix("not ") {
+            Some(text) => (true, text.trim()),
+            None => (false, text),
+        };
+        let (name, args) = text
+            .strip_suffix(')')
+            .and_then(|text| text.split_once('('))
+            .ok_or_else(|| {
+                syntax(format!(
+                    "expected a condition such as 'integer(a)', found '{text}'"
+                ))
+            })?;
+        let (_, predicate, arity) = Predicate::ALL
+            .into_iter()
+            .find(|(known, _, _)| *known == name.trim())
+            .ok_or_else(|| syntax(format!("unknown condition '{}'", name.trim())))?;
+        let args = args
+            .split(',')
+            .map(|arg| {
+                let mut chars = arg.trim().chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii_alphabetic() => Ok(c),
+                    _ => Err(syntax(format!(
+                        "'{}' is not a pattern variable",
+                        arg.trim()
+                    ))),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if args.len() != arity {
+            return Err(syntax(format!(
+                "'{}' takes {arity} argument{}",
+                name.trim(),
+                if arity == 1 { "" } else { "s" }
+            )));
+        }
+        Ok(Condition {
+            negated,
+            predicate,
+            args,
+        })
+    }
+}
+
+/// Rules tried in order on every subexpression, innermost first, until none applies.
+///
+/// ```
+/// use derivative_calculator::parse;
+/// use derivative_calculator::rewrite::RuleSet;
+///
+/// let rules = RuleSet::parse("a*x + b*x => (a+b)*x if number(a) and number(b)").unwrap();
+/// assert_eq!(parse("2y + z + 3y")?.rewrite(&rules).pretty(), "(2+3)*y+z");
+///
+/// let mut rules = RuleSet::builtin().clone();
+/// rules.extend(RuleSet::parse("a*x + b*x => (a+b)*x if number(a) and number(b)").unwrap());
+/// assert_eq!(parse("2y + z + 3y")?.rewrite(&rules).pretty(), "5*y+z");
+/// # Ok::<(), derivative_calculator::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    /// Reads one rule per line, skipping blank lines and comments starting with `#`.
+    pub fn parse(text: &str) -> Result<RuleSet, RuleError> {
+        let rules = text
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.split('#').next().unwrap_or("").trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(line, text)| Rule::parse(text, line))
+            .collect::<Result<_, _>>()?;
+        Ok(RuleSet { rules })
+    }
+
+    /// Reads the rules file at `path`, see [`RuleSet::parse`].
+    pub fn load(path: impl AsRef<Path>) -> Result<RuleSet, RuleError> {
+        let text = std::fs::read_to_string(path).map_err(|e| RuleError::Io(e.to_string()))?;
+        RuleSet::parse(&text)
+    }
+
+    /// The rules of [`DEFAULT_RULES`], which [`Expr::simplify`] applies.
+    pub fn builtin() -> &'static RuleSet {
+        static BUILTIN: OnceLock<RuleSet> = OnceLock::new();
+        BUILTIN.get_or_init(|| RuleSet::parse(DEFAULT_RULES).expect("the default rules are valid"))
+    }
+
+    /// The rules of [`ALGEBRA_RULES`]: algebraic identities in both directions, for
+    /// [`egraph::saturate`] rather than [`RuleSet::rewrite`], which they would send in circles.
+    pub fn algebra() -> &'static RuleSet {
+        static ALGEBRA: OnceLock<RuleSet> = OnceLock::new();
+        ALGEBRA.get_or_init(|| RuleSet::parse(ALGEBRA_RULES).expect("the algebra rules are valid"))
+    }
+
+    /// The rules with their `positive(...)` and `real(...)` conditions dropped, for callers
+    /// that take every expression to be positive.
+    pub(crate) fn assuming_positive(&self) -> RuleSet {
+        let mut rules = self.clone();
+        for rule in &mut rules.rules {
+            rule.conditions.retain(|c| {
+                c.negated || !matches!(c.predicate, Predicate::Positive | Predicate::Real)
+            });
+        }
+        rules
+    }
+
+    pub fn push(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+
+    /// Adds the rules of `other` after these.
+    pub fn extend(&mut self, other: RuleSet) {
+        self.rules.extend(other.rules);
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.iter()
+    }
+
+    /// `expr` with the rules applied everywhere until none applies any more, or until a
+    /// generous number of rewrites or a rewrite that grows the expression too much, in case
+    /// the rules never settle.
+    pub fn rewrite(&self, expr: &Expr) -> Expr {
+        self.rewrite_assuming(expr, &Assumptions::default(), Unproven::Skip)
+            .expr
+    }
+
+    /// [`RuleSet::rewrite`] with the sign conditions of the rules decided by `assumptions`,
+    /// and `unproven` deciding about those they don't settle.
+    pub fn rewrite_assuming(
+        &self,
+        expr: &Expr,
+        assumptions: &Assumptions,
+        unproven: Unproven,
+    ) -> Simplified {
+        engine::rewrite(self, expr, assumptions, unproven)
+    }
+}
+
+impl Expr {
+    /// Applies `rules` everywhere, see [`RuleSet::rewrite`].
+    pub fn rewrite(&self, rules: &RuleSet) -> Expr {
+        rules.rewrite(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rule, RuleError, RuleSet};
+    use crate::parse;
+
+    fn rewritten(rules: &str, input: &str) -> String {
+        let rules = RuleSet::parse(rules).unwrap();
+        parse(input).unwrap().rewrite(&rules).pretty()
+    }
+
+    #[test]
+    fn pattern_variables_bind_subexpressions() {
+        assert_eq!(rewritten("ln(exp(a)) => a", "ln(exp(y+1))*2"), "2*(y+1)");
+        assert_eq!(
+            rewritten("sin(asin(a)) => a", "sin(asin(sin(asin(x))))"),
+            "x"
+        );
+        // The same letter twice only matches equal expressions
+        assert_eq!(rewritten("a - a => 0", "sin(x)-sin(x)"), "0");
+        assert_eq!(rewritten("a - a => 0", "sin(x)-cos(x)"), "sin(x)-cos(x)");
+    }
+
+    #[test]
+    fn conditions_restrict_matches() {
+        let rule = "a+b => fold(a+b) if number(a) and number(b)";
+        assert_eq!(rewritten(rule, "1+2+x+3"), "6+x");
+        let rule: Rule = "sqrt(a^2) => a if positive(a)".parse().unwrap();
+        assert_eq!(
+            rule.apply(&parse("sqrt(4^2)").unwrap()),
+            Some(parse("4").unwrap())
+        );
+        assert_eq!(rule.apply(&parse("sqrt(y^2)").unwrap()), None);
+        let rule = "a^n => a if not integer(n)";
+        assert_eq!(rewritten(rule, "y^(1/2)+y^2"), "y+y^2");
+    }
+
+    #[test]
+    fn sums_and_products_match_in_any_order_and_grouping() {
+        let rule = "a*x + b*x => (a+b)*x";
+        assert_eq!(rewritten(rule, "2y+3y"), "(2+3)*y");
+        assert_eq!(rewritten("x + x => 2x", "y+z+y"), "2*y+z");
+        assert_eq!(rewritten("x*y*x => x^2*y", "a*(b*a)"), "a^2*b");
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let text = "# comment\n\nsin(a) => a\nsin( => 1";
+        assert!(matches!(
+            RuleSet::parse(text),
+            Err(RuleError::Parse { line: 4, .. })
+        ));
+        let syntax = |text: &str| match RuleSet::parse(text) {
+            Err(RuleError::Syntax { line, message }) => format!("{line}: {message}"),
+            other => panic!("{other:?}"),
+        };
+        assert_eq!(syntax("\nsin(a)"), "2: expected 'pattern => replacement'");
+        assert_eq!(syntax("a => b"), "1: the pattern can't be a lone variable");
+        assert_eq!(syntax("a+b => c"), "1: 'c' doesn't occur in the pattern");
+        assert_eq!(syntax("a+b => a if foo(a)"), "1: unknown condition 'foo'");
+        assert_eq!(syntax("a+b => a if free(a)"), "1: 'free' takes 2 arguments");
+        assert!(matches!(
+            RuleSet::load("/nonexistent.rules"),
+            Err(RuleError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn rules_that_never_settle_stop() {
+        let swap = "sin(a) => cos(a)\ncos(a) => sin(a)";
+        assert_eq!(rewritten(swap, "sin(x)+1"), "sin(x)+1");
+        let grown = rewritten("sin(a) => sin(a+1)", "sin(x)");
+        assert!(grown.starts_with("sin(x+1+1+") && grown.len() < 2000);
+    }
+}
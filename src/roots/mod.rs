@@ -0,0 +1,621 @@
+//! Numerical roots by Newton's method, with the derivatives taken symbolically.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::differentiator::{differentiate, DiffError};
+use crate::evaluator::EvalError;
+use crate::parser::ast::Expr;
+
+/// Where [`find_root`] starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Start {
+    /// A first guess, from which Newton's method goes wherever the function leads.
+    Guess(f64),
+    /// An interval over which the function changes sign, which the iterates never leave.
+    Bracket(f64, f64),
+}
+
+/// Tolerances and limits of [`find_root`] and [`solve_system`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewtonOptions {
+    /// A step this small relative to the iterate, `|step| <= tolerance * (1 + |x|)`, means
+    /// convergence.
+    pub tolerance: f64,
+    pub max_iterations: usize,
+    /// Most times a Newton step is halved looking for a smaller residual.
+    pub max_halvings: u32,
+}
+
+impl NewtonOptions {
+    /// Whether a step from `x` is short enough to mean convergence.
+    fn small(&self, step: f64, x: f64) -> bool {
+        step.abs() <= self.tolerance * (1.0 + x.abs())
+    }
+}
+
+impl Default for NewtonOptions {
+    fn default() -> Self {
+        NewtonOptions {
+            tolerance: 1e-12,
+            max_iterations: 100,
+            max_halvings: 30,
+        }
+    }
+}
+
+/// How an iterate was reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StepKind {
+    /// A full Newton step.
+    Newton,
+    /// A Newton step shortened by this many halvings to reduce the residual.
+    Damped(u32),
+    /// The midpoint of the bracket, where a Newton step would have left it or was too slow.
+    Bisection,
+}
+
+impl Display for StepKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StepKind::Newton => f.write_str("Newton"),
+            StepKind::Damped(1) => f.write_str("Newton halved once"),
+            StepKind::Damped(n) => write!(f, "Newton halved {n} times"),
+            StepKind::Bisection => f.write_str("bisection"),
+        }
+    }
+}
+
+/// One iteration: the new iterate, the residual there and the step that led to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Iteration {
+    pub x: f64,
+    pub residual: f64,
+    pub step: f64,
+    pub kind: StepKind,
+}
+
+/// A root with the diagnostics of how it was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Root {
+    pub x: f64,
+    /// The function's value at `x`.
+    pub residual: f64,
+    /// The derivative the iteration used.
+    pub derivative: Expr,
+    pub iterations: Vec<Iteration>,
+}
+
+impl Root {
+    /// How many iterations were of the given kind, counting all damped ones together.
+    pub fn count(&self, kind: StepKind) -> usize {
+        let same =
+            |other: &StepKind| std::mem::discriminant(other) == std::mem::discriminant(&kind);
+        self.iterations.iter().filter(|i| same(&i.kind)).count()
+    }
+
+    /// Estimated order of convergence from the last three steps, about 2 for Newton's method
+    /// near a simple root and 1 near a multiple one. `None` unless they were all full Newton
+    /// steps.
+    pub fn order(&self) -> Option<f64> {
+        let [.., a, b, c] = self.iterations[..] else {
+            return None;
+        };
+        if [a, b, c].iter().any(|i| i.kind != StepKind::Newton) {
+            return None;
+        }
+        let [a, b, c] = [a.step.abs(), b.step.abs(), c.step.abs()];
+        let order = (c / b).ln() / (b / a).ln();
+        order.is_finite().then_some(order)
+    }
+}
+
+/// A solution of a system of equations with its diagnostics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemRoot {
+    /// Values of the variables, in the order they were given.
+    pub values: Vec<f64>,
+    /// Values of the equations there.
+    pub residuals: Vec<f64>,
+    /// `jacobian[i][j]` is the derivative of equation `i` in variable `j`.
+    pub jacobian: Vec<Vec<Expr>>,
+    pub iterations: usize,
+    /// Total number of halvings of Newton steps.
+    pub halvings: u32,
+}
+
+/// Reasons a root can't be found.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum RootError {
+    Differentiate(DiffError),
+    Eval(EvalError),
+    /// The function has the same sign at both ends of the bracket.
+    NoSignChange {
+        a: f64,
+        b: f64,
+    },
+    /// The function or its derivative isn't finite at this point.
+    NotFinite(f64),
+    /// The derivative (or for a system the Jacobian) is zero or singular at this point, so
+    /// Newton's method has nowhere to go.
+    Stationary(Vec<f64>),
+    /// No step reduces the residual, however short.
+    Stalled(Vec<f64>),
+    /// The number of equations differs from the number of variables or starting values.
+    Dimensions,
+    /// The tolerance isn't reached within the iteration limit. Holds the last iterate.
+    NotConverged(Vec<f64>),
+}
+
+impl Display for RootError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let point = |values: &[f64]| {
+            let values: Vec<String> = values.iter().map(f64::to_string).collect();
+            values.join(", ")
+        };
+        match self {
+            RootError::Differentiate(e) => write!(f, "{e}"),
+            RootError::Eval(e) => write!(f, "{e}"),
+            RootError::NoSignChange { a, b } => {
+                write!(f, "the function doesn't change sign between {a} and {b}")
+            }
+            RootError::NotFinite(x) => write!(f, "the function is not finite at {x}"),
+            RootError::Stationary(at) => write!(
+                f,
+                "the derivative vanishes at {}, try another start",
+                point(at)
+            ),
+            RootError::Stalled(at) => write!(
+                f,
+                "no step from {} reduces the residual, try another start",
+                point(at)
+            ),
+            RootError::Dimensions => {
+                f.write_str("need as many equations as variables and starting values")
+            }
+            RootError::NotConverged(at) => write!(
+                f,
+                "no convergence within the iteration limit (last iterate {})",
+                point(at)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RootError {}
+
+impl From<DiffError> for RootError {
+    fn from(e: DiffError) -> Self {
+        RootError::Differentiate(e)
+    }
+}
+
+impl From<EvalError> for RootError {
+    fn from(e: EvalError) -> Self {
+        RootError::Eval(e)
+    }
+}
+
+impl Expr {
+    /// A root of the expression as a function of `var` from `start`, see [`find_root`].
+    /// Every other symbol must be bound in `values`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use derivative_calculator::parse;
+    /// use derivative_calculator::roots::{Start, StepKind};
+    ///
+    /// let none = HashMap::new();
+    /// let root = parse("x^2 - 2")?.find_root('x', Start::Guess(1.0), &none).unwrap();
+    /// assert!((root.x - 2f64.sqrt()).abs() < 1e-15);
+    /// assert!(root.order().unwrap() > 1.8);
+    ///
+    /// // A full Newton step from 2 overshoots to -3.5 where |atan| is larger, so it's halved
+    /// let root = parse("atan(x)")?.find_root('x', Start::Guess(2.0), &none).unwrap();
+    /// assert!(root.x.abs() < 1e-12 && root.count(StepKind::Damped(0)) > 0);
+    ///
+    /// // Within a bracket, bisection takes over where Newton would leave it
+    /// let root = parse("cos(x) - x^3")?.find_root('x', Start::Bracket(0.0, 2.0), &none).unwrap();
+    /// assert!((root.x - 0.865474033101614).abs() < 1e-12);
+    ///
+    /// assert!(parse("x^2 + 1")?.find_root('x', Start::Bracket(-1.0, 1.0), &none).is_err());
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn find_root(
+        &self,
+        var: char,
+        start: Start,
+        values: &HashMap<char, f64>,
+    ) -> Result<Root, RootError> {
+        find_root(self, var, start, values, &NewtonOptions::default())
+    }
+}
+
+/// Finds a root of `expr` as a function of `var` by Newton's method on the symbolic
+/// derivative.
+///
+/// From a [`Start::Guess`], a step that doesn't reduce `|f|` is halved until it does. As soon
+/// as `f` changes sign between two iterates the search continues within that bracket, as from
+/// a [`Start::Bracket`]: there a Newton step that would leave the bracket or fail to halve it
+/// every two iterations is replaced by bisection, so the iteration always converges.
+pub fn find_root(
+    expr: &Expr,
+    var: char,
+    start: Start,
+    values: &HashMap<char, f64>,
+    options: &NewtonOptions,
+) -> Result<Root, RootError> {
+    let derivative = differentiate(expr, var)?.simplify();
+    let f = expr.compile(var, values)?;
+    let df = derivative.compile(var, values)?;
+    let mut iterations = Vec::new();
+    let finish = |x: f64, iterations: Vec<Iteration>| Root {
+        x,
+        residual: f.eval(x),
+        derivative: derivative.clone(),
+        iterations,
+    };
+
+    let (mut x, mut bracket) = match start {
+        Start::Guess(x) => (x, None),
+        Start::Bracket(a, b) => {
+            let (fa, fb) = (f.eval(a), f.eval(b));
+            for (x, fx) in [(a, fa), (b, fb)] {
+                if fx == 0.0 {
+                    return Ok(finish(x, iterations));
+                }
+                if !fx.is_finite() {
+                    return Err(RootError::NotFinite(x));
+                }
+            }
+            if fa.signum() == fb.signum() {
+                return Err(RootError::NoSignChange { a, b });
+            }
+            let (lo, hi) = if fa < 0.0 { (a, b) } else { (b, a) };
+            (0.5 * (a + b), Some((lo, hi)))
+        }
+    };
+    let mut fx = f.eval(x);
+    if !fx.is_finite() {
+        return Err(RootError::NotFinite(x));
+    }
+    // Starting from the midpoint already halves the bracket, or the first bisection would
+    // land on the same point and look converged
+    bracket = bracket.map(|(lo, hi)| if fx < 0.0 { (x, hi) } else { (lo, x) });
+    // The lengths of the last two steps. A Newton step within the bracket must be shorter than
+    // half the earlier one, or bisection is faster
+    let mut steps = [f64::INFINITY; 2];
+    while iterations.len() < options.max_iterations {
+        if fx == 0.0 {
+            return Ok(finish(x, iterations));
+        }
+        let slope = df.eval(x);
+        let newton = -fx / slope;
+        let (next, kind) = match bracket {
+            // `lo` is where f is negative, `hi` where it's positive
+            Some((lo, hi)) => {
+                let target = x + newton;
+                let inside = newton.is_finite() && (target - lo) * (target - hi) < 0.0;
+                if inside && newton.abs() < 0.5 * steps[0] {
+                    (target, StepKind::Newton)
+                } else {
+                    (0.5 * (lo + hi), StepKind::Bisection)
+                }
+            }
+            None => {
+                if slope == 0.0 || !newton.is_finite() {
+                    return Err(RootError::Stationary(vec![x]));
+                }
+                match damped(|x| f.eval(x), x, fx, newton, options.max_halvings) {
+                    Some(next) => next,
+                    // Already at the root to rounding, where no step can reduce the residual
+                    None if options.small(newton, x) => return Ok(finish(x, iterations)),
+                    None => return Err(RootError::Stalled(vec![x])),
+                }
+            }
+        };
+        let fnext = f.eval(next);
+        if !fnext.is_finite() {
+            return Err(RootError::NotFinite(next));
+        }
+        let step = next - x;
+        iterations.push(Iteration {
+            x: next,
+            residual: fnext,
+            step,
+            kind,
+        });
+        steps = [steps[1], step.abs()];
+        bracket = match bracket {
+            Some((lo, hi)) => Some(if fnext < 0.0 { (next, hi) } else { (lo, next) }),
+            None if fnext.signum() != fx.signum() => {
+                Some(if fnext < 0.0 { (next, x) } else { (x, next) })
+            }
+            None => None,
+        };
+        (x, fx) = (next, fnext);
+        let converged = match bracket {
+            Some((lo, hi)) => options.small(hi - lo, x),
+            None => false,
+        };
+        if converged || options.small(step, x) {
+            return Ok(finish(x, iterations));
+        }
+    }
+    Err(RootError::NotConverged(vec![x]))
+}
+
+/// The Newton step from `x`, halved until the residual `|f|` drops below `|fx|`. `None` if it
+/// doesn't within `max_halvings`.
+fn damped(
+    f: impl Fn(f64) -> f64,
+    x: f64,
+    fx: f64,
+    step: f64,
+    max_halvings: u32,
+) -> Option<(f64, StepKind)> {
+    let mut scale = 1.0;
+    for halvings in 0..=max_halvings {
+        let next = x + scale * step;
+        let fnext = f(next);
+        if fnext.is_finite() && fnext.abs() < fx.abs() {
+            let kind = match halvings {
+                0 => StepKind::Newton,
+                n => StepKind::Damped(n),
+            };
+            return Some((next, kind));
+        }
+        scale *= 0.5;
+    }
+    None
+}
+
+/// Solves `equations = 0` for `vars` from `guess` by Newton's method on the symbolic
+/// Jacobian, halving steps that don't reduce the sum of squared residuals. Every other
+/// symbol must be bound in `values`.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use derivative_calculator::parse;
+/// use derivative_calculator::roots::{solve_system, NewtonOptions};
+///
+/// // Where the circle of radius 2 meets the line y = x
+/// let equations = [parse("x^2 + y^2 - 4")?, parse("y - x")?];
+/// let options = NewtonOptions::default();
+/// let root = solve_system(&equations, &['x', 'y'], &[1.0, 2.0], &HashMap::new(), &options).unwrap();
+/// assert!((root.values[0] - 2f64.sqrt()).abs() < 1e-12);
+/// assert!((root.values[1] - 2f64.sqrt()).abs() < 1e-12);
+/// assert_eq!(root.jacobian[0][1].pretty(), "2*y");
+/// # Ok::<(), derivative_calculator::Error>(())
+/// ```
+pub fn solve_system(
+    equations: &[Expr],
+    vars: &[char],
+    guess: &[f64],
+    values: &HashMap<char, f64>,
+    options: &NewtonOptions,
+) -> Result<SystemRoot, RootError> {
+    if equations.len() != vars.len() || guess.len() != vars.len() {
+        return Err(RootError::Dimensions);
+    }
+    let jacobian = equations
+        .iter()
+        .map(|equation| {
+            vars.iter()
+                .map(|&var| Ok(differentiate(equation, var)?.simplify()))
+                .collect::<Result<Vec<_>, RootError>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut bound = values.clone();
+    let mut eval = |point: &[f64], exprs: &[Expr]| -> Result<Vec<f64>, RootError> {
+        bound.extend(vars.iter().copied().zip(point.iter().copied()));
+        exprs
+            .iter()
+            .map(|expr| Ok(expr.eval(&bound)?))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let norm = |residuals: &[f64]| residuals.iter().map(|r| r * r).sum::<f64>();
+    let small = |step: &[f64], at: &[f64]| step.iter().zip(at).all(|(s, x)| options.small(*s, *x));
+
+    let mut point = guess.to_vec();
+    let mut residuals = eval(&point, equations)?;
+    let mut halvings = 0;
+    for iteration in 1..=options.max_iterations {
+        if norm(&residuals) == 0.0 {
+            return Ok(SystemRoot {
+                values: point,
+                residuals,
+                jacobian,
+                iterations: iteration - 1,
+                halvings,
+            });
+        }
+        let rows = jacobian
+            .iter()
+            .map(|row| eval(&point, row))
+            .collect::<Result<Vec<_>, _>>()?;
+        let negated: Vec<f64> = residuals.iter().map(|r| -r).collect();
+        let step =
+            solve_linear(rows, negated).ok_or_else(|| RootError::Stationary(point.clone()))?;
+
+        let mut scale = 1.0;
+        let (next, next_residuals) = loop {
+            let next: Vec<f64> = point
+                .iter()
+                .zip(&step)
+                .map(|(x, s)| x + scale * s)
+                .collect();
+            let next_residuals = eval(&next, equations)?;
+            let finite = next_residuals.iter().all(|r| r.is_finite());
+            if finite && norm(&next_residuals) < norm(&residuals) {
+                break (next, next_residuals);
+            }
+            // Already at the root to rounding, where no step can reduce the residual
+            if small(&step, &point) {
+                return Ok(SystemRoot {
+                    values: point,
+                    residuals,
+                    jacobian,
+                    iterations: iteration - 1,
+                    halvings,
+                });
+            }
+            if halvings_exhausted(scale, options.max_halvings) {
+                return Err(RootError::Stalled(point));
+            }
+            scale *= 0.5;
+            halvings += 1;
+        };
+        let taken: Vec<f64> = next.iter().zip(&point).map(|(x, old)| x - old).collect();
+        let converged = small(&taken, &next);
+        (point, residuals) = (next, next_residuals);
+        if converged {
+            return Ok(SystemRoot {
+                values: point,
+                residuals,
+                jacobian,
+                iterations: iteration,
+                halvings,
+            });
+        }
+    }
+    Err(RootError::NotConverged(point))
+}
+
+/// Whether a step scaled by `scale` has been halved `max_halvings` times.
+fn halvings_exhausted(scale: f64, max_halvings: u32) -> bool {
+    scale <= 0.5f64.powi(max_halvings as i32)
+}
+
+/// Solves `matrix * x = rhs` by Gaussian elimination with partial pivoting, `None` if the
+/// matrix is singular.
+fn solve_linear(mut matrix: Vec<Vec<f64>>, mut rhs: Vec<f64>) -> Option<Vec<f64>> {
+    let n = rhs.len();
+    for col in 0..n {
+        let pivot =
+            (col..n).max_by(|&a, &b| matrix[a][col].abs().total_cmp(&matrix[b][col].abs()))?;
+        if matrix[pivot][col] == 0.0 || !matrix[pivot][col].is_finite() {
+            return None;
+        }
+        matrix.swap(col, pivot);
+        rhs.swap(col, pivot);
+        for row in col + 1..n {
+            let factor = matrix[row][col] / matrix[col][col];
+            let pivot_row = matrix[col].clone();
+            for (entry, pivot_entry) in matrix[row].iter_mut().zip(&pivot_row).skip(col) {
+                *entry -= factor * pivot_entry;
+            }
+            rhs[row] -= factor * rhs[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let known: f64 = (row + 1..n).map(|col| matrix[row][col] * x[col]).sum();
+        x[row] = (rhs[row] - known) / matrix[row][row];
+    }
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{solve_system, NewtonOptions, RootError, Start, StepKind};
+    use crate::parse;
+
+    fn root(input: &str, start: Start) -> Result<super::Root, RootError> {
+        parse(input)
+            .unwrap()
+            .find_root('x', start, &HashMap::from([('a', 2.0)]))
+    }
+
+    #[test]
+    fn newton_converges_quadratically_at_simple_roots() {
+        let found = root("x^2 - a", Start::Guess(3.0)).unwrap();
+        assert!((found.x - 2f64.sqrt()).abs() < 1e-15);
+        assert_eq!(found.count(StepKind::Newton), found.iterations.len());
+        assert!(found.order().unwrap() > 1.8);
+    }
+
+    #[test]
+    fn newton_converges_linearly_at_double_roots() {
+        let found = root("(x-1)^2", Start::Guess(2.0)).unwrap();
+        assert!((found.x - 1.0).abs() < 1e-6);
+        assert!((found.order().unwrap() - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn brackets_fall_back_to_bisection() {
+        let found = root("atan(x - 1)", Start::Bracket(-3.0, 10.0)).unwrap();
+        assert!(found.residual.abs() < 1e-10);
+        assert!((found.x - 1.0).abs() < 1e-10);
+        assert!(found.count(StepKind::Bisection) > 0);
+        assert_eq!(
+            root("x^2 + 1", Start::Bracket(-1.0, 1.0)).unwrap_err(),
+            RootError::NoSignChange { a: -1.0, b: 1.0 }
+        );
+    }
+
+    #[test]
+    fn stationary_starts_are_reported() {
+        assert_eq!(
+            root("x^2 - 1", Start::Guess(0.0)).unwrap_err(),
+            RootError::Stationary(vec![0.0])
+        );
+    }
+
+    #[test]
+    fn systems_need_matching_dimensions() {
+        let equations = [parse("x + y").unwrap()];
+        let options = NewtonOptions::default();
+        assert_eq!(
+            solve_system(
+                &equations,
+                &['x', 'y'],
+                &[0.0, 0.0],
+                &HashMap::new(),
+                &options
+            )
+            .unwrap_err(),
+            RootError::Dimensions
+        );
+        let equations = [parse("x*y - 6").unwrap(), parse("x + y - 5").unwrap()];
+        let solution = solve_system(
+            &equations,
+            &['x', 'y'],
+            &[1.0, 4.0],
+            &HashMap::new(),
+            &options,
+        )
+        .unwrap();
+        assert!((solution.values[0] - 2.0).abs() < 1e-12);
+        assert!((solution.values[1] - 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn starts_at_a_root_converge() {
+        let found = root("x^2 - a", Start::Guess(2f64.sqrt())).unwrap();
+        assert_eq!(found.x, 2f64.sqrt());
+        assert!(found.iterations.len() <= 1);
+
+        let equations = [parse("x^2+y^2-4").unwrap(), parse("x-y").unwrap()];
+        let options = NewtonOptions::default();
+        let solution = solve_system(
+            &equations,
+            &['x', 'y'],
+            &[1.0, 0.0],
+            &HashMap::new(),
+            &options,
+        )
+        .unwrap();
+        assert!((solution.values[0] - 2f64.sqrt()).abs() < 1e-15);
+        assert!((solution.values[1] - 2f64.sqrt()).abs() < 1e-15);
+        let at_root = [2f64.sqrt(), 2f64.sqrt()];
+        let solution =
+            solve_system(&equations, &['x', 'y'], &at_root, &HashMap::new(), &options).unwrap();
+        assert_eq!(solution.values, at_root);
+    }
+}
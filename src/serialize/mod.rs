@@ -0,0 +1,191 @@
+//! `Serialize` and `Deserialize` for [`Expr`], enabled with the `serde` feature.
+//!
+//! Every expression node is an object whose `"type"` field says what kind of node it is:
+//!
+//! | `type`     | other fields                                   | example                                    |
+//! |------------|------------------------------------------------|--------------------------------------------|
+//! | `number`   | `value`: integer                               | `{"type":"number","value":2}`              |
+//! | `variable` | `name`: one character                          | `{"type":"variable","name":"x"}`           |
+//! | `constant` | `name`: one character                          | `{"type":"constant","name":"a"}`           |
+//! | `symbol`   | `name`: string                                 | `{"type":"symbol","name":"ans"}`           |
+//! | `call`     | `name`: function name, `arg`: node             | `{"type":"call","name":"sin","arg":...}`   |
+//! | `unary`    | `op`: `"neg"`, `expr`: node                    | `{"type":"unary","op":"neg","expr":...}`   |
+//! | `binary`   | `op`: `"add"`, `"sub"`, `"mul"`, `"div"` or `"pow"`, `left` and `right`: nodes | |
+//!
+//! ```
+//! use derivative_calculator::{parse, Expr};
+//!
+//! let expr = parse("x^2 + 1")?;
+//! let json = serde_json::to_string(&expr).unwrap();
+//! assert_eq!(
+//!     json,
+//!     r#"{"type":"binary","op":"add","left":{"type":"binary","op":"pow","left":{"type":"variable","name":"x"},"right":{"type":"number","value":2}},"right":{"type":"number","value":1}}"#
+//! );
+//! assert_eq!(serde_json::from_str::<Expr>(&json).unwrap(), expr);
+//!
+//! for input in ["-sin(2x)/ln(x)", "a*x - $1", "(x-1)^-2", "f(x)"] {
+//!     let expr = parse(input)?;
+//!     let json = serde_json::to_string(&expr).unwrap();
+//!     assert_eq!(serde_json::from_str::<Expr>(&json).unwrap(), expr);
+//! }
+//! # Ok::<(), derivative_calculator::Error>(())
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+
+/// Mirror of [`Expr`] with the documented tagged layout. Kept separate so the schema doesn't
+/// change by accident when `Expr` does.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ExprRepr {
+    Number {
+        value: i32,
+    },
+    Variable {
+        name: char,
+    },
+    Constant {
+        name: char,
+    },
+    Symbol {
+        name: String,
+    },
+    Call {
+        name: String,
+        arg: Box<Expr>,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+}
+
+/// [`ExprRepr`] borrowing from the expression, so serializing doesn't copy the tree.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Repr<'a> {
+    Number {
+        value: i32,
+    },
+    Variable {
+        name: char,
+    },
+    Constant {
+        name: char,
+    },
+    Symbol {
+        name: &'a str,
+    },
+    Call {
+        name: &'a str,
+        arg: &'a Expr,
+    },
+    Unary {
+        op: &'a UnaryOp,
+        expr: &'a Expr,
+    },
+    Binary {
+        op: &'a BinaryOp,
+        left: &'a Expr,
+        right: &'a Expr,
+    },
+}
+
+impl Serialize for Expr {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let repr = match self {
+            Expr::Number(value) => Repr::Number { value: *value },
+            Expr::Variable(name) => Repr::Variable { name: *name },
+            Expr::Constant(name) => Repr::Constant { name: *name },
+            Expr::Symbol(name) => Repr::Symbol { name },
+            Expr::Call { name, arg } => Repr::Call { name, arg },
+            Expr::UnaryOp { op, expr } => Repr::Unary { op, expr },
+            Expr::BinaryOp { op, left, right } => Repr::Binary { op, left, right },
+        };
+        repr.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Expr {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let expr = match ExprRepr::deserialize(deserializer)? {
+            ExprRepr::Number { value } => Expr::Number(value),
+            ExprRepr::Variable { name } => Expr::Variable(name),
+            ExprRepr::Constant { name } => Expr::Constant(name),
+            ExprRepr::Symbol { name } => Expr::Symbol(name),
+            ExprRepr::Call { name, arg } => Expr::Call { name, arg },
+            ExprRepr::Unary { op, expr } => Expr::UnaryOp { op, expr },
+            ExprRepr::Binary { op, left, right } => Expr::BinaryOp { op, left, right },
+        };
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+
+    fn round_trip(expr: &Expr) -> Expr {
+        serde_json::from_str(&serde_json::to_string(expr).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn symbols() {
+        let symbol = Expr::Symbol("ans".to_string());
+        assert_eq!(
+            serde_json::to_string(&symbol).unwrap(),
+            r#"{"type":"symbol","name":"ans"}"#
+        );
+        assert_eq!(round_trip(&symbol), symbol);
+    }
+
+    #[test]
+    fn calls() {
+        let call = Expr::Call {
+            name: "sqrt".to_string(),
+            arg: Box::new(Expr::Constant('a')),
+        };
+        assert_eq!(
+            serde_json::to_string(&call).unwrap(),
+            r#"{"type":"call","name":"sqrt","arg":{"type":"constant","name":"a"}}"#
+        );
+        assert_eq!(round_trip(&call), call);
+    }
+
+    #[test]
+    fn nested_operators() {
+        let expr = Expr::BinaryOp {
+            op: BinaryOp::Div,
+            left: Box::new(Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr: Box::new(Expr::BinaryOp {
+                    op: BinaryOp::Pow,
+                    left: Box::new(Expr::Variable('x')),
+                    right: Box::new(Expr::Number(-2)),
+                }),
+            }),
+            right: Box::new(Expr::BinaryOp {
+                op: BinaryOp::Sub,
+                left: Box::new(Expr::Symbol("$1".to_string())),
+                right: Box::new(Expr::Number(3)),
+            }),
+        };
+        assert_eq!(round_trip(&expr), expr);
+        assert_eq!(
+            serde_json::to_string(&expr).unwrap(),
+            r#"{"type":"binary","op":"div","left":{"type":"unary","op":"neg","expr":{"type":"binary","op":"pow","left":{"type":"variable","name":"x"},"right":{"type":"number","value":-2}}},"right":{"type":"binary","op":"sub","left":{"type":"symbol","name":"$1"},"right":{"type":"number","value":3}}}"#
+        );
+    }
+
+    #[test]
+    fn unknown_types_are_rejected() {
+        assert!(serde_json::from_str::<Expr>(r#"{"type":"matrix"}"#).is_err());
+    }
+}
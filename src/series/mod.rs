@@ -0,0 +1,439 @@
+mod recurrence;
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use recurrence::Unsupported;
+
+use crate::algebra::{scaled, signed_sum, split_constant, variable};
+use crate::differentiator::DiffError;
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+use crate::rational::Rational;
+
+/// Reasons a series expansion can't be computed.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum SeriesError {
+    /// One of the derivatives can't be taken.
+    Differentiate(DiffError),
+    /// The derivative of this order is undefined at the point, e.g. `ln(x)` at `0`.
+    Singular(u32),
+    /// Not even the coefficient of this order, the first, fits a [`Rational`].
+    Overflow(u32),
+}
+
+impl Display for SeriesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SeriesError::Differentiate(e) => write!(f, "{e}"),
+            SeriesError::Singular(order) => write!(
+                f,
+                "derivative of order {order} is undefined at the expansion point"
+            ),
+            SeriesError::Overflow(order) => write!(
+                f,
+                "coefficient of order {order} doesn't fit a fraction of 64-bit integers"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SeriesError {}
+
+impl From<DiffError> for SeriesError {
+    fn from(e: DiffError) -> Self {
+        SeriesError::Differentiate(e)
+    }
+}
+
+/// Taylor polynomial of an expression about a point, together with the order of the remainder.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    /// Variable the series is in.
+    pub var: char,
+    /// Point the series is expanded about.
+    pub point: Expr,
+    /// Terms of this power and higher are left out.
+    pub order: u32,
+    /// Coefficient of `(var - point)^k` at index `k`, simplified.
+    pub coefficients: Vec<Expr>,
+}
+
+impl Expr {
+    /// Taylor series about `var = point`, up to but not including `(var - point)^order`.
+    ///
+    /// When every coefficient is rational, as for `tan(x)` about `0`, they come from power
+    /// series arithmetic. Otherwise coefficient `k` is the `k`-th derivative at the point
+    /// divided by `k!`, kept exact: a fraction when the derivative evaluates to a rational
+    /// number, the simplified symbolic value (such as `sin(1)/2`) otherwise. Numbers beyond
+    /// the range of [`Expr::Number`] are written as products.
+    ///
+    /// Coefficients have to fit a [`Rational`], which limits `exp(x)` about `0` to order 21.
+    /// The series ends before the first coefficient that doesn't, so its [`Series::order`] can
+    /// be lower than `order`.
+    ///
+    /// ```
+    /// use derivative_calculator::{parse, Expr};
+    ///
+    /// let series = parse("sin(x)")?.series('x', &Expr::Number(0), 7).unwrap();
+    /// assert_eq!(series.to_string(), "x-x^3/6+x^5/120+O(x^7)");
+    ///
+    /// let series = parse("1/x")?.series('x', &Expr::Number(1), 3).unwrap();
+    /// assert_eq!(series.polynomial().pretty(), "1-(x-1)+(x-1)^2");
+    ///
+    /// let series = parse("exp(x)")?.series('x', &Expr::Number(0), 15).unwrap();
+    /// assert_eq!(series.coefficients[14].pretty(), "1/(1362160800*64)");
+    ///
+    /// let series = parse("exp(x)")?.series('x', &Expr::Number(0), 30).unwrap();
+    /// assert_eq!(series.order, 21);
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn series(&self, var: char, point: &Expr, order: u32) -> Result<Series, SeriesError> {
+        let coefficients = match Rational::from_expr(point) {
+            Some(at) => match recurrence::coefficients(self, var, at, order as usize) {
+                Ok(coefficients) => coefficients
+                    .into_iter()
+                    .map_while(|c| coefficient(c, None))
+                    .collect(),
+                Err(Unsupported::Overflow) => return Err(SeriesError::Overflow(0)),
+                Err(Unsupported::NotRational) => self.derivative_coefficients(var, point, order)?,
+            },
+            None => self.derivative_coefficients(var, point, order)?,
+        };
+        if order > 0 && coefficients.is_empty() {
+            return Err(SeriesError::Overflow(0));
+        }
+        Ok(Series {
+            var,
+            point: point.clone(),
+            order: coefficients.len() as u32,
+            coefficients,
+        })
+    }
+
+    /// Coefficients of [`Expr::series`] as the derivatives at the point over `k!`, up to the
+    /// first that doesn't fit.
+    fn derivative_coefficients(
+        &self,
+        var: char,
+        point: &Expr,
+        order: u32,
+    ) -> Result<Vec<Expr>, SeriesError> {
+        let at_point = HashMap::from([(var.to_string(), point.clone())]);
+        let mut derivative = reciprocal_powers(&self.simplify_full());
+        let mut coefficients = Vec::new();
+        for k in 0..order {
+            if k > 0 {
+                derivative = reciprocal_powers(&derivative.diff(var)?.simplify_full());
+            }
+            // Check before simplifying, which would turn `0*0^-1` into `0`
+            let value = derivative.substitute(&at_point);
+            if value.eval(&HashMap::new()).is_ok_and(|v| !v.is_finite()) {
+                return Err(SeriesError::Singular(k));
+            }
+            let (factor, rest) = split_constant(&value.simplify_full());
+            let Some(coefficient) = Rational::factorial(k)
+                .and_then(|factorial| factor.checked_div(factorial))
+                .and_then(|factor| coefficient(factor, rest))
+            else {
+                break;
+            };
+            coefficients.push(coefficient);
+        }
+        Ok(coefficients)
+    }
+}
+
+/// `factor * rest` as an expression, see [`wide_scaled`].
+fn coefficient(factor: Rational, rest: Option<Expr>) -> Option<Expr> {
+    wide_scaled(factor, rest, None).map(|(negative, magnitude)| match negative {
+        true => Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            expr: Box::new(magnitude),
+        },
+        false => magnitude,
+    })
+}
+
+/// [`scaled`], writing a numerator or denominator beyond the range of [`Expr::Number`] as a
+/// product of numbers in range: `1/13!` becomes `1/(1556755200*4)`. `None` when it has a prime
+/// factor too large for that.
+fn wide_scaled(factor: Rational, rest: Option<Expr>, power: Option<Expr>) -> Option<(bool, Expr)> {
+    if let Some(scaled) = scaled(factor, rest.clone(), power.clone()) {
+        return Some(scaled);
+    }
+    let numer = wide_number(factor.numer().unsigned_abs())?;
+    let denom = wide_number(factor.denom().unsigned_abs())?;
+    let mut factors: Vec<Expr> = rest.into_iter().chain(power).collect();
+    if numer != Expr::Number(1) || factors.is_empty() {
+        factors.insert(0, numer);
+    }
+    let mut magnitude = factors
+        .into_iter()
+        .reduce(|product, factor| Expr::BinaryOp {
+            op: BinaryOp::Mul,
+            left: Box::new(product),
+            right: Box::new(factor),
+        })?;
+    if denom != Expr::Number(1) {
+        magnitude = Expr::BinaryOp {
+            op: BinaryOp::Div,
+            left: Box::new(magnitude),
+            right: Box::new(denom),
+        };
+    }
+    Some((factor.is_negative(), magnitude))
+}
+
+/// `n` as a number, or as a product of as few numbers in range as first fit finds.
+fn wide_number(n: u64) -> Option<Expr> {
+    const LIMIT: u64 = i32::MAX as u64;
+    if n <= LIMIT {
+        return Some(Expr::Number(n as i32));
+    }
+    let mut primes = Vec::new();
+    let mut rest = n;
+    let mut p = 2;
+    while rest > LIMIT && p * p <= rest && p < 1 << 16 {
+        while rest.is_multiple_of(p) {
+            primes.push(p);
+            rest /= p;
+        }
+        p += 1;
+    }
+    if rest > LIMIT {
+        return None;
+    }
+    primes.push(rest);
+    primes.sort_unstable_by(|a, b| b.cmp(a));
+    let mut chunks: Vec<u64> = Vec::new();
+    for prime in primes {
+        match chunks.iter_mut().find(|chunk| **chunk * prime <= LIMIT) {
+            Some(chunk) => *chunk *= prime,
+            None => chunks.push(prime),
+        }
+    }
+    chunks
+        .into_iter()
+        .map(|chunk| Expr::Number(chunk as i32))
+        .reduce(|product, factor| Expr::BinaryOp {
+            op: BinaryOp::Mul,
+            left: Box::new(product),
+            right: Box::new(factor),
+        })
+}
+
+/// Rewrites `a/b` as `a*b^-1` and `(b^m)^n` as `b^(m*n)`. The power rule keeps repeated
+/// derivatives of these compact, where the quotient rule doubles the expression every time.
+fn reciprocal_powers(expr: &Expr) -> Expr {
+    match expr {
+        Expr::BinaryOp {
+            op: BinaryOp::Div,
+            left,
+            right,
+        } if !matches!(**right, Expr::Number(_)) => {
+            let reciprocal = reciprocal_powers(&Expr::BinaryOp {
+                op: BinaryOp::Pow,
+                left: right.clone(),
+                right: Box::new(Expr::Number(-1)),
+            });
+            match reciprocal_powers(left) {
+                Expr::Number(1) => reciprocal,
+                left => Expr::BinaryOp {
+                    op: BinaryOp::Mul,
+                    left: Box::new(left),
+                    right: Box::new(reciprocal),
+                },
+            }
+        }
+        Expr::BinaryOp {
+            op: BinaryOp::Pow,
+            left,
+            right,
+        } => {
+            let left = reciprocal_powers(left);
+            if let (
+                Expr::BinaryOp {
+                    op: BinaryOp::Pow,
+                    left: base,
+                    right: inner,
+                },
+                Expr::Number(n),
+            ) = (&left, right.as_ref())
+            {
+                if let Some(exponent) = match **inner {
+                    Expr::Number(m) => m.checked_mul(*n),
+                    _ => None,
+                } {
+                    return Expr::BinaryOp {
+                        op: BinaryOp::Pow,
+                        left: base.clone(),
+                        right: Box::new(Expr::Number(exponent)),
+                    };
+                }
+            }
+            Expr::BinaryOp {
+                op: BinaryOp::Pow,
+                left: Box::new(left),
+                right: right.clone(),
+            }
+        }
+        Expr::BinaryOp { op, left, right } => Expr::BinaryOp {
+            op: op.clone(),
+            left: Box::new(reciprocal_powers(left)),
+            right: Box::new(reciprocal_powers(right)),
+        },
+        Expr::UnaryOp { op, expr } => Expr::UnaryOp {
+            op: op.clone(),
+            expr: Box::new(reciprocal_powers(expr)),
+        },
+        Expr::Call { name, arg } => Expr::Call {
+            name: name.clone(),
+            arg: Box::new(reciprocal_powers(arg)),
+        },
+        _ => expr.clone(),
+    }
+}
+
+impl Series {
+    /// The Taylor polynomial without the order term.
+    pub fn polynomial(&self) -> Expr {
+        signed_sum(
+            self.coefficients
+                .iter()
+                .enumerate()
+                .filter_map(|(k, coefficient)| self.term(coefficient, k as u32)),
+        )
+    }
+
+    /// `O((var - point)^order)`.
+    pub fn order_term(&self) -> Expr {
+        Expr::Call {
+            name: "O".to_string(),
+            arg: Box::new(self.power(self.order)),
+        }
+    }
+
+    /// The polynomial plus the order term.
+    pub fn to_expr(&self) -> Expr {
+        Expr::BinaryOp {
+            op: BinaryOp::Add,
+            left: Box::new(self.polynomial()),
+            right: Box::new(self.order_term()),
+        }
+    }
+
+    /// `var - point`, written `var + c` when the point is a negative number.
+    fn base(&self) -> Expr {
+        let var = variable(self.var);
+        let point = Rational::from_expr(&self.point);
+        if point.is_some_and(|point| point.is_zero()) {
+            return var;
+        }
+        let negated = point
+            .filter(|point| point.is_negative())
+            .and_then(|point| (-point).to_expr());
+        Expr::BinaryOp {
+            op: if negated.is_some() {
+                BinaryOp::Add
+            } else {
+                BinaryOp::Sub
+            },
+            left: Box::new(var),
+            right: Box::new(negated.unwrap_or_else(|| self.point.clone())),
+        }
+    }
+
+    fn power(&self, k: u32) -> Expr {
+        match k {
+            1 => self.base(),
+            _ => Expr::BinaryOp {
+                op: BinaryOp::Pow,
+                left: Box::new(self.base()),
+                right: Box::new(Expr::Number(k as i32)),
+            },
+        }
+    }
+
+    /// Term `k` as its sign and magnitude, `None` if the coefficient is zero.
+    fn term(&self, coefficient: &Expr, k: u32) -> Option<(bool, Expr)> {
+        let (factor, rest) = split_constant(coefficient);
+        if factor.is_zero() {
+            return None;
+        }
+        wide_scaled(factor, rest, (k > 0).then(|| self.power(k)))
+    }
+}
+
+impl Display for Series {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.to_expr().pretty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SeriesError;
+    use crate::{parse, Expr};
+
+    fn series(input: &str, point: i32, order: u32) -> String {
+        parse(input)
+            .unwrap()
+            .series('x', &Expr::Number(point), order)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn rational_coefficients_by_recurrence() {
+        assert_eq!(
+            series("tan(x)", 0, 12),
+            "x+x^3/3+2x^5/15+17x^7/315+62x^9/2835+1382x^11/155925+O(x^12)"
+        );
+        assert_eq!(series("atan(x)", 0, 6), "x-x^3/3+x^5/5+O(x^6)");
+        assert_eq!(series("asin(x)", 0, 6), "x+x^3/6+3x^5/40+O(x^6)");
+        assert_eq!(series("ln(1+x)", 0, 4), "x-x^2/2+x^3/3+O(x^4)");
+        assert_eq!(series("sqrt(4+x)", 0, 3), "2+x/4-x^2/64+O(x^3)");
+        assert_eq!(series("1/(1-x)^2", 0, 4), "1+2x+3x^2+4x^3+O(x^4)");
+        assert_eq!(series("exp(sin(x))", 0, 4), "1+x+x^2/2+O(x^4)");
+        assert_eq!(series("x^2", 3, 4), "9+6*(x-3)+(x-3)^2+O((x-3)^4)");
+    }
+
+    #[test]
+    fn large_factorials_are_written_as_products() {
+        assert_eq!(
+            parse("exp(x)")
+                .unwrap()
+                .series('x', &Expr::Number(0), 21)
+                .unwrap()
+                .coefficients[13]
+                .pretty(),
+            "1/(1556755200*4)"
+        );
+        assert_eq!(series("exp(x)", 0, 22), series("exp(x)", 0, 21));
+        assert_eq!(
+            parse("exp(x)")
+                .unwrap()
+                .series('x', &Expr::Number(0), 200)
+                .unwrap()
+                .order,
+            21
+        );
+        assert_eq!(
+            parse("100000^4").unwrap().series('x', &Expr::Number(0), 3),
+            Err(SeriesError::Overflow(0))
+        );
+    }
+
+    #[test]
+    fn irrational_values_fall_back_to_derivatives() {
+        assert_eq!(
+            series("exp(x)", 1, 3),
+            "exp(1)+exp(1)*(x-1)+exp(1)*(x-1)^2/2+O((x-1)^3)"
+        );
+        assert_eq!(
+            parse("ln(x)").unwrap().series('x', &Expr::Number(0), 3),
+            Err(SeriesError::Singular(0))
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use crate::parser::ast::{BinaryOp, Expr, UnaryOp};
+use crate::rational::Rational;
+
+/// Why [`coefficients`] can't expand an expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Unsupported {
+    /// A value isn't rational, like `exp(1)`, or the expression isn't analytic at the point.
+    NotRational,
+    /// Not even the constant coefficient fits a [`Rational`].
+    Overflow,
+}
+
+/// Truncated power series, coefficient of `(var - point)^k` at index `k`. Since a coefficient
+/// only depends on the ones before it, every operation keeps the coefficients up to the first
+/// that doesn't fit a [`Rational`] and drops the rest, so a series can be shorter than asked
+/// for, but never empty.
+type Coefficients = Result<Vec<Rational>, Unsupported>;
+
+/// The first `n` Taylor coefficients of `expr` about `var = point`, or fewer when a later one
+/// overflows, found with power series arithmetic rather than repeated derivatives: sums and
+/// products term by term, quotients and powers by recurrences, and each function from the
+/// series of its argument.
+pub(super) fn coefficients(expr: &Expr, var: char, point: Rational, n: usize) -> Coefficients {
+    if n == 0 {
+        return Ok(Vec::new());
+    }
+    Expansion { var, point, n }.expand(expr)
+}
+
+struct Expansion {
+    var: char,
+    point: Rational,
+    n: usize,
+}
+
+impl Expansion {
+    fn expand(&self, expr: &Expr) -> Coefficients {
+        match expr {
+            Expr::Number(c) => Ok(self.constant(Rational::integer(*c as i64))),
+            Expr::Variable(c) | Expr::Constant(c) if *c == self.var => {
+                let mut series = self.constant(self.point);
+                if self.n > 1 {
+                    series[1] = Rational::ONE;
+                }
+                Ok(series)
+            }
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+            } => scale(&self.expand(expr)?, -Rational::ONE),
+            Expr::BinaryOp { op, left, right } => {
+                let a = self.expand(left)?;
+                match op {
+                    BinaryOp::Add => add(&a, &self.expand(right)?),
+                    BinaryOp::Sub => add(&a, &scale(&self.expand(right)?, -Rational::ONE)?),
+                    BinaryOp::Mul => product(&a, &self.expand(right)?),
+                    BinaryOp::Div => quotient(&a, &self.expand(right)?),
+                    BinaryOp::Pow => match Rational::from_expr(right) {
+                        Some(exponent) => power(&a, exponent),
+                        None => Err(Unsupported::NotRational),
+                    },
+                }
+            }
+            Expr::Call { name, arg } => {
+                let g = self.expand(arg)?;
+                match name.as_str() {
+                    "exp" => exp(&g),
+                    "ln" => ln(&g),
+                    "sqrt" => square_root(&g),
+                    "sin" => Ok(sin_cos(&g)?.0),
+                    "cos" => Ok(sin_cos(&g)?.1),
+                    "tan" => {
+                        let (sin, cos) = sin_cos(&g)?;
+                        quotient(&sin, &cos)
+                    }
+                    "atan" => {
+                        let one_plus_square = add(&unit(self.n), &product(&g, &g)?)?;
+                        inverse(&g, &one_plus_square)
+                    }
+                    "asin" => {
+                        let square = scale(&product(&g, &g)?, -Rational::ONE)?;
+                        inverse(&g, &square_root(&add(&unit(self.n), &square)?)?)
+                    }
+                    _ => Err(Unsupported::NotRational),
+                }
+            }
+            _ => Err(Unsupported::NotRational),
+        }
+    }
+
+    fn constant(&self, c: Rational) -> Vec<Rational> {
+        let mut series = vec![Rational::ZERO; self.n];
+        series[0] = c;
+        series
+    }
+}
+
+fn overflow<T>(value: Option<T>) -> Result<T, Unsupported> {
+    value.ok_or(Unsupported::Overflow)
+}
+
+/// `series`, unless not a single coefficient fit.
+fn truncated(series: Vec<Rational>) -> Coefficients {
+    match series.is_empty() {
+        true => Err(Unsupported::Overflow),
+        false => Ok(series),
+    }
+}
+
+fn multiply(factors: &[Rational]) -> Option<Rational> {
+    factors.iter().try_fold(Rational::ONE, |product, &factor| {
+        product.checked_mul(factor)
+    })
+}
+
+fn add(a: &[Rational], b: &[Rational]) -> Coefficients {
+    truncated(
+        a.iter()
+            .zip(b)
+            .map_while(|(&x, &y)| x.checked_add(y))
+            .collect(),
+    )
+}
+
+fn scale(a: &[Rational], factor: Rational) -> Coefficients {
+    truncated(a.iter().map_while(|&x| x.checked_mul(factor)).collect())
+}
+
+/// `Σ a_j b_(k-j)` over `j` in `from..=k`.
+fn convolution(a: &[Rational], b: &[Rational], from: usize, k: usize) -> Option<Rational> {
+    (from..=k).try_fold(Rational::ZERO, |sum, j| {
+        sum.checked_add(multiply(&[a[j], b[k - j]])?)
+    })
+}
+
+fn product(a: &[Rational], b: &[Rational]) -> Coefficients {
+    truncated(
+        (0..a.len().min(b.len()))
+            .map_while(|k| convolution(a, b, 0, k))
+            .collect(),
+    )
+}
+
+/// `a/b`, with `q_k = (a_k - Σ b_j q_(k-j)) / b_0`. The quotient has no series when `b_0` is
+/// zero.
+fn quotient(a: &[Rational], b: &[Rational]) -> Coefficients {
+    if b[0].is_zero() {
+        return Err(Unsupported::NotRational);
+    }
+    let mut q = Vec::with_capacity(a.len());
+    for (k, &a_k) in a.iter().enumerate().take(b.len()) {
+        let Some(next) = convolution(b, &q, 1, k)
+            .and_then(|rest| a_k.checked_sub(rest))
+            .and_then(|d| d.checked_div(b[0]))
+        else {
+            break;
+        };
+        q.push(next);
+    }
+    truncated(q)
+}
+
+/// `g^e`: by repeated squaring for integers, otherwise by J. C. P. Miller's recurrence
+/// `f_k = Σ ((e+1)j - k) g_j f_(k-j) / (k g_0)`, which needs a rational `g_0^e`.
+fn power(g: &[Rational], e: Rational) -> Coefficients {
+    if e.is_integer() {
+        let base = match e.is_negative() {
+            true => quotient(&unit(g.len()), g)?,
+            false => g.to_vec(),
+        };
+        let mut exponent = e.numer().unsigned_abs();
+        let (mut result, mut square) = (unit(g.len()), base);
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = product(&result, &square)?;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                square = product(&square, &square)?;
+            }
+        }
+        return Ok(result);
+    }
+    let f0 = match (g[0], e.denom()) {
+        (g0, _) if g0 == Rational::ONE => Rational::ONE,
+        (g0, 2) if !g0.is_zero() => {
+            let root = g0.sqrt().ok_or(Unsupported::NotRational)?;
+            overflow(
+                i32::try_from(e.numer())
+                    .ok()
+                    .and_then(|p| root.checked_pow(p)),
+            )?
+        }
+        _ => return Err(Unsupported::NotRational),
+    };
+    let e_plus_one = overflow(e.checked_add(Rational::ONE))?;
+    let mut f = vec![f0];
+    for k in 1..g.len() {
+        let sum = (1..=k).try_fold(Rational::ZERO, |sum, j| {
+            let weight = multiply(&[e_plus_one, Rational::integer(j as i64)])?
+                .checked_sub(Rational::integer(k as i64))?;
+            sum.checked_add(multiply(&[weight, g[j], f[k - j]])?)
+        });
+        let divisor = multiply(&[Rational::integer(k as i64), g[0]]);
+        let Some(next) = sum
+            .zip(divisor)
+            .and_then(|(sum, divisor)| sum.checked_div(divisor))
+        else {
+            break;
+        };
+        f.push(next);
+    }
+    Ok(f)
+}
+
+fn square_root(g: &[Rational]) -> Coefficients {
+    power(g, overflow(Rational::new(1, 2))?)
+}
+
+/// `1 + 0 + 0 + ...`
+fn unit(n: usize) -> Vec<Rational> {
+    let mut series = vec![Rational::ZERO; n];
+    series[0] = Rational::ONE;
+    series
+}
+
+/// `exp(g)` for `g_0 = 0`, from `f' = g' f`: `f_k = Σ j g_j f_(k-j) / k`.
+fn exp(g: &[Rational]) -> Coefficients {
+    if !g[0].is_zero() {
+        return Err(Unsupported::NotRational);
+    }
+    let mut f = vec![Rational::ONE];
+    for k in 1..g.len() {
+        let Some(next) =
+            weighted(g, &f, k).and_then(|sum| sum.checked_div(Rational::integer(k as i64)))
+        else {
+            break;
+        };
+        f.push(next);
+    }
+    Ok(f)
+}
+
+/// `sin(g)` and `cos(g)` for `g_0 = 0`, from `s' = g' c` and `c' = -g' s`.
+fn sin_cos(g: &[Rational]) -> Result<(Vec<Rational>, Vec<Rational>), Unsupported> {
+    if !g[0].is_zero() {
+        return Err(Unsupported::NotRational);
+    }
+    let (mut s, mut c) = (vec![Rational::ZERO], vec![Rational::ONE]);
+    for k in 1..g.len() {
+        let k_rational = Rational::integer(k as i64);
+        let next_s = weighted(g, &c, k).and_then(|sum| sum.checked_div(k_rational));
+        let next_c = weighted(g, &s, k).and_then(|sum| (-sum).checked_div(k_rational));
+        let (Some(next_s), Some(next_c)) = (next_s, next_c) else {
+            break;
+        };
+        s.push(next_s);
+        c.push(next_c);
+    }
+    Ok((s, c))
+}
+
+/// `Σ j g_j f_(k-j)` over `j` in `1..=k`, where `f` has at least `k` coefficients.
+fn weighted(g: &[Rational], f: &[Rational], k: usize) -> Option<Rational> {
+    (1..=k).try_fold(Rational::ZERO, |sum, j| {
+        sum.checked_add(multiply(&[Rational::integer(j as i64), g[j], f[k - j]])?)
+    })
+}
+
+/// `ln(g)` for `g_0 = 1`, the integral of `g'/g`.
+fn ln(g: &[Rational]) -> Coefficients {
+    if g[0] != Rational::ONE {
+        return Err(Unsupported::NotRational);
+    }
+    integral(&quotient(&derivative(g)?, g)?)
+}
+
+/// `atan(g)` or `asin(g)` for `g_0 = 0`, the integral of `g'/denominator`.
+fn inverse(g: &[Rational], denominator: &[Rational]) -> Coefficients {
+    if !g[0].is_zero() {
+        return Err(Unsupported::NotRational);
+    }
+    integral(&quotient(&derivative(g)?, denominator)?)
+}
+
+/// `g'`, padded with a zero: the last coefficient is unknown, and only [`integral`] reads the
+/// result, which never uses it.
+fn derivative(g: &[Rational]) -> Coefficients {
+    truncated(
+        (1..=g.len())
+            .map_while(|k| match g.get(k) {
+                Some(&c) => c.checked_mul(Rational::integer(k as i64)),
+                None => Some(Rational::ZERO),
+            })
+            .collect(),
+    )
+}
+
+/// The antiderivative of `h` that vanishes at the point.
+fn integral(h: &[Rational]) -> Coefficients {
+    let mut f = vec![Rational::ZERO];
+    for k in 1..h.len() {
+        let Some(next) = h[k - 1].checked_div(Rational::integer(k as i64)) else {
+            break;
+        };
+        f.push(next);
+    }
+    Ok(f)
+}
@@ -0,0 +1,116 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::parser::ast::Expr;
+
+impl Expr {
+    /// Replaces symbols by expressions. Keys name a variable (`"x"`), a constant (`"a"`) or a
+    /// symbol (`"ans"`); function names are left alone.
+    ///
+    /// All replacements happen at once and the inserted expressions are not substituted again,
+    /// so `{x: y, y: x}` swaps `x` and `y`. The result isn't simplified.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use derivative_calculator::{parse, Expr};
+    ///
+    /// let expr = parse("x^2 + a*x")?;
+    /// let map = HashMap::from([("x".to_string(), parse("2t + 1")?)]);
+    /// assert_eq!(expr.substitute(&map).pretty(), "(2*t+1)^2+a*(2*t+1)");
+    /// # Ok::<(), derivative_calculator::Error>(())
+    /// ```
+    pub fn substitute(&self, substitutions: &HashMap<String, Expr>) -> Expr {
+        // Nothing binds a name yet. Once something does (a sum or an integral over `k`), it must
+        // shadow `k` in `substitutions` and rename `k` if a replacement mentions it freely, see
+        // `Expr::free_symbols`.
+        match self {
+            Expr::Variable(c) | Expr::Constant(c) => substitutions
+                .get(&c.to_string())
+                .cloned()
+                .unwrap_or_else(|| self.clone()),
+            Expr::Symbol(name) => substitutions
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| self.clone()),
+            Expr::Call { name, arg } => Expr::Call {
+                name: name.clone(),
+                arg: Box::new(arg.substitute(substitutions)),
+            },
+            Expr::UnaryOp { op, expr } => Expr::UnaryOp {
+                op: op.clone(),
+                expr: Box::new(expr.substitute(substitutions)),
+            },
+            Expr::BinaryOp { op, left, right } => Expr::BinaryOp {
+                op: op.clone(),
+                left: Box::new(left.substitute(substitutions)),
+                right: Box::new(right.substitute(substitutions)),
+            },
+            Expr::Number(_) => self.clone(),
+        }
+    }
+
+    /// Names of the variables, constants and symbols the expression depends on, in the form
+    /// [`Expr::substitute`] takes them.
+    pub fn free_symbols(&self) -> BTreeSet<String> {
+        let mut symbols = BTreeSet::new();
+        collect_symbols(self, &mut symbols);
+        symbols
+    }
+}
+
+fn collect_symbols(expr: &Expr, symbols: &mut BTreeSet<String>) {
+    match expr {
+        Expr::Variable(c) | Expr::Constant(c) => {
+            symbols.insert(c.to_string());
+        }
+        Expr::Symbol(name) => {
+            symbols.insert(name.clone());
+        }
+        Expr::Call { arg, .. } => collect_symbols(arg, symbols),
+        Expr::UnaryOp { expr, .. } => collect_symbols(expr, symbols),
+        Expr::BinaryOp { left, right, .. } => {
+            collect_symbols(left, symbols);
+            collect_symbols(right, symbols);
+        }
+        Expr::Number(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{parse, Expr};
+
+    fn substituted(input: &str, substitutions: &[(&str, &str)]) -> String {
+        let substitutions: HashMap<String, Expr> = substitutions
+            .iter()
+            .map(|(name, value)| (name.to_string(), parse(value).unwrap()))
+            .collect();
+        parse(input).unwrap().substitute(&substitutions).pretty()
+    }
+
+    #[test]
+    fn replacements_happen_at_once() {
+        assert_eq!(substituted("x - 2y", &[("x", "y"), ("y", "x")]), "y-2x");
+        assert_eq!(substituted("x^2", &[("x", "x+1")]), "(x+1)^2");
+        assert_eq!(substituted("a*x", &[("x", "a"), ("a", "b")]), "b*a");
+    }
+
+    #[test]
+    fn symbols_and_calls() {
+        let ans = Expr::Symbol("ans".to_string());
+        let expr = parse("sin(x)").unwrap();
+        let map = HashMap::from([("ans".to_string(), expr.clone())]);
+        assert_eq!(ans.substitute(&map), expr);
+        // Function names aren't symbols
+        assert_eq!(substituted("sin(s)", &[("sin", "y"), ("s", "2")]), "sin(2)");
+        assert_eq!(substituted("x+1", &[("y", "2")]), "x+1");
+    }
+
+    #[test]
+    fn free_symbols_are_named_as_substitute_takes_them() {
+        let symbols = parse("a*sin(x) + b^2").unwrap().free_symbols();
+        assert_eq!(symbols.into_iter().collect::<Vec<_>>(), ["a", "b", "x"]);
+        assert!(parse("exp(1)").unwrap().free_symbols().is_empty());
+    }
+}
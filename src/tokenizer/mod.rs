@@ -6,21 +6,15 @@ use std::{iter::Peekable, str::Chars};
 use tokens::{Token, WhiteSpace};
 
 /// Token location: tracks only index for single-line input.
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub(crate) struct Location {
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Location {
     /// Index in the input string, starting at 0.
     pub expr_idx: usize,
 }
 
-impl Default for Location {
-    fn default() -> Self {
-        Self { expr_idx: 0 }
-    }
-}
-
 /// Stores both the [`Token`] and its starting location in the input string.
 #[derive(Debug, PartialEq)]
-pub(super) struct TokenWithLocation {
+pub struct TokenWithLocation {
     pub variant: Token,
     pub location: Location,
 }
@@ -30,11 +24,6 @@ impl TokenWithLocation {
     pub fn token_only(self) -> Token {
         self.variant
     }
-
-    /// Reference to [`Token`].
-    pub fn token(&self) -> &Token {
-        &self.variant
-    }
 }
 
 /// Token stream for symbolic math expressions.
@@ -69,12 +58,6 @@ impl<'i> Stream<'i> {
         self.chars.peek()
     }
 
-    /// Consumes one character in the stream and returns a reference to the next one without consuming it.
-    fn peek_next(&mut self) -> Option<&char> {
-        self.next();
-        self.peek()
-    }
-
     /// Take characters while predicate is true, return as String.
     fn take_while<P: FnMut(&char) -> bool>(&mut self, mut predicate: P) -> String {
         let mut result = String::new();
@@ -96,10 +79,10 @@ impl<'i> Stream<'i> {
 }
 
 /// Possible syntax errors for the symbolic math tokenizer.
-#[derive(Debug, PartialEq)]
-pub(crate) enum ErrorKind {
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ErrorKind {
     UnexpectedOrUnsupportedToken(char),
-    OperatorNotClosed(Token),
     Other(String),
 }
 
@@ -109,22 +92,36 @@ impl Display for ErrorKind {
             ErrorKind::UnexpectedOrUnsupportedToken(token) => {
                 write!(f, "unexpected or unsupported token '{token}'")
             }
-            ErrorKind::OperatorNotClosed(operator) => write!(f, "'{operator}' operator not closed"),
             ErrorKind::Other(message) => f.write_str(message),
         }
     }
 }
 
 /// If the tokenizer finds an error it means the syntax is not correct.
-#[derive(Debug, PartialEq)]
-pub(super) struct TokenizeError {
-    pub kind: ErrorKind,
-    pub location: Location,
-    pub input: String,
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenizeError {
+    pub(crate) kind: ErrorKind,
+    pub(crate) location: Location,
+    pub(crate) input: String,
+}
+
+impl TokenizeError {
+    /// Index of the offending character in the input, counted in characters.
+    pub fn position(&self) -> usize {
+        self.location.expr_idx
+    }
+}
+
+impl Display for TokenizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} at position {}", self.kind, self.location.expr_idx)
+    }
 }
 
+impl std::error::Error for TokenizeError {}
+
 /// Main parsing structure for symbolic math expressions.
-pub(super) struct Tokenizer<'i> {
+pub struct Tokenizer<'i> {
     stream: Stream<'i>,
     reached_eof: bool,
 }
@@ -196,8 +193,9 @@ impl<'i> Tokenizer<'i> {
             '(' => self.consume(Token::LEFTPAREN),
             ')' => self.consume(Token::RIGHTPAREN),
             '0'..='9' => self.tokenize_number(),
-            'x' => self.tokenize_variable(),
-            'a'..='w' | 'y'..='z' | 'A'..='W' | 'Y'..='Z' => self.tokenize_constants(),
+            '$' => self.tokenize_result_reference(),
+            'a'..='z' => self.tokenize_word(),
+            'A'..='Z' => self.tokenize_constants(),
             _ => {
                 let error_kind = ErrorKind::UnexpectedOrUnsupportedToken(*chr);
                 self.error(error_kind)
@@ -230,21 +228,38 @@ impl<'i> Tokenizer<'i> {
         }
     }
 
-    /// Tokenizes a variable (single alphabetic character).
-    fn tokenize_variable(&mut self) -> TokenResult {
-        let var_str = self.stream.take_while(|&chr| chr == 'x');
-        if var_str.len() == 1 {
-            Ok(Token::VARIABLE(var_str.chars().next().unwrap()))
+    /// Tokenizes a run of lowercase letters, digits and underscores. A lone `x` is the variable,
+    /// any other single letter a constant and longer words are identifiers, which the parser
+    /// keeps whole when they are names (`sin`, `ans`, ...) and splits into letters otherwise.
+    fn tokenize_word(&mut self) -> TokenResult {
+        let word = self
+            .stream
+            .take_while(|&chr| chr.is_ascii_lowercase() || chr.is_ascii_digit() || chr == '_');
+        let mut chars = word.chars();
+        match (chars.next(), chars.next()) {
+            (Some('x'), None) => Ok(Token::VARIABLE('x')),
+            (Some(chr), None) => Ok(Token::CONSTANT(chr)),
+            _ => Ok(Token::IDENT(word)),
+        }
+    }
+
+    /// Tokenizes a reference to a previous result such as `$3` into an identifier.
+    fn tokenize_result_reference(&mut self) -> TokenResult {
+        self.stream.next();
+        let digits = self.stream.take_while(|chr| chr.is_ascii_digit());
+        if digits.is_empty() {
+            self.error(ErrorKind::Other(
+                "Expected a result number after '$'".to_string(),
+            ))
         } else {
-            self.error(ErrorKind::Other("Invalid variable".to_string()))
+            Ok(Token::IDENT(format!("${digits}")))
         }
     }
 
     fn tokenize_constants(&mut self) -> TokenResult {
         if let Some(&chr) = self.stream.peek() {
-            if ('a'..='z').contains(&chr) && chr != 'x' {
-                self.stream.next();
-                Ok(Token::CONSTANT(chr))
+            if chr.is_ascii_lowercase() {
+                self.tokenize_word()
             } else {
                 self.error(ErrorKind::Other(
                     "Neither a constant nor a variable!".to_string(),
@@ -257,7 +272,7 @@ impl<'i> Tokenizer<'i> {
 }
 
 /// Iterator over Tokenizer yielding Result<TokenWithLocation, TokenizeError>
-pub(super) struct Iter<'t, 'i> {
+pub struct Iter<'t, 'i> {
     tokenizer: &'t mut Tokenizer<'i>,
 }
 
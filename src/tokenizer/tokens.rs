@@ -1,9 +1,10 @@
-#![allow(unused_variables, dead_code)]
+#![allow(unused_variables, dead_code, clippy::upper_case_acronyms)]
 use std::fmt::{self, Display, Write};
 
 // Possible(valid) tokens that can be found on a mathematical expressions entered by the user.
 #[derive(Debug, PartialEq, Clone)]
-pub(crate) enum Token {
+#[non_exhaustive]
+pub enum Token {
     NUMBER(i32),
     PLUS,
     MINUS,
@@ -14,6 +15,8 @@ pub(crate) enum Token {
     RIGHTPAREN,
     VARIABLE(char),
     CONSTANT(char),
+    /// Multi-letter name: a function, a session binding or a result reference like `$2`.
+    IDENT(String),
     EOF,
     WhiteSpace(WhiteSpace),
 }
@@ -33,6 +36,7 @@ impl Display for Token {
             Self::NUMBER(val) => write!(f, "{}", val),
             Self::VARIABLE(name) => write!(f, "{}", name),
             Self::CONSTANT(c) => write!(f, "{}", c),
+            Self::IDENT(name) => f.write_str(name),
             Self::WhiteSpace(whitespace) => write!(f, "{whitespace}"),
         }
     }
@@ -40,7 +44,7 @@ impl Display for Token {
 
 // Possible WhiteSpaces that can be encountered while parsing mathematical expressions
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub(crate) enum WhiteSpace {
+pub enum WhiteSpace {
     SPACE,
     TAB,
     NEWLINE,